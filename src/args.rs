@@ -1,9 +1,16 @@
 use std::env;
 
+/// The flags that take a value, which can be given as `--key=value` or as
+/// `--key value`.
+const VALUED: &[&str] = &["out", "emit", "target", "cratetype"];
+
 /// Returns (flags, args, preserved)
 pub fn parse(use_preserve: bool) -> (Vec<String>, Vec<String>, Vec<String>) {
-    let args = env::args().collect::<Vec<_>>();
+    parse_from(env::args().collect(), use_preserve)
+}
 
+/// Like [`parse`], for `args` starting with the program's name.
+fn parse_from(args: Vec<String>, use_preserve: bool) -> (Vec<String>, Vec<String>, Vec<String>) {
     let mut flags = Vec::new();
     let mut ret_args = Vec::new();
     let mut preserve = Vec::new();
@@ -19,7 +26,15 @@ pub fn parse(use_preserve: bool) -> (Vec<String>, Vec<String>, Vec<String>) {
             if arg == "-" {
                 ret_args.push(arg.to_owned());
             } else {
-                flags.push(resolve(arg.to_owned()));
+                let flag = resolve(arg.to_owned());
+
+                match args.get(i + 1) {
+                    Some(value) if VALUED.contains(&flag.as_str()) => {
+                        flags.push(format!("{}={}", flag, value));
+                        i += 1;
+                    }
+                    _ => flags.push(flag),
+                }
             }
         } else if is_preserve {
             preserve.push(arg.to_owned());
@@ -34,12 +49,72 @@ pub fn parse(use_preserve: bool) -> (Vec<String>, Vec<String>, Vec<String>) {
 }
 
 fn resolve(arg: String) -> String {
+    // `--key=value` flags keep their value verbatim
+    if let Some((key, value)) = arg.split_once('=') {
+        return format!("{}={}", resolve(key.to_string()), value);
+    }
+
     match arg.as_str() {
         "-V" => "verbose".to_string(),
         "-vv" => "very-verbose".to_string(),
         "-h" => "help".to_string(),
         "-v" => "version".to_string(),
         "-o" => "out".to_string(),
+        "-g" => "debug".to_string(),
         _ => arg.replace('-', ""),
     }
 }
+
+/// Returns the value of a `--key=value` flag, if it was given.
+pub fn value<'a>(flags: &'a [String], key: &str) -> Option<&'a str> {
+    flags.iter().find_map(|flag| {
+        flag.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let args = Some("qlang")
+            .into_iter()
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+
+        parse_from(args, true)
+    }
+
+    #[test]
+    fn values_follow_an_equals_sign_or_a_space() {
+        for args in [
+            &["build", "main.q", "-o=out.o"][..],
+            &["build", "main.q", "-o", "out.o"],
+            &["build", "--out", "out.o", "main.q"],
+        ] {
+            let (flags, args, _) = parse(args);
+
+            assert_eq!(value(&flags, "out"), Some("out.o"));
+            assert_eq!(args, ["build", "main.q"]);
+        }
+    }
+
+    #[test]
+    fn flags_without_values_leave_the_next_argument_alone() {
+        let (flags, args, _) = parse(&["run", "--interp", "main.q", "-g"]);
+
+        assert_eq!(flags, ["interp", "debug"]);
+        assert_eq!(args, ["run", "main.q"]);
+    }
+
+    #[test]
+    fn arguments_after_a_double_dash_are_preserved() {
+        let (flags, args, preserved) = parse(&["run", "main.q", "--", "x", "y"]);
+
+        assert!(flags.is_empty());
+        assert_eq!(args, ["run", "main.q"]);
+        assert_eq!(preserved, ["x", "y"]);
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+
+use crate::{
+    bytecode::{Function, Global, Instr, Program},
+    hir::{self, Block, Expr, ExprKind, FnId, GlobalId, Place, Projection, Stmt, StmtKind},
+    interp::Value,
+    parser::{
+        ast::ast::{NodeType, Pos},
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+    },
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// Compiles type-checked code into bytecode for the [`vm`](super::vm).
+#[derive(Debug, Default)]
+pub struct Compiler {
+    program: Program,
+    fns: HashMap<FnId, u16>,
+    /// The name of every `extern fn`, which bytecode can't call.
+    externs: HashMap<FnId, String>,
+    globals: HashMap<GlobalId, u16>,
+}
+
+/// State for the function body currently being compiled.
+struct Scope {
+    code: Vec<Instr>,
+    positions: Vec<Pos>,
+    /// The `break` jumps of every loop being compiled, innermost last.
+    loops: Vec<Vec<usize>>,
+    /// How many local slots are used, including ones the compiler needs
+    /// for itself after the function's own.
+    locals: usize,
+    at: Pos,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile every global and function in a file.
+    pub fn compile(mut self, program: &hir::Program) -> Result<Program> {
+        for (i, global) in program.globals.iter().enumerate() {
+            if i > u16::MAX as usize {
+                return Err(self.error(global.at, "too many globals".to_string()));
+            }
+
+            self.globals.insert(global.id, i as u16);
+            self.program.globals.push(Global {
+                name: global.name.clone(),
+                value: (&global.value).into(),
+            });
+        }
+
+        // number everything up front so functions can call ones defined later
+        for (i, func) in program.functions.iter().enumerate() {
+            if i > u16::MAX as usize {
+                return Err(self.error(func.at, "too many functions".to_string()));
+            }
+
+            self.fns.insert(func.id, i as u16);
+        }
+
+        for item in &program.externs {
+            self.externs.insert(item.id, item.name.clone());
+        }
+
+        for func in &program.functions {
+            let compiled = self.compile_function(func)?;
+
+            self.program.functions.push(compiled);
+        }
+
+        Ok(self.program)
+    }
+
+    fn compile_function(&mut self, func: &hir::Function) -> Result<Function> {
+        let mut scope = Scope {
+            code: Vec::new(),
+            positions: Vec::new(),
+            loops: Vec::new(),
+            locals: func.locals.len(),
+            at: func.at,
+        };
+
+        // the value of a trailing expression statement is the implicit return value
+        if func.ret == NodeType::Void {
+            self.compile_block(&func.body, false, &mut scope)?;
+            scope.emit(Instr::Void);
+        } else {
+            self.compile_block(&func.body, true, &mut scope)?;
+        }
+
+        scope.emit(Instr::Return);
+
+        if scope.locals > u16::MAX as usize {
+            return Err(self.error(func.at, "too many variables".to_string()));
+        }
+
+        Ok(Function {
+            name: func.name.clone(),
+            file: func
+                .source
+                .as_ref()
+                .and_then(|s| s.file.as_ref())
+                .map(|file| file.display().to_string()),
+            arity: func.params as u16,
+            locals: scope.locals as u16,
+            code: scope.code,
+            positions: scope.positions,
+        })
+    }
+
+    // Statements
+
+    /// Compile a block. If `keep` is set, its value is left on the stack,
+    /// which is `()` unless it ends in an expression statement.
+    fn compile_block(&mut self, block: &Block, keep: bool, scope: &mut Scope) -> Result<()> {
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            self.compile_stmt(stmt, keep && i == block.stmts.len() - 1, scope)?;
+        }
+
+        if keep && block.stmts.is_empty() {
+            scope.emit(Instr::Void);
+        }
+
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt, keep: bool, scope: &mut Scope) -> Result<()> {
+        scope.at = stmt.at;
+
+        match &stmt.kind {
+            StmtKind::Let {
+                local,
+                value: Some(value),
+            } => {
+                self.compile_expr(value, scope)?;
+                scope.emit(Instr::Store(*local as u16));
+            }
+            StmtKind::Let { value: None, .. } => {}
+            StmtKind::Expr(expr) => {
+                self.compile_expr(expr, scope)?;
+
+                if !keep {
+                    scope.emit(Instr::Pop);
+                }
+
+                return Ok(());
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => self.compile_expr(value, scope)?,
+                    None => scope.emit(Instr::Void),
+                }
+
+                scope.emit(Instr::Return);
+            }
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.compile_expr(cond, scope)?;
+                let to_else = scope.emit_jump(Instr::JumpIfFalse(0));
+
+                self.compile_block(then, false, scope)?;
+                let to_end = scope.emit_jump(Instr::Jump(0));
+
+                scope.patch(to_else);
+
+                if let Some(otherwise) = otherwise {
+                    self.compile_block(otherwise, false, scope)?;
+                }
+
+                scope.patch(to_end);
+            }
+            StmtKind::Match(arms) => {
+                let mut to_end = Vec::new();
+
+                for arm in arms {
+                    let mut to_next = Vec::new();
+
+                    for cond in &arm.conds {
+                        self.compile_expr(cond, scope)?;
+                        to_next.push(scope.emit_jump(Instr::JumpIfFalse(0)));
+                    }
+
+                    self.compile_block(&arm.body, false, scope)?;
+                    to_end.push(scope.emit_jump(Instr::Jump(0)));
+
+                    for jump in to_next {
+                        scope.patch(jump);
+                    }
+                }
+
+                for jump in to_end {
+                    scope.patch(jump);
+                }
+            }
+            StmtKind::Loop(body) => {
+                let start = scope.code.len() as u32;
+
+                scope.loops.push(Vec::new());
+                self.compile_block(body, false, scope)?;
+                scope.emit(Instr::Jump(start));
+
+                for jump in scope.loops.pop().unwrap() {
+                    scope.patch(jump);
+                }
+            }
+            StmtKind::Break => {
+                let jump = scope.emit_jump(Instr::Jump(0));
+
+                match scope.loops.last_mut() {
+                    Some(breaks) => breaks.push(jump),
+                    None => return Err(self.error(stmt.at, "`break` outside of a loop".into())),
+                }
+            }
+            StmtKind::Block(block) => return self.compile_block(block, keep, scope),
+        }
+
+        if keep {
+            scope.emit(Instr::Void);
+        }
+
+        Ok(())
+    }
+
+    // Expressions
+
+    fn compile_expr(&mut self, expr: &Expr, scope: &mut Scope) -> Result<()> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.constant(literal.into(), scope)?,
+            ExprKind::Local(local) => scope.emit(Instr::Load(*local as u16)),
+            ExprKind::Global(global) => self.load(Place::Global(*global), scope)?,
+            ExprKind::Call { func, args } => {
+                let func = match (self.fns.get(func), self.externs.get(func)) {
+                    (Some(func), _) => *func,
+                    (None, Some(name)) => {
+                        return Err(self.error(
+                            scope.at,
+                            format!(
+                                "extern function `{}` can only be called from compiled code",
+                                name
+                            ),
+                        ))
+                    }
+                    (None, None) => {
+                        return Err(
+                            self.error(scope.at, "call to a function outside the program".into())
+                        )
+                    }
+                };
+
+                for arg in args {
+                    self.compile_expr(arg, scope)?;
+                }
+
+                scope.emit(Instr::Call(func));
+            }
+            ExprKind::Assign { place, path, value } if path.is_empty() => {
+                // assignments are expressions, so keep a copy of the value around
+                self.compile_expr(value, scope)?;
+                scope.emit(Instr::Dup);
+                self.store(*place, scope)?;
+            }
+            ExprKind::Assign { place, path, value } => {
+                // the value is set aside while the path is walked
+                self.compile_expr(value, scope)?;
+                let slot = scope.scratch();
+                scope.emit(Instr::Store(slot));
+
+                // push the struct or array at every step of the path, along
+                // with the index into it, replace the last part and then put
+                // each one back into the one holding it
+                self.load(*place, scope)?;
+
+                for (i, projection) in path.iter().enumerate() {
+                    let last = i == path.len() - 1;
+
+                    match projection {
+                        Projection::Field(field) if !last => {
+                            scope.emit(Instr::Dup);
+                            scope.emit(Instr::GetField(*field as u16));
+                        }
+                        Projection::Field(_) => {}
+                        Projection::Index(index) => {
+                            self.compile_expr(index, scope)?;
+
+                            if !last {
+                                scope.emit(Instr::Dup2);
+                                scope.emit(Instr::GetIndex);
+                            }
+                        }
+                    }
+                }
+
+                scope.emit(Instr::Load(slot));
+
+                for projection in path.iter().rev() {
+                    match projection {
+                        Projection::Field(field) => scope.emit(Instr::SetField(*field as u16)),
+                        Projection::Index(_) => scope.emit(Instr::SetIndex),
+                    }
+                }
+
+                self.store(*place, scope)?;
+                scope.emit(Instr::Load(slot));
+            }
+            ExprKind::Array(elements) => {
+                for element in elements {
+                    self.compile_expr(element, scope)?;
+                }
+
+                scope.emit(Instr::MakeArray(elements.len() as u16));
+            }
+            ExprKind::Index { base, index } => {
+                self.compile_expr(base, scope)?;
+                self.compile_expr(index, scope)?;
+                scope.emit(Instr::GetIndex);
+            }
+            ExprKind::Builtin { builtin, args } => {
+                for arg in args {
+                    self.compile_expr(arg, scope)?;
+                }
+
+                scope.emit(Instr::Builtin(*builtin));
+            }
+            ExprKind::Struct(fields) => {
+                for field in fields {
+                    self.compile_expr(field, scope)?;
+                }
+
+                scope.emit(Instr::MakeStruct(fields.len() as u16));
+            }
+            ExprKind::Field { base, index } | ExprKind::Payload { base, index, .. } => {
+                self.compile_expr(base, scope)?;
+                scope.emit(Instr::GetField(*index as u16));
+            }
+            ExprKind::Variant { variant, fields } => {
+                for field in fields {
+                    self.compile_expr(field, scope)?;
+                }
+
+                scope.emit(Instr::MakeVariant(*variant as u16, fields.len() as u16));
+            }
+            ExprKind::Tag(base) => {
+                self.compile_expr(base, scope)?;
+                scope.emit(Instr::GetTag);
+            }
+            ExprKind::Unary { op, operand } => {
+                self.compile_expr(operand, scope)?;
+                scope.emit(Instr::Unary(*op));
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                self.compile_expr(lhs, scope)?;
+                self.compile_expr(rhs, scope)?;
+                scope.emit(Instr::Binary(*op));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Helpers
+
+    fn load(&self, place: Place, scope: &mut Scope) -> Result<()> {
+        match place {
+            Place::Local(local) => scope.emit(Instr::Load(local as u16)),
+            Place::Global(global) => {
+                let global = self.global(global, scope)?;
+
+                scope.emit(Instr::LoadGlobal(global));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn store(&self, place: Place, scope: &mut Scope) -> Result<()> {
+        match place {
+            Place::Local(local) => scope.emit(Instr::Store(local as u16)),
+            Place::Global(global) => {
+                let global = self.global(global, scope)?;
+
+                scope.emit(Instr::StoreGlobal(global));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn global(&self, global: GlobalId, scope: &Scope) -> Result<u16> {
+        self.globals
+            .get(&global)
+            .copied()
+            .ok_or_else(|| self.error(scope.at, "use of a static outside the program".into()))
+    }
+
+    /// Push a constant, reusing an existing pool entry if there is one.
+    fn constant(&mut self, value: Value, scope: &mut Scope) -> Result<()> {
+        let constants = &mut self.program.constants;
+        let index = match constants.iter().position(|c| *c == value) {
+            Some(index) => index,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+
+        if index > u16::MAX as usize {
+            return Err(self.error(scope.at, "too many constants".to_string()));
+        }
+
+        scope.emit(Instr::Const(index as u16));
+
+        Ok(())
+    }
+
+    fn error(&self, at: Pos, msg: String) -> CompileError {
+        CompileErrorBuilder::new()
+            .from(ErrorCodes::CodegenError)
+            .at((at.0, at.1 + 1))
+            .note(&msg)
+            .build()
+    }
+}
+
+impl Scope {
+    fn emit(&mut self, instr: Instr) {
+        self.code.push(instr);
+        self.positions.push(self.at);
+    }
+
+    /// A local slot for the compiler's own use.
+    fn scratch(&mut self) -> u16 {
+        self.locals += 1;
+        (self.locals - 1) as u16
+    }
+
+    /// Emit a jump whose target is filled in later by [`Scope::patch`].
+    fn emit_jump(&mut self, instr: Instr) -> usize {
+        self.emit(instr);
+        self.code.len() - 1
+    }
+
+    /// Make the jump at `at` go to the next instruction emitted.
+    fn patch(&mut self, at: usize) {
+        let to = self.code.len() as u32;
+
+        match &mut self.code[at] {
+            Instr::Jump(target) | Instr::JumpIfFalse(target) => *target = to,
+            instr => panic!("{:?} is not a jump", instr),
+        }
+    }
+}
@@ -0,0 +1,483 @@
+//! The `.qbc` file format, a serialised [`Program`].
+//!
+//! All integers are little-endian. A file is the magic bytes and a version,
+//! followed by the constants pool, every global and then every function:
+//!
+//! ```text
+//! file     = "QBC\0" version:u8 u32 constant* u32 global* u32 function*
+//! constant = tag:u8 payload
+//! global   = string constant
+//! function = string file arity:u16 locals:u16 u32 (instr line:u32 column:u32)*
+//! file     = 0 | 1 string
+//! instr    = opcode:u8 operand
+//! string   = len:u32 utf8-bytes
+//! ```
+
+use std::convert::TryInto;
+
+use crate::{
+    bytecode::{Function, Global, Instr, Program},
+    hir::Builtin,
+    interp::Value,
+    parser::ast::ast::Op,
+};
+
+pub const MAGIC: &[u8; 4] = b"QBC\0";
+pub const VERSION: u8 = 7;
+
+/// Operators in the order of their encoding.
+const OPS: &[Op] = &[
+    Op::Eq,
+    Op::Neq,
+    Op::Lt,
+    Op::Gt,
+    Op::Le,
+    Op::Ge,
+    Op::Add,
+    Op::Sub,
+    Op::Mul,
+    Op::Div,
+    Op::Mod,
+    Op::And,
+    Op::Or,
+    Op::Not,
+    Op::Assign,
+];
+
+/// Whether `bytes` look like a `.qbc` file.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn serialise(program: &Program) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+
+    write_u32(&mut out, program.constants.len() as u32);
+
+    for constant in &program.constants {
+        write_value(&mut out, constant);
+    }
+
+    write_u32(&mut out, program.globals.len() as u32);
+
+    for global in &program.globals {
+        write_str(&mut out, &global.name);
+        write_value(&mut out, &global.value);
+    }
+
+    write_u32(&mut out, program.functions.len() as u32);
+
+    for func in &program.functions {
+        write_str(&mut out, &func.name);
+
+        match &func.file {
+            Some(file) => {
+                out.push(1);
+                write_str(&mut out, file);
+            }
+            None => out.push(0),
+        }
+
+        out.extend_from_slice(&func.arity.to_le_bytes());
+        out.extend_from_slice(&func.locals.to_le_bytes());
+        write_u32(&mut out, func.code.len() as u32);
+
+        for (instr, at) in func.code.iter().zip(&func.positions) {
+            match instr {
+                Instr::Const(c) => write_u16(&mut out, 0, *c),
+                Instr::Load(slot) => write_u16(&mut out, 1, *slot),
+                Instr::Store(slot) => write_u16(&mut out, 2, *slot),
+                Instr::LoadGlobal(g) => write_u16(&mut out, 12, *g),
+                Instr::StoreGlobal(g) => write_u16(&mut out, 13, *g),
+                Instr::MakeStruct(n) => write_u16(&mut out, 14, *n),
+                Instr::GetField(field) => write_u16(&mut out, 15, *field),
+                Instr::SetField(field) => write_u16(&mut out, 16, *field),
+                Instr::MakeVariant(variant, n) => {
+                    write_u16(&mut out, 17, *variant);
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+                Instr::GetTag => out.push(18),
+                Instr::MakeArray(n) => write_u16(&mut out, 19, *n),
+                Instr::GetIndex => out.push(20),
+                Instr::SetIndex => out.push(21),
+                Instr::Dup2 => out.push(22),
+                Instr::Builtin(builtin) => out.extend_from_slice(&[23, encode_builtin(*builtin)]),
+                Instr::Pop => out.push(3),
+                Instr::Dup => out.push(4),
+                Instr::Void => out.push(5),
+                Instr::Unary(op) => out.extend_from_slice(&[6, encode_op(*op)]),
+                Instr::Binary(op) => out.extend_from_slice(&[7, encode_op(*op)]),
+                Instr::Jump(to) => {
+                    out.push(8);
+                    write_u32(&mut out, *to);
+                }
+                Instr::JumpIfFalse(to) => {
+                    out.push(9);
+                    write_u32(&mut out, *to);
+                }
+                Instr::Call(func) => write_u16(&mut out, 10, *func),
+                Instr::Return => out.push(11),
+            }
+
+            write_u32(&mut out, at.0 as u32);
+            write_u32(&mut out, at.1 as u32);
+        }
+    }
+
+    out
+}
+
+/// Read a program back, checking that every instruction refers to
+/// something that exists so the VM can't index out of bounds.
+pub fn deserialise(bytes: &[u8]) -> Result<Program, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    if reader.take(4)? != MAGIC {
+        return Err("not a Qlang bytecode file".to_string());
+    }
+
+    let version = reader.u8()?;
+
+    if version != VERSION {
+        return Err(format!(
+            "unsupported bytecode version {} (expected {})",
+            version, VERSION
+        ));
+    }
+
+    let mut program = Program::default();
+
+    for _ in 0..reader.u32()? {
+        let constant = reader.value()?;
+
+        program.constants.push(constant);
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let value = reader.value()?;
+
+        program.globals.push(Global { name, value });
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let file = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.string()?),
+            tag => return Err(format!("unknown file tag {} in `{}`", tag, name)),
+        };
+        let arity = reader.u16()?;
+        let locals = reader.u16()?;
+        let len = reader.u32()?;
+
+        let mut code = Vec::new();
+        let mut positions = Vec::new();
+
+        for _ in 0..len {
+            let instr = match reader.u8()? {
+                0 => Instr::Const(reader.u16()?),
+                1 => Instr::Load(reader.u16()?),
+                2 => Instr::Store(reader.u16()?),
+                3 => Instr::Pop,
+                4 => Instr::Dup,
+                5 => Instr::Void,
+                6 => Instr::Unary(decode_op(reader.u8()?)?),
+                7 => Instr::Binary(decode_op(reader.u8()?)?),
+                8 => Instr::Jump(reader.u32()?),
+                9 => Instr::JumpIfFalse(reader.u32()?),
+                10 => Instr::Call(reader.u16()?),
+                11 => Instr::Return,
+                12 => Instr::LoadGlobal(reader.u16()?),
+                13 => Instr::StoreGlobal(reader.u16()?),
+                14 => Instr::MakeStruct(reader.u16()?),
+                15 => Instr::GetField(reader.u16()?),
+                16 => Instr::SetField(reader.u16()?),
+                17 => Instr::MakeVariant(reader.u16()?, reader.u16()?),
+                18 => Instr::GetTag,
+                19 => Instr::MakeArray(reader.u16()?),
+                20 => Instr::GetIndex,
+                21 => Instr::SetIndex,
+                22 => Instr::Dup2,
+                23 => Instr::Builtin(decode_builtin(reader.u8()?)?),
+                opcode => return Err(format!("unknown opcode {} in `{}`", opcode, name)),
+            };
+
+            code.push(instr);
+            positions.push((reader.u32()? as usize, reader.u32()? as usize));
+        }
+
+        program.functions.push(Function {
+            name,
+            file,
+            arity,
+            locals,
+            code,
+            positions,
+        });
+    }
+
+    if reader.pos != bytes.len() {
+        return Err("trailing bytes after the last function".to_string());
+    }
+
+    validate(&program)?;
+
+    Ok(program)
+}
+
+fn validate(program: &Program) -> Result<(), String> {
+    for func in &program.functions {
+        let invalid = |instr: &Instr| format!("invalid instruction {:?} in `{}`", instr, func.name);
+
+        if func.arity > func.locals {
+            return Err(format!("`{}` has fewer locals than arguments", func.name));
+        }
+
+        // running off the end of a function would be just as bad
+        if func.code.last() != Some(&Instr::Return) {
+            return Err(format!("`{}` doesn't end in a return", func.name));
+        }
+
+        for instr in &func.code {
+            let valid = match instr {
+                Instr::Const(c) => (*c as usize) < program.constants.len(),
+                Instr::Load(slot) | Instr::Store(slot) => *slot < func.locals,
+                Instr::LoadGlobal(g) | Instr::StoreGlobal(g) => {
+                    (*g as usize) < program.globals.len()
+                }
+                Instr::Jump(to) | Instr::JumpIfFalse(to) => (*to as usize) < func.code.len(),
+                Instr::Call(f) => (*f as usize) < program.functions.len(),
+                _ => true,
+            };
+
+            if !valid {
+                return Err(invalid(instr));
+            }
+        }
+
+        check_stack(program, func)?;
+    }
+
+    Ok(())
+}
+
+/// Check that no instruction in `func` pops more values than there are,
+/// whichever way it's reached, so that the VM never pops an empty stack or
+/// the values of the function calling it.
+fn check_stack(program: &Program, func: &Function) -> Result<(), String> {
+    let mut heights = vec![None; func.code.len()];
+    let mut next = vec![(0, 0)];
+
+    while let Some((ip, height)) = next.pop() {
+        match heights[ip] {
+            Some(seen) if seen == height => continue,
+            Some(_) => {
+                return Err(format!(
+                    "the stack isn't always as high at instruction {} of `{}`",
+                    ip, func.name
+                ))
+            }
+            None => heights[ip] = Some(height),
+        }
+
+        let instr = &func.code[ip];
+        let (pops, pushes) = match instr {
+            Instr::Const(_) | Instr::Load(_) | Instr::LoadGlobal(_) | Instr::Void => (0, 1),
+            Instr::Store(_) | Instr::StoreGlobal(_) | Instr::Pop | Instr::JumpIfFalse(_) => (1, 0),
+            Instr::MakeStruct(n) | Instr::MakeArray(n) | Instr::MakeVariant(_, n) => {
+                (*n as usize, 1)
+            }
+            Instr::GetField(_) | Instr::GetTag | Instr::Unary(_) => (1, 1),
+            Instr::SetField(_) | Instr::GetIndex | Instr::Binary(_) => (2, 1),
+            Instr::SetIndex => (3, 1),
+            Instr::Builtin(builtin) => (builtin.arity(), 1),
+            Instr::Dup => (1, 2),
+            Instr::Dup2 => (2, 4),
+            Instr::Call(f) => (program.functions[*f as usize].arity as usize, 1),
+            Instr::Jump(_) => (0, 0),
+            Instr::Return => (1, 0),
+        };
+
+        if height < pops {
+            return Err(format!(
+                "instruction {} of `{}` pops more values than there are",
+                ip, func.name
+            ));
+        }
+
+        let height = height - pops + pushes;
+
+        // it ends in a return, so there's always an instruction after
+        // anything else
+        match instr {
+            Instr::Return => {}
+            Instr::Jump(to) => next.push((*to as usize, height)),
+            Instr::JumpIfFalse(to) => next.extend([(*to as usize, height), (ip + 1, height)]),
+            _ => next.push((ip + 1, height)),
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_op(op: Op) -> u8 {
+    OPS.iter().position(|o| *o == op).unwrap() as u8
+}
+
+fn decode_op(byte: u8) -> Result<Op, String> {
+    OPS.get(byte as usize)
+        .copied()
+        .ok_or_else(|| format!("unknown operator {}", byte))
+}
+
+fn encode_builtin(builtin: Builtin) -> u8 {
+    Builtin::ALL.iter().position(|b| *b == builtin).unwrap() as u8
+}
+
+fn decode_builtin(byte: u8) -> Result<Builtin, String> {
+    Builtin::ALL
+        .get(byte as usize)
+        .copied()
+        .ok_or_else(|| format!("unknown builtin {}", byte))
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::String(s) => {
+            out.push(0);
+            write_str(out, s);
+        }
+        Value::Integer(i) => {
+            out.push(1);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::Float(f) => {
+            out.push(2);
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Char(c) => {
+            out.push(3);
+            write_u32(out, *c as u32);
+        }
+        Value::Bool(b) => out.extend_from_slice(&[4, *b as u8]),
+        Value::Void => out.push(5),
+        Value::Struct(fields) => {
+            out.push(6);
+            write_u32(out, fields.len() as u32);
+
+            for field in fields {
+                write_value(out, field);
+            }
+        }
+        Value::Array(elements) => {
+            out.push(8);
+            write_u32(out, elements.len() as u32);
+
+            for element in elements {
+                write_value(out, element);
+            }
+        }
+        Value::Variant(variant, fields) => {
+            out.push(7);
+            write_u32(out, *variant as u32);
+            write_u32(out, fields.len() as u32);
+
+            for field in fields {
+                write_value(out, field);
+            }
+        }
+        Value::Vec(_) | Value::Slice(..) => {
+            unreachable!("`Vec`s only exist at runtime, so they're never constants")
+        }
+    }
+}
+
+fn write_u16(out: &mut Vec<u8>, opcode: u8, operand: u16) {
+    out.push(opcode);
+    out.extend_from_slice(&operand.to_le_bytes());
+}
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len() as u32);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| "unexpected end of bytecode".to_string())?;
+
+        self.pos += n;
+
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let value = match self.u8()? {
+            0 => Value::String(self.string()?),
+            1 => Value::Integer(i32::from_le_bytes(self.array()?)),
+            2 => Value::Float(f32::from_le_bytes(self.array()?)),
+            3 => Value::Char(
+                char::from_u32(self.u32()?).ok_or_else(|| "invalid char constant".to_string())?,
+            ),
+            4 => Value::Bool(self.u8()? != 0),
+            5 => Value::Void,
+            6 => Value::Struct(
+                (0..self.u32()?)
+                    .map(|_| self.value())
+                    .collect::<Result<_, _>>()?,
+            ),
+            7 => Value::Variant(
+                self.u32()? as usize,
+                (0..self.u32()?)
+                    .map(|_| self.value())
+                    .collect::<Result<_, _>>()?,
+            ),
+            8 => Value::Array(
+                (0..self.u32()?)
+                    .map(|_| self.value())
+                    .collect::<Result<_, _>>()?,
+            ),
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        };
+
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::{self, Display};
+
+use crate::{hir::Builtin, interp::Value, parser::ast::ast::Op, parser::ast::ast::Pos};
+
+pub mod compiler;
+pub mod format;
+pub mod vm;
+
+/// A single VM instruction. Operands index into the constants pool, the
+/// program's globals, the current function's local slots, its code, the
+/// program's functions or a struct's fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    /// Push a constant.
+    Const(u16),
+    /// Push the value of a local.
+    Load(u16),
+    /// Pop a value into a local.
+    Store(u16),
+    /// Push the value of a global.
+    LoadGlobal(u16),
+    /// Pop a value into a global.
+    StoreGlobal(u16),
+    /// Pop as many values as a struct has fields, last field on top, and
+    /// push the struct.
+    MakeStruct(u16),
+    /// Pop a struct or an enum variant and push one of its fields.
+    GetField(u16),
+    /// Pop a value and a struct below it, and push the struct with one of
+    /// its fields replaced by the value.
+    SetField(u16),
+    /// Pop as many values as a variant (the first operand) has fields, last
+    /// field on top, and push the enum value.
+    MakeVariant(u16, u16),
+    /// Pop an enum value and push the index of its variant.
+    GetTag,
+    /// Pop as many values as an array has elements, last element on top,
+    /// and push the array.
+    MakeArray(u16),
+    /// Pop an index and an array below it, and push the element at the
+    /// index.
+    GetIndex,
+    /// Pop a value, an index and an array, and push the array with the
+    /// element at the index replaced by the value.
+    SetIndex,
+    /// Pop as many values as a builtin takes arguments, last argument on
+    /// top, and push what it returns.
+    Builtin(Builtin),
+    Pop,
+    Dup,
+    /// Push copies of the top two values, keeping their order.
+    Dup2,
+    /// Push `()`, the value of statements that aren't expressions.
+    Void,
+    Unary(Op),
+    Binary(Op),
+    Jump(u32),
+    /// Pop a bool and jump if it's false.
+    JumpIfFalse(u32),
+    /// Call a function with its arguments on top of the stack.
+    Call(u16),
+    /// Pop the return value and go back to the caller.
+    Return,
+}
+
+/// A compiled function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// The file it was compiled from, `None` for the file being compiled,
+    /// which runtime errors in it are shown against.
+    pub file: Option<String>,
+    pub arity: u16,
+    /// How many local slots the function needs, including its arguments.
+    pub locals: u16,
+    pub code: Vec<Instr>,
+    /// The source position of every instruction, for runtime errors.
+    pub positions: Vec<Pos>,
+}
+
+/// A global variable and the value it starts out with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub value: Value,
+}
+
+/// A whole compiled file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub constants: Vec<Value>,
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    /// The index of the function called `name`.
+    pub fn function(&self, name: &str) -> Option<u16> {
+        self.functions
+            .iter()
+            .position(|func| func.name == name)
+            .map(|i| i as u16)
+    }
+}
+
+/// Disassembles the program.
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "constants:")?;
+
+        for (i, constant) in self.constants.iter().enumerate() {
+            writeln!(f, "    {:>4}  {}", i, constant)?;
+        }
+
+        if !self.globals.is_empty() {
+            writeln!(f, "\nglobals:")?;
+        }
+
+        for (i, global) in self.globals.iter().enumerate() {
+            writeln!(f, "    {:>4}  {} = {}", i, global.name, global.value)?;
+        }
+
+        for func in &self.functions {
+            writeln!(
+                f,
+                "\nfn {} (arity {}, locals {}):",
+                func.name, func.arity, func.locals
+            )?;
+
+            for (i, instr) in func.code.iter().enumerate() {
+                let line = func.positions[i].0 + 1;
+
+                write!(f, "    {:04}  {:>4}  ", i, line)?;
+
+                match instr {
+                    Instr::Const(c) => {
+                        writeln!(f, "const {:<8} ; {}", c, self.constants[*c as usize])?
+                    }
+                    Instr::Load(slot) => writeln!(f, "load {}", slot)?,
+                    Instr::Store(slot) => writeln!(f, "store {}", slot)?,
+                    Instr::LoadGlobal(g) => writeln!(
+                        f,
+                        "load_global {:<2} ; {}",
+                        g, self.globals[*g as usize].name
+                    )?,
+                    Instr::StoreGlobal(g) => {
+                        writeln!(f, "store_global {} ; {}", g, self.globals[*g as usize].name)?
+                    }
+                    Instr::MakeStruct(n) => writeln!(f, "make_struct {}", n)?,
+                    Instr::GetField(field) => writeln!(f, "get_field {}", field)?,
+                    Instr::SetField(field) => writeln!(f, "set_field {}", field)?,
+                    Instr::MakeVariant(variant, n) => {
+                        writeln!(f, "make_variant {} {}", variant, n)?
+                    }
+                    Instr::GetTag => writeln!(f, "get_tag")?,
+                    Instr::MakeArray(n) => writeln!(f, "make_array {}", n)?,
+                    Instr::GetIndex => writeln!(f, "get_index")?,
+                    Instr::SetIndex => writeln!(f, "set_index")?,
+                    Instr::Builtin(builtin) => writeln!(f, "builtin {}", builtin)?,
+                    Instr::Pop => writeln!(f, "pop")?,
+                    Instr::Dup => writeln!(f, "dup")?,
+                    Instr::Dup2 => writeln!(f, "dup2")?,
+                    Instr::Void => writeln!(f, "void")?,
+                    Instr::Unary(op) => writeln!(f, "unary {:?}", op)?,
+                    Instr::Binary(op) => writeln!(f, "binary {:?}", op)?,
+                    Instr::Jump(to) => writeln!(f, "jump {:04}", to)?,
+                    Instr::JumpIfFalse(to) => writeln!(f, "jump_if_false {:04}", to)?,
+                    Instr::Call(func) => writeln!(
+                        f,
+                        "call {:<9} ; {}",
+                        func, self.functions[*func as usize].name
+                    )?,
+                    Instr::Return => writeln!(f, "return")?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
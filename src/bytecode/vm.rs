@@ -0,0 +1,286 @@
+use std::path::Path;
+
+use crate::{
+    bytecode::{Instr, Program},
+    hir::Projection,
+    interp::{self, Value},
+    parser::{
+        ast::ast::Pos,
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+    },
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// How many calls may be active at once.
+const MAX_FRAMES: usize = 1 << 16;
+
+/// A stack machine that runs compiled bytecode.
+#[derive(Debug)]
+pub struct Vm<'p> {
+    program: &'p Program,
+    /// The current value of every global, which persists between calls.
+    globals: Vec<Value>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+/// A function call in progress.
+#[derive(Debug)]
+struct Frame {
+    func: usize,
+    ip: usize,
+    /// Where the function's locals start on the stack.
+    base: usize,
+}
+
+impl<'p> Vm<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            globals: program
+                .globals
+                .iter()
+                .map(|global| global.value.clone())
+                .collect(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Call the function `name` with already evaluated arguments and run
+    /// until it returns.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let func = self.program.function(name).ok_or_else(|| {
+            CompileErrorBuilder::new()
+                .from(ErrorCodes::RuntimeError)
+                .note(&format!("unknown function `{}`", name))
+                .build()
+        })?;
+        let arity = self.program.functions[func as usize].arity as usize;
+
+        if args.len() != arity {
+            return Err(CompileErrorBuilder::new()
+                .from(ErrorCodes::RuntimeError)
+                .note(&format!(
+                    "`{}` takes {} arguments but {} were given",
+                    name,
+                    arity,
+                    args.len()
+                ))
+                .build());
+        }
+
+        self.stack.clear();
+        self.frames.clear();
+        self.stack.extend(args);
+        self.enter(func as usize)?;
+
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<Value> {
+        let program = self.program;
+
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let func = &program.functions[frame.func];
+            let instr = func.code[frame.ip];
+
+            frame.ip += 1;
+
+            match instr {
+                Instr::Const(c) => self.stack.push(program.constants[c as usize].clone()),
+                Instr::Load(slot) => {
+                    let value = self.stack[frame.base + slot as usize].clone();
+
+                    self.stack.push(value);
+                }
+                Instr::Store(slot) => {
+                    let value = self.pop();
+                    let base = self.frame().base;
+
+                    self.stack[base + slot as usize] = value;
+                }
+                Instr::LoadGlobal(g) => self.stack.push(self.globals[g as usize].clone()),
+                Instr::StoreGlobal(g) => {
+                    let value = self.pop();
+
+                    self.globals[g as usize] = value;
+                }
+                Instr::MakeStruct(n) => {
+                    let fields = self.stack.split_off(self.stack.len() - n as usize);
+
+                    self.stack.push(Value::Struct(fields));
+                }
+                Instr::GetField(field) => match self.pop() {
+                    Value::Struct(mut fields) | Value::Variant(_, mut fields)
+                        if (field as usize) < fields.len() =>
+                    {
+                        self.stack.push(fields.swap_remove(field as usize));
+                    }
+                    value => return Err(self.error(format!("{} has no field {}", value, field))),
+                },
+                Instr::SetField(field) => {
+                    let value = self.pop();
+                    let mut base = self.pop();
+
+                    match interp::project(&mut base, &[Projection::Field(field as usize)]) {
+                        Ok(target) => *target = value,
+                        Err(_) => {
+                            return Err(self.error(format!("{} has no field {}", base, field)));
+                        }
+                    }
+
+                    self.stack.push(base);
+                }
+                Instr::MakeArray(n) => {
+                    let elements = self.stack.split_off(self.stack.len() - n as usize);
+
+                    self.stack.push(Value::Array(elements));
+                }
+                Instr::GetIndex => {
+                    let index = self.pop();
+                    let array = self.pop();
+                    let element = interp::element(array, index).map_err(|e| self.error(e))?;
+
+                    self.stack.push(element);
+                }
+                Instr::SetIndex => {
+                    let value = self.pop();
+                    let index = match self.pop() {
+                        Value::Integer(index) => index,
+                        value => {
+                            return Err(self.error(format!("expected an int, found {}", value)));
+                        }
+                    };
+                    let mut array = self.pop();
+
+                    *interp::project(&mut array, &[Projection::Index(index)])
+                        .map_err(|e| self.error(e))? = value;
+
+                    self.stack.push(array);
+                }
+                Instr::Builtin(builtin) => {
+                    let args = self.stack.split_off(self.stack.len() - builtin.arity());
+                    let value = interp::builtin(builtin, args).map_err(|e| self.error(e))?;
+
+                    self.stack.push(value);
+                }
+                Instr::MakeVariant(variant, n) => {
+                    let fields = self.stack.split_off(self.stack.len() - n as usize);
+
+                    self.stack.push(Value::Variant(variant as usize, fields));
+                }
+                Instr::GetTag => match self.pop() {
+                    Value::Variant(variant, _) => self.stack.push(Value::Integer(variant as i32)),
+                    value => return Err(self.error(format!("{} isn't an enum value", value))),
+                },
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Dup => {
+                    let value = self.stack.last().unwrap().clone();
+
+                    self.stack.push(value);
+                }
+                Instr::Dup2 => {
+                    let top = self.stack[self.stack.len() - 2..].to_vec();
+
+                    self.stack.extend(top);
+                }
+                Instr::Void => self.stack.push(Value::Void),
+                Instr::Unary(op) => {
+                    let value = self.pop();
+                    let result = interp::unary(op, value).map_err(|e| self.error(e))?;
+
+                    self.stack.push(result);
+                }
+                Instr::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let result = interp::binary(op, lhs, rhs).map_err(|e| self.error(e))?;
+
+                    self.stack.push(result);
+                }
+                Instr::Jump(to) => frame.ip = to as usize,
+                Instr::JumpIfFalse(to) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.frame().ip = to as usize,
+                    value => {
+                        return Err(self.error(format!("expected a bool, found {}", value)));
+                    }
+                },
+                Instr::Call(func) => self.enter(func as usize)?,
+                Instr::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    /// Start running `func`, whose arguments are on top of the stack.
+    fn enter(&mut self, func: usize) -> Result<()> {
+        let function = &self.program.functions[func];
+
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(format!("stack overflow while calling `{}`", function.name)));
+        }
+
+        let base = self.stack.len() - function.arity as usize;
+
+        // arguments are the first locals, the rest start out empty
+        self.stack
+            .resize(base + function.locals as usize, Value::Void);
+        self.frames.push(Frame { func, ip: 0, base });
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("bytecode popped an empty stack")
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    /// The source position of the instruction being run.
+    fn at(&self) -> Pos {
+        match self.frames.last() {
+            Some(frame) => self.program.functions[frame.func].positions[frame.ip - 1],
+            None => (0, 0),
+        }
+    }
+
+    /// An error at the instruction being run, which says which file it's
+    /// in if that isn't the one being compiled. Only the caller has the
+    /// source to show it against.
+    fn error(&self, msg: String) -> CompileError {
+        let at = self.at();
+        let mut error = CompileErrorBuilder::new()
+            .from(ErrorCodes::RuntimeError)
+            .at((at.0, at.1 + 1))
+            .note(&msg)
+            .build();
+        let file = self
+            .frames
+            .last()
+            .and_then(|frame| self.program.functions[frame.func].file.as_ref());
+
+        if let Some(file) = file {
+            error.in_source(Some(Path::new(file)), "");
+        }
+
+        error
+    }
+}
@@ -0,0 +1,443 @@
+//! Embedding Qlang in a Rust program: compiling source code, letting it
+//! call functions of the host's and calling its functions from the host.
+//! Code that isn't trusted can be run in a [`Sandbox`].
+
+use std::{collections::HashSet, rc::Rc};
+
+#[cfg(feature = "llvm")]
+use inkwell::context::Context;
+
+#[cfg(feature = "llvm")]
+use crate::llvm::codegen::Codegen;
+use crate::{
+    hir::{Builtin, Extern, Function, Program, Source},
+    interp::{HostFn, Interpreter, Limits, Value},
+    parser::{
+        ast::ast::{self, Node, NodeType},
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+        TopLevel,
+    },
+    typeck::{self, TypeChecker},
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// Runs the code an [`Engine`] compiles.
+trait Backend {
+    fn define(&mut self, program: Program) -> Result<()>;
+
+    fn define_host(&mut self, item: &Extern, host: HostFn) -> Result<()>;
+
+    /// Run `call`, as checked by [`TypeChecker::check_body`], which calls
+    /// the function `name` with `args`.
+    fn call(&mut self, call: &Function, name: &str, args: Vec<Value>) -> Result<Value>;
+}
+
+#[cfg(feature = "llvm")]
+impl Backend for Codegen<'_> {
+    fn define(&mut self, program: Program) -> Result<()> {
+        self.compile(program)
+    }
+
+    fn define_host(&mut self, item: &Extern, host: HostFn) -> Result<()> {
+        Codegen::define_host(self, item, host)
+    }
+
+    // calling the function directly saves compiling the module again
+    fn call(&mut self, call: &Function, name: &str, args: Vec<Value>) -> Result<Value> {
+        Codegen::call(self, name, args, &call.ret)
+    }
+}
+
+impl Backend for Interpreter {
+    fn define(&mut self, program: Program) -> Result<()> {
+        Interpreter::define(self, program)
+    }
+
+    fn define_host(&mut self, item: &Extern, host: HostFn) -> Result<()> {
+        Interpreter::define_host(self, item.id, host);
+
+        Ok(())
+    }
+
+    fn call(&mut self, call: &Function, _: &str, _: Vec<Value>) -> Result<Value> {
+        Interpreter::evaluate(self, call)
+    }
+}
+
+/// A Rust type that values of a Qlang type can be passed as.
+pub trait HostValue: Sized {
+    /// The Qlang type of the values.
+    fn ty() -> NodeType;
+
+    fn into_value(self) -> Value;
+
+    /// `None` if `value` isn't of this type.
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+macro_rules! host_value {
+    ($($rust:ty => $ty:ident($variant:ident)),*) => {
+        $(
+            impl HostValue for $rust {
+                fn ty() -> NodeType {
+                    NodeType::$ty
+                }
+
+                fn into_value(self) -> Value {
+                    Value::$variant(self)
+                }
+
+                fn from_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::$variant(value) => Some(value),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+host_value! {
+    i32 => Integer(Integer),
+    f32 => Float(Float),
+    bool => Bool(Bool),
+    char => Char(Char),
+    String => String(String)
+}
+
+impl HostValue for () {
+    fn ty() -> NodeType {
+        NodeType::Void
+    }
+
+    fn into_value(self) -> Value {
+        Value::Void
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Void => Some(()),
+            _ => None,
+        }
+    }
+}
+
+/// The arguments of a call to a Qlang function, as a tuple of
+/// [`HostValue`]s.
+pub trait HostArgs {
+    fn into_values(self) -> Vec<Value>;
+}
+
+/// A Rust function Qlang code can call, taking the [`HostValue`]s `Args`.
+pub trait HostFunction<Args>: 'static {
+    /// The Qlang types of the arguments.
+    fn params() -> Vec<NodeType>;
+
+    /// The Qlang type of the return value.
+    fn ret() -> NodeType;
+
+    fn into_host(self) -> HostFn;
+}
+
+macro_rules! host_function {
+    ($($arg:ident $value:ident),*) => {
+        impl<$($arg: HostValue),*> HostArgs for ($($arg,)*) {
+            fn into_values(self) -> Vec<Value> {
+                let ($($value,)*) = self;
+
+                vec![$($value.into_value()),*]
+            }
+        }
+
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostValue,
+            $($arg: HostValue),*
+        {
+            fn params() -> Vec<NodeType> {
+                vec![$($arg::ty()),*]
+            }
+
+            fn ret() -> NodeType {
+                R::ty()
+            }
+
+            fn into_host(self) -> HostFn {
+                HostFn::new(move |args| {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut args = args.into_iter();
+
+                    $(
+                        let $value = args
+                            .next()
+                            .and_then($arg::from_value)
+                            .expect("host functions are given the arguments they're declared with");
+                    )*
+
+                    self($($value),*).into_value()
+                })
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A a);
+host_function!(A a, B b);
+host_function!(A a, B b, C c);
+host_function!(A a, B b, C c, D d);
+
+/// The builtins that reach outside of a sandbox, which code in one can only
+/// call if they're allowed.
+const CONSOLE: [Builtin; 3] = [Builtin::Print, Builtin::Println, Builtin::ReadLine];
+
+/// What code compiled by an engine made with [`Engine::sandboxed`] may do.
+/// It can't declare `extern fn`s, can only call the host functions and the
+/// console builtins (`print`, `println` and `read_line`) allowed here and is
+/// stopped once it goes over its [`Limits`].
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    limits: Limits,
+    /// The names of the host functions that may be registered and of the
+    /// console builtins that may be called.
+    allowed: HashSet<String>,
+}
+
+impl Sandbox {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            allowed: HashSet::new(),
+        }
+    }
+
+    /// Allow registering the host function `name`, or calling the console
+    /// builtin `name`.
+    pub fn allow(mut self, name: &str) -> Self {
+        self.allowed.insert(name.to_string());
+        self
+    }
+
+    /// Fail if `func` calls a console builtin that isn't allowed.
+    fn check(&self, func: &Function) -> Result<()> {
+        let denied = func.builtins().into_iter().find(|(builtin, _)| {
+            CONSOLE.contains(builtin) && !self.allowed.contains(builtin.name())
+        });
+
+        match denied {
+            Some((builtin, at)) => Err(CompileErrorBuilder::new()
+                .from(ErrorCodes::SandboxViolation)
+                .at((at.0, at.1 + 1))
+                .note(&format!(
+                    "`{}` can't be called in this sandbox",
+                    builtin.name()
+                ))
+                .hint(&format!(
+                    "allow it with `Sandbox::allow(\"{}\")`",
+                    builtin.name()
+                ))
+                .build()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Compiles Qlang code and runs it on behalf of a Rust program, with the
+/// JIT or the interpreter. Everything compiled with the same engine can
+/// use everything compiled before it.
+pub struct Engine<'ctx> {
+    backend: Box<dyn Backend + 'ctx>,
+    checker: TypeChecker,
+    sandbox: Option<Sandbox>,
+}
+
+impl<'ctx> Engine<'ctx> {
+    /// An engine that compiles code with the JIT.
+    #[cfg(feature = "llvm")]
+    pub fn new(context: &'ctx Context) -> Self {
+        Self::with_backend(Codegen::new(context, context.create_module("qlang")))
+    }
+
+    /// An engine that runs code with the interpreter, without needing LLVM.
+    pub fn interpreted() -> Self {
+        Self::with_backend(Interpreter::new())
+    }
+
+    /// An engine that runs code with the interpreter, within `sandbox`.
+    /// Going over its limits stops the code with an
+    /// [`OutOfFuel`](ErrorCodes::OutOfFuel), an
+    /// [`OutOfMemory`](ErrorCodes::OutOfMemory) or an
+    /// [`OutOfStack`](ErrorCodes::OutOfStack) error, and anything else it
+    /// doesn't allow fails with a
+    /// [`SandboxViolation`](ErrorCodes::SandboxViolation).
+    pub fn sandboxed(sandbox: Sandbox) -> Self {
+        Self {
+            sandbox: Some(sandbox.clone()),
+            ..Self::with_backend(Interpreter::new().with_limits(sandbox.limits))
+        }
+    }
+
+    fn with_backend(backend: impl Backend + 'ctx) -> Self {
+        Self {
+            backend: Box::new(backend),
+            checker: TypeChecker::new(),
+            sandbox: None,
+        }
+    }
+
+    /// Compile the items in `source`. If any of them fails to compile, none
+    /// of them are kept.
+    pub fn compile(&mut self, source: &str) -> Result<()> {
+        self.define(source).map_err(|mut e| {
+            e.with_raw(source.to_string());
+            e
+        })
+    }
+
+    fn define(&mut self, source: &str) -> Result<()> {
+        let ast: TopLevel = source.parse()?;
+
+        if let (Some(_), Some(item)) = (&self.sandbox, typeck::externs(&ast).first()) {
+            return Err(CompileErrorBuilder::new()
+                .from(ErrorCodes::SandboxViolation)
+                .at((item.at.0, item.at.1 + 1))
+                .note("`extern` functions can't be declared in a sandbox")
+                .build());
+        }
+
+        // only keep the new signatures around if everything compiles
+        let mut next = self.checker.clone();
+        let mut program = next.check(&ast)?;
+        let source = Rc::new(Source {
+            file: None,
+            text: source.to_string(),
+        });
+
+        // so that errors found while calling them later show this code
+        for func in &mut program.functions {
+            func.source = Some(source.clone());
+        }
+
+        if let Some(sandbox) = &self.sandbox {
+            for func in &program.functions {
+                sandbox.check(func)?;
+            }
+        }
+
+        self.backend.define(program)?;
+
+        self.checker = next;
+
+        Ok(())
+    }
+
+    /// Let code compiled afterwards call `function` as `name`, like an
+    /// `extern fn` taking and returning the Qlang types of its arguments
+    /// and return value. A host function that panics while being called
+    /// from the JIT aborts the process.
+    pub fn register<Args, F: HostFunction<Args>>(&mut self, name: &str, function: F) -> Result<()> {
+        if let Some(sandbox) = &self.sandbox {
+            if !sandbox.allowed.contains(name) {
+                return Err(error(
+                    ErrorCodes::SandboxViolation,
+                    format!("host function `{}` isn't allowed in this sandbox", name),
+                ));
+            }
+        }
+        let item = ast::Extern {
+            name: name.to_string(),
+            public: false,
+            args: F::params()
+                .into_iter()
+                .enumerate()
+                .map(|(i, ty)| (format!("arg{}", i), ty))
+                .collect(),
+            variadic: false,
+            ret: F::ret(),
+            link: Vec::new(),
+            at: (0, 0),
+        };
+
+        let mut next = self.checker.clone();
+        let item = next.define_extern(&item)?;
+        self.backend.define_host(&item, function.into_host())?;
+
+        self.checker = next;
+
+        Ok(())
+    }
+
+    /// Call the function `name` with `args`, a tuple of its arguments, and
+    /// return what it returns as an `R`.
+    pub fn call<R: HostValue>(&mut self, name: &str, args: impl HostArgs) -> Result<R> {
+        let values = args.into_values();
+        let args = values
+            .iter()
+            .cloned()
+            .map(literal)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                error(
+                    ErrorCodes::MismatchedTypes,
+                    format!("`{}` can't be given a value of type void", name),
+                )
+            })?;
+
+        // checking a call to it checks the arguments against its signature
+        let call = Node::Call {
+            name: name.to_string(),
+            args,
+        };
+        let func = self.checker.check_body(&call)?;
+
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.check(&func)?;
+        }
+
+        if func.ret != R::ty() {
+            return Err(error(
+                ErrorCodes::MismatchedTypes,
+                format!(
+                    "`{}` returns a value of type {}, not {}",
+                    name,
+                    func.ret,
+                    R::ty()
+                ),
+            ));
+        }
+
+        let value = self.backend.call(&func, name, values)?;
+
+        R::from_value(value).ok_or_else(|| {
+            error(
+                ErrorCodes::RuntimeError,
+                format!("`{}` didn't return a value of type {}", name, R::ty()),
+            )
+        })
+    }
+
+    /// The warnings found while compiling code since this was last called.
+    pub fn warnings(&mut self) -> Vec<CompileError> {
+        self.checker.warnings()
+    }
+}
+
+/// The literal a value passed to a Qlang function is written as, `None` for
+/// `void`.
+fn literal(value: Value) -> Option<Node> {
+    match value {
+        Value::String(s) => Some(Node::String(s)),
+        Value::Integer(i) => Some(Node::Integer(i)),
+        Value::Float(f) => Some(Node::Float(f)),
+        Value::Char(c) => Some(Node::Char(c)),
+        Value::Bool(b) => Some(Node::Bool(b)),
+        _ => None,
+    }
+}
+
+fn error(code: ErrorCodes, note: String) -> CompileError {
+    CompileErrorBuilder::new().from(code).note(&note).build()
+}
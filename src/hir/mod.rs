@@ -0,0 +1,494 @@
+//! The high-level IR: a desugared, fully typed form of the syntax tree that
+//! the type checker produces and every backend consumes.
+//!
+//! Unlike [`Node`](crate::parser::ast::ast::Node) it carries no syntax. Every
+//! expression has its resolved type, variables, globals and functions are
+//! referred to by ID instead of by name, and `while` loops are lowered to
+//! `loop`s that `break` out.
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use crate::parser::ast::ast::{NodeType, Op, Pos};
+
+/// Identifies a function for the lifetime of a [`TypeChecker`], so that
+/// code checked later (like REPL input) can call earlier functions.
+///
+/// [`TypeChecker`]: crate::typeck::TypeChecker
+pub type FnId = usize;
+
+/// Identifies a variable within a function, as an index into
+/// [`Function::locals`].
+pub type LocalId = usize;
+
+/// Identifies a `static` for the lifetime of a [`TypeChecker`], like a
+/// [`FnId`].
+///
+/// [`TypeChecker`]: crate::typeck::TypeChecker
+pub type GlobalId = usize;
+
+/// Every type, global and function in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub globals: Vec<Global>,
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    /// The libraries named by `#[link]` attributes, without repeats, in the
+    /// order they first appear.
+    pub fn libraries(&self) -> Vec<String> {
+        let mut libraries = Vec::new();
+
+        for name in self.externs.iter().flat_map(|item| &item.link) {
+            if !libraries.contains(name) {
+                libraries.push(name.clone());
+            }
+        }
+
+        libraries
+    }
+}
+
+/// A `struct` type, referred to by name in [`NodeType::Struct`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub name: String,
+    /// Fields are laid out in the order they were declared, as in C.
+    pub fields: Vec<(String, NodeType)>,
+    pub at: Pos,
+}
+
+/// An `enum` type, referred to by name in [`NodeType::Enum`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub at: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    /// The fields of a variant like `Circle(float)` are named by their
+    /// position.
+    pub fields: Vec<(String, NodeType)>,
+    /// Whether the fields were declared with names, like `Rect { w: float }`.
+    pub named: bool,
+}
+
+/// A `static`, which holds its value for as long as the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub id: GlobalId,
+    pub name: String,
+    pub ty: NodeType,
+    pub mutable: bool,
+    /// The value it starts out with.
+    pub value: Literal,
+    pub at: Pos,
+}
+
+/// An `extern fn`, a function defined outside the program, in C or
+/// anything else that follows its calling convention. Only compiled code
+/// can call one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    pub id: FnId,
+    /// The symbol it's defined by, which unlike a function's isn't
+    /// prefixed with its module.
+    pub name: String,
+    pub params: Vec<NodeType>,
+    /// Whether it takes more arguments after `params`, of any type C can
+    /// be given.
+    pub variadic: bool,
+    pub ret: NodeType,
+    /// The libraries it's defined in, which executables calling it are
+    /// linked with.
+    pub link: Vec<String>,
+    pub at: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub id: FnId,
+    pub name: String,
+    /// Whether it was declared `pub`, and so can be called from other modules.
+    pub public: bool,
+    /// The name C code calls it by, if it was marked `#[no_mangle]`. Its
+    /// arguments and return value are passed the way an [`Extern`]'s are.
+    pub export: Option<String>,
+    /// The arguments are always the first locals.
+    pub params: usize,
+    pub locals: Vec<Local>,
+    pub ret: NodeType,
+    pub body: Block,
+    pub at: Pos,
+    /// The code it was checked from, `None` for code the host made up, like
+    /// a call from the REPL.
+    pub source: Option<Rc<Source>>,
+}
+
+/// Code that functions were checked from, which errors found while running
+/// them are shown against.
+#[derive(Debug, PartialEq)]
+pub struct Source {
+    /// The file it's in, `None` for the file being compiled or code that
+    /// isn't in a file.
+    pub file: Option<PathBuf>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub name: String,
+    pub ty: NodeType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    /// The type of the trailing expression statement, or `void`.
+    pub ty: NodeType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub at: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// Declares a variable, which is only assigned later if there's no
+    /// `value`.
+    Let {
+        local: LocalId,
+        value: Option<Expr>,
+    },
+    Expr(Expr),
+    Return(Option<Expr>),
+    If {
+        cond: Expr,
+        then: Block,
+        otherwise: Option<Block>,
+    },
+    /// Runs the first arm whose conditions all hold, checking them in order
+    /// and stopping at the first that doesn't. The last arm has none, since
+    /// a `match` is only let through if its arms cover every value.
+    Match(Vec<Arm>),
+    /// Runs until a `break`.
+    Loop(Block),
+    Break,
+    Block(Block),
+}
+
+/// An arm of a `match`, whose pattern has been turned into conditions on
+/// the value being matched. Its body starts by binding the pattern's
+/// variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub conds: Vec<Expr>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: NodeType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Local(LocalId),
+    Global(GlobalId),
+    Call {
+        func: FnId,
+        args: Vec<Expr>,
+    },
+    /// Runs `value` and then the indices in `path`, before assigning.
+    Assign {
+        place: Place,
+        /// The path to the part of a struct or array being assigned, empty
+        /// when assigning to the whole place.
+        path: Vec<Projection>,
+        value: Box<Expr>,
+    },
+    /// A struct literal, with the value of every field in declaration order.
+    Struct(Vec<Expr>),
+    /// Reads the field at an index into a struct's fields.
+    Field {
+        base: Box<Expr>,
+        index: usize,
+    },
+    /// A value of an enum, with the value of every field of the variant in
+    /// declaration order.
+    Variant {
+        variant: usize,
+        fields: Vec<Expr>,
+    },
+    /// An array literal, with the value of every element.
+    Array(Vec<Expr>),
+    /// Reads an element of an array, a `Vec` or a slice, which fails at
+    /// runtime if the index is out of bounds.
+    Index {
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// A call to a function built into the language, which every backend
+    /// implements itself.
+    Builtin {
+        builtin: Builtin,
+        args: Vec<Expr>,
+    },
+    /// The index of the variant an enum value is, as an `int`.
+    Tag(Box<Expr>),
+    /// Reads a field of an enum value that's known to be `variant`.
+    Payload {
+        base: Box<Expr>,
+        variant: usize,
+        index: usize,
+    },
+    Unary {
+        op: Op,
+        operand: Box<Expr>,
+    },
+    /// Never `&&` or `||`, which are lowered to an `if` so that the
+    /// right-hand side only runs when it's needed.
+    Binary {
+        op: Op,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+/// A step into a struct or an array, on the way to the part of a place
+/// being assigned. The MIR uses its own kind of index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection<I = Expr> {
+    Field(usize),
+    Index(I),
+}
+
+/// The functions built into the language for working with `Vec`s, slices
+/// and strings, and for reading and writing the console. They fail at
+/// runtime when given an index that's out of bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `Vec::new()`, an empty `Vec` of the type of the expression.
+    VecNew,
+    /// The number of elements in a `Vec` or a slice, or of bytes in a
+    /// string. The length of an array is known without running anything.
+    Len,
+    /// Adds a value to the end of a `Vec`.
+    Push,
+    /// Removes the last value of a `Vec` and returns it, which fails if
+    /// there isn't one.
+    Pop,
+    /// Replaces the element of a `Vec` at an index.
+    Set,
+    /// The elements of a `Vec` or a slice from a start index up to an end
+    /// index, or the bytes of a string, which have to start and end on
+    /// the boundaries of UTF-8 characters.
+    Slice,
+    /// Gives the memory of a `Vec` back, after which it can't be used.
+    Free,
+    /// An int, a float, a bool or a char written out as a string.
+    Str,
+    /// Parses a string as an int, which fails if it isn't one.
+    Int,
+    /// Parses a string as a float, which fails if it isn't one.
+    Float,
+    /// Writes a string to standard output.
+    Print,
+    /// Writes a string and a newline to standard output.
+    Println,
+    /// The next line of standard input without its line ending, or an empty
+    /// string at the end of the input.
+    ReadLine,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 13] = [
+        Builtin::VecNew,
+        Builtin::Len,
+        Builtin::Push,
+        Builtin::Pop,
+        Builtin::Set,
+        Builtin::Slice,
+        Builtin::Free,
+        Builtin::Str,
+        Builtin::Int,
+        Builtin::Float,
+        Builtin::Print,
+        Builtin::Println,
+        Builtin::ReadLine,
+    ];
+
+    /// The name it's called by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::VecNew => "Vec::new",
+            Builtin::Len => "len",
+            Builtin::Push => "push",
+            Builtin::Pop => "pop",
+            Builtin::Set => "set",
+            Builtin::Slice => "slice",
+            Builtin::Free => "free",
+            Builtin::Str => "str",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+            Builtin::Print => "print",
+            Builtin::Println => "println",
+            Builtin::ReadLine => "read_line",
+        }
+    }
+
+    /// How many arguments it takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::VecNew | Builtin::ReadLine => 0,
+            Builtin::Len
+            | Builtin::Pop
+            | Builtin::Free
+            | Builtin::Str
+            | Builtin::Int
+            | Builtin::Float
+            | Builtin::Print
+            | Builtin::Println => 1,
+            Builtin::Push => 2,
+            Builtin::Set | Builtin::Slice => 3,
+        }
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Something that can be assigned to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Place {
+    Local(LocalId),
+    Global(GlobalId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Integer(i32),
+    Float(f32),
+    Char(char),
+    Bool(bool),
+}
+
+impl Literal {
+    pub fn ty(&self) -> NodeType {
+        match self {
+            Literal::String(_) => NodeType::String,
+            Literal::Integer(_) => NodeType::Integer,
+            Literal::Float(_) => NodeType::Float,
+            Literal::Char(_) => NodeType::Char,
+            Literal::Bool(_) => NodeType::Bool,
+        }
+    }
+}
+
+impl From<Literal> for ExprKind {
+    fn from(literal: Literal) -> Self {
+        ExprKind::Literal(literal)
+    }
+}
+
+impl Function {
+    /// Every builtin the body calls, along with where the statement calling
+    /// it is, in the order the statements appear.
+    pub fn builtins(&self) -> Vec<(Builtin, Pos)> {
+        let mut found = Vec::new();
+
+        self.body.builtins(&mut found);
+        found
+    }
+}
+
+impl Block {
+    fn builtins(&self, found: &mut Vec<(Builtin, Pos)>) {
+        for stmt in &self.stmts {
+            let mut exprs: Vec<&Expr> = Vec::new();
+            let mut blocks: Vec<&Block> = Vec::new();
+
+            match &stmt.kind {
+                StmtKind::Let { value, .. } | StmtKind::Return(value) => exprs.extend(value),
+                StmtKind::Expr(expr) => exprs.push(expr),
+                StmtKind::If {
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    exprs.push(cond);
+                    blocks.push(then);
+                    blocks.extend(otherwise);
+                }
+                StmtKind::Match(arms) => {
+                    for arm in arms {
+                        exprs.extend(&arm.conds);
+                        blocks.push(&arm.body);
+                    }
+                }
+                StmtKind::Loop(body) | StmtKind::Block(body) => blocks.push(body),
+                StmtKind::Break => {}
+            }
+
+            while let Some(expr) = exprs.pop() {
+                match &expr.kind {
+                    ExprKind::Builtin { builtin, args } => {
+                        found.push((*builtin, stmt.at));
+                        exprs.extend(args);
+                    }
+                    ExprKind::Call { args, .. }
+                    | ExprKind::Struct(args)
+                    | ExprKind::Variant { fields: args, .. }
+                    | ExprKind::Array(args) => exprs.extend(args),
+                    ExprKind::Assign { path, value, .. } => {
+                        exprs.push(value);
+                        exprs.extend(path.iter().filter_map(|projection| match projection {
+                            Projection::Index(index) => Some(index),
+                            Projection::Field(_) => None,
+                        }));
+                    }
+                    ExprKind::Field { base, .. }
+                    | ExprKind::Tag(base)
+                    | ExprKind::Payload { base, .. }
+                    | ExprKind::Unary { operand: base, .. } => exprs.push(base),
+                    ExprKind::Index { base, index } => exprs.extend([&**base, &**index]),
+                    ExprKind::Binary { lhs, rhs, .. } => exprs.extend([&**lhs, &**rhs]),
+                    ExprKind::Literal(_) | ExprKind::Local(_) | ExprKind::Global(_) => {}
+                }
+            }
+
+            for block in blocks {
+                block.builtins(found);
+            }
+        }
+    }
+
+    /// The trailing expression, whose value is the value of the block.
+    pub fn value(&self) -> Option<&Expr> {
+        match self.stmts.last() {
+            Some(Stmt {
+                kind: StmtKind::Expr(expr),
+                ..
+            }) => Some(expr),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,997 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{self, Display},
+    io::{self, BufRead, Write},
+    mem,
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    hir::{
+        Block, Builtin, Expr, ExprKind, FnId, Function, GlobalId, Literal, Place, Program,
+        Projection, Stmt, StmtKind,
+    },
+    parser::{
+        ast::ast::{NodeType, Op, Pos},
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+    },
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// How many bytes of the host's stack code may take up unless it's told
+/// otherwise: half of what threads spawned by the standard library get.
+const STACK: usize = 1024 * 1024;
+
+/// A value produced by running Qlang code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i32),
+    Float(f32),
+    Char(char),
+    Bool(bool),
+    /// The value of every field of a struct, in declaration order.
+    Struct(Vec<Value>),
+    /// The index of an enum variant, and the value of each of its fields.
+    Variant(usize, Vec<Value>),
+    Array(Vec<Value>),
+    /// The elements of a `Vec`, which every copy of it shares.
+    Vec(Rc<RefCell<Vec<Value>>>),
+    /// A view of the elements of a `Vec` in a range.
+    Slice(Rc<RefCell<Vec<Value>>>, Range<usize>),
+    Void,
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Struct(fields) => {
+                let fields = fields.iter().map(Value::to_string).collect::<Vec<_>>();
+
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            Value::Variant(variant, fields) if fields.is_empty() => write!(f, "#{}", variant),
+            Value::Variant(variant, fields) => {
+                let fields = fields.iter().map(Value::to_string).collect::<Vec<_>>();
+
+                write!(f, "#{}({})", variant, fields.join(", "))
+            }
+            Value::Array(elements) => {
+                let elements = elements.iter().map(Value::to_string).collect::<Vec<_>>();
+
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Vec(elements) => {
+                let elements = elements.borrow();
+
+                Value::Array(elements.clone()).fmt(f)
+            }
+            Value::Slice(elements, range) => {
+                let elements = elements.borrow();
+                let elements = elements.get(range.clone()).unwrap_or_default();
+
+                Value::Array(elements.to_vec()).fmt(f)
+            }
+            Value::Void => f.write_str("()"),
+        }
+    }
+}
+
+/// A function of the host's that Qlang code can call like an `extern fn`.
+/// It's only ever given arguments of the types it was declared with, and
+/// has to return a value of the type it was declared to.
+pub struct HostFn(Box<dyn Fn(Vec<Value>) -> Value>);
+
+impl HostFn {
+    pub fn new(function: impl Fn(Vec<Value>) -> Value + 'static) -> Self {
+        Self(Box::new(function))
+    }
+
+    pub fn call(&self, args: Vec<Value>) -> Value {
+        (self.0)(args)
+    }
+}
+
+impl fmt::Debug for HostFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HostFn(..)")
+    }
+}
+
+/// Bounds on how much code may do while running, for code that isn't
+/// trusted. Every call from the host gets the whole of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How many statements may run, counting every iteration of a loop.
+    pub fuel: u64,
+    /// How many bytes of memory may be allocated, counting every copy made
+    /// of a string, an array, a struct or an enum value, including ones
+    /// that are no longer used.
+    pub memory: usize,
+    /// How many bytes of the host's stack may be taken up, which has to be
+    /// less than the stack of the thread calling in (2 MiB for threads
+    /// spawned by the standard library). Each call takes up a few
+    /// kilobytes, and more in a debug build.
+    pub stack: usize,
+}
+
+/// Runs type-checked code by walking its [`hir`], without needing LLVM.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    fns: HashMap<FnId, Function>,
+    /// The name of every `extern fn`, which can't be called from here.
+    externs: HashMap<FnId, String>,
+    /// The host functions `extern fn`s stand for, which can.
+    hosts: HashMap<FnId, HostFn>,
+    /// The current value of every global.
+    globals: RefCell<HashMap<GlobalId, Value>>,
+    limits: Option<Limits>,
+    /// How many bytes of the host's stack code without limits may take
+    /// up, [`STACK`] unless set.
+    stack_size: Option<usize>,
+    /// What's left of the fuel in the current call from the host.
+    fuel: Cell<u64>,
+    /// The memory used so far in the current call from the host.
+    memory: Cell<usize>,
+    /// Where the host's stack was at the start of the current call from
+    /// the host.
+    base: Cell<usize>,
+}
+
+/// How a statement finished running.
+enum Flow {
+    /// Carry on with the next statement. Holds the value of an expression
+    /// statement, or `Void` for anything else.
+    Next(Value),
+    Return(Value),
+    Break,
+}
+
+/// The variables of the function call currently running, indexed by
+/// [`LocalId`](hir::LocalId).
+struct Frame {
+    locals: Vec<Value>,
+    at: Pos,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop running code that goes over `limits`, with an
+    /// [`OutOfFuel`](ErrorCodes::OutOfFuel), an
+    /// [`OutOfMemory`](ErrorCodes::OutOfMemory) or an
+    /// [`OutOfStack`](ErrorCodes::OutOfStack) error.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Let code without limits take up `bytes` of the host's stack, for
+    /// hosts that run it on a thread with a bigger stack than usual. It
+    /// fails with an [`OutOfStack`](ErrorCodes::OutOfStack) error past that.
+    pub fn with_stack(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Add every global and function in `program`. If any of them is already
+    /// defined, none of them are kept.
+    pub fn define(&mut self, program: Program) -> Result<()> {
+        if let Some(global) = program
+            .globals
+            .iter()
+            .find(|global| self.globals.borrow().contains_key(&global.id))
+        {
+            return Err(self.error(
+                global.at,
+                format!("static `{}` is already defined", global.name),
+            ));
+        }
+
+        if let Some(func) = program
+            .functions
+            .iter()
+            .find(|func| self.fns.contains_key(&func.id))
+        {
+            return Err(self.error(
+                func.at,
+                format!("function `{}` is already defined", func.name),
+            ));
+        }
+
+        let globals = self.globals.get_mut();
+
+        for global in program.globals {
+            globals.insert(global.id, (&global.value).into());
+        }
+
+        for item in program.externs {
+            self.externs.insert(item.id, item.name);
+        }
+
+        for func in program.functions {
+            self.fns.insert(func.id, func);
+        }
+
+        Ok(())
+    }
+
+    /// Call `host` whenever the `extern fn` that [`TypeChecker::define_extern`]
+    /// returned `id` for is.
+    ///
+    /// [`TypeChecker::define_extern`]: crate::typeck::TypeChecker::define_extern
+    pub fn define_host(&mut self, id: FnId, host: HostFn) {
+        self.hosts.insert(id, host);
+    }
+
+    /// Run code outside of any function, as checked by
+    /// [`TypeChecker::check_body`](crate::typeck::TypeChecker::check_body).
+    pub fn evaluate(&self, func: &Function) -> Result<Value> {
+        self.refuel();
+        self.run(func, Vec::new(), func.at)
+    }
+
+    /// Call the function `name` with already evaluated arguments.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let func = self
+            .fns
+            .values()
+            .find(|func| func.name == name)
+            .ok_or_else(|| self.error((0, 0), format!("unknown function `{}`", name)))?;
+
+        if func.params != args.len() {
+            return Err(self.error(
+                (0, 0),
+                format!(
+                    "`{}` takes {} arguments but {} were given",
+                    name,
+                    func.params,
+                    args.len()
+                ),
+            ));
+        }
+
+        self.refuel();
+        self.run(func, args, (0, 0))
+    }
+
+    /// Give the code about to run the whole of the limits.
+    fn refuel(&self) {
+        self.base.set(stack_pointer());
+
+        if let Some(limits) = self.limits {
+            self.fuel.set(limits.fuel);
+            self.memory.set(0);
+        }
+    }
+
+    /// Use up a unit of fuel, failing if there's none left.
+    fn burn(&self, at: Pos) -> Result<()> {
+        let limit = match self.limits {
+            Some(limits) => limits.fuel,
+            None => return Ok(()),
+        };
+
+        match self.fuel.get().checked_sub(1) {
+            Some(fuel) => {
+                self.fuel.set(fuel);
+                Ok(())
+            }
+            None => Err(self.fail(
+                ErrorCodes::OutOfFuel,
+                at,
+                format!("ran out of fuel after running {} statements", limit),
+            )),
+        }
+    }
+
+    /// Account for `bytes` more memory being used, failing if that goes
+    /// over the limit.
+    fn allocate(&self, bytes: usize, at: Pos) -> Result<()> {
+        let limit = match self.limits {
+            Some(limits) => limits.memory,
+            None => return Ok(()),
+        };
+        let used = self.memory.get().saturating_add(bytes);
+
+        if used > limit {
+            return Err(self.fail(
+                ErrorCodes::OutOfMemory,
+                at,
+                format!("used more than the {} bytes of memory allowed", limit),
+            ));
+        }
+
+        self.memory.set(used);
+
+        Ok(())
+    }
+
+    /// Fail if running code has taken up more of the host's stack than it
+    /// may, before it overflows and takes the host down with it.
+    fn grow(&self, at: Pos) -> Result<()> {
+        let limit = match self.limits {
+            Some(limits) => limits.stack,
+            None => self.stack_size.unwrap_or(STACK),
+        };
+
+        if stack_pointer().abs_diff(self.base.get()) > limit {
+            return Err(self.fail(
+                ErrorCodes::OutOfStack,
+                at,
+                format!("used more than the {} bytes of stack allowed", limit),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn run(&self, func: &Function, mut args: Vec<Value>, at: Pos) -> Result<Value> {
+        self.grow(at)?;
+
+        // arguments are the first locals, the rest start out empty
+        args.resize(func.locals.len(), Value::Void);
+
+        let mut frame = Frame {
+            locals: args,
+            at: func.at,
+        };
+
+        let result = self.exec_block(&func.body, &mut frame);
+
+        let result = match result {
+            Ok(_) if func.ret == NodeType::Void => Ok(Value::Void),
+            Ok(Flow::Next(value) | Flow::Return(value)) => Ok(value),
+            Ok(Flow::Break) => Err(self.error(frame.at, "`break` outside of a loop".to_string())),
+            Err(e) => Err(e),
+        };
+
+        // positions within the body are in the code it was checked from
+        result.map_err(|mut e| {
+            if let Some(source) = &func.source {
+                e.in_source(source.file.as_deref(), &source.text);
+            }
+
+            e
+        })
+    }
+
+    // Statements
+
+    fn exec_block(&self, block: &Block, frame: &mut Frame) -> Result<Flow> {
+        // blocks nest without calls too
+        self.grow(frame.at)?;
+
+        let mut last = Flow::Next(Value::Void);
+
+        for stmt in &block.stmts {
+            last = match self.exec(stmt, frame)? {
+                Flow::Next(value) => Flow::Next(value),
+                flow => return Ok(flow),
+            };
+        }
+
+        Ok(last)
+    }
+
+    fn exec(&self, stmt: &Stmt, frame: &mut Frame) -> Result<Flow> {
+        frame.at = stmt.at;
+        self.burn(frame.at)?;
+
+        let flow = match &stmt.kind {
+            StmtKind::Let { local, value } => {
+                if let Some(value) = value {
+                    frame.locals[*local] = self.eval(value, frame)?;
+                }
+
+                Flow::Next(Value::Void)
+            }
+            StmtKind::Expr(expr) => Flow::Next(self.eval(expr, frame)?),
+            StmtKind::Return(value) => Flow::Return(match value {
+                Some(value) => self.eval(value, frame)?,
+                None => Value::Void,
+            }),
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let flow = if self.condition(cond, frame)? {
+                    self.exec_block(then, frame)?
+                } else if let Some(otherwise) = otherwise {
+                    self.exec_block(otherwise, frame)?
+                } else {
+                    Flow::Next(Value::Void)
+                };
+
+                match flow {
+                    Flow::Next(_) => Flow::Next(Value::Void),
+                    flow => flow,
+                }
+            }
+            StmtKind::Match(arms) => {
+                let mut flow = None;
+
+                for arm in arms {
+                    if self.matches(&arm.conds, frame)? {
+                        flow = Some(self.exec_block(&arm.body, frame)?);
+                        break;
+                    }
+                }
+
+                match flow {
+                    Some(Flow::Next(_)) => Flow::Next(Value::Void),
+                    Some(flow) => flow,
+                    None => {
+                        return Err(self.error(frame.at, "no arm of the `match` matched".into()))
+                    }
+                }
+            }
+            StmtKind::Loop(body) => loop {
+                // even a loop with nothing in it uses up fuel
+                self.burn(frame.at)?;
+
+                match self.exec_block(body, frame)? {
+                    Flow::Next(_) => {}
+                    Flow::Break => break Flow::Next(Value::Void),
+                    flow => break flow,
+                }
+            },
+            StmtKind::Break => Flow::Break,
+            StmtKind::Block(block) => self.exec_block(block, frame)?,
+        };
+
+        Ok(flow)
+    }
+
+    fn condition(&self, cond: &Expr, frame: &mut Frame) -> Result<bool> {
+        match self.eval(cond, frame)? {
+            Value::Bool(b) => Ok(b),
+            value => Err(self.error(frame.at, format!("expected a bool, found {}", value))),
+        }
+    }
+
+    /// Whether every one of an arm's conditions holds. Later ones can
+    /// depend on earlier ones, so they're only checked while they do.
+    fn matches(&self, conds: &[Expr], frame: &mut Frame) -> Result<bool> {
+        for cond in conds {
+            if !self.condition(cond, frame)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Expressions
+
+    fn eval(&self, expr: &Expr, frame: &mut Frame) -> Result<Value> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.copied(literal.into(), frame.at),
+            ExprKind::Local(local) => self.copied(frame.locals[*local].clone(), frame.at),
+            ExprKind::Global(global) => {
+                let value = self
+                    .globals
+                    .borrow()
+                    .get(global)
+                    .cloned()
+                    .ok_or_else(|| self.error(frame.at, "use of an unknown static".into()))?;
+
+                self.copied(value, frame.at)
+            }
+            ExprKind::Call { func, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, frame))
+                    .collect::<Result<Vec<_>>>()?;
+
+                if let Some(host) = self.hosts.get(func) {
+                    return self.copied(host.call(args), frame.at);
+                }
+
+                let func = match (self.fns.get(func), self.externs.get(func)) {
+                    (Some(func), _) => func,
+                    (None, Some(name)) => {
+                        return Err(self.error(
+                            frame.at,
+                            format!(
+                                "extern function `{}` can only be called from compiled code",
+                                name
+                            ),
+                        ))
+                    }
+                    (None, None) => {
+                        return Err(self.error(frame.at, "call to an unknown function".into()))
+                    }
+                };
+
+                self.run(func, args, frame.at)
+            }
+            ExprKind::Assign { place, path, value } => {
+                let value = self.eval(value, frame)?;
+                let path = path
+                    .iter()
+                    .map(|projection| match projection {
+                        Projection::Field(field) => Ok(Projection::Field(*field)),
+                        Projection::Index(index) => match self.eval(index, frame)? {
+                            Value::Integer(i) => Ok(Projection::Index(i)),
+                            value => Err(self.error(
+                                frame.at,
+                                format!("expected an int index, found {}", value),
+                            )),
+                        },
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let at = frame.at;
+                let mut globals = self.globals.borrow_mut();
+                let whole = match place {
+                    Place::Local(local) => &mut frame.locals[*local],
+                    Place::Global(global) => globals.entry(*global).or_insert(Value::Void),
+                };
+
+                *project(whole, &path).map_err(|e| self.error(at, e))? = value.clone();
+
+                self.allocate(size(&value), at)?;
+
+                Ok(value)
+            }
+            ExprKind::Struct(fields) => {
+                let fields = self.eval_all(fields, frame)?;
+
+                Ok(Value::Struct(fields))
+            }
+            ExprKind::Field { base, index } => match self.eval(base, frame)? {
+                Value::Struct(mut fields) if *index < fields.len() => {
+                    Ok(fields.swap_remove(*index))
+                }
+                value => Err(self.error(frame.at, format!("{} has no field {}", value, index))),
+            },
+            ExprKind::Array(elements) => {
+                let elements = self.eval_all(elements, frame)?;
+
+                Ok(Value::Array(elements))
+            }
+            ExprKind::Index { base, index } => {
+                let base = self.eval(base, frame)?;
+                let index = self.eval(index, frame)?;
+                // the elements of an array are moved out of the copy read,
+                // but those of a `Vec` are shared, so they're copied
+                let shared = matches!(base, Value::Vec(_) | Value::Slice(..));
+                let value = element(base, index).map_err(|e| self.error(frame.at, e))?;
+
+                if shared {
+                    self.copied(value, frame.at)
+                } else {
+                    Ok(value)
+                }
+            }
+            ExprKind::Builtin {
+                builtin: called,
+                args,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, frame))
+                    .collect::<Result<_>>()?;
+
+                let value = builtin(*called, args).map_err(|e| self.error(frame.at, e))?;
+                let bytes = match called {
+                    // the value pushed was counted when it was copied
+                    Builtin::Push => mem::size_of::<Value>(),
+                    // and the one popped when it was pushed
+                    Builtin::Pop => 0,
+                    _ => size(&value),
+                };
+
+                self.allocate(bytes, frame.at)?;
+
+                Ok(value)
+            }
+            ExprKind::Variant { variant, fields } => {
+                let fields = self.eval_all(fields, frame)?;
+
+                Ok(Value::Variant(*variant, fields))
+            }
+            ExprKind::Tag(base) => match self.eval(base, frame)? {
+                Value::Variant(variant, _) => Ok(Value::Integer(variant as i32)),
+                value => Err(self.error(frame.at, format!("{} isn't an enum value", value))),
+            },
+            ExprKind::Payload {
+                base,
+                variant,
+                index,
+            } => match self.eval(base, frame)? {
+                Value::Variant(tag, mut fields) if tag == *variant && *index < fields.len() => {
+                    Ok(fields.swap_remove(*index))
+                }
+                value => Err(self.error(
+                    frame.at,
+                    format!("{} has no field {} of variant #{}", value, index, variant),
+                )),
+            },
+            ExprKind::Unary { op, operand } => {
+                let value = self.eval(operand, frame)?;
+
+                unary(*op, value).map_err(|e| self.error(frame.at, e))
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                // both sides are always evaluated, like the LLVM backend does
+                let lhs = self.eval(lhs, frame)?;
+                let rhs = self.eval(rhs, frame)?;
+
+                let value = binary(*op, lhs, rhs).map_err(|e| self.error(frame.at, e))?;
+
+                self.copied(value, frame.at)
+            }
+        }
+    }
+
+    /// Evaluate the fields of a struct or an enum value or the elements of
+    /// an array, accounting for the memory they're kept in.
+    fn eval_all(&self, exprs: &[Expr], frame: &mut Frame) -> Result<Vec<Value>> {
+        let values = exprs
+            .iter()
+            .map(|expr| self.eval(expr, frame))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.allocate(mem::size_of_val(&values[..]), frame.at)?;
+
+        Ok(values)
+    }
+
+    /// Account for the memory a new copy of `value` takes up.
+    fn copied(&self, value: Value, at: Pos) -> Result<Value> {
+        self.allocate(size(&value), at)?;
+
+        Ok(value)
+    }
+
+    fn error(&self, at: Pos, msg: String) -> CompileError {
+        self.fail(ErrorCodes::RuntimeError, at, msg)
+    }
+
+    fn fail(&self, code: ErrorCodes, at: Pos, msg: String) -> CompileError {
+        CompileErrorBuilder::new()
+            .from(code)
+            .at((at.0, at.1 + 1))
+            .note(&msg)
+            .build()
+    }
+}
+
+/// Roughly where the top of the host's stack is, as the address of a
+/// variable in the caller's frame.
+#[inline(always)]
+fn stack_pointer() -> usize {
+    let marker = 0u8;
+
+    &marker as *const u8 as usize
+}
+
+/// The bytes of memory a copy of `value` takes up besides its own, which
+/// only strings, arrays, structs and enum values have. The elements of a
+/// `Vec` are shared by every copy of it, and counted as they're pushed.
+fn size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::Struct(values) | Value::Variant(_, values) | Value::Array(values) => {
+            mem::size_of_val(&values[..]) + values.iter().map(size).sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::String(s) => Value::String(s.clone()),
+            Literal::Integer(i) => Value::Integer(*i),
+            Literal::Float(f) => Value::Float(*f),
+            Literal::Char(c) => Value::Char(*c),
+            Literal::Bool(b) => Value::Bool(*b),
+        }
+    }
+}
+
+/// The part of a struct or an array at the end of a path, which fails if
+/// an index is out of bounds.
+pub fn project<'v>(
+    value: &'v mut Value,
+    path: &[Projection<i32>],
+) -> std::result::Result<&'v mut Value, String> {
+    path.iter()
+        .try_fold(value, |value, projection| match (value, projection) {
+            (Value::Struct(fields), Projection::Field(field)) => fields
+                .get_mut(*field)
+                .ok_or_else(|| format!("assignment to field {} that doesn't exist", field)),
+            (Value::Array(elements), Projection::Index(index)) => {
+                let index = bounds(*index, elements.len())?;
+
+                Ok(&mut elements[index])
+            }
+            _ => Err("assignment to a part of a value that isn't a struct or an array".into()),
+        })
+}
+
+/// Read an element of an array, a `Vec` or a slice, which fails if the
+/// index is out of bounds.
+pub fn element(array: Value, index: Value) -> std::result::Result<Value, String> {
+    match (array, index) {
+        (Value::Array(mut elements), Value::Integer(index)) => {
+            let index = bounds(index, elements.len())?;
+
+            Ok(elements.swap_remove(index))
+        }
+        (Value::Vec(elements), Value::Integer(index)) => {
+            let elements = elements.borrow();
+            let index = bounds(index, elements.len())?;
+
+            Ok(elements[index].clone())
+        }
+        (Value::Slice(elements, range), Value::Integer(index)) => {
+            let index = bounds(index, range.len())?;
+
+            // the `Vec` may have shrunk since the slice was taken
+            elements
+                .borrow()
+                .get(range.start + index)
+                .cloned()
+                .ok_or_else(|| "the `Vec` this slice is of has shrunk".to_string())
+        }
+        (array, index) => Err(format!("cannot index into {} with {}", array, index)),
+    }
+}
+
+/// Call a builtin with already evaluated arguments.
+pub fn builtin(builtin: Builtin, args: Vec<Value>) -> std::result::Result<Value, String> {
+    let mut args = args.into_iter();
+    let mut arg = || args.next().unwrap_or(Value::Void);
+
+    let value = match (builtin, arg()) {
+        (Builtin::VecNew, _) => Value::Vec(Rc::default()),
+        (Builtin::Len, Value::Vec(elements)) => Value::Integer(elements.borrow().len() as i32),
+        (Builtin::Len, Value::Slice(_, range)) => Value::Integer(range.len() as i32),
+        (Builtin::Len, Value::String(s)) => Value::Integer(s.len() as i32),
+        (Builtin::Push, Value::Vec(elements)) => {
+            elements.borrow_mut().push(arg());
+            Value::Void
+        }
+        (Builtin::Pop, Value::Vec(elements)) => elements
+            .borrow_mut()
+            .pop()
+            .ok_or_else(|| "cannot pop from an empty `Vec`".to_string())?,
+        (Builtin::Set, Value::Vec(elements)) => {
+            let mut elements = elements.borrow_mut();
+            let index = match arg() {
+                Value::Integer(index) => bounds(index, elements.len())?,
+                index => return Err(format!("expected an int index, found {}", index)),
+            };
+
+            elements[index] = arg();
+            Value::Void
+        }
+        (Builtin::Slice, Value::Vec(elements)) => {
+            let len = elements.borrow().len();
+            let range = range(arg(), arg(), len)?;
+
+            Value::Slice(elements, range)
+        }
+        (Builtin::Slice, Value::Slice(elements, outer)) => {
+            let range = range(arg(), arg(), outer.len())?;
+
+            Value::Slice(elements, outer.start + range.start..outer.start + range.end)
+        }
+        (Builtin::Slice, Value::String(s)) => {
+            let range = range(arg(), arg(), s.len())?;
+
+            match s.get(range.clone()) {
+                Some(s) => Value::String(s.to_string()),
+                None => {
+                    return Err(format!(
+                        "range {}..{} of a string doesn't start and end on character boundaries",
+                        range.start, range.end
+                    ))
+                }
+            }
+        }
+        (Builtin::Free, Value::Vec(elements)) => {
+            // any other copies see an empty `Vec` from now on
+            *elements.borrow_mut() = Vec::new();
+            Value::Void
+        }
+        (Builtin::Str, Value::Integer(i)) => Value::String(i.to_string()),
+        (Builtin::Str, Value::Float(f)) => Value::String(float_to_str(f)),
+        (Builtin::Str, Value::Bool(b)) => Value::String(b.to_string()),
+        (Builtin::Str, Value::Char(c)) => Value::String(c.to_string()),
+        (Builtin::Int, Value::String(s)) => match s.parse() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => return Err(format!("cannot parse {:?} as an int", s)),
+        },
+        (Builtin::Float, Value::String(s)) => match s.parse() {
+            Ok(f) => Value::Float(f),
+            Err(_) => return Err(format!("cannot parse {:?} as a float", s)),
+        },
+        (Builtin::Print, Value::String(s)) => {
+            let mut stdout = io::stdout();
+
+            // without a newline the output would otherwise wait in the buffer
+            write!(stdout, "{}", s).and_then(|_| stdout.flush()).ok();
+            Value::Void
+        }
+        (Builtin::Println, Value::String(s)) => {
+            println!("{}", s);
+            Value::Void
+        }
+        (Builtin::ReadLine, _) => Value::String(read_line()),
+        (builtin, value) => return Err(format!("cannot call `{}` with {}", builtin, value)),
+    };
+
+    Ok(value)
+}
+
+/// The next line of standard input without its line ending, or an empty
+/// string at the end of the input or if it can't be read. Anything that
+/// isn't UTF-8 is replaced.
+pub fn read_line() -> String {
+    let mut line = Vec::new();
+
+    io::stdin().lock().read_until(b'\n', &mut line).ok();
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+
+    String::from_utf8_lossy(&line).into_owned()
+}
+
+/// Write out a float the way C's `printf("%g")` does, as the LLVM backend
+/// does: with 6 significant digits and no trailing zeros, switching to an
+/// exponent for very large and very small numbers.
+pub fn float_to_str(f: f32) -> String {
+    const PRECISION: i32 = 6;
+
+    // `printf` is given a double, so round from that
+    let f = f as f64;
+
+    if f.is_nan() {
+        return "nan".to_string();
+    } else if f.is_infinite() {
+        return if f < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+
+    let scientific = format!("{:.*e}", PRECISION as usize - 1, f);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+
+    let trim = |s: &str| {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            s.to_string()
+        }
+    };
+
+    if (-4..PRECISION).contains(&exponent) {
+        let decimals = (PRECISION - 1 - exponent) as usize;
+
+        trim(&format!("{:.*}", decimals, f))
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+
+        format!("{}e{}{:02}", trim(mantissa), sign, exponent.abs())
+    }
+}
+
+/// Check that `start..end` is a range of indices of an array of length
+/// `len`.
+fn range(start: Value, end: Value, len: usize) -> std::result::Result<Range<usize>, String> {
+    match (start, end) {
+        (Value::Integer(start), Value::Integer(end)) => {
+            match (usize::try_from(start), usize::try_from(end)) {
+                (Ok(s), Ok(e)) if s <= e && e <= len => Ok(s..e),
+                _ => Err(format!(
+                    "range {}..{} is out of bounds for an array of length {}",
+                    start, end, len
+                )),
+            }
+        }
+        (start, end) => Err(format!("expected an int range, found {}..{}", start, end)),
+    }
+}
+
+/// Check that `index` is in bounds for an array of length `len`.
+fn bounds(index: i32, len: usize) -> std::result::Result<usize, String> {
+    match usize::try_from(index) {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(format!(
+            "index {} is out of bounds for an array of length {}",
+            index, len
+        )),
+    }
+}
+
+/// Apply a unary operator, with the same semantics as the LLVM backend.
+pub fn unary(op: Op, value: Value) -> std::result::Result<Value, String> {
+    match (op, value) {
+        (Op::Sub, Value::Integer(i)) => Ok(Value::Integer(i.wrapping_neg())),
+        (Op::Sub, Value::Float(f)) => Ok(Value::Float(-f)),
+        (Op::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (op, value) => Err(format!("cannot apply `{:?}` to {}", op, value)),
+    }
+}
+
+/// Apply a binary operator, with the same semantics as the LLVM backend:
+/// integers wrap around on overflow, but dividing by zero is an error.
+pub fn binary(op: Op, lhs: Value, rhs: Value) -> std::result::Result<Value, String> {
+    use Value::*;
+
+    let unsupported = || format!("cannot apply `{:?}` to these values", op);
+
+    let value = match (lhs, rhs) {
+        (Integer(l), Integer(r)) => match op {
+            Op::Add => Integer(l.wrapping_add(r)),
+            Op::Sub => Integer(l.wrapping_sub(r)),
+            Op::Mul => Integer(l.wrapping_mul(r)),
+            Op::Div | Op::Mod if r == 0 => return Err("attempt to divide by zero".into()),
+            Op::Div => Integer(l.wrapping_div(r)),
+            Op::Mod => Integer(l.wrapping_rem(r)),
+            _ => compare(op, &l, &r).ok_or_else(unsupported)?,
+        },
+        (Float(l), Float(r)) => match op {
+            Op::Add => Float(l + r),
+            Op::Sub => Float(l - r),
+            Op::Mul => Float(l * r),
+            Op::Div => Float(l / r),
+            Op::Mod => Float(l % r),
+            _ => compare(op, &l, &r).ok_or_else(unsupported)?,
+        },
+        (Char(l), Char(r)) => compare(op, &l, &r).ok_or_else(unsupported)?,
+        (Bool(l), Bool(r)) => match op {
+            Op::And => Bool(l && r),
+            Op::Or => Bool(l || r),
+            Op::Eq => Bool(l == r),
+            Op::Neq => Bool(l != r),
+            _ => return Err(unsupported()),
+        },
+        // strings compare byte by byte, which for UTF-8 is the same as by
+        // characters
+        (String(l), String(r)) => match op {
+            Op::Add => String(l + &r),
+            _ => compare(op, &l, &r).ok_or_else(unsupported)?,
+        },
+        _ => return Err(format!("mismatched types for `{:?}`", op)),
+    };
+
+    Ok(value)
+}
+
+/// Apply a comparison operator, or `None` if `op` isn't one.
+fn compare<T: PartialOrd + ?Sized>(op: Op, l: &T, r: &T) -> Option<Value> {
+    let result = match op {
+        Op::Eq => l == r,
+        Op::Neq => l != r,
+        Op::Lt => l < r,
+        Op::Gt => l > r,
+        Op::Le => l <= r,
+        Op::Ge => l >= r,
+        _ => return None,
+    };
+
+    Some(Value::Bool(result))
+}
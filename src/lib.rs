@@ -5,11 +5,22 @@ use std::{
     time::Instant,
 };
 
-use crate::parser::TopLevel;
+use crate::parser::{error::CompileError, TopLevel};
 
+pub mod bytecode;
+pub mod engine;
+pub mod hir;
+pub mod interp;
+#[cfg(feature = "llvm")]
 pub mod llvm;
+pub mod loader;
+pub mod mir;
 pub mod parser;
+pub mod typeck;
+pub use engine::{Engine, Sandbox};
+#[cfg(feature = "llvm")]
 pub use inkwell;
+pub use interp::Limits;
 
 #[macro_export]
 macro_rules! debug {
@@ -34,7 +45,7 @@ pub fn stdin() -> Result<TopLevel, Box<dyn Error>> {
 
     buf.read_to_string(&mut input)?;
 
-    let root = parse_file(input);
+    let root = parse_file(input)?;
 
     Ok(root)
 }
@@ -43,18 +54,18 @@ pub fn stdin() -> Result<TopLevel, Box<dyn Error>> {
 pub fn file(path: &str) -> Result<TopLevel, Box<dyn Error>> {
     let mut content = String::new();
 
-    File::open(&path)?.read_to_string(&mut content)?;
+    File::open(path)?.read_to_string(&mut content)?;
 
-    let root = parse_file(content);
+    let root = parse_file(content)?;
 
     Ok(root)
 }
 
-fn parse_file(raw: String) -> TopLevel {
+pub(crate) fn parse_file(raw: String) -> Result<TopLevel, CompileError> {
     let now = Instant::now();
-    let parsed = raw.parse::<TopLevel>().unwrap();
+    let parsed = raw.parse::<TopLevel>()?;
     debug!(&parsed);
     debug!("done in {}ms", now.elapsed().as_millis());
 
-    parsed
+    Ok(parsed)
 }
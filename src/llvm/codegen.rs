@@ -1,46 +1,163 @@
-use std::process::exit;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    execution_engine::ExecutionEngine,
-    module::Module,
-    types::{BasicMetadataTypeEnum, FunctionType},
-    values::FunctionValue,
-    AddressSpace, OptimizationLevel,
+    debug_info::DISubprogram,
+    module::{Linkage, Module},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{
+        BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, GlobalValue, IntValue,
+        PointerValue, StructValue,
+    },
+    AddressSpace, FloatPredicate, IntPredicate,
 };
 
-use crate::parser::{
-    ast::ast::{Function, Node, NodeType},
-    TopLevel,
+use crate::{
+    hir::{
+        Block, Builtin, Enum, Expr, ExprKind, Extern, FnId, Function, Global, GlobalId, Literal,
+        Place, Program, Projection, Source, Stmt, StmtKind, Struct,
+    },
+    interp::{HostFn, Value},
+    llvm::{
+        debug::DebugInfo,
+        jit::{Jit, Memory, Trap},
+        runtime::{self, Runtime},
+        target::{self, Target},
+    },
+    parser::{
+        ast::ast::{NodeType, Op, Pos},
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+        TopLevel,
+    },
+    typeck::{self, TypeChecker},
 };
 
+type Result<T> = std::result::Result<T, CompileError>;
+
 #[derive(Debug)]
 pub struct Codegen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    execution_engine: ExecutionEngine<'ctx>,
+    target: Target,
+    debug: Option<DebugInfo<'ctx>>,
+    /// Whether indexing into an array checks the index first.
+    bounds_checks: bool,
+    /// Whether `main` is called through a C `main`, as an executable's
+    /// entry point.
+    entry_point: bool,
+    /// The LLVM function of every function compiled so far.
+    fns: HashMap<FnId, FunctionValue<'ctx>>,
+    /// The LLVM declaration of every `extern fn` compiled so far.
+    externs: HashMap<FnId, FunctionValue<'ctx>>,
+    /// The LLVM global of every `static` compiled so far.
+    globals: HashMap<GlobalId, GlobalValue<'ctx>>,
+    /// The LLVM type of every `struct` compiled so far, by name.
+    structs: HashMap<String, StructType<'ctx>>,
+    /// The LLVM types of every `enum` compiled so far, by name.
+    enums: HashMap<String, TaggedUnion<'ctx>>,
+    /// Where code run with the JIT keeps what outlives a single run, `None`
+    /// when emitting an object file.
+    memory: Option<Rc<RefCell<Memory>>>,
+    /// The code every function compiled so far was checked from, which
+    /// the checks failing in it are shown against.
+    sources: HashMap<FnId, Rc<Source>>,
+    /// The ID of every function compiled so far, by name, for the host to
+    /// call it by.
+    names: HashMap<String, FnId>,
+    /// What the host calls every function it has called so far through.
+    trampolines: HashMap<FnId, FunctionValue<'ctx>>,
+    /// The module as the JIT compiled it for the last call from the host,
+    /// `None` if it has changed since.
+    jit: Option<Jit<'ctx>>,
 }
 
-impl Clone for Codegen<'_> {
+impl<'ctx> Clone for Codegen<'ctx> {
     fn clone(&self) -> Self {
+        let module = self.module.clone();
+        let functions = |fns: &HashMap<FnId, FunctionValue<'ctx>>| -> HashMap<_, _> {
+            fns.iter()
+                .filter_map(|(id, function)| {
+                    let name = function.get_name().to_str().ok()?;
+
+                    Some((*id, module.get_function(name)?))
+                })
+                .collect()
+        };
+        let fns = functions(&self.fns);
+        let externs = functions(&self.externs);
+        let trampolines = functions(&self.trampolines);
+        let globals = self
+            .globals
+            .iter()
+            .filter_map(|(id, global)| {
+                let name = global.get_name().to_str().ok()?;
+
+                Some((*id, module.get_global(name)?))
+            })
+            .collect();
+
         Self {
             context: self.context,
-            module: self.module.clone(),
+            module,
             builder: self.context.create_builder(),
-            execution_engine: self.execution_engine.clone(),
+            target: self.target,
+            // debug info builders are tied to the module they were created for
+            debug: None,
+            bounds_checks: self.bounds_checks,
+            entry_point: self.entry_point,
+            fns,
+            externs,
+            globals,
+            // types belong to the context rather than the module
+            structs: self.structs.clone(),
+            enums: self.enums.clone(),
+            // both copies see the same values of the mutable globals
+            memory: self.memory.clone(),
+            sources: self.sources.clone(),
+            names: self.names.clone(),
+            trampolines,
+            jit: None,
         }
     }
 }
 
+/// An enum value is its variant's index followed by enough space for the
+/// fields of its largest variant, which are read and written through a
+/// pointer to the variant's own struct.
+#[derive(Debug, Clone)]
+struct TaggedUnion<'ctx> {
+    ty: StructType<'ctx>,
+    /// The fields of every variant, laid out as a struct.
+    payloads: Vec<StructType<'ctx>>,
+}
+
+/// State for the function body currently being lowered.
+struct Scope<'a, 'ctx> {
+    func: &'a Function,
+    /// The stack slot of every local, indexed by [`LocalId`](crate::hir::LocalId).
+    locals: Vec<PointerValue<'ctx>>,
+    /// Where a `break` jumps to in every loop being lowered, innermost last.
+    loops: Vec<BasicBlock<'ctx>>,
+    subprogram: Option<DISubprogram<'ctx>>,
+    at: Pos,
+}
+
 macro_rules! coerce_node_type {
     ($ctx:tt, $ty:tt) => {
         match $ty {
             NodeType::Bool => $ctx.bool_type().into(),
             NodeType::Integer => $ctx.i32_type().into(),
             NodeType::Float => $ctx.f32_type().into(),
-            NodeType::String => $ctx.i8_type().ptr_type(AddressSpace::Generic).into(),
+            NodeType::Char => $ctx.i32_type().into(),
             _ => panic!("Unsupported type"),
         }
     };
@@ -48,74 +165,2084 @@ macro_rules! coerce_node_type {
 
 impl<'ctx> Codegen<'ctx> {
     pub fn new(context: &'ctx Context, module: Module<'ctx>) -> Self {
-        let execution_engine = module
-            .create_jit_execution_engine(OptimizationLevel::None)
-            .unwrap();
         Self {
             context,
             module,
             builder: context.create_builder(),
-            execution_engine,
+            target: Target::Native,
+            debug: None,
+            bounds_checks: true,
+            entry_point: false,
+            fns: HashMap::new(),
+            externs: HashMap::new(),
+            globals: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            memory: Some(Rc::default()),
+            sources: HashMap::new(),
+            names: HashMap::new(),
+            trampolines: HashMap::new(),
+            jit: None,
         }
     }
 
-    pub fn interpret(&self, code: &str) {
-        let ast: TopLevel = code.parse().unwrap();
+    /// Create a code generator that emits an object file for `target`
+    /// instead of JIT-compiling into the current process.
+    pub fn with_target(context: &'ctx Context, module: Module<'ctx>, target: Target) -> Self {
+        module.set_triple(&target.triple());
 
-        for func in ast.fns {
-            self.create_function(func);
+        Self {
+            context,
+            module,
+            builder: context.create_builder(),
+            target,
+            debug: None,
+            bounds_checks: true,
+            entry_point: false,
+            fns: HashMap::new(),
+            externs: HashMap::new(),
+            globals: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            memory: None,
+            sources: HashMap::new(),
+            names: HashMap::new(),
+            trampolines: HashMap::new(),
+            jit: None,
         }
     }
 
-    fn create_function(&self, func: Node) {
-        if let Node::Fn(func) = func {
-            let ty = self.type_for_function(&func);
+    /// Emit DWARF debug information for code compiled from `file`.
+    pub fn with_debug_info(mut self, file: &Path) -> Self {
+        self.debug = Some(DebugInfo::new(
+            self.context,
+            &self.module,
+            file,
+            self.target,
+        ));
+        self
+    }
+
+    /// Call `main` through a C `main` that exits with what it returns, or 0
+    /// if it doesn't return anything, for linking into an executable.
+    pub fn with_entry_point(mut self) -> Self {
+        self.entry_point = true;
+        self
+    }
+
+    /// Index into arrays without checking that the index is in bounds,
+    /// which makes indexing past the end undefined behaviour.
+    pub fn without_bounds_checks(mut self) -> Self {
+        self.bounds_checks = false;
+        self
+    }
+
+    pub fn interpret(&mut self, code: &str) -> Result<()> {
+        let ast: TopLevel = code.parse()?;
+        let program = TypeChecker::new().check(&ast)?;
+
+        self.compile(program)
+    }
+
+    /// Add every type, global and function in `program` to the module. If
+    /// any of them fails to compile, none of them are kept.
+    pub fn compile(&mut self, program: Program) -> Result<()> {
+        self.jit = None;
+
+        let mut globals = Vec::new();
+        let mut declared = Vec::new();
+        let mut created = Vec::new();
+        let mut define = |codegen: &mut Self| -> Result<()> {
+            codegen.create_types(&program.structs, &program.enums)?;
+
+            for global in &program.globals {
+                let value = codegen.create_global(global)?;
+
+                codegen.globals.insert(global.id, value);
+                globals.push(value);
+            }
+
+            for item in &program.externs {
+                let existing = codegen.module.get_function(&item.name);
+                let function = codegen.create_extern(item)?;
 
-            let created = self.module.add_function(&func.name, ty, None);
-            self.create_entry(&func, created);
+                codegen.externs.insert(item.id, function);
+
+                if existing.is_none() {
+                    declared.push(function);
+                }
+            }
+
+            // declare everything up front so functions can call ones defined later
+            for func in &program.functions {
+                let function = codegen.create_function(func)?;
+
+                codegen.fns.insert(func.id, function);
+                created.push(function);
+            }
+
+            for (func, function) in program.functions.iter().zip(created.clone()) {
+                codegen.create_entry(func, function)?;
+
+                if let Some(symbol) = &func.export {
+                    created.push(codegen.create_export(func, function, symbol, symbol)?);
+                } else if codegen.target == Target::Wasm32
+                    && codegen.is_external(func)
+                    && takes_c_types(func)
+                {
+                    // the wrapper can't take the name of the function it calls
+                    let symbol = format!("{}.wasm", func.name);
+
+                    created.push(codegen.create_export(func, function, &symbol, &func.name)?);
+                }
+
+                if codegen.entry_point && func.name == "main" {
+                    created.push(codegen.create_c_main(func, function)?);
+                }
+            }
+
+            Ok(())
+        };
+
+        let result = define(self);
+
+        if result.is_err() {
+            for def in &program.structs {
+                self.structs.remove(&def.name);
+            }
+
+            for def in &program.enums {
+                self.enums.remove(&def.name);
+            }
+
+            for func in &program.functions {
+                self.fns.remove(&func.id);
+            }
+
+            for item in &program.externs {
+                self.externs.remove(&item.id);
+            }
+
+            for function in created.into_iter().chain(declared) {
+                unsafe { function.delete() };
+            }
+
+            for global in &program.globals {
+                self.globals.remove(&global.id);
+
+                if let Some(memory) = &self.memory {
+                    memory.borrow_mut().remove(&global.name);
+                }
+            }
+
+            for global in globals {
+                unsafe { global.delete() };
+            }
         } else {
-            panic!("Expected function");
+            for func in &program.functions {
+                self.names.insert(func.name.clone(), func.id);
+
+                if let Some(source) = &func.source {
+                    self.sources.insert(func.id, source.clone());
+                }
+            }
+
+            for item in &program.externs {
+                self.names.insert(item.name.clone(), item.id);
+            }
         }
+
+        result
     }
 
-    fn type_for_function(&self, func: &Function) -> FunctionType<'ctx> {
-        let mut args: Vec<BasicMetadataTypeEnum> = Vec::new();
-        let ctx = &self.context;
+    /// Compile calls to the `extern fn` `item`, as returned by
+    /// [`TypeChecker::define_extern`], into calls to `host`. Only code run
+    /// with the JIT can call host functions.
+    pub fn define_host(&mut self, item: &Extern, host: HostFn) -> Result<()> {
+        let memory = match &self.memory {
+            Some(memory) => memory.clone(),
+            None => {
+                return Err(self.error(
+                    item.at,
+                    format!(
+                        "host function `{}` can only be called with the JIT",
+                        item.name
+                    ),
+                ))
+            }
+        };
+
+        self.jit = None;
+
+        let params = item
+            .params
+            .iter()
+            .map(|ty| self.basic_type(ty).into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let ty = match &item.ret {
+            NodeType::Void => self.context.void_type().fn_type(&params, false),
+            ty => self.basic_type(ty).fn_type(&params, false),
+        };
+        let function =
+            self.module
+                .add_function(&format!("{}.host", item.name), ty, Some(Linkage::Internal));
+
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        // every argument and the return value get a slot of the host's, see
+        // `jit::Memory::define_host`
+        let i32_type = self.context.i32_type();
+        let slot = self.context.i64_type().array_type(2);
+        let args = self
+            .builder
+            .build_alloca(slot.array_type(params.len() as u32), "args");
+        let ret = self.builder.build_alloca(slot, "ret");
+
+        for (i, param) in function.get_param_iter().enumerate() {
+            let index = [i32_type.const_zero(), i32_type.const_int(i as u64, false)];
+            let arg = unsafe { self.builder.build_in_bounds_gep(args, &index, "arg") };
+            let arg = self
+                .builder
+                .build_bitcast(arg, param.get_type().ptr_type(AddressSpace::Generic), "arg")
+                .into_pointer_value();
+
+            self.builder.build_store(arg, param);
+        }
+
+        let args = self
+            .builder
+            .build_bitcast(args, slot.ptr_type(AddressSpace::Generic), "args");
+        let index = memory.borrow_mut().define_host(item.params.clone(), host);
+        let memory = self
+            .context
+            .i64_type()
+            .const_int(memory.as_ptr() as u64, false)
+            .const_to_pointer(self.context.i8_type().ptr_type(AddressSpace::Generic));
+
+        self.builder.build_call(
+            self.runtime().host_call(),
+            &[
+                memory.into(),
+                i32_type.const_int(index as u64, false).into(),
+                args.into(),
+                ret.into(),
+            ],
+            "",
+        );
+
+        match &item.ret {
+            NodeType::Void => {
+                self.builder.build_return(None);
+            }
+            ty => {
+                let ret = self
+                    .builder
+                    .build_bitcast(
+                        ret,
+                        self.basic_type(ty).ptr_type(AddressSpace::Generic),
+                        "ret",
+                    )
+                    .into_pointer_value();
+                let value = self.builder.build_load(ret, "value");
+
+                self.builder.build_return(Some(&value));
+            }
+        }
+
+        self.fns.insert(item.id, function);
+        self.names.insert(item.name.clone(), item.id);
+
+        Ok(())
+    }
+
+    /// Compile code outside of any function, as checked by
+    /// [`TypeChecker::check_body`], run it with the JIT and return its
+    /// result. Only functions previously added with [`Codegen::compile`] are
+    /// kept in the module afterwards.
+    pub fn evaluate(&self, func: &Function) -> Result<Value> {
+        let created = self.create_function(func)?;
+
+        // the JIT takes ownership of whatever module it's given, so let it
+        // have a copy and keep this one around for further definitions
+        let result = self.create_entry(func, created).and_then(|_| {
+            let trampoline = self.create_trampoline(&func.name, created);
+            let memory = self.memory.clone().unwrap_or_default();
+            let result = Jit::new(&self.module, &memory.borrow())
+                .map_err(Trap::from)
+                .and_then(|jit| {
+                    jit.call(
+                        &trampoline.get_name().to_string_lossy(),
+                        Vec::new(),
+                        &func.ret,
+                    )
+                });
+
+            unsafe { trampoline.delete() };
+
+            result.map_err(|trap| self.trapped(trap, func.at))
+        });
+
+        unsafe { created.delete() };
+
+        result
+    }
+
+    /// Call the function `name`, as added with [`Codegen::compile`] or
+    /// [`Codegen::define_host`], with `args` and return its result, which
+    /// is of type `ret`. The JIT only compiles the module again if it has
+    /// changed since the last call, so calling into it over and over is
+    /// cheap.
+    pub fn call(&mut self, name: &str, args: Vec<Value>, ret: &NodeType) -> Result<Value> {
+        let id = *self
+            .names
+            .get(name)
+            .ok_or_else(|| self.error((0, 0), format!("unknown function `{}`", name)))?;
+
+        let trampoline = match self.trampolines.get(&id) {
+            Some(trampoline) => *trampoline,
+            None => {
+                let inner = self.fns.get(&id).or_else(|| self.externs.get(&id));
+                let trampoline = self.create_trampoline(name, *inner.unwrap());
+
+                self.trampolines.insert(id, trampoline);
+                self.jit = None;
+                trampoline
+            }
+        };
+
+        if self.jit.is_none() {
+            let memory = self.memory.clone().unwrap_or_default();
+            let jit = Jit::new(&self.module, &memory.borrow())
+                .map_err(|message| self.trapped(message.into(), (0, 0)))?;
+
+            self.jit = Some(jit);
+        }
+
+        let jit = self.jit.as_ref().unwrap();
+
+        jit.call(&trampoline.get_name().to_string_lossy(), args, ret)
+            .map_err(|trap| self.trapped(trap, (0, 0)))
+    }
+
+    /// The error for code run with the JIT not returning a value, at `at`
+    /// if it isn't known which check didn't hold.
+    fn trapped(&self, trap: Trap, at: Pos) -> CompileError {
+        let (at, source) = match trap.at {
+            Some((id, at)) => (at, self.sources.get(&id)),
+            None => (at, None),
+        };
+        let mut e = CompileErrorBuilder::new()
+            .from(ErrorCodes::RuntimeError)
+            .at((at.0, at.1 + 1))
+            .note(&trap.message)
+            .build();
+
+        if let Some(source) = source {
+            e.in_source(source.file.as_deref(), &source.text);
+        }
+
+        e
+    }
+
+    /// The textual LLVM IR of a function in the module.
+    pub fn function_ir(&self, name: &str) -> Option<String> {
+        self.module
+            .get_function(name)
+            .map(|function| function.print_to_string().to_string())
+    }
+
+    /// Emit the module for this code generator's target, see [`target::emit`].
+    pub fn emit(&self, out: &Path) -> std::result::Result<PathBuf, String> {
+        if let Some(debug) = &self.debug {
+            debug.finalize();
+        }
+
+        target::emit(&self.module, self.target, out)
+    }
+
+    fn create_function(&self, func: &Function) -> Result<FunctionValue<'ctx>> {
+        // C calls an exported function through a wrapper that takes its name
+        let name = match func.export {
+            Some(_) => format!("{}.qlang", func.name),
+            // and an executable calls `main` through a C `main`
+            None if self.entry_point && func.name == "main" => "main.qlang".to_string(),
+            None => func.name.clone(),
+        };
+
+        if self.module.get_function(&name).is_some() {
+            return Err(self.error(
+                func.at,
+                format!("function `{}` is already defined", func.name),
+            ));
+        }
+
+        let ty = self.type_for_function(func);
+        let linkage = if self.is_external(func) {
+            None
+        } else {
+            Some(Linkage::Internal)
+        };
+
+        Ok(self.module.add_function(&name, ty, linkage))
+    }
+
+    /// Whether code outside the module can call `func` by its own name. It
+    /// can't call a private function, so the optimiser is free to inline or
+    /// drop one.
+    fn is_external(&self, func: &Function) -> bool {
+        (func.public && func.export.is_none()) || (func.name == "main" && !self.entry_point)
+    }
+
+    /// Define the function C code calls an exported function through, as
+    /// `symbol`, which is exported from WebAssembly as `export`. It
+    /// converts to and from C's types the way a call to an `extern fn` does,
+    /// the other way around. WebAssembly only exports public functions
+    /// through one, so the ones taking or returning values C has no type
+    /// for aren't exported at all.
+    fn create_export(
+        &self,
+        func: &Function,
+        inner: FunctionValue<'ctx>,
+        symbol: &str,
+        export: &str,
+    ) -> Result<FunctionValue<'ctx>> {
+        if self.module.get_function(symbol).is_some() {
+            return Err(self.error(func.at, format!("function `{}` is already defined", symbol)));
+        }
+
+        let params = func.locals[..func.params]
+            .iter()
+            .map(|local| local.ty.clone())
+            .collect::<Vec<_>>();
+        let ty = self.c_function_type(&params, &func.ret, false);
+        let created = self.module.add_function(symbol, ty, None);
+        let runtime = self.runtime();
+
+        self.widen_bools(created, &params, &func.ret);
+        self.export_to_wasm(created, export);
+
+        let entry = self.context.append_basic_block(created, "entry");
+        self.builder.position_at_end(entry);
+
+        let args = created
+            .get_param_iter()
+            .zip(&params)
+            .map(|(param, ty)| match ty {
+                NodeType::String => self
+                    .call(runtime.str_from_c(), &[param.into()], "str")
+                    .into(),
+                _ => param.into(),
+            })
+            .collect::<Vec<BasicMetadataValueEnum>>();
+        let value = self
+            .builder
+            .build_call(inner, &args, "call")
+            .try_as_basic_value()
+            .left();
+
+        match (&func.ret, value) {
+            (NodeType::String, Some(string)) => {
+                let bytes = self.call(runtime.str_to_c(), &[string.into()], "cstr");
+
+                self.builder.build_return(Some(&bytes));
+            }
+            (_, Some(value)) => {
+                self.builder.build_return(Some(&value));
+            }
+            (_, None) => {
+                self.builder.build_return(None);
+            }
+        }
+
+        Ok(created)
+    }
+
+    /// Define the function the JIT calls to run `inner`, the function
+    /// `name`, which returns 0 after calling `inner` with the arguments in
+    /// the slots its first parameter points at and storing what `inner`
+    /// returns in the slot its second one does, or 1 if a check failed
+    /// along the way, see [`Self::check`]. Slots are laid out like the
+    /// host's, see `jit::Memory::define_host`.
+    fn create_trampoline(&self, name: &str, inner: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+        let runtime = self.runtime();
+        let i32_type = self.context.i32_type();
+        let bytes = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let created = self.module.add_function(
+            &format!("{}.run", name),
+            i32_type.fn_type(&[bytes.into(), bytes.into()], false),
+            None,
+        );
+
+        let entry = self.context.append_basic_block(created, "entry");
+        let run = self.context.append_basic_block(created, "run");
+        let failed = self.context.append_basic_block(created, "failed");
+        self.builder.position_at_end(entry);
+
+        // big and aligned enough for a `jmp_buf` wherever the JIT runs
+        let jump = self
+            .builder
+            .build_alloca(self.context.i64_type().array_type(64), "jmpbuf");
+        let jump = self.builder.build_pointer_cast(jump, bytes, "jump");
+
+        self.builder
+            .build_store(runtime.trap().as_pointer_value(), jump);
+
+        let jumped = self
+            .call(runtime.libc("_setjmp"), &[jump.into()], "jumped")
+            .into_int_value();
+        let jumped = self.builder.build_int_compare(
+            IntPredicate::NE,
+            jumped,
+            i32_type.const_zero(),
+            "trapped",
+        );
+
+        self.builder.build_conditional_branch(jumped, failed, run);
+        self.builder.position_at_end(run);
+
+        let slot = self.context.i64_type().array_type(2);
+        let slots = created.get_nth_param(0).unwrap().into_pointer_value();
+        let slots =
+            self.builder
+                .build_pointer_cast(slots, slot.ptr_type(AddressSpace::Generic), "args");
+        let args = inner
+            .get_param_iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let index = i32_type.const_int(i as u64, false);
+                let arg = unsafe { self.builder.build_in_bounds_gep(slots, &[index], "arg") };
+                let ty = param.get_type().ptr_type(AddressSpace::Generic);
+                let arg = self.builder.build_pointer_cast(arg, ty, "arg");
+
+                self.builder.build_load(arg, "arg").into()
+            })
+            .collect::<Vec<BasicMetadataValueEnum>>();
+
+        let value = self
+            .builder
+            .build_call(inner, &args, "value")
+            .try_as_basic_value()
+            .left();
+
+        if let Some(value) = value {
+            let slot = created.get_nth_param(1).unwrap().into_pointer_value();
+            let ty = value.get_type().ptr_type(AddressSpace::Generic);
+            let slot = self.builder.build_pointer_cast(slot, ty, "slot");
+
+            self.builder.build_store(slot, value);
+        }
+
+        self.builder.build_return(Some(&i32_type.const_zero()));
+        self.builder.position_at_end(failed);
+        self.builder
+            .build_return(Some(&i32_type.const_int(1, false)));
+
+        created
+    }
+
+    /// Define the C `main` an executable starts at, which calls `main` and
+    /// exits with what it returns, or 0 if it doesn't return anything.
+    fn create_c_main(
+        &self,
+        func: &Function,
+        inner: FunctionValue<'ctx>,
+    ) -> Result<FunctionValue<'ctx>> {
+        if func.params > 0 || !matches!(func.ret, NodeType::Integer | NodeType::Void) {
+            return Err(self.error(
+                func.at,
+                "`main` has to take no arguments and return `int` or `void` to start an executable"
+                    .to_string(),
+            ));
+        }
+
+        if self.module.get_function("main").is_some() {
+            return Err(self.error(func.at, "function `main` is already defined".to_string()));
+        }
+
+        let i32_type = self.context.i32_type();
+        let created = self
+            .module
+            .add_function("main", i32_type.fn_type(&[], false), None);
+
+        let entry = self.context.append_basic_block(created, "entry");
+        self.builder.position_at_end(entry);
+
+        let status = self
+            .builder
+            .build_call(inner, &[], "call")
+            .try_as_basic_value()
+            .left()
+            .unwrap_or_else(|| i32_type.const_zero().into());
+
+        self.builder.build_return(Some(&status));
+
+        Ok(created)
+    }
+
+    /// Make a function callable by the host of a WebAssembly module.
+    fn export_to_wasm(&self, function: FunctionValue<'ctx>, name: &str) {
+        if self.target == Target::Wasm32 {
+            let export = self
+                .context
+                .create_string_attribute("wasm-export-name", name);
+
+            function.add_attribute(AttributeLoc::Function, export);
+        }
+    }
+
+    /// Declare an `extern fn` with the C calling convention, unless another
+    /// one already declared the same function, which has to agree on its
+    /// signature.
+    fn create_extern(&self, item: &Extern) -> Result<FunctionValue<'ctx>> {
+        let ty = self.c_function_type(&item.params, &item.ret, item.variadic);
+
+        if let Some(function) = self.module.get_function(&item.name) {
+            // a function defined here has blocks, a declaration doesn't
+            if function.get_type() != ty || function.count_basic_blocks() > 0 {
+                return Err(self.error(
+                    item.at,
+                    format!(
+                        "`{}` is already declared with a different signature",
+                        item.name
+                    ),
+                ));
+            }
+
+            return Ok(function);
+        }
+
+        let function = self
+            .module
+            .add_function(&item.name, ty, Some(Linkage::External));
+
+        // 0 is `ccc`, the C calling convention
+        function.set_call_conventions(0);
+        self.widen_bools(function, &item.params, &item.ret);
+
+        Ok(function)
+    }
+
+    /// The type of a function taking and returning C's types, see
+    /// [`Codegen::c_type`].
+    fn c_function_type(
+        &self,
+        params: &[NodeType],
+        ret: &NodeType,
+        variadic: bool,
+    ) -> FunctionType<'ctx> {
+        let params = params
+            .iter()
+            .map(|ty| self.c_type(ty).into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
 
-        for arg in &func.args {
-            let arg_ty = &arg.1;
-            let ty = coerce_node_type!(ctx, arg_ty);
+        match ret {
+            NodeType::Void => self.context.void_type().fn_type(&params, variadic),
+            ty => self.c_type(ty).fn_type(&params, variadic),
+        }
+    }
+
+    /// Mark the `bool`s a function shares with C as widened to a whole
+    /// register, which C expects as clang does.
+    fn widen_bools(&self, function: FunctionValue<'ctx>, params: &[NodeType], ret: &NodeType) {
+        let zeroext = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0);
+
+        for (i, ty) in params.iter().enumerate() {
+            if *ty == NodeType::Bool {
+                function.add_attribute(AttributeLoc::Param(i as u32), zeroext);
+            }
+        }
+
+        if *ret == NodeType::Bool {
+            function.add_attribute(AttributeLoc::Return, zeroext);
+        }
+    }
+
+    /// Declare every struct and enum before giving them their fields, which
+    /// may be other types from the same program.
+    fn create_types(&mut self, structs: &[Struct], enums: &[Enum]) -> Result<()> {
+        for def in structs {
+            if self.structs.contains_key(&def.name) {
+                return Err(self.error(def.at, format!("struct `{}` is already defined", def.name)));
+            }
+
+            let ty = self.context.opaque_struct_type(&def.name);
+
+            self.structs.insert(def.name.clone(), ty);
+        }
+
+        for def in enums {
+            if self.enums.contains_key(&def.name) {
+                return Err(self.error(def.at, format!("enum `{}` is already defined", def.name)));
+            }
+
+            let ty = TaggedUnion {
+                ty: self.context.opaque_struct_type(&def.name),
+                payloads: Vec::new(),
+            };
 
-            args.push(ty);
+            self.enums.insert(def.name.clone(), ty);
         }
 
+        for def in structs {
+            let fields = def
+                .fields
+                .iter()
+                .map(|(_, ty)| self.basic_type(ty))
+                .collect::<Vec<_>>();
+
+            // not packed, so fields are aligned the way a C compiler would
+            self.structs[&def.name].set_body(&fields, false);
+        }
+
+        self.create_enums(enums)
+    }
+
+    /// Give every enum its body, which is only possible once the size of
+    /// every type in its variants is known. Types can't contain themselves,
+    /// so each pass sizes at least one more enum.
+    fn create_enums(&mut self, enums: &[Enum]) -> Result<()> {
+        if enums.is_empty() {
+            return Ok(());
+        }
+
+        let machine = self.target.machine().map_err(|e| self.error((0, 0), e))?;
+        let data = machine.get_target_data();
+        let mut left = enums.iter().collect::<Vec<_>>();
+
+        while !left.is_empty() {
+            let (ready, waiting) = left.into_iter().partition::<Vec<_>, _>(|def| {
+                def.variants
+                    .iter()
+                    .flat_map(|variant| &variant.fields)
+                    .all(|(_, ty)| self.basic_type(ty).is_sized())
+            });
+
+            if ready.is_empty() {
+                let def = waiting[0];
+
+                return Err(self.error(def.at, format!("enum `{}` has no size", def.name)));
+            }
+
+            for def in ready {
+                let payloads = def
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let fields = variant
+                            .fields
+                            .iter()
+                            .map(|(_, ty)| self.basic_type(ty))
+                            .collect::<Vec<_>>();
+
+                        self.context.struct_type(&fields, false)
+                    })
+                    .collect::<Vec<_>>();
+
+                let size = payloads
+                    .iter()
+                    .map(|ty| data.get_abi_size(ty))
+                    .max()
+                    .unwrap_or(0);
+                let align = payloads
+                    .iter()
+                    .map(|ty| data.get_abi_alignment(ty))
+                    .max()
+                    .unwrap_or(1)
+                    .max(1);
+
+                // an array of integers as wide as the strictest alignment
+                // keeps every variant's fields aligned
+                let unit = self.context.custom_width_int_type(align * 8);
+                let space = unit.array_type(((size + align as u64 - 1) / align as u64) as u32);
+
+                let union = self.enums.get_mut(&def.name).unwrap();
+
+                union
+                    .ty
+                    .set_body(&[self.context.i32_type().into(), space.into()], false);
+                union.payloads = payloads;
+            }
+
+            left = waiting;
+        }
+
+        Ok(())
+    }
+
+    fn create_global(&self, global: &Global) -> Result<GlobalValue<'ctx>> {
+        if self.module.get_global(&global.name).is_some() {
+            return Err(self.error(
+                global.at,
+                format!("static `{}` is already defined", global.name),
+            ));
+        }
+
+        let created = self
+            .module
+            .add_global(self.basic_type(&global.ty), None, &global.name);
+
+        match &self.memory {
+            // left as a declaration that the JIT points at the host's copy,
+            // so changes to it carry over to code compiled later
+            Some(memory) if global.mutable => {
+                memory.borrow_mut().define(&global.name, &global.value);
+            }
+            _ => {
+                created.set_initializer(&self.literal(&global.value));
+                created.set_constant(!global.mutable);
+            }
+        }
+
+        Ok(created)
+    }
+
+    fn type_for_function(&self, func: &Function) -> FunctionType<'ctx> {
+        let args = func.locals[..func.params]
+            .iter()
+            .map(|local| self.basic_type(&local.ty).into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+
         match func.ret {
-            NodeType::Bool => self.context.bool_type().fn_type(&args[..], false),
-            NodeType::Integer => self.context.i32_type().fn_type(&args[..], false),
-            NodeType::Float => self.context.f32_type().fn_type(&args[..], false),
+            NodeType::Void => self.context.void_type().fn_type(&args, false),
+            ref ty => self.basic_type(ty).fn_type(&args, false),
+        }
+    }
+
+    /// How C sees a value of type `ty`, which for a string is a pointer to
+    /// its NUL-terminated bytes.
+    fn c_type(&self, ty: &NodeType) -> BasicTypeEnum<'ctx> {
+        match ty {
             NodeType::String => self
                 .context
                 .i8_type()
                 .ptr_type(AddressSpace::Generic)
-                .fn_type(&args[..], false),
-            _ => panic!("Unsupported type"),
+                .into(),
+            ty => self.basic_type(ty),
         }
     }
 
-    fn create_entry(&self, func: &Function, created: FunctionValue<'ctx>) {
+    fn basic_type(&self, ty: &NodeType) -> BasicTypeEnum<'ctx> {
+        let ctx = &self.context;
+
+        match ty {
+            NodeType::Struct(name) => self.structs[name].as_basic_type_enum(),
+            NodeType::Enum(name) => self.enums[name].ty.as_basic_type_enum(),
+            NodeType::Array(element, len) => self
+                .basic_type(element)
+                .array_type(*len as u32)
+                .as_basic_type_enum(),
+            NodeType::Vec(_) => self
+                .runtime()
+                .vec_type()
+                .ptr_type(AddressSpace::Generic)
+                .as_basic_type_enum(),
+            NodeType::Slice(_) => self.runtime().slice_type().as_basic_type_enum(),
+            NodeType::String => self.runtime().str_type().as_basic_type_enum(),
+            ty => coerce_node_type!(ctx, ty),
+        }
+    }
+
+    fn create_entry(&self, func: &Function, created: FunctionValue<'ctx>) -> Result<()> {
         let entry = self.context.append_basic_block(created, "entry");
         self.builder.position_at_end(entry);
 
-        let _body = match &func.body {
-            Node::Block(body) => body,
-            _ => panic!("Expected block"),
+        let mut scope = Scope {
+            func,
+            locals: Vec::new(),
+            loops: Vec::new(),
+            subprogram: self
+                .debug
+                .as_ref()
+                .map(|debug| debug.function(func, created)),
+            at: func.at,
         };
 
-        self.builder.build_return(None);
+        self.set_location(&scope, func.at);
+
+        // every variable gets its stack slot up front, so that declaring one
+        // inside a loop doesn't grow the stack on every iteration
+        for local in &func.locals {
+            let ptr = self
+                .builder
+                .build_alloca(self.basic_type(&local.ty), &local.name);
+
+            scope.locals.push(ptr);
+        }
+
+        for (i, param) in created.get_param_iter().enumerate() {
+            self.builder.build_store(scope.locals[i], param);
+            self.declare(&scope, i, Some(i as u32 + 1));
+        }
+
+        // the value of a trailing expression statement is the implicit return value
+        let value = self.lower_block(&func.body, &mut scope)?;
+
+        if !self.terminated() {
+            match (&func.ret, value) {
+                (NodeType::Void, _) => {
+                    self.builder.build_return(None);
+                }
+                (_, Some(value)) => {
+                    self.builder.build_return(Some(&value));
+                }
+                // the type checker made sure every path returns, so this is
+                // only the end of a loop that's never left, like `while true`
+                (_, None) => {
+                    self.builder.build_unreachable();
+                }
+            }
+        }
+
+        self.builder.unset_current_debug_location();
+
+        Ok(())
+    }
+
+    // Statements
+
+    fn lower_block(
+        &self,
+        block: &Block,
+        scope: &mut Scope<'_, 'ctx>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let mut last = None;
+
+        for stmt in &block.stmts {
+            if self.terminated() {
+                break;
+            }
+
+            last = self.lower_stmt(stmt, scope)?;
+        }
+
+        Ok(last)
+    }
+
+    fn lower_stmt(
+        &self,
+        stmt: &Stmt,
+        scope: &mut Scope<'_, 'ctx>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        scope.at = stmt.at;
+        self.set_location(scope, stmt.at);
+
+        match &stmt.kind {
+            StmtKind::Let { local, value } => {
+                if let Some(value) = value {
+                    let value = self.lower_expr(value, scope)?;
+
+                    self.builder.build_store(scope.locals[*local], value);
+                }
+
+                self.declare(scope, *local, None);
+            }
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Call { func, args },
+                ty,
+            }) => return self.lower_call(*func, args, ty, scope),
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Builtin { builtin, args },
+                ty,
+            }) => return self.lower_builtin(*builtin, args, ty, scope),
+            StmtKind::Expr(expr) => return Ok(Some(self.lower_expr(expr, scope)?)),
+            StmtKind::Return(value) => match value {
+                Some(value) => {
+                    let value = self.lower_expr(value, scope)?;
+
+                    self.builder.build_return(Some(&value));
+                }
+                None => {
+                    self.builder.build_return(None);
+                }
+            },
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let cond = self.lower_expr(cond, scope)?.into_int_value();
+                let function = self.function();
+                let then_block = self.context.append_basic_block(function, "then");
+                let else_block = self.context.append_basic_block(function, "else");
+
+                self.builder
+                    .build_conditional_branch(cond, then_block, else_block);
+
+                self.builder.position_at_end(then_block);
+                self.lower_block(then, scope)?;
+                let then_end = self.open_block();
+
+                self.builder.position_at_end(else_block);
+                if let Some(otherwise) = otherwise {
+                    self.lower_block(otherwise, scope)?;
+                }
+                let else_end = self.open_block();
+
+                // if both branches return there's nothing left to continue with
+                let open = then_end.into_iter().chain(else_end).collect::<Vec<_>>();
+
+                if !open.is_empty() {
+                    let merge = self.context.append_basic_block(function, "endif");
+
+                    for block in open {
+                        self.builder.position_at_end(block);
+                        self.builder.build_unconditional_branch(merge);
+                    }
+
+                    self.builder.position_at_end(merge);
+                }
+            }
+            StmtKind::Match(arms) => {
+                let function = self.function();
+                let mut open = Vec::new();
+
+                for arm in arms {
+                    let next = if arm.conds.is_empty() {
+                        None
+                    } else {
+                        Some(self.context.append_basic_block(function, "nextarm"))
+                    };
+
+                    // a failed condition moves on to the next arm
+                    for cond in &arm.conds {
+                        let cond = self.lower_expr(cond, scope)?.into_int_value();
+                        let then = self.context.append_basic_block(function, "arm");
+
+                        self.builder
+                            .build_conditional_branch(cond, then, next.unwrap());
+                        self.builder.position_at_end(then);
+                    }
+
+                    self.lower_block(&arm.body, scope)?;
+                    open.extend(self.open_block());
+
+                    if let Some(next) = next {
+                        self.builder.position_at_end(next);
+                    }
+                }
+
+                if !open.is_empty() {
+                    let merge = self.context.append_basic_block(function, "endmatch");
+
+                    for block in open {
+                        self.builder.position_at_end(block);
+                        self.builder.build_unconditional_branch(merge);
+                    }
+
+                    self.builder.position_at_end(merge);
+                }
+            }
+            StmtKind::Loop(body) => {
+                let function = self.function();
+                let body_block = self.context.append_basic_block(function, "loop");
+                let end_block = self.context.append_basic_block(function, "endloop");
+
+                self.builder.build_unconditional_branch(body_block);
+                self.builder.position_at_end(body_block);
+
+                scope.loops.push(end_block);
+                self.lower_block(body, scope)?;
+                scope.loops.pop();
+
+                if !self.terminated() {
+                    self.builder.build_unconditional_branch(body_block);
+                }
+
+                self.builder.position_at_end(end_block);
+            }
+            StmtKind::Break => match scope.loops.last() {
+                Some(end) => {
+                    self.builder.build_unconditional_branch(*end);
+                }
+                None => return Err(self.error(scope.at, "`break` outside of a loop".to_string())),
+            },
+            StmtKind::Block(block) => return self.lower_block(block, scope),
+        }
+
+        Ok(None)
+    }
+
+    // Expressions
+
+    fn lower_expr(&self, expr: &Expr, scope: &Scope<'_, 'ctx>) -> Result<BasicValueEnum<'ctx>> {
+        let value = match &expr.kind {
+            ExprKind::Literal(literal) => self.literal(literal),
+            ExprKind::Local(local) => {
+                let name = &scope.func.locals[*local].name;
+
+                self.builder.build_load(scope.locals[*local], name)
+            }
+            ExprKind::Global(global) => {
+                let global = self.global(*global, scope)?;
+                let name = global.get_name().to_string_lossy();
+
+                self.builder.build_load(global.as_pointer_value(), &name)
+            }
+            ExprKind::Call { func, args } => match self.lower_call(*func, args, &expr.ty, scope)? {
+                Some(value) => value,
+                None => return Err(self.error(scope.at, "call doesn't return a value".into())),
+            },
+            ExprKind::Assign { place, path, value } => {
+                let value = self.lower_expr(value, scope)?;
+                let mut ptr = match place {
+                    Place::Local(local) => scope.locals[*local],
+                    Place::Global(global) => self.global(*global, scope)?.as_pointer_value(),
+                };
+
+                // only the assigned part is written, the rest stays as it is
+                for projection in path {
+                    ptr = match projection {
+                        Projection::Field(field) => self
+                            .builder
+                            .build_struct_gep(ptr, *field as u32, "field")
+                            .map_err(|_| {
+                                self.error(
+                                    scope.at,
+                                    "assignment to a field that doesn't exist".into(),
+                                )
+                            })?,
+                        Projection::Index(index) => {
+                            let index = self.lower_expr(index, scope)?.into_int_value();
+
+                            self.element(ptr, index, scope)
+                        }
+                    };
+                }
+
+                self.builder.build_store(ptr, value);
+
+                value
+            }
+            ExprKind::Array(elements) => {
+                let mut value = self.basic_type(&expr.ty).into_array_type().get_undef();
+
+                for (i, element) in elements.iter().enumerate() {
+                    let element = self.lower_expr(element, scope)?;
+
+                    value = self
+                        .builder
+                        .build_insert_value(value, element, i as u32, "array")
+                        .unwrap()
+                        .into_array_value();
+                }
+
+                value.into()
+            }
+            ExprKind::Index { base, index } if !matches!(base.ty, NodeType::Array(..)) => {
+                let value = self.lower_expr(base, scope)?;
+                let index = self.lower_expr(index, scope)?.into_int_value();
+                let (elements, len) = self.elements(value, &base.ty);
+                let ptr = self.element_at(elements, len, index, scope);
+
+                self.builder.build_load(ptr, "element")
+            }
+            ExprKind::Index { base, index } => {
+                let array = self.lower_expr(base, scope)?;
+                let index = self.lower_expr(index, scope)?.into_int_value();
+
+                // elements can only be picked out by a variable index in memory
+                let slot = self.entry_alloca(array.get_type(), "array");
+                self.builder.build_store(slot, array);
+
+                let ptr = self.element(slot, index, scope);
+
+                self.builder.build_load(ptr, "element")
+            }
+            ExprKind::Builtin { builtin, args } => {
+                match self.lower_builtin(*builtin, args, &expr.ty, scope)? {
+                    Some(value) => value,
+                    None => {
+                        return Err(
+                            self.error(scope.at, format!("`{}` doesn't return a value", builtin))
+                        )
+                    }
+                }
+            }
+            ExprKind::Struct(fields) => {
+                let mut value = self.basic_type(&expr.ty).into_struct_type().get_undef();
+
+                for (i, field) in fields.iter().enumerate() {
+                    let field = self.lower_expr(field, scope)?;
+
+                    value = self
+                        .builder
+                        .build_insert_value(value, field, i as u32, "struct")
+                        .unwrap()
+                        .into_struct_value();
+                }
+
+                value.into()
+            }
+            ExprKind::Field { base, index } => {
+                let base = self.lower_expr(base, scope)?.into_struct_value();
+
+                self.builder
+                    .build_extract_value(base, *index as u32, "field")
+                    .ok_or_else(|| {
+                        self.error(scope.at, "read of a field that doesn't exist".into())
+                    })?
+            }
+            ExprKind::Variant { variant, fields } => {
+                let union = self.union(&expr.ty);
+                let slot = self.entry_alloca(union.ty.into(), "enum");
+                let tag = self.builder.build_struct_gep(slot, 0, "tag").unwrap();
+
+                self.builder.build_store(
+                    tag,
+                    self.context.i32_type().const_int(*variant as u64, false),
+                );
+
+                let payload = self.payload(slot, &union.payloads[*variant]);
+
+                for (i, field) in fields.iter().enumerate() {
+                    let value = self.lower_expr(field, scope)?;
+                    let ptr = self
+                        .builder
+                        .build_struct_gep(payload, i as u32, "field")
+                        .unwrap();
+
+                    self.builder.build_store(ptr, value);
+                }
+
+                self.builder.build_load(slot, "enum")
+            }
+            ExprKind::Tag(base) => {
+                let base = self.lower_expr(base, scope)?.into_struct_value();
+
+                self.builder.build_extract_value(base, 0, "tag").unwrap()
+            }
+            ExprKind::Payload {
+                base,
+                variant,
+                index,
+            } => {
+                let union = self.union(&base.ty);
+                let value = self.lower_expr(base, scope)?;
+                let slot = self.entry_alloca(union.ty.into(), "enum");
+
+                // the payload can only be reinterpreted through memory
+                self.builder.build_store(slot, value);
+
+                let payload = self.payload(slot, &union.payloads[*variant]);
+                let ptr = self
+                    .builder
+                    .build_struct_gep(payload, *index as u32, "field")
+                    .map_err(|_| {
+                        self.error(scope.at, "read of a field that doesn't exist".into())
+                    })?;
+
+                self.builder.build_load(ptr, "field")
+            }
+            ExprKind::Unary { op, operand } => {
+                let value = self.lower_expr(operand, scope)?;
+
+                match (op, &operand.ty) {
+                    (Op::Sub, NodeType::Float) => self
+                        .builder
+                        .build_float_neg(value.into_float_value(), "neg")
+                        .into(),
+                    (Op::Sub, _) => self
+                        .builder
+                        .build_int_neg(value.into_int_value(), "neg")
+                        .into(),
+                    (Op::Not, _) => self.builder.build_not(value.into_int_value(), "not").into(),
+                    (op, ty) => {
+                        return Err(self.error(
+                            scope.at,
+                            format!("cannot apply `{:?}` to a value of type {}", op, ty),
+                        ))
+                    }
+                }
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let ty = &lhs.ty;
+                let lhs = self.lower_expr(lhs, scope)?;
+                let rhs = self.lower_expr(rhs, scope)?;
+
+                match ty {
+                    NodeType::Float => {
+                        self.float_op(scope, *op, lhs.into_float_value(), rhs.into_float_value())?
+                    }
+                    NodeType::String => self.str_op(scope, *op, lhs, rhs)?,
+                    _ => self.int_op(scope, *op, lhs.into_int_value(), rhs.into_int_value())?,
+                }
+            }
+        };
+
+        Ok(value)
     }
 
-    fn _emit_error(&self, msg: String) -> ! {
-        eprintln!("{}", msg);
-        exit(1)
+    /// A constant that can be used both in functions and as the value of a
+    /// global.
+    fn literal(&self, literal: &Literal) -> BasicValueEnum<'ctx> {
+        match literal {
+            Literal::Integer(i) => self.context.i32_type().const_int(*i as u64, true).into(),
+            Literal::Float(f) => self.context.f32_type().const_float(*f as f64).into(),
+            Literal::Bool(b) => self.context.bool_type().const_int(*b as u64, false).into(),
+            Literal::Char(c) => self.context.i32_type().const_int(*c as u64, false).into(),
+            Literal::String(s) => self.string(s).into(),
+        }
+    }
+
+    /// A constant string, whose bytes are followed by a NUL that isn't
+    /// counted in its length.
+    fn string(&self, s: &str) -> StructValue<'ctx> {
+        let len = self.context.i32_type().const_int(s.len() as u64, false);
+
+        self.runtime()
+            .str_type()
+            .const_named_struct(&[self.c_string(s).into(), len.into()])
     }
+
+    /// A pointer to the bytes of `s` followed by a NUL.
+    fn c_string(&self, s: &str) -> PointerValue<'ctx> {
+        let ty = self.context.i8_type().ptr_type(AddressSpace::Generic);
+
+        match &self.memory {
+            // the JIT's copy of the module is gone after each run, but a
+            // mutable global may still point at the string
+            Some(memory) => {
+                let ptr = memory.borrow_mut().string(s);
+
+                self.context
+                    .i64_type()
+                    .const_int(ptr as u64, false)
+                    .const_to_pointer(ty)
+            }
+            None => {
+                let i8_type = self.context.i8_type();
+                let bytes = s
+                    .bytes()
+                    .chain(Some(0))
+                    .map(|b| i8_type.const_int(b as u64, false))
+                    .collect::<Vec<_>>();
+
+                let global =
+                    self.module
+                        .add_global(i8_type.array_type(bytes.len() as u32), None, "str");
+
+                global.set_initializer(&i8_type.const_array(&bytes));
+                global.set_constant(true);
+                global.set_unnamed_addr(true);
+                global.set_linkage(Linkage::Private);
+
+                global.as_pointer_value().const_cast(ty)
+            }
+        }
+    }
+
+    /// Lower a call to a function returning a value of type `ty`, which is
+    /// `None` if it doesn't return one.
+    fn lower_call(
+        &self,
+        func: FnId,
+        args: &[Expr],
+        ty: &NodeType,
+        scope: &Scope<'_, 'ctx>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        if let Some(function) = self.externs.get(&func) {
+            return self.lower_extern_call(*function, args, ty, scope);
+        }
+
+        let function = *self
+            .fns
+            .get(&func)
+            .ok_or_else(|| self.error(scope.at, "call to an unknown function".to_string()))?;
+
+        let args = args
+            .iter()
+            .map(|arg| Ok(self.lower_expr(arg, scope)?.into()))
+            .collect::<Result<Vec<BasicMetadataValueEnum>>>()?;
+
+        Ok(self
+            .builder
+            .build_call(function, &args, "call")
+            .try_as_basic_value()
+            .left())
+    }
+
+    /// Lower a call to an `extern fn`, passing strings as C strings and
+    /// promoting what's passed through `...` the way C does.
+    fn lower_extern_call(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[Expr],
+        ty: &NodeType,
+        scope: &Scope<'_, 'ctx>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let runtime = self.runtime();
+        let fixed = function.count_params() as usize;
+        let mut values = Vec::new();
+
+        for (i, arg) in args.iter().enumerate() {
+            let value = match (&arg.ty, self.lower_expr(arg, scope)?) {
+                (NodeType::String, value) => self.call(runtime.str_to_c(), &[value.into()], "cstr"),
+                (NodeType::Float, BasicValueEnum::FloatValue(f)) if i >= fixed => self
+                    .builder
+                    .build_float_ext(f, self.context.f64_type(), "double")
+                    .into(),
+                (NodeType::Bool, BasicValueEnum::IntValue(b)) if i >= fixed => self
+                    .builder
+                    .build_int_z_extend(b, self.context.i32_type(), "int")
+                    .into(),
+                (_, value) => value,
+            };
+
+            values.push(value.into());
+        }
+
+        let value = self
+            .builder
+            .build_call(function, &values, "call")
+            .try_as_basic_value()
+            .left();
+
+        Ok(match (ty, value) {
+            (NodeType::String, Some(bytes)) => {
+                Some(self.call(runtime.str_from_c(), &[bytes.into()], "str"))
+            }
+            (_, value) => value,
+        })
+    }
+
+    /// Lower a call to a builtin returning a value of type `ty`, which is
+    /// `None` if it doesn't return one.
+    fn lower_builtin(
+        &self,
+        builtin: Builtin,
+        args: &[Expr],
+        ty: &NodeType,
+        scope: &Scope<'_, 'ctx>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let values = args
+            .iter()
+            .map(|arg| self.lower_expr(arg, scope))
+            .collect::<Result<Vec<_>>>()?;
+        let runtime = self.runtime();
+        let i32_type = self.context.i32_type();
+
+        let value = match builtin {
+            Builtin::VecNew => self
+                .builder
+                .build_call(runtime.vec_new(), &[], "vec")
+                .try_as_basic_value()
+                .left(),
+            Builtin::Len => Some(self.elements(values[0], &args[0].ty).1.into()),
+            Builtin::Push => {
+                let element = self.basic_type(&args[1].ty);
+                let size = element.size_of().unwrap();
+                let size = self
+                    .builder
+                    .build_int_cast(size, runtime.size_type(), "size");
+                let slot = self
+                    .builder
+                    .build_call(runtime.vec_push(), &[values[0].into(), size.into()], "slot")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                let slot = self.builder.build_pointer_cast(
+                    slot,
+                    element.ptr_type(AddressSpace::Generic),
+                    "slot",
+                );
+
+                self.builder.build_store(slot, values[1]);
+
+                None
+            }
+            Builtin::Pop => {
+                let (elements, len) = self.elements(values[0], &args[0].ty);
+                let empty = self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    len,
+                    i32_type.const_zero(),
+                    "nonempty",
+                );
+
+                self.check(empty, "cannot pop from an empty `Vec`", &[], scope);
+
+                let len = self
+                    .builder
+                    .build_int_sub(len, i32_type.const_int(1, false), "len");
+                let len_ptr = self
+                    .builder
+                    .build_struct_gep(values[0].into_pointer_value(), 1, "len")
+                    .unwrap();
+
+                self.builder.build_store(len_ptr, len);
+
+                let ptr = unsafe { self.builder.build_in_bounds_gep(elements, &[len], "last") };
+
+                Some(self.builder.build_load(ptr, "element"))
+            }
+            Builtin::Set => {
+                let (elements, len) = self.elements(values[0], &args[0].ty);
+                let ptr = self.element_at(elements, len, values[1].into_int_value(), scope);
+
+                self.builder.build_store(ptr, values[2]);
+
+                None
+            }
+            Builtin::Slice => {
+                let (elements, len) = self.elements(values[0], &args[0].ty);
+                let (start, end) = (values[1].into_int_value(), values[2].into_int_value());
+
+                if self.bounds_checks {
+                    let ordered =
+                        self.builder
+                            .build_int_compare(IntPredicate::ULE, start, end, "ordered");
+                    let within =
+                        self.builder
+                            .build_int_compare(IntPredicate::ULE, end, len, "within");
+                    let in_bounds = self.builder.build_and(ordered, within, "inbounds");
+
+                    self.check(
+                        in_bounds,
+                        "range %d..%d is out of bounds for an array of length %d",
+                        &[start.into(), end.into(), len.into()],
+                        scope,
+                    );
+                }
+
+                // a string has to stay valid UTF-8 whether or not bounds are
+                // checked, and the boundary checks stay within it anyway
+                if args[0].ty == NodeType::String {
+                    let boundary = runtime.str_boundary();
+                    let starts = self
+                        .call(boundary, &[values[0].into(), start.into()], "starts")
+                        .into_int_value();
+                    let ends = self
+                        .call(boundary, &[values[0].into(), end.into()], "ends")
+                        .into_int_value();
+                    let boundaries = self.builder.build_and(starts, ends, "boundaries");
+
+                    self.check(
+                        boundaries,
+                        "range %d..%d of a string doesn't start and end on character boundaries",
+                        &[start.into(), end.into()],
+                        scope,
+                    );
+                }
+
+                let first = unsafe {
+                    self.builder
+                        .build_in_bounds_gep(elements, &[start], "first")
+                };
+                let first = self.builder.build_pointer_cast(
+                    first,
+                    self.context.i8_type().ptr_type(AddressSpace::Generic),
+                    "first",
+                );
+                let len = self.builder.build_int_sub(end, start, "len");
+                let slice = self.basic_type(ty).into_struct_type().get_undef();
+                let slice = self
+                    .builder
+                    .build_insert_value(slice, first, 0, "slice")
+                    .unwrap()
+                    .into_struct_value();
+                let slice = self
+                    .builder
+                    .build_insert_value(slice, len, 1, "slice")
+                    .unwrap()
+                    .into_struct_value();
+
+                Some(slice.into())
+            }
+            Builtin::Free => {
+                self.builder
+                    .build_call(runtime.vec_free(), &[values[0].into()], "");
+
+                None
+            }
+            Builtin::Print | Builtin::Println => {
+                let function = match builtin {
+                    Builtin::Print => runtime.print(),
+                    _ => runtime.println(),
+                };
+
+                self.builder.build_call(function, &[values[0].into()], "");
+
+                None
+            }
+            Builtin::ReadLine => Some(self.call(runtime.read_line(), &[], "line")),
+            Builtin::Str => match args[0].ty {
+                NodeType::Bool => Some(self.builder.build_select(
+                    values[0].into_int_value(),
+                    self.string("true"),
+                    self.string("false"),
+                    "string",
+                )),
+                NodeType::Float => {
+                    Some(self.call(runtime.float_to_str(), &[values[0].into()], "string"))
+                }
+                NodeType::Char => {
+                    Some(self.call(runtime.char_to_str(), &[values[0].into()], "string"))
+                }
+                _ => Some(self.call(runtime.int_to_str(), &[values[0].into()], "string")),
+            },
+            Builtin::Int | Builtin::Float => {
+                let (function, message) = match builtin {
+                    Builtin::Int => (runtime.str_to_int(), "cannot parse \"%.*s\" as an int"),
+                    _ => (runtime.str_to_float(), "cannot parse \"%.*s\" as a float"),
+                };
+                let slot = self.entry_alloca(self.basic_type(ty), "parsed");
+                let parsed = self
+                    .call(function, &[values[0].into(), slot.into()], "ok")
+                    .into_int_value();
+                let (bytes, len) = self.elements(values[0], &args[0].ty);
+
+                self.check(parsed, message, &[len.into(), bytes.into()], scope);
+
+                Some(self.builder.build_load(slot, "parsed"))
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn int_op(
+        &self,
+        scope: &Scope<'_, 'ctx>,
+        op: Op,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+
+        let predicate = match op {
+            Op::Add => return Ok(b.build_int_add(l, r, "add").into()),
+            Op::Sub => return Ok(b.build_int_sub(l, r, "sub").into()),
+            Op::Mul => return Ok(b.build_int_mul(l, r, "mul").into()),
+            Op::Div => {
+                let r = self.divisor(l, r, scope);
+                return Ok(b.build_int_signed_div(l, r, "div").into());
+            }
+            Op::Mod => {
+                let r = self.divisor(l, r, scope);
+                return Ok(b.build_int_signed_rem(l, r, "rem").into());
+            }
+            Op::And => return Ok(b.build_and(l, r, "and").into()),
+            Op::Or => return Ok(b.build_or(l, r, "or").into()),
+            Op::Eq => IntPredicate::EQ,
+            Op::Neq => IntPredicate::NE,
+            Op::Lt => IntPredicate::SLT,
+            Op::Gt => IntPredicate::SGT,
+            Op::Le => IntPredicate::SLE,
+            Op::Ge => IntPredicate::SGE,
+            Op::Not | Op::Assign => {
+                return Err(self.error(scope.at, format!("`{:?}` is not a binary operator", op)))
+            }
+        };
+
+        Ok(b.build_int_compare(predicate, l, r, "cmp").into())
+    }
+
+    /// Concatenate or compare two strings.
+    fn str_op(
+        &self,
+        scope: &Scope<'_, 'ctx>,
+        op: Op,
+        l: BasicValueEnum<'ctx>,
+        r: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let runtime = self.runtime();
+        let args = [l.into(), r.into()];
+
+        let predicate = match op {
+            Op::Add => return Ok(self.call(runtime.str_concat(), &args, "concat")),
+            Op::Eq => IntPredicate::EQ,
+            Op::Neq => IntPredicate::NE,
+            Op::Lt => IntPredicate::SLT,
+            Op::Gt => IntPredicate::SGT,
+            Op::Le => IntPredicate::SLE,
+            Op::Ge => IntPredicate::SGE,
+            _ => {
+                return Err(self.error(scope.at, format!("`{:?}` cannot be applied to strings", op)))
+            }
+        };
+
+        let order = self
+            .call(runtime.str_compare(), &args, "order")
+            .into_int_value();
+
+        Ok(self
+            .builder
+            .build_int_compare(
+                predicate,
+                order,
+                self.context.i32_type().const_zero(),
+                "cmp",
+            )
+            .into())
+    }
+
+    fn float_op(
+        &self,
+        scope: &Scope<'_, 'ctx>,
+        op: Op,
+        l: FloatValue<'ctx>,
+        r: FloatValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+
+        let predicate = match op {
+            Op::Add => return Ok(b.build_float_add(l, r, "add").into()),
+            Op::Sub => return Ok(b.build_float_sub(l, r, "sub").into()),
+            Op::Mul => return Ok(b.build_float_mul(l, r, "mul").into()),
+            Op::Div => return Ok(b.build_float_div(l, r, "div").into()),
+            Op::Mod => return Ok(b.build_float_rem(l, r, "rem").into()),
+            Op::Eq => FloatPredicate::OEQ,
+            Op::Neq => FloatPredicate::ONE,
+            Op::Lt => FloatPredicate::OLT,
+            Op::Gt => FloatPredicate::OGT,
+            Op::Le => FloatPredicate::OLE,
+            Op::Ge => FloatPredicate::OGE,
+            Op::And | Op::Or | Op::Not | Op::Assign => {
+                return Err(self.error(scope.at, format!("`{:?}` cannot be applied to floats", op)))
+            }
+        };
+
+        Ok(b.build_float_compare(predicate, l, r, "cmp").into())
+    }
+
+    // Helpers
+
+    fn global(&self, global: GlobalId, scope: &Scope<'_, 'ctx>) -> Result<GlobalValue<'ctx>> {
+        self.globals
+            .get(&global)
+            .copied()
+            .ok_or_else(|| self.error(scope.at, "use of an unknown static".to_string()))
+    }
+
+    /// A pointer to an element of the array `array` points to, after
+    /// checking that `index` is in bounds unless that's turned off.
+    fn element(
+        &self,
+        array: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+        scope: &Scope<'_, 'ctx>,
+    ) -> PointerValue<'ctx> {
+        let len = array.get_type().get_element_type().into_array_type().len();
+        let i32_type = self.context.i32_type();
+
+        if self.bounds_checks {
+            self.check_bounds(index, i32_type.const_int(len as u64, false), scope);
+        }
+
+        unsafe {
+            self.builder
+                .build_in_bounds_gep(array, &[i32_type.const_zero(), index], "element")
+        }
+    }
+
+    /// A pointer to the element at `index` of the `len` that `elements`
+    /// points to the first of, like [`Codegen::element`].
+    fn element_at(
+        &self,
+        elements: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+        index: IntValue<'ctx>,
+        scope: &Scope<'_, 'ctx>,
+    ) -> PointerValue<'ctx> {
+        if self.bounds_checks {
+            self.check_bounds(index, len, scope);
+        }
+
+        unsafe {
+            self.builder
+                .build_in_bounds_gep(elements, &[index], "element")
+        }
+    }
+
+    /// A pointer to the first element of a `Vec`, a slice or a string of
+    /// type `ty`, and how many there are.
+    fn elements(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        ty: &NodeType,
+    ) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let (elements, len) = match ty {
+            NodeType::Vec(_) => {
+                let vec = value.into_pointer_value();
+                let elements = self.builder.build_struct_gep(vec, 0, "elements").unwrap();
+                let len = self.builder.build_struct_gep(vec, 1, "len").unwrap();
+
+                (
+                    self.builder.build_load(elements, "elements"),
+                    self.builder.build_load(len, "len"),
+                )
+            }
+            _ => {
+                let slice = value.into_struct_value();
+
+                (
+                    self.builder
+                        .build_extract_value(slice, 0, "elements")
+                        .unwrap(),
+                    self.builder.build_extract_value(slice, 1, "len").unwrap(),
+                )
+            }
+        };
+
+        let element = match ty {
+            NodeType::Vec(element) | NodeType::Slice(element) => self.basic_type(element),
+            NodeType::String => self.context.i8_type().into(),
+            ty => panic!("{} is not a `Vec`, a slice or a string", ty),
+        };
+        let elements = self.builder.build_pointer_cast(
+            elements.into_pointer_value(),
+            element.ptr_type(AddressSpace::Generic),
+            "elements",
+        );
+
+        (elements, len.into_int_value())
+    }
+
+    /// Stop the program if `r` is zero, or else a divisor for `l` that gives
+    /// the same result as `r` without trapping: `i32::MIN / -1` overflows,
+    /// which wraps around to `i32::MIN` like dividing by 1 does.
+    fn divisor(
+        &self,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        scope: &Scope<'_, 'ctx>,
+    ) -> IntValue<'ctx> {
+        let b = &self.builder;
+        let ty = r.get_type();
+
+        let nonzero = b.build_int_compare(IntPredicate::NE, r, ty.const_zero(), "nonzero");
+        self.check(nonzero, "attempt to divide by zero", &[], scope);
+
+        let min = ty.const_int(i32::MIN as u64, true);
+        let minus_one = ty.const_all_ones();
+        let overflows = b.build_and(
+            b.build_int_compare(IntPredicate::EQ, l, min, "min"),
+            b.build_int_compare(IntPredicate::EQ, r, minus_one, "minusone"),
+            "overflows",
+        );
+
+        b.build_select(overflows, ty.const_int(1, false), r, "divisor")
+            .into_int_value()
+    }
+
+    /// Stop the program, with a message saying where, if `index` is out of
+    /// bounds for an array of `len` elements.
+    fn check_bounds(&self, index: IntValue<'ctx>, len: IntValue<'ctx>, scope: &Scope<'_, 'ctx>) {
+        // negative indices are huge ones when compared unsigned
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "inbounds");
+
+        self.check(
+            in_bounds,
+            "index %d is out of bounds for an array of length %d",
+            &[index.into(), len.into()],
+            scope,
+        );
+    }
+
+    /// Stop the program unless `ok` holds, printing `message` as a `printf`
+    /// format for `args` along with where in the source it stopped. Code run
+    /// with the JIT returns to the host with the message and where instead.
+    fn check(
+        &self,
+        ok: IntValue<'ctx>,
+        message: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        scope: &Scope<'_, 'ctx>,
+    ) {
+        let at = scope.at;
+        let function = self.function();
+        let fail = self.context.append_basic_block(function, "fail");
+        let next = self.context.append_basic_block(function, "ok");
+
+        self.builder.build_conditional_branch(ok, next, fail);
+        self.builder.position_at_end(fail);
+
+        // WebAssembly traps on `unreachable`, which is all it can do without
+        // a host to print for it
+        if self.memory.is_some() {
+            let runtime = self.runtime();
+            let i32_type = self.context.i32_type();
+            let place = [scope.func.id, at.0, at.1]
+                .iter()
+                .map(|n| i32_type.const_int(*n as u64, false))
+                .collect::<Vec<_>>();
+
+            self.builder.build_store(
+                runtime.trap_at().as_pointer_value(),
+                i32_type.const_array(&place),
+            );
+
+            let bytes = self.context.i8_type().ptr_type(AddressSpace::Generic);
+            let buffer = runtime.trap_message().as_pointer_value().const_cast(bytes);
+            let capacity = runtime
+                .size_type()
+                .const_int(runtime::TRAP_CAPACITY as u64, false);
+            let mut printed: Vec<BasicMetadataValueEnum> = vec![
+                buffer.into(),
+                capacity.into(),
+                self.c_string(message).into(),
+            ];
+
+            printed.extend_from_slice(args);
+
+            self.builder
+                .build_call(runtime.libc("snprintf"), &printed, "");
+
+            let jump = self
+                .builder
+                .build_load(runtime.trap().as_pointer_value(), "jump");
+            let status = self.context.i32_type().const_int(1, false);
+
+            self.builder
+                .build_call(runtime.libc("_longjmp"), &[jump.into(), status.into()], "");
+        } else if self.target == Target::Native {
+            let runtime = self.runtime();
+            // functions of imported modules say which file they're in
+            let file = match scope.func.source.as_ref().and_then(|s| s.file.as_ref()) {
+                Some(file) => format!("{}:", file.display()).replace('%', "%%"),
+                None => String::new(),
+            };
+            let format = format!("error: {} at {}{}:{}\n", message, file, at.0 + 1, at.1 + 1);
+            let stderr = self.context.i32_type().const_int(2, false);
+            let mut printed: Vec<BasicMetadataValueEnum> =
+                vec![stderr.into(), self.c_string(&format).into()];
+
+            printed.extend_from_slice(args);
+
+            self.builder
+                .build_call(runtime.libc("dprintf"), &printed, "");
+            runtime.abort(&self.builder);
+        }
+
+        self.builder.build_unreachable();
+        self.builder.position_at_end(next);
+    }
+
+    /// Call a function that returns a value.
+    fn call(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        name: &str,
+    ) -> BasicValueEnum<'ctx> {
+        self.builder
+            .build_call(function, args, name)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    /// The runtime support library, for the module being built.
+    fn runtime(&self) -> Runtime<'_, 'ctx> {
+        Runtime::new(self.context, &self.module, self.target)
+    }
+
+    fn union(&self, ty: &NodeType) -> &TaggedUnion<'ctx> {
+        match ty {
+            NodeType::Enum(name) => &self.enums[name],
+            ty => panic!("{} is not an enum", ty),
+        }
+    }
+
+    /// A stack slot in the entry block of the function being built, so that
+    /// it's only allocated once however often the code using it runs.
+    fn entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let entry = self.function().get_first_basic_block().unwrap();
+        let builder = self.context.create_builder();
+
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+
+        builder.build_alloca(ty, name)
+    }
+
+    /// A pointer to the fields of the enum value in `slot`, as the variant
+    /// whose fields are laid out as `payload`.
+    fn payload(&self, slot: PointerValue<'ctx>, payload: &StructType<'ctx>) -> PointerValue<'ctx> {
+        let space = self.builder.build_struct_gep(slot, 1, "payload").unwrap();
+
+        self.builder
+            .build_bitcast(space, payload.ptr_type(AddressSpace::Generic), "variant")
+            .into_pointer_value()
+    }
+
+    /// The function currently being built.
+    fn function(&self) -> FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .expect("builder is not positioned in a function")
+    }
+
+    /// The block being built, unless it already ends in a terminator.
+    fn open_block(&self) -> Option<BasicBlock<'ctx>> {
+        self.builder
+            .get_insert_block()
+            .filter(|block| block.get_terminator().is_none())
+    }
+
+    /// Whether the block being built already ends in a terminator.
+    fn terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    fn set_location(&self, scope: &Scope<'_, 'ctx>, at: Pos) {
+        if let (Some(debug), Some(subprogram)) = (&self.debug, scope.subprogram) {
+            let location = debug.location(at, subprogram);
+
+            self.builder
+                .set_current_debug_location(self.context, location);
+        }
+    }
+
+    /// Describe a local's stack slot to the debugger, as the `arg_no`th
+    /// parameter if given.
+    fn declare(&self, scope: &Scope<'_, 'ctx>, local: usize, arg_no: Option<u32>) {
+        if let (Some(debug), Some(subprogram)) = (&self.debug, scope.subprogram) {
+            let block = self.builder.get_insert_block().unwrap();
+            // values the type checker keeps around aren't variables
+            if scope.func.locals[local].name.is_empty() {
+                return;
+            }
+
+            debug.declare(
+                scope.locals[local],
+                scope.func,
+                local,
+                arg_no,
+                scope.at,
+                subprogram,
+                block,
+            );
+        }
+    }
+
+    fn error(&self, at: Pos, msg: String) -> CompileError {
+        CompileErrorBuilder::new()
+            .from(ErrorCodes::CodegenError)
+            .at((at.0, at.1 + 1))
+            .note(&msg)
+            .build()
+    }
+}
+
+/// Whether C has a type for everything `func` takes and returns, see
+/// [`typeck::is_c_type`].
+fn takes_c_types(func: &Function) -> bool {
+    func.locals[..func.params]
+        .iter()
+        .map(|local| &local.ty)
+        .chain(Some(&func.ret).filter(|ret| **ret != NodeType::Void))
+        .all(typeck::is_c_type)
 }
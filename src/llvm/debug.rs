@@ -0,0 +1,329 @@
+use std::{fmt, path::Path};
+
+use inkwell::{
+    basic_block::BasicBlock,
+    context::Context,
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+        DILocation, DISubprogram, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
+    values::{FunctionValue, PointerValue},
+    AddressSpace,
+};
+
+use crate::{
+    hir::{Function, Local},
+    llvm::target::Target,
+    parser::ast::ast::{NodeType, Pos},
+};
+
+// DWARF base type encodings, see section 7.8 of the DWARF 4 spec
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+const DW_ATE_UTF: u32 = 0x10;
+
+/// DWARF debug information for a single compile unit (a Qlang source file
+/// and the modules it imports).
+pub struct DebugInfo<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    pointer_width: u64,
+}
+
+impl fmt::Debug for DebugInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DebugInfo")
+            .field("unit", &self.unit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub fn new(context: &'ctx Context, module: &Module<'ctx>, path: &Path, target: Target) -> Self {
+        let (filename, directory) = split(path);
+
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(4, false),
+        );
+
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            filename,
+            directory,
+            concat!("qlang ", env!("CARGO_PKG_VERSION")),
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        Self {
+            context,
+            builder,
+            unit,
+            pointer_width: target.pointer_width(),
+        }
+    }
+
+    /// The debug type for a Qlang type, `None` for `void` and for structs,
+    /// enums, arrays, `Vec`s and slices, which aren't described to debuggers
+    /// yet.
+    pub fn ty(&self, ty: &NodeType) -> Option<DIType<'ctx>> {
+        let (name, size, encoding) = match ty {
+            NodeType::Integer => ("int", 32, DW_ATE_SIGNED),
+            NodeType::Float => ("float", 32, DW_ATE_FLOAT),
+            NodeType::Bool => ("bool", 8, DW_ATE_BOOLEAN),
+            NodeType::Char => ("char", 32, DW_ATE_UTF),
+            NodeType::String => return Some(self.string()),
+            NodeType::Void
+            | NodeType::Fn { .. }
+            | NodeType::Struct(_)
+            | NodeType::Enum(_)
+            | NodeType::Array(..)
+            | NodeType::Vec(_)
+            | NodeType::Slice(_) => return None,
+        };
+
+        let basic = self
+            .builder
+            .create_basic_type(name, size, encoding, DIFlags::PUBLIC)
+            .unwrap();
+
+        Some(basic.as_type())
+    }
+
+    /// The debug type of a parameter or variable of type `ty`, which is an
+    /// opaque struct named after it if [`DebugInfo::ty`] can't describe it,
+    /// so that functions still have as many parameters as they take.
+    fn variable(&self, ty: &NodeType) -> DIType<'ctx> {
+        if let Some(described) = self.ty(ty) {
+            return described;
+        }
+
+        let name = ty.to_string();
+
+        self.builder
+            .create_struct_type(
+                self.unit.as_debug_info_scope(),
+                &name,
+                self.unit.get_file(),
+                0,
+                0,
+                0,
+                DIFlags::FWD_DECL,
+                None,
+                &[],
+                0,
+                None,
+                &name,
+            )
+            .as_type()
+    }
+
+    /// `{ u8* bytes, int len }`, how a string is laid out.
+    fn string(&self) -> DIType<'ctx> {
+        let scope = self.unit.as_debug_info_scope();
+        let file = self.unit.get_file();
+        let width = self.pointer_width;
+
+        let byte = self
+            .builder
+            .create_basic_type("u8", 8, DW_ATE_SIGNED_CHAR, DIFlags::PUBLIC)
+            .unwrap();
+        let bytes = self.builder.create_pointer_type(
+            "u8*",
+            byte.as_type(),
+            width,
+            width as u32,
+            AddressSpace::Generic,
+        );
+        let len = self.ty(&NodeType::Integer).unwrap();
+
+        let fields = [
+            ("bytes", bytes.as_type(), width, 0),
+            ("len", len, 32, width),
+        ]
+        .iter()
+        .map(|&(name, ty, size, offset)| {
+            self.builder
+                .create_member_type(
+                    scope,
+                    name,
+                    file,
+                    0,
+                    size,
+                    size as u32,
+                    offset,
+                    DIFlags::PUBLIC,
+                    ty,
+                )
+                .as_type()
+        })
+        .collect::<Vec<_>>();
+
+        // the length is padded out to the alignment of the pointer
+        self.builder
+            .create_struct_type(
+                scope,
+                "str",
+                file,
+                0,
+                width * 2,
+                width as u32,
+                DIFlags::PUBLIC,
+                None,
+                &fields,
+                0,
+                None,
+                "str",
+            )
+            .as_type()
+    }
+
+    /// The file `func` is in: the compile unit's, unless it's in an
+    /// imported module.
+    fn file(&self, func: &Function) -> DIFile<'ctx> {
+        match func.source.as_ref().and_then(|s| s.file.as_ref()) {
+            Some(path) => {
+                let (filename, directory) = split(path);
+
+                self.builder.create_file(filename, directory)
+            }
+            None => self.unit.get_file(),
+        }
+    }
+
+    /// Attach a `DISubprogram` describing `func` to its LLVM function.
+    pub fn function(&self, func: &Function, value: FunctionValue<'ctx>) -> DISubprogram<'ctx> {
+        let file = self.file(func);
+        let params = func.locals[..func.params]
+            .iter()
+            .map(|local| self.variable(&local.ty))
+            .collect::<Vec<_>>();
+
+        let subroutine =
+            self.builder
+                .create_subroutine_type(file, self.ty(&func.ret), &params, DIFlags::PUBLIC);
+
+        let line = line(func.at);
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            &func.name,
+            None,
+            file,
+            line,
+            subroutine,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false,
+        );
+
+        value.set_subprogram(subprogram);
+
+        subprogram
+    }
+
+    pub fn location(&self, at: Pos, scope: DISubprogram<'ctx>) -> DILocation<'ctx> {
+        self.builder.create_debug_location(
+            self.context,
+            line(at),
+            at.1 as u32 + 1,
+            scope.as_debug_info_scope(),
+            None,
+        )
+    }
+
+    /// Describe the stack slot of the `local`th local variable of `func`, or
+    /// of a parameter if `arg_no` (starting at 1) is given.
+    #[allow(clippy::too_many_arguments)]
+    pub fn declare(
+        &self,
+        storage: PointerValue<'ctx>,
+        func: &Function,
+        local: usize,
+        arg_no: Option<u32>,
+        at: Pos,
+        scope: DISubprogram<'ctx>,
+        block: BasicBlock<'ctx>,
+    ) {
+        let Local { name, ty } = &func.locals[local];
+        let ty = self.variable(ty);
+
+        let file = self.file(func);
+        let variable = match arg_no {
+            Some(arg_no) => self.builder.create_parameter_variable(
+                scope.as_debug_info_scope(),
+                name,
+                arg_no,
+                file,
+                line(at),
+                ty,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => self.builder.create_auto_variable(
+                scope.as_debug_info_scope(),
+                name,
+                file,
+                line(at),
+                ty,
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        };
+
+        self.builder.insert_declare_at_end(
+            storage,
+            Some(variable),
+            None,
+            self.location(at, scope),
+            block,
+        );
+    }
+
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
+
+/// The name of the file at `path` and the directory it's in.
+fn split(path: &Path) -> (&str, &str) {
+    let filename = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or("<unknown>");
+    let directory = match path.parent().and_then(|d| d.to_str()) {
+        Some("") | None => ".",
+        Some(dir) => dir,
+    };
+
+    (filename, directory)
+}
+
+#[inline(always)]
+fn line(at: Pos) -> u32 {
+    at.0 as u32 + 1
+}
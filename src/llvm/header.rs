@@ -0,0 +1,76 @@
+//! Generates the C header declaring the functions a library exports with
+//! `#[no_mangle]`, for `qlang build --crate-type`.
+
+use std::fmt::Write;
+
+use crate::{hir::Program, parser::ast::ast::NodeType};
+
+/// A header declaring every function `program` exports, whose include
+/// guard is made from `name`.
+pub fn render(program: &Program, name: &str) -> String {
+    let guard = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect::<String>();
+
+    let mut out = String::new();
+
+    writeln!(out, "/* Generated by qlang, do not edit. */").unwrap();
+    writeln!(out, "#ifndef {}_H", guard).unwrap();
+    writeln!(out, "#define {}_H\n", guard).unwrap();
+    out.push_str("#include <stdbool.h>\n");
+    out.push_str("#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    out.push_str(
+        "/* Strings are NUL-terminated UTF-8. The library keeps using the ones\n \
+         * it's given, so they have to stay alive, and never frees the ones it\n \
+         * returns. A `uint32_t` standing for a `char` is a Unicode code point. */\n\n",
+    );
+
+    for func in &program.functions {
+        let symbol = match &func.export {
+            Some(symbol) => symbol,
+            None => continue,
+        };
+
+        let params = func.locals[..func.params]
+            .iter()
+            .map(|local| declaration(&local.ty, &local.name))
+            .collect::<Vec<_>>();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+
+        writeln!(
+            out,
+            "{};",
+            declaration(&func.ret, &format!("{}({})", symbol, params))
+        )
+        .unwrap();
+    }
+
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n");
+    writeln!(out, "#endif /* {}_H */", guard).unwrap();
+    out
+}
+
+/// `name` declared as having the C type values of type `ty` are passed as,
+/// which is how [`Codegen`](super::codegen::Codegen) passes them.
+fn declaration(ty: &NodeType, name: &str) -> String {
+    let ty = match ty {
+        NodeType::Integer => "int32_t",
+        NodeType::Float => "float",
+        NodeType::Bool => "bool",
+        NodeType::Char => "uint32_t",
+        NodeType::Void => "void",
+        NodeType::String => return format!("const char *{}", name),
+        ty => unreachable!("values of type {} can't be exported", ty),
+    };
+
+    format!("{} {}", ty, name)
+}
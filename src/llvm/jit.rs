@@ -0,0 +1,328 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    io::{self, Write},
+    slice,
+};
+
+use inkwell::{execution_engine::ExecutionEngine, module::Module, OptimizationLevel};
+
+use crate::{
+    hir::{FnId, Literal},
+    interp::{self, HostFn, Value},
+    llvm::runtime,
+    parser::ast::ast::{NodeType, Pos},
+};
+
+/// Storage for a value of any type generated code can pass to or get back
+/// from a host function, which is big enough for a string.
+type Slot = [u64; 2];
+
+/// A string as generated code sees it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Str {
+    bytes: *const u8,
+    len: i32,
+}
+
+impl Str {
+    /// A copy of `s` followed by a NUL, which is never freed.
+    fn leak(s: String) -> Self {
+        let len = s.len() as i32;
+        let bytes: Box<[u8]> = s.into_bytes().into_iter().chain(Some(0)).collect();
+
+        Self {
+            bytes: Box::leak(bytes).as_ptr(),
+            len,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The string has to have come from generated code, which never frees
+    /// one.
+    unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        slice::from_raw_parts(self.bytes, self.len as usize)
+    }
+}
+
+/// The functions of the host's that generated code calls: console I/O,
+/// which goes through the same buffer as the REPL's rather than the C
+/// library's, and the way into host functions.
+fn symbols() -> [(&'static str, usize); 4] {
+    [
+        (runtime::PRINT, print as usize),
+        (runtime::PRINTLN, println as usize),
+        (runtime::READ_LINE, read_line as usize),
+        (runtime::HOST_CALL, host_call as usize),
+    ]
+}
+
+extern "C" fn print(string: Str) {
+    let mut stdout = io::stdout();
+    let bytes = unsafe { string.as_bytes() };
+
+    stdout.write_all(bytes).and_then(|_| stdout.flush()).ok();
+}
+
+extern "C" fn println(string: Str) {
+    let mut stdout = io::stdout();
+    let bytes = unsafe { string.as_bytes() };
+
+    stdout
+        .write_all(bytes)
+        .and_then(|_| stdout.write_all(b"\n"))
+        .ok();
+}
+
+extern "C" fn read_line() -> Str {
+    Str::leak(interp::read_line())
+}
+
+/// Call the host function `index` of `memory` with the arguments in `args`,
+/// storing what it returns in `ret`.
+extern "C" fn host_call(memory: *const Memory, index: u32, args: *const Slot, ret: *mut Slot) {
+    let host = unsafe { &(*memory).hosts[index as usize] };
+    let args = host
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| unsafe { read(args.add(i), ty) })
+        .collect();
+
+    unsafe { write(ret, host.function.call(args)) };
+}
+
+/// The value of type `ty` in a slot.
+///
+/// # Safety
+///
+/// The slot has to have been written by generated code, as a `ty`.
+unsafe fn read(slot: *const Slot, ty: &NodeType) -> Value {
+    match ty {
+        NodeType::Integer => Value::Integer(slot.cast::<i32>().read()),
+        NodeType::Float => Value::Float(slot.cast::<f32>().read()),
+        NodeType::Bool => Value::Bool(slot.cast::<u8>().read() & 1 == 1),
+        NodeType::Char => Value::Char(
+            char::from_u32(slot.cast::<u32>().read()).unwrap_or(char::REPLACEMENT_CHARACTER),
+        ),
+        NodeType::String => {
+            let bytes = slot.cast::<Str>().read().as_bytes();
+
+            Value::String(String::from_utf8_lossy(bytes).into_owned())
+        }
+        _ => Value::Void,
+    }
+}
+
+/// Store a value where generated code can read it, leaking strings since
+/// generated code never frees them.
+///
+/// # Safety
+///
+/// The slot has to be valid for writes.
+unsafe fn write(slot: *mut Slot, value: Value) {
+    match value {
+        Value::Integer(i) => slot.cast::<i32>().write(i),
+        Value::Float(f) => slot.cast::<f32>().write(f),
+        Value::Bool(b) => slot.cast::<u8>().write(b as u8),
+        Value::Char(c) => slot.cast::<u32>().write(c as u32),
+        Value::String(s) => slot.cast::<Str>().write(Str::leak(s)),
+        _ => {}
+    }
+}
+
+/// A host function, along with the types of what it's given and returns.
+#[derive(Debug)]
+struct Host {
+    params: Vec<NodeType>,
+    function: HostFn,
+}
+
+/// Memory that code run with the JIT shares with the host. The JIT compiles
+/// a copy of the module that's thrown away once the module changes, so
+/// anything that has to outlive it, like the values of mutable globals,
+/// lives here instead.
+#[derive(Debug, Default)]
+pub struct Memory {
+    /// The storage of every mutable global, by name, which is big enough
+    /// for a string or any value that fits in a register.
+    globals: HashMap<String, Box<Cell<[u64; 2]>>>,
+    /// The bytes of every string literal followed by a NUL, so that a
+    /// global can still point at one after the code that assigned it is
+    /// gone.
+    strings: HashMap<String, Box<[u8]>>,
+    /// Every host function, indexed by what generated code calls it by.
+    hosts: Vec<Host>,
+}
+
+impl Memory {
+    /// Allocate storage for a global, starting out as `value`.
+    pub fn define(&mut self, name: &str, value: &Literal) {
+        let cell = Box::new(Cell::new([0; 2]));
+        let ptr = cell.as_ptr();
+
+        unsafe {
+            match value {
+                Literal::Integer(i) => ptr.cast::<i32>().write(*i),
+                Literal::Float(f) => ptr.cast::<f32>().write(*f),
+                Literal::Char(c) => ptr.cast::<u32>().write(*c as u32),
+                Literal::Bool(b) => ptr.cast::<bool>().write(*b),
+                Literal::String(s) => ptr.cast::<Str>().write(Str {
+                    bytes: self.string(s),
+                    len: s.len() as i32,
+                }),
+            }
+        }
+
+        self.globals.insert(name.to_string(), cell);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.globals.remove(name);
+    }
+
+    /// Add a host function taking arguments of types `params`, returning
+    /// the index that generated code passes to
+    /// [`qlang_host_call`](runtime::Runtime::host_call) to call it.
+    pub fn define_host(&mut self, params: Vec<NodeType>, function: HostFn) -> u32 {
+        self.hosts.push(Host { params, function });
+
+        self.hosts.len() as u32 - 1
+    }
+
+    /// A copy of the bytes of `s` followed by a NUL, which lives as long as
+    /// the memory does.
+    pub fn string(&mut self, s: &str) -> *const u8 {
+        self.strings
+            .entry(s.to_string())
+            .or_insert_with(|| s.bytes().chain(Some(0)).collect())
+            .as_ptr()
+    }
+}
+
+/// Why code run with the JIT didn't return a value.
+#[derive(Debug)]
+pub struct Trap {
+    pub message: String,
+    /// The ID of the function with the check that didn't hold and where in
+    /// it the check is, `None` if the code couldn't be run at all.
+    pub at: Option<(FnId, Pos)>,
+}
+
+impl From<String> for Trap {
+    fn from(message: String) -> Self {
+        Self { message, at: None }
+    }
+}
+
+/// Where a failed check jumps back to, the message it fails with and where
+/// the check is.
+#[derive(Debug)]
+struct Checks {
+    jump: Cell<usize>,
+    message: Cell<[u8; runtime::TRAP_CAPACITY]>,
+    at: Cell<[u32; 3]>,
+}
+
+/// A module compiled by the JIT, whose trampolines can be called for as long
+/// as it's kept around. It's a copy of the module it was made from, so it
+/// doesn't see anything added to that afterwards.
+#[derive(Debug)]
+pub struct Jit<'ctx> {
+    engine: ExecutionEngine<'ctx>,
+    /// Boxed so that the module's runtime globals can keep pointing at it.
+    checks: Box<Checks>,
+}
+
+impl<'ctx> Jit<'ctx> {
+    /// JIT-compile a copy of `module`. Globals declared in it without a
+    /// value are found in `memory`.
+    pub fn new(module: &Module<'ctx>, memory: &Memory) -> Result<Self, String> {
+        let module = module.clone();
+        let engine = module
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .map_err(|e| e.to_string())?;
+        let checks = Box::new(Checks {
+            jump: Cell::new(0),
+            message: Cell::new([0; runtime::TRAP_CAPACITY]),
+            at: Cell::new([0; 3]),
+        });
+
+        for (name, cell) in &memory.globals {
+            if let Some(global) = module.get_global(name) {
+                engine.add_global_mapping(&global, cell.as_ptr() as usize);
+            }
+        }
+
+        for (name, address) in [
+            (runtime::TRAP, checks.jump.as_ptr() as usize),
+            (runtime::TRAP_MESSAGE, checks.message.as_ptr() as usize),
+            (runtime::TRAP_AT, checks.at.as_ptr() as usize),
+        ] {
+            if let Some(global) = module.get_global(name) {
+                engine.add_global_mapping(&global, address);
+            }
+        }
+
+        for (name, address) in symbols() {
+            if let Some(function) = module.get_function(name) {
+                engine.add_global_mapping(&function, address);
+            }
+        }
+
+        Ok(Self { engine, checks })
+    }
+
+    /// Call the trampoline `name`, which reads its arguments from a slot
+    /// each and stores a value of type `ret` in the slot it's given, or
+    /// fails with a check that didn't hold.
+    pub fn call(&self, name: &str, args: Vec<Value>, ret: &NodeType) -> Result<Value, Trap> {
+        if !matches!(
+            ret,
+            NodeType::Integer
+                | NodeType::Float
+                | NodeType::Bool
+                | NodeType::Char
+                | NodeType::String
+                | NodeType::Void
+        ) {
+            return Err(format!("values of type {} can't be returned to the host", ret).into());
+        }
+
+        let mut args = args
+            .into_iter()
+            .map(|arg| {
+                let mut slot: Slot = [0; 2];
+
+                unsafe { write(&mut slot, arg) };
+                slot
+            })
+            .collect::<Vec<_>>();
+        let mut slot: Slot = [0; 2];
+        let failed = unsafe {
+            self.engine
+                .get_function::<unsafe extern "C" fn(*mut Slot, *mut Slot) -> i32>(name)
+                .map_err(|e| e.to_string())?
+                .call(args.as_mut_ptr(), &mut slot)
+        };
+
+        if failed != 0 {
+            let message = self.checks.message.get();
+            let len = message
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(message.len());
+
+            let [func, line, column] = self.checks.at.get();
+
+            return Err(Trap {
+                message: String::from_utf8_lossy(&message[..len]).into_owned(),
+                at: Some((func as FnId, (line as usize, column as usize))),
+            });
+        }
+
+        Ok(unsafe { read(&slot, ret) })
+    }
+}
@@ -1 +1,6 @@
 pub mod codegen;
+pub mod debug;
+pub mod header;
+pub mod jit;
+pub mod runtime;
+pub mod target;
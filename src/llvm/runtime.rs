@@ -0,0 +1,1067 @@
+//! The runtime support library, the functions generated code calls for
+//! what's too much to inline, like growing a `Vec` or concatenating
+//! strings.
+//!
+//! It's written in LLVM IR and added to whichever module needs it, so that
+//! it's compiled along with the program and works the same whether the
+//! program is run with the JIT, emitted as an object file or compiled to
+//! WebAssembly. Memory comes from the C library's `malloc`, `realloc` and
+//! `free`, numbers are written out and parsed with `snprintf`, `strtoll`
+//! and `strtof`, and strings returned by C are measured with `strlen`, all
+//! of which a WebAssembly host has to provide.
+//!
+//! Console I/O is the exception: code only declares the functions for it,
+//! which the JIT points at the host's own and which native executables are
+//! linked with from an object file of their own, see [`emit`]. So is calling
+//! host functions and returning to the host when a check fails, which only
+//! the JIT can do.
+
+use std::path::{Path, PathBuf};
+
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    builder::Builder,
+    context::Context,
+    module::{Linkage, Module},
+    types::{BasicType, BasicTypeEnum, FunctionType, IntType, PointerType, StructType},
+    values::{
+        BasicValue, BasicValueEnum, FunctionValue, GlobalValue, IntValue, PointerValue, StructValue,
+    },
+    AddressSpace, IntPredicate,
+};
+
+use crate::llvm::target::{self, Target};
+
+pub const PRINT: &str = "qlang_print";
+pub const PRINTLN: &str = "qlang_println";
+pub const READ_LINE: &str = "qlang_read_line";
+pub const HOST_CALL: &str = "qlang_host_call";
+pub const TRAP: &str = "qlang_trap";
+pub const TRAP_MESSAGE: &str = "qlang_trap_message";
+pub const TRAP_AT: &str = "qlang_trap_at";
+
+/// How many bytes of the message code run with the JIT fails with are
+/// kept, with the NUL at the end.
+pub const TRAP_CAPACITY: usize = 256;
+
+const VEC_NEW: &str = "qlang_vec_new";
+const VEC_PUSH: &str = "qlang_vec_push";
+const VEC_FREE: &str = "qlang_vec_free";
+const STR_CONCAT: &str = "qlang_str_concat";
+const STR_COMPARE: &str = "qlang_str_compare";
+const STR_BOUNDARY: &str = "qlang_str_boundary";
+const INT_TO_STR: &str = "qlang_int_to_str";
+const FLOAT_TO_STR: &str = "qlang_float_to_str";
+const CHAR_TO_STR: &str = "qlang_char_to_str";
+const STR_TO_INT: &str = "qlang_str_to_int";
+const STR_TO_FLOAT: &str = "qlang_str_to_float";
+const STR_TO_C: &str = "qlang_str_to_c";
+const STR_FROM_C: &str = "qlang_str_from_c";
+
+/// How many elements a `Vec` makes room for the first time it grows.
+const INITIAL_CAPACITY: u64 = 4;
+
+/// Enough bytes for any int or float written out by `snprintf`, with the
+/// NUL at the end.
+const NUMBER_CAPACITY: u64 = 32;
+
+/// How many bytes of a line `read_line` makes room for at first.
+const LINE_CAPACITY: u64 = 64;
+
+/// Write an object file defining the console I/O functions, which native
+/// executables are linked with.
+pub fn emit(target: Target, out: &Path) -> Result<PathBuf, String> {
+    let context = Context::create();
+    let module = context.create_module("qlang_runtime");
+
+    Runtime::new(&context, &module, target).define_io();
+    target::emit(&module, target, out)
+}
+
+pub struct Runtime<'a, 'ctx> {
+    context: &'ctx Context,
+    module: &'a Module<'ctx>,
+    target: Target,
+}
+
+impl<'a, 'ctx> Runtime<'a, 'ctx> {
+    pub fn new(context: &'ctx Context, module: &'a Module<'ctx>, target: Target) -> Self {
+        Self {
+            context,
+            module,
+            target,
+        }
+    }
+
+    /// `{ i8* elements, i32 len, i32 capacity }`, which a `Vec` points to.
+    /// They're kept on the heap so that every copy of the `Vec` sees the
+    /// same ones.
+    pub fn vec_type(&self) -> StructType<'ctx> {
+        let i32_type = self.context.i32_type();
+
+        self.context.struct_type(
+            &[self.bytes().into(), i32_type.into(), i32_type.into()],
+            false,
+        )
+    }
+
+    /// `{ i8* elements, i32 len }`, a slice.
+    pub fn slice_type(&self) -> StructType<'ctx> {
+        let i32_type = self.context.i32_type();
+
+        self.context
+            .struct_type(&[self.bytes().into(), i32_type.into()], false)
+    }
+
+    /// `{ i8* bytes, i32 len }`, a string of UTF-8 bytes. Literals and the
+    /// strings the runtime makes are followed by a NUL that isn't counted
+    /// in `len`, so C can read them too, but slices of them aren't.
+    pub fn str_type(&self) -> StructType<'ctx> {
+        self.slice_type()
+    }
+
+    /// `size_t`, which is as wide as a pointer.
+    pub fn size_type(&self) -> IntType<'ctx> {
+        self.context
+            .custom_width_int_type(self.target.pointer_width() as u32)
+    }
+
+    /// `qlang_vec_new() -> vec*`, which allocates an empty `Vec`.
+    pub fn vec_new(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(VEC_NEW) {
+            return function;
+        }
+
+        let vec_ptr = self.vec_type().ptr_type(AddressSpace::Generic);
+        let function = self.define(VEC_NEW, vec_ptr.fn_type(&[], false));
+        let builder = self.entry(function);
+
+        let size = self.vec_type().size_of().unwrap();
+        let size = builder.build_int_cast(size, self.size_type(), "size");
+        let memory = self.allocate(&builder, function, None, size);
+        let vec = builder.build_pointer_cast(memory, vec_ptr, "vec");
+
+        builder.build_store(vec, self.vec_type().const_zero());
+        builder.build_return(Some(&vec));
+
+        function
+    }
+
+    /// `qlang_vec_push(vec*, size_t size) -> i8*`, which makes room at the
+    /// end of a `Vec` for an element of `size` bytes and returns where it
+    /// goes. The capacity doubles whenever it runs out.
+    pub fn vec_push(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(VEC_PUSH) {
+            return function;
+        }
+
+        let vec_ptr = self.vec_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.size_type();
+        let ty = self
+            .bytes()
+            .fn_type(&[vec_ptr.into(), size_type.into()], false);
+        let function = self.define(VEC_PUSH, ty);
+        let builder = self.entry(function);
+
+        let vec = function.get_nth_param(0).unwrap().into_pointer_value();
+        let size = function.get_nth_param(1).unwrap().into_int_value();
+
+        let elements = builder.build_struct_gep(vec, 0, "elements").unwrap();
+        let len_ptr = builder.build_struct_gep(vec, 1, "len").unwrap();
+        let capacity_ptr = builder.build_struct_gep(vec, 2, "capacity").unwrap();
+        let len = builder.build_load(len_ptr, "len").into_int_value();
+        let capacity = builder
+            .build_load(capacity_ptr, "capacity")
+            .into_int_value();
+
+        let grow = self.context.append_basic_block(function, "grow");
+        let push = self.context.append_basic_block(function, "push");
+        let full = builder.build_int_compare(IntPredicate::EQ, len, capacity, "full");
+
+        builder.build_conditional_branch(full, grow, push);
+        builder.position_at_end(grow);
+
+        let i32_type = self.context.i32_type();
+        let empty =
+            builder.build_int_compare(IntPredicate::EQ, capacity, i32_type.const_zero(), "empty");
+        let doubled = builder.build_int_mul(capacity, i32_type.const_int(2, false), "doubled");
+        let capacity = builder
+            .build_select(
+                empty,
+                i32_type.const_int(INITIAL_CAPACITY, false),
+                doubled,
+                "capacity",
+            )
+            .into_int_value();
+        let count = builder.build_int_z_extend(capacity, size_type, "count");
+        let bytes = builder.build_int_mul(count, size, "bytes");
+        let old = builder.build_load(elements, "old").into_pointer_value();
+        let new = self.allocate(&builder, function, Some(old), bytes);
+
+        builder.build_store(elements, new);
+        builder.build_store(capacity_ptr, capacity);
+        builder.build_unconditional_branch(push);
+        builder.position_at_end(push);
+
+        let start = builder
+            .build_load(elements, "elements")
+            .into_pointer_value();
+        let index = builder.build_int_z_extend(len, size_type, "index");
+        let offset = builder.build_int_mul(index, size, "offset");
+        let slot = unsafe { builder.build_in_bounds_gep(start, &[offset], "slot") };
+        let len = builder.build_int_add(len, i32_type.const_int(1, false), "len");
+
+        builder.build_store(len_ptr, len);
+        builder.build_return(Some(&slot));
+
+        function
+    }
+
+    /// `qlang_vec_free(vec*)`, which gives back the memory of a `Vec` and
+    /// its elements.
+    pub fn vec_free(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(VEC_FREE) {
+            return function;
+        }
+
+        let vec_ptr = self.vec_type().ptr_type(AddressSpace::Generic);
+        let ty = self.context.void_type().fn_type(&[vec_ptr.into()], false);
+        let function = self.define(VEC_FREE, ty);
+        let builder = self.entry(function);
+
+        let vec = function.get_nth_param(0).unwrap().into_pointer_value();
+        let elements = builder.build_struct_gep(vec, 0, "elements").unwrap();
+        let elements = builder.build_load(elements, "elements");
+        let memory = builder.build_pointer_cast(vec, self.bytes(), "memory");
+
+        // `free` ignores the null pointer of a `Vec` that never grew
+        builder.build_call(self.libc("free"), &[elements.into()], "");
+        builder.build_call(self.libc("free"), &[memory.into()], "");
+        builder.build_return(None);
+
+        function
+    }
+
+    /// `qlang_str_concat(str, str) -> str`, which copies two strings one
+    /// after the other into a new one. Its memory is never given back.
+    pub fn str_concat(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(STR_CONCAT) {
+            return function;
+        }
+
+        let str_type = self.str_type();
+        let ty = str_type.fn_type(&[str_type.into(), str_type.into()], false);
+        let function = self.define(STR_CONCAT, ty);
+        let builder = self.entry(function);
+
+        let (left, left_len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+        let (right, right_len) = self.parts(&builder, function.get_nth_param(1).unwrap());
+        let len = builder.build_int_add(left_len, right_len, "len");
+        let size = self.size(&builder, len);
+        let size = builder.build_int_add(size, self.size_type().const_int(1, false), "size");
+        let bytes = self.allocate(&builder, function, None, size);
+        let rest = unsafe { builder.build_in_bounds_gep(bytes, &[left_len], "rest") };
+        let end = unsafe { builder.build_in_bounds_gep(bytes, &[len], "end") };
+
+        builder
+            .build_memcpy(bytes, 1, left, 1, self.size(&builder, left_len))
+            .unwrap();
+        builder
+            .build_memcpy(rest, 1, right, 1, self.size(&builder, right_len))
+            .unwrap();
+        builder.build_store(end, self.context.i8_type().const_zero());
+        builder.build_return(Some(&self.string(&builder, bytes, len)));
+
+        function
+    }
+
+    /// `qlang_str_compare(str, str) -> i32`, which is negative if the first
+    /// string sorts before the second, zero if they're equal and positive
+    /// otherwise. Comparing UTF-8 byte by byte puts characters in order.
+    pub fn str_compare(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(STR_COMPARE) {
+            return function;
+        }
+
+        let str_type = self.str_type();
+        let ty = self
+            .context
+            .i32_type()
+            .fn_type(&[str_type.into(), str_type.into()], false);
+        let function = self.define(STR_COMPARE, ty);
+        let builder = self.entry(function);
+
+        let (left, left_len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+        let (right, right_len) = self.parts(&builder, function.get_nth_param(1).unwrap());
+        let shorter = builder.build_int_compare(IntPredicate::ULT, left_len, right_len, "shorter");
+        let common = builder
+            .build_select(shorter, left_len, right_len, "common")
+            .into_int_value();
+        let size = self.size(&builder, common);
+        let order = builder
+            .build_call(
+                self.libc("memcmp"),
+                &[left.into(), right.into(), size.into()],
+                "order",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+
+        // when one starts with the other, the shorter one goes first
+        let same = builder.build_int_compare(
+            IntPredicate::EQ,
+            order,
+            self.context.i32_type().const_zero(),
+            "same",
+        );
+        let by_len = builder.build_int_sub(left_len, right_len, "bylen");
+        let order = builder.build_select(same, by_len, order, "order");
+
+        builder.build_return(Some(&order));
+
+        function
+    }
+
+    /// `qlang_str_boundary(str, i32 index) -> i1`, whether `index` is where
+    /// a character of the string starts or where the string ends, which a
+    /// slice has to start and end at to be valid UTF-8.
+    pub fn str_boundary(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(STR_BOUNDARY) {
+            return function;
+        }
+
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let ty = self
+            .context
+            .bool_type()
+            .fn_type(&[self.str_type().into(), i32_type.into()], false);
+        let function = self.define(STR_BOUNDARY, ty);
+        let builder = self.entry(function);
+        let entry = builder.get_insert_block().unwrap();
+
+        let (bytes, len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+        let index = function.get_nth_param(1).unwrap().into_int_value();
+        let at_end = builder.build_int_compare(IntPredicate::EQ, index, len, "atend");
+        let inside = builder.build_int_compare(IntPredicate::ULT, index, len, "inside");
+
+        let check = self.context.append_basic_block(function, "check");
+        let done = self.context.append_basic_block(function, "done");
+
+        builder.build_conditional_branch(inside, check, done);
+        builder.position_at_end(check);
+
+        // every byte of a character but the first looks like 0b10xxxxxx
+        let byte = unsafe { builder.build_in_bounds_gep(bytes, &[index], "byte") };
+        let byte = builder.build_load(byte, "byte").into_int_value();
+        let top = builder.build_and(byte, i8_type.const_int(0xc0, false), "top");
+        let starts = builder.build_int_compare(
+            IntPredicate::NE,
+            top,
+            i8_type.const_int(0x80, false),
+            "starts",
+        );
+
+        builder.build_unconditional_branch(done);
+        builder.position_at_end(done);
+
+        let boundary = builder.build_phi(self.context.bool_type(), "boundary");
+        boundary.add_incoming(&[
+            (&at_end as &dyn BasicValue, entry),
+            (&starts as &dyn BasicValue, check),
+        ]);
+        builder.build_return(Some(&boundary.as_basic_value()));
+
+        function
+    }
+
+    /// `qlang_int_to_str(i32) -> str`, an int written out in decimal.
+    pub fn int_to_str(&self) -> FunctionValue<'ctx> {
+        self.format(INT_TO_STR, self.context.i32_type().into(), "%d")
+    }
+
+    /// `qlang_float_to_str(float) -> str`, a float written out with
+    /// `printf`'s `%g`.
+    pub fn float_to_str(&self) -> FunctionValue<'ctx> {
+        self.format(FLOAT_TO_STR, self.context.f32_type().into(), "%g")
+    }
+
+    /// `qlang_char_to_str(i32) -> str`, a char encoded as UTF-8.
+    pub fn char_to_str(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(CHAR_TO_STR) {
+            return function;
+        }
+
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let ty = self.str_type().fn_type(&[i32_type.into()], false);
+        let function = self.define(CHAR_TO_STR, ty);
+        let builder = self.entry(function);
+        let c = function.get_nth_param(0).unwrap().into_int_value();
+        let int = |i: u64| i32_type.const_int(i, false);
+
+        // a char takes one more byte past each of these
+        let mut len = int(4);
+
+        for (i, limit) in [0x80, 0x800, 0x10000].iter().enumerate().rev() {
+            let fits = builder.build_int_compare(IntPredicate::ULT, c, int(*limit), "fits");
+
+            len = builder
+                .build_select(fits, int(i as u64 + 1), len, "len")
+                .into_int_value();
+        }
+
+        let size = self.size_type().const_int(5, false);
+        let bytes = self.allocate(&builder, function, None, size);
+
+        // the first byte says how many there are in its top bits, and every
+        // other one holds the next 6 bits below `0b10`
+        for i in 0..4 {
+            let index = int(i);
+            let used = builder.build_int_compare(IntPredicate::ULT, index, len, "used");
+            let after = builder.build_int_sub(len, int(i + 1), "after");
+            let shift = builder.build_int_mul(after, int(6), "shift");
+            let shift = builder
+                .build_select(used, shift, int(0), "shift")
+                .into_int_value();
+            let bits = builder.build_right_shift(c, shift, false, "bits");
+            let byte = if i == 0 {
+                let single = builder.build_int_compare(IntPredicate::EQ, len, int(1), "single");
+                let unused = builder.build_int_sub(int(4), len, "unused");
+                let marker = builder.build_left_shift(int(0xf0), unused, "marker");
+                let marker = builder.build_and(marker, int(0xf0), "marker");
+                let marker = builder
+                    .build_select(single, int(0), marker, "marker")
+                    .into_int_value();
+
+                builder.build_or(bits, marker, "lead")
+            } else {
+                let bits = builder.build_and(bits, int(0x3f), "bits");
+
+                builder.build_or(bits, int(0x80), "continuation")
+            };
+
+            let byte = builder.build_int_truncate(byte, i8_type, "byte");
+            let slot = unsafe { builder.build_in_bounds_gep(bytes, &[index], "slot") };
+
+            builder.build_store(slot, byte);
+        }
+
+        let end = unsafe { builder.build_in_bounds_gep(bytes, &[len], "end") };
+
+        builder.build_store(end, i8_type.const_zero());
+        builder.build_return(Some(&self.string(&builder, bytes, len)));
+
+        function
+    }
+
+    /// `qlang_print(str)`, which writes a string to standard output.
+    pub fn print(&self) -> FunctionValue<'ctx> {
+        let ty = self
+            .context
+            .void_type()
+            .fn_type(&[self.str_type().into()], false);
+
+        self.declare(PRINT, ty)
+    }
+
+    /// `qlang_println(str)`, which writes a string and a newline to
+    /// standard output.
+    pub fn println(&self) -> FunctionValue<'ctx> {
+        let ty = self
+            .context
+            .void_type()
+            .fn_type(&[self.str_type().into()], false);
+
+        self.declare(PRINTLN, ty)
+    }
+
+    /// `qlang_read_line() -> str`, the next line of standard input without
+    /// its line ending, or an empty string at the end of the input.
+    pub fn read_line(&self) -> FunctionValue<'ctx> {
+        self.declare(READ_LINE, self.str_type().fn_type(&[], false))
+    }
+
+    /// `qlang_host_call(i8* memory, i32 index, [2 x i64]* args, [2 x i64]* ret)`,
+    /// which calls a host function with the arguments stored in `args` and
+    /// stores what it returns in `ret`, see [`jit::Memory::define_host`].
+    ///
+    /// [`jit::Memory::define_host`]: crate::llvm::jit::Memory::define_host
+    pub fn host_call(&self) -> FunctionValue<'ctx> {
+        let slot = self
+            .context
+            .i64_type()
+            .array_type(2)
+            .ptr_type(AddressSpace::Generic);
+        let ty = self.context.void_type().fn_type(
+            &[
+                self.bytes().into(),
+                self.context.i32_type().into(),
+                slot.into(),
+                slot.into(),
+            ],
+            false,
+        );
+
+        self.declare(HOST_CALL, ty)
+    }
+
+    /// `qlang_trap`, a pointer to the `jmp_buf` that code run with the JIT
+    /// jumps back to when it fails, instead of stopping the host. The JIT
+    /// points it at memory of the host's.
+    pub fn trap(&self) -> GlobalValue<'ctx> {
+        self.module
+            .get_global(TRAP)
+            .unwrap_or_else(|| self.module.add_global(self.bytes(), None, TRAP))
+    }
+
+    /// `qlang_trap_message`, the [`TRAP_CAPACITY`] bytes that the message
+    /// code run with the JIT fails with is written to, which the JIT points
+    /// at memory of the host's too.
+    pub fn trap_message(&self) -> GlobalValue<'ctx> {
+        let ty = self.context.i8_type().array_type(TRAP_CAPACITY as u32);
+
+        self.module
+            .get_global(TRAP_MESSAGE)
+            .unwrap_or_else(|| self.module.add_global(ty, None, TRAP_MESSAGE))
+    }
+
+    /// `qlang_trap_at`, the three `i32`s that the ID of the function code
+    /// run with the JIT fails in and the line and column it fails at are
+    /// written to, which the JIT points at memory of the host's as well.
+    pub fn trap_at(&self) -> GlobalValue<'ctx> {
+        let ty = self.context.i32_type().array_type(3);
+
+        self.module
+            .get_global(TRAP_AT)
+            .unwrap_or_else(|| self.module.add_global(ty, None, TRAP_AT))
+    }
+
+    /// Define the console I/O functions with the C library's `printf` and
+    /// `getchar`. Unlike the host's, they pass on whatever bytes they're
+    /// given without checking that they're UTF-8.
+    pub fn define_io(&self) {
+        self.define_print(self.print(), "%.*s");
+        self.define_print(self.println(), "%.*s\n");
+        self.define_read_line();
+    }
+
+    fn define_print(&self, function: FunctionValue<'ctx>, format: &str) {
+        let builder = self.entry(function);
+        let (bytes, len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+        let format = builder.build_global_string_ptr(format, "format");
+
+        builder.build_call(
+            self.libc("printf"),
+            &[format.as_pointer_value().into(), len.into(), bytes.into()],
+            "",
+        );
+        builder.build_return(None);
+    }
+
+    fn define_read_line(&self) {
+        let function = self.read_line();
+        let builder = self.entry(function);
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let int = |i: u64| i32_type.const_int(i, false);
+
+        let size = self.size_type().const_int(LINE_CAPACITY + 1, false);
+        let first = self.allocate(&builder, function, None, size);
+        let bytes_ptr = builder.build_alloca(self.bytes(), "bytes");
+        let len_ptr = builder.build_alloca(i32_type, "len");
+        let capacity_ptr = builder.build_alloca(i32_type, "capacity");
+
+        builder.build_store(bytes_ptr, first);
+        builder.build_store(len_ptr, int(0));
+        builder.build_store(capacity_ptr, int(LINE_CAPACITY));
+
+        let read = self.context.append_basic_block(function, "read");
+        let append = self.context.append_basic_block(function, "append");
+        let grow = self.context.append_basic_block(function, "grow");
+        let store = self.context.append_basic_block(function, "store");
+        let done = self.context.append_basic_block(function, "done");
+
+        builder.build_unconditional_branch(read);
+        builder.position_at_end(read);
+
+        let c = builder
+            .build_call(self.libc("getchar"), &[], "c")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        // `EOF` is -1
+        let eof = builder.build_int_compare(IntPredicate::EQ, c, i32_type.const_all_ones(), "eof");
+        let newline = builder.build_int_compare(IntPredicate::EQ, c, int(b'\n' as u64), "newline");
+        let ended = builder.build_or(eof, newline, "ended");
+
+        builder.build_conditional_branch(ended, done, append);
+        builder.position_at_end(append);
+
+        let len = builder.build_load(len_ptr, "len").into_int_value();
+        let capacity = builder
+            .build_load(capacity_ptr, "capacity")
+            .into_int_value();
+        let full = builder.build_int_compare(IntPredicate::EQ, len, capacity, "full");
+
+        builder.build_conditional_branch(full, grow, store);
+        builder.position_at_end(grow);
+
+        // with room for the NUL at the end
+        let capacity = builder.build_int_mul(capacity, int(2), "capacity");
+        let size = self.size(&builder, capacity);
+        let size = builder.build_int_add(size, self.size_type().const_int(1, false), "size");
+        let old = builder.build_load(bytes_ptr, "old").into_pointer_value();
+        let new = self.allocate(&builder, function, Some(old), size);
+
+        builder.build_store(bytes_ptr, new);
+        builder.build_store(capacity_ptr, capacity);
+        builder.build_unconditional_branch(store);
+        builder.position_at_end(store);
+
+        let bytes = builder.build_load(bytes_ptr, "bytes").into_pointer_value();
+        let slot = unsafe { builder.build_in_bounds_gep(bytes, &[len], "slot") };
+
+        builder.build_store(slot, builder.build_int_truncate(c, i8_type, "byte"));
+        builder.build_store(len_ptr, builder.build_int_add(len, int(1), "len"));
+        builder.build_unconditional_branch(read);
+        builder.position_at_end(done);
+
+        // a line ending in "\r\n" loses both
+        let bytes = builder.build_load(bytes_ptr, "bytes").into_pointer_value();
+        let len = builder.build_load(len_ptr, "len").into_int_value();
+        let nonempty = builder.build_int_compare(IntPredicate::NE, len, int(0), "nonempty");
+        let last = builder.build_int_sub(len, int(1), "last");
+        let last = builder
+            .build_select(nonempty, last, int(0), "last")
+            .into_int_value();
+        let last_byte = unsafe { builder.build_in_bounds_gep(bytes, &[last], "lastbyte") };
+        let last_byte = builder.build_load(last_byte, "lastbyte").into_int_value();
+        let carriage_return = builder.build_int_compare(
+            IntPredicate::EQ,
+            last_byte,
+            i8_type.const_int(b'\r' as u64, false),
+            "cr",
+        );
+        let carriage_return = builder.build_and(nonempty, carriage_return, "cr");
+        let len = builder
+            .build_select(carriage_return, last, len, "len")
+            .into_int_value();
+        let end = unsafe { builder.build_in_bounds_gep(bytes, &[len], "end") };
+
+        builder.build_store(end, i8_type.const_zero());
+        builder.build_return(Some(&self.string(&builder, bytes, len)));
+    }
+
+    /// `qlang_str_to_int(str, i32*) -> i1`, which parses a string as an int
+    /// and returns whether it was one.
+    pub fn str_to_int(&self) -> FunctionValue<'ctx> {
+        self.parse(STR_TO_INT, self.context.i32_type().into())
+    }
+
+    /// `qlang_str_to_float(str, float*) -> i1`, which parses a string as a
+    /// float and returns whether it was one.
+    pub fn str_to_float(&self) -> FunctionValue<'ctx> {
+        self.parse(STR_TO_FLOAT, self.context.f32_type().into())
+    }
+
+    /// `qlang_str_to_c(str) -> i8*`, the bytes of a string followed by a
+    /// NUL, the way C expects them. Strings already end in one unless
+    /// they're a slice ending before the string it was taken from, which is
+    /// copied into memory that's never given back.
+    pub fn str_to_c(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(STR_TO_C) {
+            return function;
+        }
+
+        let ty = self.bytes().fn_type(&[self.str_type().into()], false);
+        let function = self.define(STR_TO_C, ty);
+        let builder = self.entry(function);
+        let i8_type = self.context.i8_type();
+
+        let (bytes, len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+
+        // the byte after a string is always there to read, since it's the
+        // NUL or a byte of the string it was sliced from
+        let end = unsafe { builder.build_in_bounds_gep(bytes, &[len], "end") };
+        let end = builder.build_load(end, "end").into_int_value();
+        let terminated =
+            builder.build_int_compare(IntPredicate::EQ, end, i8_type.const_zero(), "terminated");
+
+        let same = self.context.append_basic_block(function, "same");
+        let copy = self.context.append_basic_block(function, "copy");
+
+        builder.build_conditional_branch(terminated, same, copy);
+        builder.position_at_end(same);
+        builder.build_return(Some(&bytes));
+        builder.position_at_end(copy);
+
+        let size = self.size(&builder, len);
+        let with_nul = builder.build_int_add(size, self.size_type().const_int(1, false), "size");
+        let copied = self.allocate(&builder, function, None, with_nul);
+        let end = unsafe { builder.build_in_bounds_gep(copied, &[len], "end") };
+
+        builder.build_memcpy(copied, 1, bytes, 1, size).unwrap();
+        builder.build_store(end, i8_type.const_zero());
+        builder.build_return(Some(&copied));
+
+        function
+    }
+
+    /// `qlang_str_from_c(i8*) -> str`, a string of the bytes before the NUL
+    /// that a C string ends with, or an empty one for a null pointer. The
+    /// bytes aren't copied or checked to be UTF-8.
+    pub fn str_from_c(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(STR_FROM_C) {
+            return function;
+        }
+
+        let ty = self.str_type().fn_type(&[self.bytes().into()], false);
+        let function = self.define(STR_FROM_C, ty);
+        let builder = self.entry(function);
+        let i32_type = self.context.i32_type();
+
+        let bytes = function.get_nth_param(0).unwrap().into_pointer_value();
+        let null = builder.build_is_null(bytes, "null");
+
+        let empty = self.context.append_basic_block(function, "empty");
+        let measure = self.context.append_basic_block(function, "measure");
+
+        builder.build_conditional_branch(null, empty, measure);
+        builder.position_at_end(empty);
+
+        let nothing = builder.build_global_string_ptr("", "empty");
+
+        builder.build_return(Some(&self.string(
+            &builder,
+            nothing.as_pointer_value(),
+            i32_type.const_zero(),
+        )));
+        builder.position_at_end(measure);
+
+        let len = builder
+            .build_call(self.libc("strlen"), &[bytes.into()], "len")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let len = builder.build_int_truncate_or_bit_cast(len, i32_type, "len");
+
+        builder.build_return(Some(&self.string(&builder, bytes, len)));
+
+        function
+    }
+
+    /// Declare a function from the C library the first time it's used.
+    pub fn libc(&self, name: &str) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name) {
+            return function;
+        }
+
+        let i32_type = self.context.i32_type();
+        let bytes = self.bytes();
+        let size_type = self.size_type();
+        let ty = match name {
+            "dprintf" => i32_type.fn_type(&[i32_type.into(), bytes.into()], true),
+            "abort" => self.context.void_type().fn_type(&[], false),
+            // the jumps code run with the JIT makes when it fails, which
+            // unlike `setjmp` and `longjmp` leave the signal mask alone
+            "_setjmp" => i32_type.fn_type(&[bytes.into()], false),
+            "_longjmp" => self
+                .context
+                .void_type()
+                .fn_type(&[bytes.into(), i32_type.into()], false),
+            "malloc" => bytes.fn_type(&[size_type.into()], false),
+            "realloc" => bytes.fn_type(&[bytes.into(), size_type.into()], false),
+            "free" => self.context.void_type().fn_type(&[bytes.into()], false),
+            "printf" => i32_type.fn_type(&[bytes.into()], true),
+            "getchar" => i32_type.fn_type(&[], false),
+            // a `FILE*`, or null to flush every stream
+            "fflush" => i32_type.fn_type(&[bytes.into()], false),
+            "strlen" => size_type.fn_type(&[bytes.into()], false),
+            "memcmp" => i32_type.fn_type(&[bytes.into(), bytes.into(), size_type.into()], false),
+            "snprintf" => i32_type.fn_type(&[bytes.into(), size_type.into(), bytes.into()], true),
+            "strtoll" => {
+                let end = bytes.ptr_type(AddressSpace::Generic);
+
+                self.context
+                    .i64_type()
+                    .fn_type(&[bytes.into(), end.into(), i32_type.into()], false)
+            }
+            "strtof" => {
+                let end = bytes.ptr_type(AddressSpace::Generic);
+
+                self.context
+                    .f32_type()
+                    .fn_type(&[bytes.into(), end.into()], false)
+            }
+            name => unreachable!("`{}` isn't a libc function qlang uses", name),
+        };
+
+        let function = self.module.add_function(name, ty, Some(Linkage::External));
+
+        if name == "_setjmp" {
+            let returns_twice = Attribute::get_named_enum_kind_id("returns_twice");
+
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(returns_twice, 0),
+            );
+        }
+
+        function
+    }
+
+    /// `malloc` or, given memory to resize, `realloc` `size` bytes, stopping
+    /// the program if there isn't enough memory left.
+    fn allocate(
+        &self,
+        builder: &Builder<'ctx>,
+        function: FunctionValue<'ctx>,
+        old: Option<PointerValue<'ctx>>,
+        size: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let memory = match old {
+            Some(old) => {
+                builder.build_call(self.libc("realloc"), &[old.into(), size.into()], "new")
+            }
+            None => builder.build_call(self.libc("malloc"), &[size.into()], "new"),
+        }
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+        .into_pointer_value();
+
+        let failed = self.context.append_basic_block(function, "outofmemory");
+        let ok = self.context.append_basic_block(function, "allocated");
+        let null = builder.build_is_null(memory, "null");
+
+        builder.build_conditional_branch(null, failed, ok);
+        builder.position_at_end(failed);
+
+        if self.target == Target::Native {
+            self.abort(builder);
+        }
+
+        builder.build_unreachable();
+        builder.position_at_end(ok);
+
+        memory
+    }
+
+    /// A function writing out a value of type `ty` with the `printf`
+    /// format `format`, into a new string.
+    fn format(&self, name: &str, ty: BasicTypeEnum<'ctx>, format: &str) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name) {
+            return function;
+        }
+
+        let function = self.define(name, self.str_type().fn_type(&[ty.into()], false));
+        let builder = self.entry(function);
+
+        let capacity = self.size_type().const_int(NUMBER_CAPACITY, false);
+        let bytes = self.allocate(&builder, function, None, capacity);
+        let format = builder.build_global_string_ptr(format, "format");
+        let value = match function.get_nth_param(0).unwrap() {
+            // C passes floats to variadic functions as doubles
+            BasicValueEnum::FloatValue(f) => builder
+                .build_float_ext(f, self.context.f64_type(), "double")
+                .into(),
+            value => value,
+        };
+        let len = builder
+            .build_call(
+                self.libc("snprintf"),
+                &[
+                    bytes.into(),
+                    capacity.into(),
+                    format.as_pointer_value().into(),
+                    value.into(),
+                ],
+                "len",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+
+        builder.build_return(Some(&self.string(&builder, bytes, len)));
+
+        function
+    }
+
+    /// A function parsing a string as a value of type `ty`, an `i32` or a
+    /// `float`, which it stores through the pointer it's given. Unlike the
+    /// C functions doing the parsing, it doesn't allow leading whitespace
+    /// or anything after the number, or an int that doesn't fit.
+    fn parse(&self, name: &str, ty: BasicTypeEnum<'ctx>) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name) {
+            return function;
+        }
+
+        let out = ty.ptr_type(AddressSpace::Generic);
+        let fn_type = self
+            .context
+            .bool_type()
+            .fn_type(&[self.str_type().into(), out.into()], false);
+        let function = self.define(name, fn_type);
+        let builder = self.entry(function);
+
+        let i8_type = self.context.i8_type();
+        let size_type = self.size_type();
+        let (bytes, len) = self.parts(&builder, function.get_nth_param(0).unwrap());
+        let out = function.get_nth_param(1).unwrap().into_pointer_value();
+
+        // the C functions need a NUL to stop at, which a slice may not have
+        let size = self.size(&builder, len);
+        let with_nul = builder.build_int_add(size, size_type.const_int(1, false), "size");
+        let copy = self.allocate(&builder, function, None, with_nul);
+        let end = unsafe { builder.build_in_bounds_gep(copy, &[len], "end") };
+
+        builder.build_memcpy(copy, 1, bytes, 1, size).unwrap();
+        builder.build_store(end, i8_type.const_zero());
+
+        let parsed_end = builder.build_alloca(self.bytes(), "parsedend");
+        let parsed = match ty {
+            BasicTypeEnum::FloatType(_) => builder.build_call(
+                self.libc("strtof"),
+                &[copy.into(), parsed_end.into()],
+                "parsed",
+            ),
+            _ => builder.build_call(
+                self.libc("strtoll"),
+                &[
+                    copy.into(),
+                    parsed_end.into(),
+                    self.context.i32_type().const_int(10, false).into(),
+                ],
+                "parsed",
+            ),
+        }
+        .try_as_basic_value()
+        .left()
+        .unwrap();
+
+        let parsed_end = builder
+            .build_load(parsed_end, "parsedend")
+            .into_pointer_value();
+        let parsed_end = builder.build_ptr_to_int(parsed_end, size_type, "parsedend");
+        let end = builder.build_ptr_to_int(end, size_type, "end");
+        let whole = builder.build_int_compare(IntPredicate::EQ, parsed_end, end, "whole");
+
+        // whitespace is ' ' and '\t' to '\r'
+        let first = builder.build_load(copy, "first").into_int_value();
+        let space = builder.build_int_compare(
+            IntPredicate::EQ,
+            first,
+            i8_type.const_int(b' ' as u64, false),
+            "space",
+        );
+        let control =
+            builder.build_int_sub(first, i8_type.const_int(b'\t' as u64, false), "control");
+        let control = builder.build_int_compare(
+            IntPredicate::ULE,
+            control,
+            i8_type.const_int((b'\r' - b'\t') as u64, false),
+            "control",
+        );
+        let blank = builder.build_or(space, control, "blank");
+        let nonempty = builder.build_int_compare(
+            IntPredicate::NE,
+            len,
+            self.context.i32_type().const_zero(),
+            "nonempty",
+        );
+
+        let ok = builder.build_and(whole, nonempty, "ok");
+        let ok = builder.build_and(ok, builder.build_not(blank, "notblank"), "ok");
+        let (ok, value) = match parsed {
+            BasicValueEnum::IntValue(parsed) => {
+                let int = builder.build_int_truncate(parsed, ty.into_int_type(), "int");
+                let widened = builder.build_int_s_extend(int, parsed.get_type(), "widened");
+                let fits = builder.build_int_compare(IntPredicate::EQ, widened, parsed, "fits");
+
+                (builder.build_and(ok, fits, "ok"), int.into())
+            }
+            parsed => (ok, parsed),
+        };
+
+        builder.build_store(out, value);
+        builder.build_call(self.libc("free"), &[copy.into()], "");
+        builder.build_return(Some(&ok));
+
+        function
+    }
+
+    /// The bytes and the length of a string.
+    fn parts(
+        &self,
+        builder: &Builder<'ctx>,
+        string: BasicValueEnum<'ctx>,
+    ) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let string = string.into_struct_value();
+        let bytes = builder.build_extract_value(string, 0, "bytes").unwrap();
+        let len = builder.build_extract_value(string, 1, "len").unwrap();
+
+        (bytes.into_pointer_value(), len.into_int_value())
+    }
+
+    /// A string of the `len` bytes that `bytes` points to.
+    fn string(
+        &self,
+        builder: &Builder<'ctx>,
+        bytes: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> StructValue<'ctx> {
+        let string = self.str_type().get_undef();
+        let string = builder
+            .build_insert_value(string, bytes, 0, "string")
+            .unwrap()
+            .into_struct_value();
+
+        builder
+            .build_insert_value(string, len, 1, "string")
+            .unwrap()
+            .into_struct_value()
+    }
+
+    /// A length as a `size_t`, which is no narrower than it.
+    fn size(&self, builder: &Builder<'ctx>, len: IntValue<'ctx>) -> IntValue<'ctx> {
+        builder.build_int_z_extend_or_bit_cast(len, self.size_type(), "size")
+    }
+
+    /// Stop the program, after writing out what it printed so far.
+    pub fn abort(&self, builder: &Builder<'ctx>) {
+        builder.build_call(self.libc("fflush"), &[self.bytes().const_null().into()], "");
+        builder.build_call(self.libc("abort"), &[], "");
+    }
+
+    /// Declare a function that's defined outside the module.
+    fn declare(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, ty, Some(Linkage::External)))
+    }
+
+    fn define(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        // every module gets its own copy, so they don't clash when linked
+        self.module.add_function(name, ty, Some(Linkage::Internal))
+    }
+
+    /// A builder positioned in a new entry block of `function`.
+    fn entry(&self, function: FunctionValue<'ctx>) -> Builder<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = self.context.append_basic_block(function, "entry");
+
+        builder.position_at_end(entry);
+        builder
+    }
+
+    /// `i8*`, how the runtime sees memory whatever it holds.
+    fn bytes(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::Generic)
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use inkwell::{
+    module::Module,
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target as LLVMTarget, TargetMachine,
+        TargetTriple,
+    },
+    OptimizationLevel,
+};
+
+/// The platforms `Codegen` knows how to emit code for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Native,
+    Wasm32,
+}
+
+impl Target {
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        match triple {
+            "native" => Some(Target::Native),
+            "wasm32-unknown-unknown" | "wasm32" => Some(Target::Wasm32),
+            _ => None,
+        }
+    }
+
+    pub fn triple(&self) -> TargetTriple {
+        match self {
+            Target::Native => TargetMachine::get_default_triple(),
+            Target::Wasm32 => TargetTriple::create("wasm32-unknown-unknown"),
+        }
+    }
+
+    /// The file extension of the final artifact produced by [`emit`].
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Target::Native => "o",
+            Target::Wasm32 => "wasm",
+        }
+    }
+
+    pub fn pointer_width(&self) -> u64 {
+        match self {
+            Target::Native => usize::BITS as u64,
+            Target::Wasm32 => 32,
+        }
+    }
+
+    pub fn machine(&self) -> Result<TargetMachine, String> {
+        let config = InitializationConfig::default();
+
+        match self {
+            Target::Native => LLVMTarget::initialize_native(&config)?,
+            Target::Wasm32 => LLVMTarget::initialize_webassembly(&config),
+        }
+
+        let triple = self.triple();
+        let target = LLVMTarget::from_triple(&triple).map_err(|e| e.to_string())?;
+
+        let (cpu, features) = match self {
+            Target::Native => (
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            ),
+            Target::Wasm32 => ("generic".to_string(), String::new()),
+        };
+
+        target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                OptimizationLevel::Default,
+                RelocMode::PIC,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| format!("unable to create a target machine for {:?}", triple))
+    }
+}
+
+/// Write `module` to `out` for the given target, returning the path of the
+/// artifact that was actually produced.
+///
+/// WebAssembly modules are linked with `wasm-ld` when it's on the `PATH`;
+/// otherwise the relocatable object file is left next to `out` instead.
+pub fn emit(module: &Module, target: Target, out: &Path) -> Result<PathBuf, String> {
+    let machine = target.machine()?;
+
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    module.verify().map_err(|e| e.to_string())?;
+
+    let object = out.with_extension("o");
+
+    machine
+        .write_to_file(module, FileType::Object, &object)
+        .map_err(|e| e.to_string())?;
+
+    match target {
+        Target::Native => Ok(object),
+        Target::Wasm32 => match wasm_ld(&object, out) {
+            Ok(()) => {
+                fs::remove_file(&object).ok();
+                Ok(out.to_path_buf())
+            }
+            Err(e) => {
+                eprintln!("warning: {}, emitting an object file instead", e);
+                Ok(object)
+            }
+        },
+    }
+}
+
+/// Link object files into a native executable with the system's C compiler,
+/// which brings in the C library, along with the `libraries` they need.
+/// `$CC` is used instead of `cc` if it's set.
+pub fn link(objects: &[PathBuf], libraries: &[String], out: &Path) -> Result<(), String> {
+    cc(objects, libraries, None, out)
+}
+
+/// Link object files into a shared library, like [`link`] does an
+/// executable.
+pub fn link_shared(objects: &[PathBuf], libraries: &[String], out: &Path) -> Result<(), String> {
+    cc(objects, libraries, Some("-shared"), out)
+}
+
+/// Bundle object files into a static library with `ar`, or `$AR` if it's
+/// set. The libraries they need are left for whoever links it to add.
+pub fn archive(objects: &[PathBuf], out: &Path) -> Result<(), String> {
+    let ar = env::var("AR").unwrap_or_else(|_| "ar".to_string());
+
+    // `ar` adds to an archive that's already there rather than replacing it
+    fs::remove_file(out).ok();
+
+    let status = Command::new(&ar)
+        .arg("rcs")
+        .arg(out)
+        .args(objects)
+        .status()
+        .map_err(|_| format!("`{}` was not found", ar))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{}` exited with {}", ar, status))
+    }
+}
+
+fn cc(
+    objects: &[PathBuf],
+    libraries: &[String],
+    flag: Option<&str>,
+    out: &Path,
+) -> Result<(), String> {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .args(flag)
+        .args(objects)
+        .args(libraries.iter().map(|name| format!("-l{}", name)))
+        .arg("-o")
+        .arg(out)
+        .status()
+        .map_err(|_| format!("`{}` was not found", cc))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{}` exited with {}", cc, status))
+    }
+}
+
+fn wasm_ld(object: &Path, out: &Path) -> Result<(), String> {
+    let status = Command::new("wasm-ld")
+        .arg("--no-entry")
+        .arg("--allow-undefined")
+        .arg("-o")
+        .arg(out)
+        .arg(object)
+        .status()
+        .map_err(|_| "`wasm-ld` was not found".to_string())?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`wasm-ld` exited with {}", status))
+    }
+}
@@ -0,0 +1,196 @@
+//! Finds every file a program is made of by following its `use` items.
+//!
+//! `use foo::bar::baz;` imports `baz` from `foo/bar.q`, or `foo/bar/mod.q`,
+//! next to the file the `use` is written in.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::parser::{
+    ast::ast::{Import, Node},
+    error::{CompileErrorBuilder, ErrorCodes},
+    TopLevel,
+};
+
+/// Identifies a module as an index into the modules [`load`] returns.
+pub type ModuleId = usize;
+
+/// A parsed source file.
+#[derive(Debug)]
+pub struct Module {
+    /// The path to the module from the root's directory, like `foo::bar`
+    /// for `foo/bar.q`. It's empty for the root itself.
+    pub name: Vec<String>,
+    pub path: PathBuf,
+    pub source: String,
+    pub ast: TopLevel,
+    /// The module each of the file's imports refers to, in order.
+    pub imports: Vec<ModuleId>,
+}
+
+/// Load a file and every module it imports, directly or not. Each module
+/// comes after the ones it imports, so the file itself is last.
+pub fn load(path: &Path) -> Result<Vec<Module>, Box<dyn Error>> {
+    let mut loader = Loader {
+        root: canonical(path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        modules: Vec::new(),
+        ids: HashMap::new(),
+        stack: Vec::new(),
+    };
+
+    loader.visit(path)?;
+
+    Ok(loader.modules)
+}
+
+/// The `use` items of a file.
+pub fn imports(ast: &TopLevel) -> Vec<&Import> {
+    ast.imports
+        .iter()
+        .filter_map(|import| match import {
+            Node::Use(import) => Some(import.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Loader {
+    root: PathBuf,
+    modules: Vec<Module>,
+    ids: HashMap<PathBuf, ModuleId>,
+    /// The files being loaded, each imported by the one before it.
+    stack: Vec<PathBuf>,
+}
+
+impl Loader {
+    fn visit(&mut self, path: &Path) -> Result<ModuleId, Box<dyn Error>> {
+        let key = canonical(path);
+
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(*id);
+        }
+
+        let source = fs::read_to_string(path)?;
+        let ast = crate::parse_file(source.clone()).map_err(|mut e| {
+            e.in_file(path, source.clone());
+            e
+        })?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut ids = Vec::new();
+
+        self.stack.push(key.clone());
+
+        for import in imports(&ast) {
+            let error = |code: ErrorCodes, note: String| {
+                let mut e = CompileErrorBuilder::new()
+                    .from(code)
+                    .at((import.at.0, import.at.1 + 1))
+                    .note(&note)
+                    .build();
+
+                e.in_file(path, source.clone());
+                e
+            };
+
+            let file = resolve(dir, import).ok_or_else(|| {
+                let base = import.path.join("/");
+
+                error(
+                    ErrorCodes::UnresolvedImport,
+                    format!(
+                        "can't find `{}.q` or `{}/mod.q` for module `{}`",
+                        base,
+                        base,
+                        import.module()
+                    ),
+                )
+            })?;
+
+            if let Some(start) = self.stack.iter().position(|p| *p == canonical(&file)) {
+                let cycle = self.stack[start..]
+                    .iter()
+                    .chain(Some(&self.stack[start]))
+                    .map(|p| self.relative(p))
+                    .collect::<Vec<_>>();
+
+                return Err(Box::new(error(
+                    ErrorCodes::ImportCycle,
+                    format!("the imports form a cycle: {}", cycle.join(" -> ")),
+                )));
+            }
+
+            ids.push(self.visit(&file)?);
+        }
+
+        self.stack.pop();
+
+        // only the root isn't imported by anything
+        let name = if self.stack.is_empty() {
+            Vec::new()
+        } else {
+            self.name(&key)
+        };
+
+        self.modules.push(Module {
+            name,
+            path: path.to_path_buf(),
+            source,
+            ast,
+            imports: ids,
+        });
+        self.ids.insert(key, self.modules.len() - 1);
+
+        Ok(self.modules.len() - 1)
+    }
+
+    /// A module's path from the root's directory, without the extension or
+    /// a trailing `mod`.
+    fn name(&self, path: &Path) -> Vec<String> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut name = relative
+            .with_extension("")
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        if name.len() > 1 && name.last().map(String::as_str) == Some("mod") {
+            name.pop();
+        }
+
+        name
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+/// The file a `use` refers to, relative to the importing file's directory.
+fn resolve(dir: &Path, import: &Import) -> Option<PathBuf> {
+    let base = import
+        .path
+        .iter()
+        .fold(dir.to_path_buf(), |p, part| p.join(part));
+
+    [base.with_extension("q"), base.join("mod.q")]
+        .iter()
+        .find(|file| file.is_file())
+        .cloned()
+}
+
+/// Files are told apart by their canonical path, so that one imported in
+/// different ways is only loaded once.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -1,6 +1,27 @@
 use std::{
-    env,
+    env, fs,
+    path::{Path, PathBuf},
     process::{exit, Command},
+    thread,
+};
+
+use qlang::{
+    bytecode::{compiler::Compiler, format, vm::Vm, Program},
+    hir,
+    interp::{Interpreter, Value},
+    loader::{self, Module},
+    mir,
+    parser::{ast::ast::Node, error::CompileError},
+    typeck::TypeChecker,
+};
+#[cfg(feature = "llvm")]
+use qlang::{
+    inkwell::context::Context,
+    llvm::{
+        codegen::Codegen,
+        header, runtime,
+        target::{self, Target},
+    },
 };
 
 mod args;
@@ -9,7 +30,26 @@ use repl::repl;
 
 mod repl;
 
+/// The interpreter recurses once per Qlang call, so give it room to do so.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// An interpreter that may take up half of the CLI's stack.
+fn interpreter() -> Interpreter {
+    Interpreter::new().with_stack(STACK_SIZE / 2)
+}
+
 fn main() {
+    let cli = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(cli)
+        .unwrap();
+
+    if cli.join().is_err() {
+        exit(101);
+    }
+}
+
+fn cli() {
     header();
     let (flags, args, _) = args(false);
 
@@ -20,12 +60,13 @@ fn main() {
         version();
         exit(0);
     } else if args.is_empty() {
-        repl();
+        repl(flags.contains(&"interp".to_string()));
     } else {
-        match args.get(0).unwrap().as_str() {
+        match args[0].as_str() {
             "help" => help(true),
-            "run" => run(args),
-            "repl" => repl(),
+            "run" => run(args, flags),
+            "build" => build(args, flags),
+            "repl" => repl(flags.contains(&"interp".to_string())),
             cmd => {
                 eprintln!("Unknown subcommand: {}", cmd);
                 exit(1);
@@ -36,10 +77,370 @@ fn main() {
 
 // Projects
 
-fn run(args: Vec<String>) {
-    let file = args.get(1).unwrap();
+/// What `build` turns a program compiled with LLVM into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Artifact {
+    Object,
+    Executable,
+    /// A `.a` archive, with a header declaring what it exports.
+    StaticLib,
+    /// A shared library, with a header declaring what it exports.
+    DynamicLib,
+}
+
+/// What runs the code given to `run`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Jit,
+    Interp,
+    Vm,
+}
+
+fn run(args: Vec<String>, flags: Vec<String>) {
+    let file = args.get(1).unwrap_or_else(|| {
+        eprintln!("No input file given");
+        exit(1);
+    });
+
+    let bytes = fs::read(file).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", file, e);
+        exit(1);
+    });
+
+    let result = if format::is_bytecode(&bytes) {
+        let program = format::deserialise(&bytes).unwrap_or_else(|e| {
+            eprintln!("Invalid bytecode in {}: {}", file, e);
+            exit(1);
+        });
+
+        Vm::new(&program).call("main", Vec::new())
+    } else {
+        let modules = load(file);
+
+        let backend = if flags.contains(&"vm".to_string()) {
+            Backend::Vm
+        } else if flags.contains(&"interp".to_string()) || cfg!(not(feature = "llvm")) {
+            Backend::Interp
+        } else {
+            Backend::Jit
+        };
+
+        let mut checker = TypeChecker::new();
+        let program = check(&mut checker, &modules);
+
+        run_main(checker, program, backend)
+    };
+
+    match result {
+        // an `int` returned from `main` is the exit code
+        Ok(Value::Integer(code)) => exit(code),
+        Ok(_) => {}
+        Err(mut e) => {
+            // an error in an imported module says which file it's in
+            match e.file().map(PathBuf::from) {
+                Some(path) => {
+                    let raw = fs::read_to_string(&path).unwrap_or_default();
+
+                    e.in_file(&path, raw);
+                }
+                // there's no source to show for bytecode
+                None if format::is_bytecode(&bytes) => {}
+                None => {
+                    e.with_raw(fs::read_to_string(file).unwrap_or_default());
+                }
+            }
+
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Call the `main` function of a checked program with `backend`.
+fn run_main(
+    mut checker: TypeChecker,
+    program: hir::Program,
+    backend: Backend,
+) -> Result<Value, CompileError> {
+    if !checker.function_names().iter().any(|name| name == "main") {
+        eprintln!("No `main` function found");
+        exit(1);
+    }
+
+    let main = checker.check_body(&Node::Call {
+        name: "main".to_string(),
+        args: Vec::new(),
+    })?;
+
+    match backend {
+        Backend::Interp => {
+            let mut interpreter = interpreter();
+            interpreter.define(program)?;
+
+            interpreter.evaluate(&main)
+        }
+        Backend::Vm => {
+            let program = Compiler::new().compile(&program)?;
+
+            Vm::new(&program).call("main", Vec::new())
+        }
+        Backend::Jit => jit(program, main),
+    }
+}
+
+#[cfg(feature = "llvm")]
+fn jit(program: hir::Program, main: hir::Function) -> Result<Value, CompileError> {
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, context.create_module("main"));
+
+    codegen.compile(program)?;
+    codegen.evaluate(&main)
+}
+
+#[cfg(not(feature = "llvm"))]
+fn jit(_: hir::Program, _: hir::Function) -> Result<Value, CompileError> {
+    unreachable!("code is always interpreted without LLVM")
+}
+
+fn build(args: Vec<String>, flags: Vec<String>) {
+    let file = args.get(1).unwrap_or_else(|| {
+        eprintln!("No input file given");
+        exit(1);
+    });
+
+    match args::value(&flags, "cratetype") {
+        Some("staticlib") => return build_native(file, flags, Artifact::StaticLib),
+        Some("cdylib") => return build_native(file, flags, Artifact::DynamicLib),
+        Some(kind) => {
+            eprintln!("Unknown crate type: {}", kind);
+            exit(1);
+        }
+        None => {}
+    }
+
+    match args::value(&flags, "emit") {
+        None | Some("obj") => build_native(file, flags, Artifact::Object),
+        Some("exe") => build_native(file, flags, Artifact::Executable),
+        Some("bytecode") => {
+            let program = compile_bytecode(file);
+
+            match args::value(&flags, "out") {
+                Some(out) => write(Path::new(out), program.to_string().as_bytes()),
+                None => print!("{}", program),
+            }
+        }
+        Some("mir") => {
+            let program = check(&mut TypeChecker::new(), &load(file));
+            let bodies = program.functions.iter().map(mir::build).collect::<Vec<_>>();
+            let dot = mir::dot::render(&bodies, &program.externs, &program.globals);
+
+            match args::value(&flags, "out") {
+                Some(out) => write(Path::new(out), dot.as_bytes()),
+                None => print!("{}", dot),
+            }
+        }
+        Some("qbc") => {
+            let program = compile_bytecode(file);
+            let out = args::value(&flags, "out")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| Path::new(file).with_extension("qbc"));
+
+            write(&out, &format::serialise(&program));
+        }
+        Some(emit) => {
+            eprintln!("Unknown output kind: {}", emit);
+            exit(1);
+        }
+    }
+}
+
+/// Parse a file and every module it imports, exiting if any can't be.
+fn load(file: &str) -> Vec<Module> {
+    loader::load(Path::new(file)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    })
+}
+
+/// Type check a program, printing any warnings and exiting if it has errors.
+fn check(checker: &mut TypeChecker, modules: &[Module]) -> hir::Program {
+    let result = checker.check_modules(modules);
+    // errors in imported modules already know which file they're from
+    let raw = modules
+        .last()
+        .map(|root| root.source.clone())
+        .unwrap_or_default();
+
+    for mut warning in checker.warnings() {
+        eprintln!("{}", warning.with_raw(raw.clone()));
+    }
+
+    result.unwrap_or_else(|mut e| {
+        eprintln!("{}", e.with_raw(raw));
+        exit(1);
+    })
+}
+
+/// Compile a source file to bytecode, or load an already compiled one.
+fn compile_bytecode(file: &str) -> Program {
+    let bytes = fs::read(file).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", file, e);
+        exit(1);
+    });
+
+    if format::is_bytecode(&bytes) {
+        return format::deserialise(&bytes).unwrap_or_else(|e| {
+            eprintln!("Invalid bytecode in {}: {}", file, e);
+            exit(1);
+        });
+    }
+
+    let program = check(&mut TypeChecker::new(), &load(file));
+
+    Compiler::new().compile(&program).unwrap_or_else(|mut e| {
+        let raw = String::from_utf8_lossy(&bytes).into_owned();
+
+        eprintln!("{}", e.with_raw(raw));
+        exit(1);
+    })
+}
+
+fn write(out: &Path, bytes: &[u8]) {
+    match fs::write(out, bytes) {
+        Ok(_) => eprintln!("Wrote {}", out.display()),
+        Err(e) => {
+            eprintln!("Couldn't write {}: {}", out.display(), e);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn build_native(_: &str, _: Vec<String>, _: Artifact) {
+    eprintln!("qlang was compiled without the `llvm` feature, so it can only emit bytecode");
+    exit(1);
+}
+
+#[cfg(feature = "llvm")]
+fn build_native(file: &str, flags: Vec<String>, artifact: Artifact) {
+    let target = match args::value(&flags, "target") {
+        Some(triple) => Target::from_triple(triple).unwrap_or_else(|| {
+            eprintln!("Unknown target: {}", triple);
+            exit(1);
+        }),
+        None => Target::Native,
+    };
+
+    if artifact != Artifact::Object && target != Target::Native {
+        eprintln!("Executables and libraries can only be built for the native target");
+        exit(1);
+    }
+
+    let source = Path::new(file);
+    let stem = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let out = args::value(&flags, "out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| match artifact {
+            Artifact::Object => source.with_extension(target.extension()),
+            Artifact::Executable => source.with_extension(env::consts::EXE_EXTENSION),
+            Artifact::StaticLib => source.with_file_name(format!("lib{}.a", stem)),
+            Artifact::DynamicLib => source.with_file_name(format!(
+                "{}{}{}",
+                env::consts::DLL_PREFIX,
+                stem,
+                env::consts::DLL_SUFFIX
+            )),
+        });
+
+    let mut checker = TypeChecker::new();
+    let program = check(&mut checker, &load(file));
+    let libraries = program.libraries();
+
+    if artifact == Artifact::Executable
+        && !checker.function_names().iter().any(|name| name == "main")
+    {
+        eprintln!("No `main` function found");
+        exit(1);
+    }
+    let header = match artifact {
+        Artifact::StaticLib | Artifact::DynamicLib => Some(header::render(&program, &stem)),
+        _ => None,
+    };
+
+    let context = Context::create();
+    let module = context.create_module(file);
+    let mut codegen = Codegen::with_target(&context, module, target);
+
+    if flags.contains(&"debug".to_string()) {
+        let path = Path::new(file)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(file));
+
+        codegen = codegen.with_debug_info(&path);
+    }
+
+    if flags.contains(&"noboundschecks".to_string()) {
+        codegen = codegen.without_bounds_checks();
+    }
+
+    if artifact == Artifact::Executable {
+        codegen = codegen.with_entry_point();
+    }
+
+    if let Err(mut e) = codegen.compile(program) {
+        let raw = fs::read_to_string(file).unwrap_or_default();
+
+        eprintln!("{}", e.with_raw(raw));
+        exit(1);
+    }
+
+    let emitted = match artifact {
+        Artifact::Object => codegen.emit(&out),
+        artifact => link(&codegen, artifact, &libraries, &out),
+    };
+
+    match emitted {
+        Ok(path) => eprintln!("Wrote {}", path.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+
+    if let Some(header) = header {
+        write(
+            &out.with_file_name(format!("{}.h", stem)),
+            header.as_bytes(),
+        );
+    }
+}
+
+/// Emit a program as an object file and link it into an executable or a
+/// library along with the runtime's and the `libraries` its `extern fn`s
+/// are in. The object files are removed afterwards.
+#[cfg(feature = "llvm")]
+fn link(
+    codegen: &Codegen,
+    artifact: Artifact,
+    libraries: &[String],
+    out: &Path,
+) -> Result<PathBuf, String> {
+    let program = codegen.emit(&out.with_extension("o"))?;
+    let runtime = runtime::emit(Target::Native, &out.with_extension("runtime.o"))?;
+    let objects = [program.clone(), runtime.clone()];
+    let linked = match artifact {
+        Artifact::StaticLib => target::archive(&objects, out),
+        Artifact::DynamicLib => target::link_shared(&objects, libraries, out),
+        _ => target::link(&objects, libraries, out),
+    };
+
+    fs::remove_file(program).ok();
+    fs::remove_file(runtime).ok();
 
-    qlang::file(file).ok();
+    linked.map(|_| out.to_path_buf())
 }
 
 // Metadata
@@ -49,7 +450,12 @@ If no arguments are given, the REPL is started.
 
 Subcommands:
     run [file] [-- args...]
-        Runs the specified file or the current project.
+        Runs the `main` function of the specified file or the current
+        project. An `int` returned from `main` is used as the exit code.
+        `.qbc` bytecode files are run on the VM.
+    build [file]
+        Compiles the specified file to an object file, to bytecode with
+        `--emit`, or to a library with `--crate-type`.
     repl
         Starts the interactive Qlang REPL.
 
@@ -61,7 +467,40 @@ Options:
     -V, --verbose
         Prints additional debug information.
     -vv, --very-verbose
-        Prints all debug information. \
+        Prints all debug information.
+    --interp
+        Makes `run` and the REPL use the tree-walking interpreter instead
+        of compiling with LLVM. Always on if qlang was compiled without the
+        `llvm` feature.
+    --vm
+        Makes `run` compile to bytecode and run it on the stack VM.
+    --emit [kind]
+        What `build` produces: `obj` (the default), `exe` for an executable
+        linked with the C compiler (`$CC` or `cc`) and the libraries named
+        by `#[link(name = \"..\")]` attributes, which starts at a `main`
+        taking no arguments and exits with the `int` it returns or 0 if it
+        returns `void`, `bytecode` to print the
+        disassembled bytecode, `qbc` to write a bytecode file that `run`
+        can load, or `mir` to print the control flow graph of every
+        function in Graphviz DOT format.
+    --crate-type [kind]
+        Makes `build` produce a library instead, `staticlib` for a `.a`
+        archive or `cdylib` for a shared library, along with a C header
+        declaring every function marked `#[no_mangle]`.
+    -g, --debug
+        Makes `build` emit DWARF debug information.
+    --no-bounds-checks
+        Makes `build` leave out the checks that stop a program when it
+        indexes past the end of an array, so that doing so is undefined
+        behaviour. The interpreter and the VM always check.
+    -o, --out [path]
+        Where `build` writes its output.
+    --target [triple]
+        The target `build` compiles for, either `native` (the default) or
+        `wasm32-unknown-unknown`, which emits a `.wasm` module exporting
+        `main` and every `pub` function that only takes and returns ints,
+        floats, bools, chars and strings, the way `#[no_mangle]` does
+        (linked with `wasm-ld` if it is installed). \
 ";
 
 pub fn help(spawn: bool) {
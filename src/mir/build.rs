@@ -0,0 +1,388 @@
+use crate::{
+    hir::{Block, Expr, ExprKind, Function, Local, LocalId, Place, Projection, Stmt, StmtKind},
+    mir::{
+        BasicBlock, BlockId, Body, Operand, Rvalue, Statement, StatementKind, Terminator,
+        TerminatorKind, ENTRY,
+    },
+    parser::ast::ast::{NodeType, Pos},
+};
+
+/// Build the control flow graph of a function.
+pub fn build(func: &Function) -> Body {
+    let mut builder = Builder {
+        locals: func.locals.clone(),
+        blocks: vec![Partial::default()],
+        current: Some(ENTRY),
+        loops: Vec::new(),
+        at: func.at,
+    };
+
+    let stmts = &func.body.stmts;
+
+    // the value of a trailing expression statement is the implicit return value
+    let value = match func.body.value() {
+        Some(value) if func.ret != NodeType::Void => Some(value),
+        _ => None,
+    };
+    let rest = match value {
+        Some(_) => &stmts[..stmts.len() - 1],
+        None => &stmts[..],
+    };
+
+    for stmt in rest {
+        builder.stmt(stmt);
+    }
+
+    match value {
+        Some(value) => {
+            builder.at = stmts.last().unwrap().at;
+
+            let value = builder.operand(value);
+            builder.terminate(TerminatorKind::Return(Some(value)));
+        }
+        // every path that returned already has its own terminator
+        None if builder.current.is_some() => builder.terminate(TerminatorKind::Return(None)),
+        None => {}
+    }
+
+    let blocks = builder
+        .blocks
+        .into_iter()
+        .map(|block| BasicBlock {
+            stmts: block.stmts,
+            terminator: block.terminator.expect("block was left unterminated"),
+        })
+        .collect();
+
+    Body {
+        id: func.id,
+        name: func.name.clone(),
+        params: func.params,
+        locals: builder.locals,
+        ret: func.ret.clone(),
+        blocks,
+        at: func.at,
+    }
+}
+
+struct Builder {
+    locals: Vec<Local>,
+    blocks: Vec<Partial>,
+    /// The block code is being added to, `None` right after a terminator.
+    current: Option<BlockId>,
+    /// Where a `break` jumps to in every loop being built, innermost last.
+    loops: Vec<BlockId>,
+    at: Pos,
+}
+
+/// A block that may not have its terminator yet.
+#[derive(Default)]
+struct Partial {
+    stmts: Vec<Statement>,
+    terminator: Option<Terminator>,
+}
+
+impl Builder {
+    // Statements
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.at = stmt.at;
+
+        match &stmt.kind {
+            StmtKind::Let {
+                local,
+                value: Some(value),
+            } => {
+                let value = self.rvalue(value);
+
+                self.emit(StatementKind::Assign(Place::Local(*local), value));
+            }
+            StmtKind::Let { local, value: None } => self.emit(StatementKind::Declare(*local)),
+            StmtKind::Expr(expr) => self.effect(expr),
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.operand(value));
+
+                self.terminate(TerminatorKind::Return(value));
+            }
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let cond = self.operand(cond);
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+
+                self.terminate(TerminatorKind::Branch {
+                    cond,
+                    then: then_block,
+                    otherwise: else_block,
+                });
+
+                self.current = Some(then_block);
+                self.block(then);
+                let then_end = self.current.take();
+
+                self.current = Some(else_block);
+                if let Some(otherwise) = otherwise {
+                    self.block(otherwise);
+                }
+                let else_end = self.current.take();
+
+                // if both branches return there's nothing left to continue with
+                let open = then_end.into_iter().chain(else_end).collect::<Vec<_>>();
+
+                if !open.is_empty() {
+                    let merge = self.new_block();
+
+                    for block in open {
+                        self.goto(block, merge);
+                    }
+
+                    self.current = Some(merge);
+                }
+            }
+            StmtKind::Match(arms) => {
+                let mut open = Vec::new();
+
+                for arm in arms {
+                    // a failed condition moves on to the next arm
+                    let next = if arm.conds.is_empty() {
+                        None
+                    } else {
+                        Some(self.new_block())
+                    };
+
+                    for cond in &arm.conds {
+                        let cond = self.operand(cond);
+                        let then = self.new_block();
+
+                        self.terminate(TerminatorKind::Branch {
+                            cond,
+                            then,
+                            otherwise: next.unwrap(),
+                        });
+
+                        self.current = Some(then);
+                    }
+
+                    self.block(&arm.body);
+                    open.extend(self.current.take());
+
+                    self.current = next;
+                }
+
+                if !open.is_empty() {
+                    let merge = self.new_block();
+
+                    for block in open {
+                        self.goto(block, merge);
+                    }
+
+                    self.current = Some(merge);
+                }
+            }
+            StmtKind::Loop(body) => {
+                let head = self.new_block();
+                let exit = self.new_block();
+
+                self.terminate(TerminatorKind::Goto(head));
+                self.current = Some(head);
+
+                self.loops.push(exit);
+                self.block(body);
+                self.loops.pop();
+
+                if self.current.is_some() {
+                    self.terminate(TerminatorKind::Goto(head));
+                }
+
+                self.current = Some(exit);
+            }
+            StmtKind::Break => {
+                let exit = *self.loops.last().expect("`break` outside of a loop");
+
+                self.terminate(TerminatorKind::Goto(exit));
+            }
+            StmtKind::Block(block) => self.block(block),
+        }
+    }
+
+    /// Evaluate an expression whose value isn't used.
+    fn effect(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign { place, path, value } => {
+                self.assign(*place, path, value);
+            }
+            _ => {
+                let value = self.rvalue(expr);
+
+                self.emit(StatementKind::Eval(value));
+            }
+        }
+    }
+
+    // Expressions
+
+    fn rvalue(&mut self, expr: &Expr) -> Rvalue {
+        match &expr.kind {
+            ExprKind::Call { func, args } => {
+                let args = args.iter().map(|arg| self.operand(arg)).collect();
+
+                Rvalue::Call(*func, args)
+            }
+            ExprKind::Builtin { builtin, args } => {
+                let args = args.iter().map(|arg| self.operand(arg)).collect();
+
+                Rvalue::Builtin(*builtin, args)
+            }
+            ExprKind::Unary { op, operand } => Rvalue::Unary(*op, self.operand(operand)),
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.operand(lhs);
+                let rhs = self.operand(rhs);
+
+                Rvalue::Binary(*op, lhs, rhs)
+            }
+            ExprKind::Struct(fields) => {
+                let fields = fields.iter().map(|field| self.operand(field)).collect();
+
+                Rvalue::Aggregate(fields)
+            }
+            ExprKind::Field { base, index } => Rvalue::Field(self.operand(base), *index),
+            ExprKind::Variant { variant, fields } => {
+                let fields = fields.iter().map(|field| self.operand(field)).collect();
+
+                Rvalue::Variant(*variant, fields)
+            }
+            ExprKind::Array(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.operand(element))
+                    .collect();
+
+                Rvalue::Array(elements)
+            }
+            ExprKind::Index { base, index } => {
+                let base = self.operand(base);
+                let index = self.operand(index);
+
+                Rvalue::Index(base, index)
+            }
+            ExprKind::Tag(base) => Rvalue::Tag(self.operand(base)),
+            ExprKind::Payload {
+                base,
+                variant,
+                index,
+            } => Rvalue::Payload(self.operand(base), *variant, *index),
+            ExprKind::Literal(_)
+            | ExprKind::Local(_)
+            | ExprKind::Global(_)
+            | ExprKind::Assign { .. } => Rvalue::Use(self.operand(expr)),
+        }
+    }
+
+    fn operand(&mut self, expr: &Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Literal(literal) => Operand::Const(literal.clone()),
+            ExprKind::Local(local) => Operand::Local(*local),
+            ExprKind::Global(global) => Operand::Global(*global),
+            ExprKind::Assign { place, path, value } => self.assign(*place, path, value),
+            _ => {
+                let value = self.rvalue(expr);
+                let temp = self.temp(expr.ty.clone());
+
+                self.emit(StatementKind::Assign(Place::Local(temp), value));
+
+                Operand::Local(temp)
+            }
+        }
+    }
+
+    /// Assign to a place, or to a part of it, returning an operand that
+    /// holds the value assigned.
+    fn assign(&mut self, place: Place, path: &[Projection], value: &Expr) -> Operand {
+        if path.is_empty() {
+            let value = self.rvalue(value);
+
+            self.emit(StatementKind::Assign(place, value));
+
+            return place.into();
+        }
+
+        // the whole struct or array is replaced, so it has to have a value
+        // already
+        let value = self.operand(value);
+        let path = path
+            .iter()
+            .map(|projection| match projection {
+                Projection::Field(field) => Projection::Field(*field),
+                Projection::Index(index) => Projection::Index(self.operand(index)),
+            })
+            .collect();
+        let update = Rvalue::Update(place.into(), path, value.clone());
+
+        self.emit(StatementKind::Assign(place, update));
+
+        value
+    }
+
+    // Helpers
+
+    fn temp(&mut self, ty: NodeType) -> LocalId {
+        self.locals.push(Local {
+            name: String::new(),
+            ty,
+        });
+
+        self.locals.len() - 1
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Partial::default());
+        self.blocks.len() - 1
+    }
+
+    /// The block being built. Code after a terminator starts a new block
+    /// that nothing jumps to.
+    fn current(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+
+                self.current = Some(block);
+                block
+            }
+        }
+    }
+
+    fn emit(&mut self, kind: StatementKind) {
+        let block = self.current();
+        let at = self.at;
+
+        self.blocks[block].stmts.push(Statement { kind, at });
+    }
+
+    fn terminate(&mut self, kind: TerminatorKind) {
+        let block = self.current();
+
+        self.set_terminator(block, kind);
+        self.current = None;
+    }
+
+    fn goto(&mut self, from: BlockId, to: BlockId) {
+        self.set_terminator(from, TerminatorKind::Goto(to));
+    }
+
+    fn set_terminator(&mut self, block: BlockId, kind: TerminatorKind) {
+        let at = self.at;
+
+        self.blocks[block].terminator = Some(Terminator { kind, at });
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    mir::{
+        dataflow::{self, DefinitelyInitialised},
+        Body, Location, TerminatorKind, ENTRY,
+    },
+    parser::{
+        ast::ast::{NodeType, Pos},
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+    },
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// Check that every variable is assigned before it's read and that a
+/// function returns a value on every path that needs one. Code that can
+/// never run is returned as warnings.
+pub fn check(body: &Body) -> Result<Vec<CompileError>> {
+    let reachable = body.reachable();
+
+    missing_return(body, &reachable)?;
+    uninitialised(body, &reachable)?;
+
+    Ok(unreachable(body, &reachable))
+}
+
+fn missing_return(body: &Body, reachable: &[bool]) -> Result<()> {
+    if body.ret == NodeType::Void {
+        return Ok(());
+    }
+
+    let falls_through = body.blocks.iter().zip(reachable).any(|(block, reachable)| {
+        *reachable && block.terminator.kind == TerminatorKind::Return(None)
+    });
+
+    if falls_through {
+        return Err(error(
+            ErrorCodes::MissingReturn,
+            body.at,
+            &format!(
+                "`{}` must return a value of type {} on every path",
+                body.name, body.ret
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn uninitialised(body: &Body, reachable: &[bool]) -> Result<()> {
+    let analysis = DefinitelyInitialised;
+    let results = dataflow::solve(&analysis, body);
+
+    for (id, block) in body.blocks.iter().enumerate() {
+        if !reachable[id] {
+            continue;
+        }
+
+        let mut found = None;
+
+        results.visit(
+            &analysis,
+            body,
+            id,
+            |initialised, Location { index, .. }| {
+                let (operands, at) = match block.stmts.get(index) {
+                    Some(stmt) => (stmt.operands(), stmt.at),
+                    None => (block.terminator.operands(), block.terminator.at),
+                };
+
+                let uninit = operands
+                    .iter()
+                    .filter_map(|operand| operand.local())
+                    .find(|local| !initialised.contains(*local));
+
+                if let (None, Some(local)) = (&found, uninit) {
+                    found = Some((local, at));
+                }
+            },
+        );
+
+        if let Some((local, at)) = found {
+            return Err(error(
+                ErrorCodes::UninitialisedVariable,
+                at,
+                &format!(
+                    "`{}` may be read before it's assigned a value",
+                    body.locals[local].name
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// A warning for the start of every stretch of code that nothing jumps to.
+fn unreachable(body: &Body, reachable: &[bool]) -> Vec<CompileError> {
+    let preds = body.predecessors();
+
+    body.blocks
+        .iter()
+        .enumerate()
+        .filter(|(id, _)| *id != ENTRY && !reachable[*id] && preds[*id].is_empty())
+        .map(|(_, block)| {
+            let at = match block.stmts.first() {
+                Some(stmt) => stmt.at,
+                None => block.terminator.at,
+            };
+
+            CompileErrorBuilder::new()
+                .from(ErrorCodes::UnreachableCode)
+                .warning()
+                .at((at.0, at.1 + 1))
+                .note("this code will never run")
+                .build()
+        })
+        .collect()
+}
+
+fn error(code: ErrorCodes, at: Pos, note: &str) -> CompileError {
+    CompileErrorBuilder::new()
+        .from(code)
+        .at((at.0, at.1 + 1))
+        .note(note)
+        .build()
+}
@@ -0,0 +1,421 @@
+//! A framework for dataflow analyses over a [`Body`], and the analyses the
+//! compiler needs.
+//!
+//! An analysis describes how a single statement changes what's known at a
+//! point in the program, and how to combine what's known where control flow
+//! merges. [`solve`] then finds what's known at the start and end of every
+//! block by iterating until nothing changes.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    hir::{LocalId, Place},
+    mir::{Body, Location, Statement, StatementKind, Terminator, ENTRY},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Facts flow from the entry block towards the returns.
+    Forward,
+    /// Facts flow from the returns back towards the entry block.
+    Backward,
+}
+
+pub trait Analysis {
+    type Domain: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// The state every block starts out with, before anything flows into it.
+    fn bottom(&self, body: &Body) -> Self::Domain;
+
+    /// The state on entry to the function for forward analyses, or at every
+    /// return for backward ones.
+    fn boundary(&self, body: &Body) -> Self::Domain;
+
+    /// Combine the states of two paths that meet.
+    fn join(&self, state: &mut Self::Domain, other: &Self::Domain);
+
+    fn statement(&self, state: &mut Self::Domain, stmt: &Statement, location: Location);
+
+    fn terminator(&self, _state: &mut Self::Domain, _terminator: &Terminator, _location: Location) {
+    }
+}
+
+/// The state at the start and end of every block, in program order
+/// whichever way the analysis runs.
+#[derive(Debug, Clone)]
+pub struct Results<D> {
+    pub before: Vec<D>,
+    pub after: Vec<D>,
+}
+
+/// Run an analysis to a fixed point.
+pub fn solve<A: Analysis>(analysis: &A, body: &Body) -> Results<A::Domain> {
+    let bottom = analysis.bottom(body);
+    let mut results = Results {
+        before: vec![bottom.clone(); body.blocks.len()],
+        after: vec![bottom; body.blocks.len()],
+    };
+
+    let preds = body.predecessors();
+    let mut queue = (0..body.blocks.len()).collect::<VecDeque<_>>();
+    let mut queued = vec![true; body.blocks.len()];
+
+    while let Some(id) = queue.pop_front() {
+        queued[id] = false;
+
+        let block = &body.blocks[id];
+        let succs = block.terminator.successors();
+
+        let changed = match A::DIRECTION {
+            Direction::Forward => {
+                let mut state = match id {
+                    ENTRY => analysis.boundary(body),
+                    _ => analysis.bottom(body),
+                };
+
+                for pred in &preds[id] {
+                    analysis.join(&mut state, &results.after[*pred]);
+                }
+
+                results.before[id] = state.clone();
+                apply(analysis, body, id, &mut state);
+
+                let changed = state != results.after[id];
+                results.after[id] = state;
+                changed
+            }
+            Direction::Backward => {
+                let mut state = if succs.is_empty() {
+                    analysis.boundary(body)
+                } else {
+                    analysis.bottom(body)
+                };
+
+                for succ in &succs {
+                    analysis.join(&mut state, &results.before[*succ]);
+                }
+
+                results.after[id] = state.clone();
+                apply(analysis, body, id, &mut state);
+
+                let changed = state != results.before[id];
+                results.before[id] = state;
+                changed
+            }
+        };
+
+        if changed {
+            let next = match A::DIRECTION {
+                Direction::Forward => succs,
+                Direction::Backward => preds[id].clone(),
+            };
+
+            for block in next {
+                if !queued[block] {
+                    queued[block] = true;
+                    queue.push_back(block);
+                }
+            }
+        }
+    }
+
+    results
+}
+
+impl<D: Clone> Results<D> {
+    /// Call `f` with the state just before every statement and terminator
+    /// of a block runs, which for backward analyses is the state flowing
+    /// into it from the statements after it.
+    pub fn visit<A>(&self, analysis: &A, body: &Body, block: usize, mut f: impl FnMut(&D, Location))
+    where
+        A: Analysis<Domain = D>,
+    {
+        let stmts = &body.blocks[block].stmts;
+        let terminator = Location {
+            block,
+            index: stmts.len(),
+        };
+
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut state = self.before[block].clone();
+
+                for (index, stmt) in stmts.iter().enumerate() {
+                    let location = Location { block, index };
+
+                    f(&state, location);
+                    analysis.statement(&mut state, stmt, location);
+                }
+
+                f(&state, terminator);
+            }
+            Direction::Backward => {
+                let mut state = self.after[block].clone();
+
+                f(&state, terminator);
+                analysis.terminator(&mut state, &body.blocks[block].terminator, terminator);
+
+                for (index, stmt) in stmts.iter().enumerate().rev() {
+                    let location = Location { block, index };
+
+                    f(&state, location);
+                    analysis.statement(&mut state, stmt, location);
+                }
+            }
+        }
+    }
+}
+
+/// Run the transfer function of a whole block.
+fn apply<A: Analysis>(analysis: &A, body: &Body, block: usize, state: &mut A::Domain) {
+    let stmts = &body.blocks[block].stmts;
+    let terminator = &body.blocks[block].terminator;
+    let end = Location {
+        block,
+        index: stmts.len(),
+    };
+
+    match A::DIRECTION {
+        Direction::Forward => {
+            for (index, stmt) in stmts.iter().enumerate() {
+                analysis.statement(state, stmt, Location { block, index });
+            }
+
+            analysis.terminator(state, terminator, end);
+        }
+        Direction::Backward => {
+            analysis.terminator(state, terminator, end);
+
+            for (index, stmt) in stmts.iter().enumerate().rev() {
+                analysis.statement(state, stmt, Location { block, index });
+            }
+        }
+    }
+}
+
+/// A fixed-size set of small integers, like [`LocalId`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub fn empty(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    pub fn filled(len: usize) -> Self {
+        let mut set = Self::empty(len);
+
+        for i in 0..len {
+            set.insert(i);
+        }
+
+        set
+    }
+
+    pub fn insert(&mut self, i: usize) {
+        assert!(i < self.len, "{} is out of range for the set", i);
+
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.len && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn union(&mut self, other: &Self) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    pub fn intersect(&mut self, other: &Self) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(move |i| self.contains(*i))
+    }
+}
+
+/// Which locals may still be read before they are next assigned.
+#[derive(Debug, Default)]
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn bottom(&self, body: &Body) -> BitSet {
+        BitSet::empty(body.locals.len())
+    }
+
+    fn boundary(&self, body: &Body) -> BitSet {
+        BitSet::empty(body.locals.len())
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet) {
+        state.union(other);
+    }
+
+    fn statement(&self, state: &mut BitSet, stmt: &Statement, _: Location) {
+        match stmt.kind {
+            StatementKind::Assign(Place::Local(local), _) | StatementKind::Declare(local) => {
+                state.remove(local)
+            }
+            StatementKind::Assign(Place::Global(_), _) | StatementKind::Eval(_) => {}
+        }
+
+        // `x = x + 1` needs `x` before it's overwritten
+        for local in stmt.operands().iter().filter_map(|operand| operand.local()) {
+            state.insert(local);
+        }
+    }
+
+    fn terminator(&self, state: &mut BitSet, terminator: &Terminator, _: Location) {
+        for local in terminator
+            .operands()
+            .iter()
+            .filter_map(|operand| operand.local())
+        {
+            state.insert(local);
+        }
+    }
+}
+
+/// Which assignments may have given each local its current value.
+#[derive(Debug)]
+pub struct ReachingDefinitions {
+    /// Every assignment in the body, starting with the arguments.
+    pub defs: Vec<Definition>,
+    at: HashMap<Location, usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub local: LocalId,
+    /// `None` for the value an argument was called with.
+    pub location: Option<Location>,
+}
+
+impl ReachingDefinitions {
+    pub fn new(body: &Body) -> Self {
+        let mut defs = (0..body.params)
+            .map(|local| Definition {
+                local,
+                location: None,
+            })
+            .collect::<Vec<_>>();
+        let mut at = HashMap::new();
+
+        for (block, data) in body.blocks.iter().enumerate() {
+            for (index, stmt) in data.stmts.iter().enumerate() {
+                if let Some(local) = stmt.assigns() {
+                    let location = Location { block, index };
+
+                    at.insert(location, defs.len());
+                    defs.push(Definition {
+                        local,
+                        location: Some(location),
+                    });
+                }
+            }
+        }
+
+        Self { defs, at }
+    }
+
+    fn kill(&self, state: &mut BitSet, local: LocalId) {
+        for (i, def) in self.defs.iter().enumerate() {
+            if def.local == local {
+                state.remove(i);
+            }
+        }
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self, _: &Body) -> BitSet {
+        BitSet::empty(self.defs.len())
+    }
+
+    fn boundary(&self, body: &Body) -> BitSet {
+        let mut state = BitSet::empty(self.defs.len());
+
+        for param in 0..body.params {
+            state.insert(param);
+        }
+
+        state
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet) {
+        state.union(other);
+    }
+
+    fn statement(&self, state: &mut BitSet, stmt: &Statement, location: Location) {
+        match stmt.kind {
+            StatementKind::Assign(Place::Local(local), _) => {
+                self.kill(state, local);
+                state.insert(self.at[&location]);
+            }
+            StatementKind::Declare(local) => self.kill(state, local),
+            StatementKind::Assign(Place::Global(_), _) | StatementKind::Eval(_) => {}
+        }
+    }
+}
+
+/// Which locals have been assigned on every path to a point.
+#[derive(Debug, Default)]
+pub struct DefinitelyInitialised;
+
+impl Analysis for DefinitelyInitialised {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    // everything is initialised until a path shows otherwise
+    fn bottom(&self, body: &Body) -> BitSet {
+        BitSet::filled(body.locals.len())
+    }
+
+    fn boundary(&self, body: &Body) -> BitSet {
+        let mut state = BitSet::empty(body.locals.len());
+
+        for param in 0..body.params {
+            state.insert(param);
+        }
+
+        state
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet) {
+        state.intersect(other);
+    }
+
+    fn statement(&self, state: &mut BitSet, stmt: &Statement, _: Location) {
+        match stmt.kind {
+            StatementKind::Assign(Place::Local(local), _) => state.insert(local),
+            StatementKind::Declare(local) => state.remove(local),
+            StatementKind::Assign(Place::Global(_), _) | StatementKind::Eval(_) => {}
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Renders bodies as a Graphviz DOT graph, for `qlang build --emit=mir`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    hir::{Extern, FnId, Global, GlobalId, Literal, LocalId, Place, Projection},
+    mir::{
+        dataflow::{self, Liveness},
+        Body, Operand, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+    },
+};
+
+/// One graph with a cluster for every body. Each block is labelled with
+/// the variables live on entry to it.
+pub fn render(bodies: &[Body], externs: &[Extern], globals: &[Global]) -> String {
+    let names = bodies
+        .iter()
+        .map(|body| (body.id, body.name.as_str()))
+        .chain(externs.iter().map(|item| (item.id, item.name.as_str())))
+        .collect::<HashMap<_, _>>();
+    let globals = globals
+        .iter()
+        .map(|global| (global.id, global.name.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = String::new();
+
+    out.push_str("digraph mir {\n");
+    out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for body in bodies {
+        Renderer {
+            body,
+            names: &names,
+            globals: &globals,
+        }
+        .render(&mut out);
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+struct Renderer<'a> {
+    body: &'a Body,
+    names: &'a HashMap<FnId, &'a str>,
+    globals: &'a HashMap<GlobalId, &'a str>,
+}
+
+impl Renderer<'_> {
+    fn render(&self, out: &mut String) {
+        let body = self.body;
+        let params = (0..body.params)
+            .map(|param| format!("{}: {}", self.local(param), body.locals[param].ty))
+            .collect::<Vec<_>>();
+        let live = dataflow::solve(&Liveness, body);
+
+        writeln!(out, "    subgraph \"cluster_{}\" {{", escape(&body.name)).unwrap();
+        writeln!(
+            out,
+            "        label=\"fn {}({}) -> {}\";",
+            escape(&body.name),
+            escape(&params.join(", ")),
+            body.ret
+        )
+        .unwrap();
+
+        for (id, block) in body.blocks.iter().enumerate() {
+            let mut label = format!("bb{}:\\l", id);
+
+            let live = live.before[id]
+                .iter()
+                .map(|local| self.local(local))
+                .collect::<Vec<_>>();
+
+            if !live.is_empty() {
+                label += &format!("// live: {}\\l", live.join(", "));
+            }
+
+            for stmt in &block.stmts {
+                label += &escape(&self.statement(stmt));
+                label += "\\l";
+            }
+
+            label += &escape(&self.terminator(&block.terminator));
+            label += "\\l";
+
+            writeln!(out, "        \"{}\" [label=\"{}\"];", self.node(id), label).unwrap();
+        }
+
+        for (id, block) in body.blocks.iter().enumerate() {
+            match block.terminator.kind {
+                TerminatorKind::Goto(to) => writeln!(
+                    out,
+                    "        \"{}\" -> \"{}\";",
+                    self.node(id),
+                    self.node(to)
+                )
+                .unwrap(),
+                TerminatorKind::Branch {
+                    then, otherwise, ..
+                } => {
+                    for (to, label) in [(then, "true"), (otherwise, "false")] {
+                        writeln!(
+                            out,
+                            "        \"{}\" -> \"{}\" [label=\"{}\"];",
+                            self.node(id),
+                            self.node(to),
+                            label
+                        )
+                        .unwrap();
+                    }
+                }
+                TerminatorKind::Return(_) => {}
+            }
+        }
+
+        out.push_str("    }\n");
+    }
+
+    fn node(&self, block: usize) -> String {
+        escape(&format!("{}.bb{}", self.body.name, block))
+    }
+
+    fn statement(&self, stmt: &Statement) -> String {
+        match &stmt.kind {
+            StatementKind::Assign(Place::Local(local), value) => {
+                format!("{} = {}", self.local(*local), self.rvalue(value))
+            }
+            StatementKind::Assign(Place::Global(global), value) => {
+                format!("{} = {}", self.global(*global), self.rvalue(value))
+            }
+            StatementKind::Eval(value) => self.rvalue(value),
+            StatementKind::Declare(local) => format!(
+                "let {}: {}",
+                self.local(*local),
+                self.body.locals[*local].ty
+            ),
+        }
+    }
+
+    fn terminator(&self, terminator: &Terminator) -> String {
+        match &terminator.kind {
+            TerminatorKind::Goto(to) => format!("goto -> bb{}", to),
+            TerminatorKind::Branch {
+                cond,
+                then,
+                otherwise,
+            } => format!("if {} -> [bb{}, bb{}]", self.operand(cond), then, otherwise),
+            TerminatorKind::Return(Some(value)) => format!("return {}", self.operand(value)),
+            TerminatorKind::Return(None) => "return".to_string(),
+        }
+    }
+
+    fn rvalue(&self, value: &Rvalue) -> String {
+        match value {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Unary(op, operand) => format!("{}{}", op, self.operand(operand)),
+            Rvalue::Binary(op, lhs, rhs) => {
+                format!("{} {} {}", self.operand(lhs), op, self.operand(rhs))
+            }
+            Rvalue::Call(func, args) => {
+                let args = args.iter().map(|arg| self.operand(arg)).collect::<Vec<_>>();
+
+                match self.names.get(func) {
+                    Some(name) => format!("{}({})", name, args.join(", ")),
+                    None => format!("fn#{}({})", func, args.join(", ")),
+                }
+            }
+            Rvalue::Builtin(builtin, args) => {
+                let args = args.iter().map(|arg| self.operand(arg)).collect::<Vec<_>>();
+
+                format!("{}({})", builtin, args.join(", "))
+            }
+            Rvalue::Aggregate(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| self.operand(field))
+                    .collect::<Vec<_>>();
+
+                format!("{{{}}}", fields.join(", "))
+            }
+            Rvalue::Field(base, index) => format!("{}.{}", self.operand(base), index),
+            Rvalue::Variant(variant, fields) if fields.is_empty() => format!("#{}", variant),
+            Rvalue::Variant(variant, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| self.operand(field))
+                    .collect::<Vec<_>>();
+
+                format!("#{}({})", variant, fields.join(", "))
+            }
+            Rvalue::Tag(base) => format!("tag {}", self.operand(base)),
+            Rvalue::Payload(base, variant, index) => {
+                format!("{}#{}.{}", self.operand(base), variant, index)
+            }
+            Rvalue::Array(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.operand(element))
+                    .collect::<Vec<_>>();
+
+                format!("[{}]", elements.join(", "))
+            }
+            Rvalue::Index(base, index) => {
+                format!("{}[{}]", self.operand(base), self.operand(index))
+            }
+            Rvalue::Update(base, path, value) => {
+                let path = path
+                    .iter()
+                    .map(|projection| match projection {
+                        Projection::Field(field) => format!(".{}", field),
+                        Projection::Index(index) => format!("[{}]", self.operand(index)),
+                    })
+                    .collect::<String>();
+
+                format!(
+                    "{{{} with {} = {}}}",
+                    self.operand(base),
+                    path,
+                    self.operand(value)
+                )
+            }
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Local(local) => self.local(*local),
+            Operand::Global(global) => self.global(*global),
+            Operand::Const(Literal::String(s)) => format!("{:?}", s),
+            Operand::Const(Literal::Integer(i)) => i.to_string(),
+            Operand::Const(Literal::Float(f)) => format!("{:?}", f),
+            Operand::Const(Literal::Char(c)) => format!("{:?}", c),
+            Operand::Const(Literal::Bool(b)) => b.to_string(),
+        }
+    }
+
+    /// Globals are marked so they can't be mistaken for locals.
+    fn global(&self, global: GlobalId) -> String {
+        match self.globals.get(&global) {
+            Some(name) => format!("static {}", name),
+            None => format!("static#{}", global),
+        }
+    }
+
+    /// A variable's name, or `_N` for temporaries. Variables that shadow
+    /// another of the same name are told apart by their ID.
+    fn local(&self, local: LocalId) -> String {
+        let name = &self.body.locals[local].name;
+
+        if name.is_empty() {
+            return format!("_{}", local);
+        }
+
+        let shadowed = self
+            .body
+            .locals
+            .iter()
+            .filter(|other| other.name == *name)
+            .count()
+            > 1;
+
+        if shadowed {
+            format!("{}.{}", name, local)
+        } else {
+            name.clone()
+        }
+    }
+}
+
+/// Make text safe to put between double quotes in DOT.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,257 @@
+//! The mid-level IR: a function body as a control flow graph of basic
+//! blocks, built from [`hir`](crate::hir) for analyses that need to know
+//! which paths code can take, like [`check`].
+//!
+//! Expressions are flattened so that every operation works on [`Operand`]s,
+//! which are constants, locals or globals. Intermediate values get their own
+//! unnamed locals.
+
+use crate::{
+    hir::{Builtin, FnId, GlobalId, Literal, Local, LocalId, Place, Projection},
+    parser::ast::ast::{NodeType, Op, Pos},
+};
+
+pub mod build;
+pub mod check;
+pub mod dataflow;
+pub mod dot;
+
+pub use self::build::build;
+
+/// Identifies a block within a body, as an index into [`Body::blocks`].
+pub type BlockId = usize;
+
+/// The block that a body starts in.
+pub const ENTRY: BlockId = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Body {
+    pub id: FnId,
+    pub name: String,
+    /// The arguments are always the first locals.
+    pub params: usize,
+    /// The function's variables followed by temporaries, which have no name.
+    pub locals: Vec<Local>,
+    pub ret: NodeType,
+    pub blocks: Vec<BasicBlock>,
+    pub at: Pos,
+}
+
+/// Straight-line code that always runs to the end, where its terminator
+/// decides where to go next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub stmts: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub at: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Assign(Place, Rvalue),
+    /// Computes a value only for its side effects.
+    Eval(Rvalue),
+    /// A variable declared without a value, which makes it uninitialised
+    /// again if it's declared in a loop.
+    Declare(LocalId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Terminator {
+    pub kind: TerminatorKind,
+    pub at: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminatorKind {
+    Goto(BlockId),
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// Returns from the function, without a value if it's `void` or the end
+    /// of a function was reached that should have returned one.
+    Return(Option<Operand>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rvalue {
+    Use(Operand),
+    Unary(Op, Operand),
+    Binary(Op, Operand, Operand),
+    Call(FnId, Vec<Operand>),
+    Builtin(Builtin, Vec<Operand>),
+    /// A struct, with the value of every field.
+    Aggregate(Vec<Operand>),
+    /// Reads a field of a struct by index.
+    Field(Operand, usize),
+    /// A copy of a struct or an array with the part at the end of a path
+    /// replaced, which is how fields and elements are assigned to.
+    Update(Operand, Vec<Projection<Operand>>, Operand),
+    /// An array, with the value of every element.
+    Array(Vec<Operand>),
+    /// Reads an element of an array, a `Vec` or a slice.
+    Index(Operand, Operand),
+    /// An enum value of a variant, with the value of every field.
+    Variant(usize, Vec<Operand>),
+    /// The index of the variant an enum value is.
+    Tag(Operand),
+    /// Reads a field of an enum value by variant and index.
+    Payload(Operand, usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Const(Literal),
+    Local(LocalId),
+    /// Globals always hold a value, so the analyses leave them alone.
+    Global(GlobalId),
+}
+
+/// A statement in a body, or a block's terminator if `index` is past its
+/// last statement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub block: BlockId,
+    pub index: usize,
+}
+
+impl Body {
+    /// The blocks each block can be jumped to from.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                preds[succ].push(id);
+            }
+        }
+
+        preds
+    }
+
+    /// Which blocks can be reached from the entry block, never taking the
+    /// side of a branch on a constant that it can't take. The loop of a
+    /// `while true` is only left by a `break` or a `return`.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![ENTRY];
+
+        while let Some(id) = stack.pop() {
+            if !reachable[id] {
+                reachable[id] = true;
+                stack.extend(self.blocks[id].terminator.taken());
+            }
+        }
+
+        reachable
+    }
+}
+
+impl Terminator {
+    /// The blocks it can jump to.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self.kind {
+            TerminatorKind::Goto(to) => vec![to],
+            TerminatorKind::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            TerminatorKind::Return(_) => Vec::new(),
+        }
+    }
+
+    /// The blocks it can jump to given the values it's known to have,
+    /// which for a branch on a constant is only the side it takes.
+    pub fn taken(&self) -> Vec<BlockId> {
+        match &self.kind {
+            TerminatorKind::Branch {
+                cond: Operand::Const(Literal::Bool(cond)),
+                then,
+                otherwise,
+            } => vec![if *cond { *then } else { *otherwise }],
+            _ => self.successors(),
+        }
+    }
+
+    /// The operands read by the terminator.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match &self.kind {
+            TerminatorKind::Branch { cond, .. } => vec![cond],
+            TerminatorKind::Return(Some(value)) => vec![value],
+            TerminatorKind::Goto(_) | TerminatorKind::Return(None) => Vec::new(),
+        }
+    }
+}
+
+impl Statement {
+    /// The operands read by the statement.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match &self.kind {
+            StatementKind::Assign(_, value) | StatementKind::Eval(value) => value.operands(),
+            StatementKind::Declare(_) => Vec::new(),
+        }
+    }
+
+    /// The local the statement assigns to, if any.
+    pub fn assigns(&self) -> Option<LocalId> {
+        match self.kind {
+            StatementKind::Assign(Place::Local(local), _) => Some(local),
+            _ => None,
+        }
+    }
+}
+
+impl Rvalue {
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Rvalue::Use(operand) | Rvalue::Unary(_, operand) => vec![operand],
+            Rvalue::Binary(_, lhs, rhs) => vec![lhs, rhs],
+            Rvalue::Call(_, args)
+            | Rvalue::Builtin(_, args)
+            | Rvalue::Aggregate(args)
+            | Rvalue::Variant(_, args)
+            | Rvalue::Array(args) => args.iter().collect(),
+            Rvalue::Index(base, index) => vec![base, index],
+            Rvalue::Field(base, _) | Rvalue::Tag(base) | Rvalue::Payload(base, _, _) => {
+                vec![base]
+            }
+            Rvalue::Update(base, path, value) => {
+                let mut operands = vec![base];
+
+                for projection in path {
+                    if let Projection::Index(index) = projection {
+                        operands.push(index);
+                    }
+                }
+
+                operands.push(value);
+                operands
+            }
+        }
+    }
+}
+
+impl From<Place> for Operand {
+    fn from(place: Place) -> Self {
+        match place {
+            Place::Local(local) => Operand::Local(local),
+            Place::Global(global) => Operand::Global(global),
+        }
+    }
+}
+
+impl Operand {
+    /// The local the operand reads, if any.
+    pub fn local(&self) -> Option<LocalId> {
+        match self {
+            Operand::Local(local) => Some(*local),
+            Operand::Const(_) | Operand::Global(_) => None,
+        }
+    }
+}
@@ -1,26 +1,37 @@
-use crate::parser::ast::lexer::Slice;
+use std::fmt::{self, Display};
+
+/// A zero-based `(line, column)` position in the source.
+pub type Pos = (usize, usize);
 
 #[derive(Debug)]
 pub struct TopLevel {
     pub fns: Vec<Node>,
+    pub externs: Vec<Node>,
+    pub consts: Vec<Node>,
+    pub statics: Vec<Node>,
+    pub structs: Vec<Node>,
+    pub enums: Vec<Node>,
+    pub imports: Vec<Node>,
 }
 
+/// What a `use` brings into scope from a module.
 #[derive(Debug)]
 pub enum ImportMember {
-    All(Slice),
+    /// `use foo::*;`, everything.
     AllDestructured,
+    /// `use foo::bar;` or `use foo::{bar, baz};`, as identifiers.
     Named(Vec<Node>),
 }
 
 #[derive(Debug)]
 pub enum Node {
-    String(Slice),
+    String(String),
     Integer(i32),
     Float(f32),
     Char(char),
     Bool(bool),
 
-    Identifier(Slice),
+    Identifier(String),
 
     Block(Vec<Node>),
     Expr {
@@ -28,16 +39,117 @@ pub enum Node {
         op: Op,
         rhs: Option<Box<Node>>,
     },
-    Stmt(Box<Node>),
+    Stmt {
+        node: Box<Node>,
+        at: Pos,
+    },
+    Let {
+        name: String,
+        ty: Option<NodeType>,
+        /// `None` if the variable is assigned later.
+        value: Option<Box<Node>>,
+    },
+    Return(Option<Box<Node>>),
+    If {
+        cond: Box<Node>,
+        then: Box<Node>,
+        otherwise: Option<Box<Node>>,
+    },
+    While {
+        cond: Box<Node>,
+        body: Box<Node>,
+    },
+    /// `for x in values { .. }`, over the elements of an array, a `Vec` or
+    /// a slice.
+    For {
+        name: String,
+        iter: Box<Node>,
+        body: Box<Node>,
+    },
 
     Fn(Box<Function>),
+    Extern(Box<Extern>),
+    Const(Box<Constant>),
+    Static(Box<Static>),
+    Struct(Box<Struct>),
+    Enum(Box<Enum>),
+    Use(Box<Import>),
     Call {
         name: String,
         args: Vec<Node>,
     },
+    /// `Point { x: 1.0, y: 2.0 }`, with the fields in the order they were
+    /// written.
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, Node)>,
+    },
+    /// `value.field`
+    Field {
+        base: Box<Node>,
+        name: String,
+    },
+    /// `[1, 2, 3]`
+    Array(Vec<Node>),
+    /// `"hello {name}"`, the text of a string literal and the expressions
+    /// embedded in it, in order.
+    Interpolation(Vec<Node>),
+    /// `array[index]`
+    Index {
+        base: Box<Node>,
+        index: Box<Node>,
+    },
+    /// `Shape::Circle(1.0)`, a value of an enum.
+    Variant {
+        ty: String,
+        name: String,
+        payload: Payload<Node>,
+    },
+    Match {
+        scrutinee: Box<Node>,
+        arms: Vec<MatchArm>,
+    },
+}
+
+/// The fields of an enum variant, or of a value or pattern for one.
+#[derive(Debug)]
+pub enum Payload<T> {
+    /// `Empty`, without any.
+    Unit,
+    /// `Circle(float)`, known by their position.
+    Tuple(Vec<T>),
+    /// `Rect { w: float, h: float }`, known by name.
+    Named(Vec<(String, T)>),
 }
 
+/// `pattern => body` in a `match`.
 #[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// A block, or an expression for the value of the arm.
+    pub body: Node,
+    pub at: Pos,
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A name, which matches anything and binds it to a new variable.
+    Binding(String),
+    /// A literal, possibly negated.
+    Literal(Node),
+    /// `Shape::Rect { w, .. }`. With `rest` set, fields the pattern leaves
+    /// out match anything.
+    Variant {
+        ty: String,
+        name: String,
+        payload: Payload<Pattern>,
+        rest: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Op {
     Eq,
     Neq,
@@ -56,7 +168,29 @@ pub enum Op {
     Assign,
 }
 
-#[derive(Debug)]
+impl Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "==",
+            Op::Neq => "!=",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Le => "<=",
+            Op::Ge => ">=",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Mod => "%",
+            Op::And => "&&",
+            Op::Or => "||",
+            Op::Not => "!",
+            Op::Assign => "=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum NodeType {
     String,
     Integer,
@@ -68,12 +202,141 @@ pub enum NodeType {
         args: Vec<NodeType>,
         ret: Box<NodeType>,
     },
+    /// A `struct` by name. Once checked, the name includes the module the
+    /// struct is defined in. Until then, enums are written as structs too.
+    Struct(String),
+    /// An `enum` by name, like a struct.
+    Enum(String),
+    /// `[int; 4]`, a fixed number of values of the same type.
+    Array(Box<NodeType>, usize),
+    /// `Vec<int>`, a growable array on the heap. Copies of a `Vec` share
+    /// its elements.
+    Vec(Box<NodeType>),
+    /// `[int]`, a view of some of the elements of a `Vec`.
+    Slice(Box<NodeType>),
+}
+
+impl Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeType::String => f.write_str("str"),
+            NodeType::Integer => f.write_str("int"),
+            NodeType::Float => f.write_str("float"),
+            NodeType::Char => f.write_str("char"),
+            NodeType::Bool => f.write_str("bool"),
+            NodeType::Void => f.write_str("void"),
+            NodeType::Fn { args, ret } => {
+                let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+                write!(f, "fn({}) -> :{}", args.join(", "), ret)
+            }
+            NodeType::Struct(name) | NodeType::Enum(name) => f.write_str(name),
+            NodeType::Array(ty, len) => write!(f, "[{}; {}]", ty, len),
+            NodeType::Vec(ty) => write!(f, "Vec<{}>", ty),
+            NodeType::Slice(ty) => write!(f, "[{}]", ty),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Function {
     pub name: String,
+    /// Whether it was declared `pub` and so can be imported by other modules.
+    pub public: bool,
+    /// Whether it was marked `#[no_mangle]`, which exports it to C under
+    /// its own name.
+    pub no_mangle: bool,
     pub args: Vec<(String, NodeType)>,
     pub body: Node,
     pub ret: NodeType,
+    pub at: Pos,
+}
+
+/// An `extern fn` item, a function with the C calling convention that's
+/// defined outside the program, like `extern fn puts(s: str) -> :int;`.
+#[derive(Debug)]
+pub struct Extern {
+    pub name: String,
+    pub public: bool,
+    pub args: Vec<(String, NodeType)>,
+    /// Whether it takes any number of arguments after `args`, declared
+    /// with a trailing `...`.
+    pub variadic: bool,
+    pub ret: NodeType,
+    /// The libraries named by its `#[link(name = "..")]` attributes, which
+    /// executables calling it are linked with.
+    pub link: Vec<String>,
+    pub at: Pos,
+}
+
+/// A `#[name(key = "value", ..)]` attribute on the item after it.
+#[derive(Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<(String, String)>,
+    pub at: Pos,
+}
+
+/// A `const` item, whose value is computed at compile time.
+#[derive(Debug)]
+pub struct Constant {
+    pub name: String,
+    pub public: bool,
+    pub ty: NodeType,
+    pub value: Node,
+    pub at: Pos,
+}
+
+/// A `static` item, a global variable whose initial value is computed at
+/// compile time.
+#[derive(Debug)]
+pub struct Static {
+    pub name: String,
+    pub public: bool,
+    pub ty: NodeType,
+    /// Whether it was declared `static mut` and so can be assigned to.
+    pub mutable: bool,
+    pub value: Node,
+    pub at: Pos,
+}
+
+/// A `struct` item, a type made of named fields.
+#[derive(Debug)]
+pub struct Struct {
+    pub name: String,
+    pub public: bool,
+    pub fields: Vec<(String, NodeType)>,
+    pub at: Pos,
+}
+
+/// An `enum` item, a type whose values are one of several variants.
+#[derive(Debug)]
+pub struct Enum {
+    pub name: String,
+    pub public: bool,
+    pub variants: Vec<Variant>,
+    pub at: Pos,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub name: String,
+    pub payload: Payload<NodeType>,
+    pub at: Pos,
+}
+
+/// A `use` item.
+#[derive(Debug)]
+pub struct Import {
+    /// The path of the module, relative to the importing file.
+    pub path: Vec<String>,
+    pub members: ImportMember,
+    pub at: Pos,
+}
+
+impl Import {
+    /// The path as it was written, like `foo::bar`.
+    pub fn module(&self) -> String {
+        self.path.join("::")
+    }
 }
@@ -1,4 +1,6 @@
-use logos::{Logos, Span};
+use std::mem;
+
+use logos::{Lexer, Logos, Span};
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 pub enum Tokens {
@@ -31,10 +33,16 @@ pub enum Tokens {
     Semicolon,
     #[token(":")]
     Colon,
+    #[token("::")]
+    PathSep,
     #[token(",")]
     Comma,
     #[token("=")]
     Equals,
+    #[token("==")]
+    EqualEqual,
+    #[token("!")]
+    Bang,
     #[token("!=")]
     NotEqual,
     #[token(">=")]
@@ -51,17 +59,32 @@ pub enum Tokens {
     Pipe,
     #[token("->")]
     RArrow,
+    #[token("=>")]
+    FatArrow,
+    #[token("&&")]
+    And,
+    #[token("...")]
+    Ellipsis,
+    /// The start of an attribute, like `#[link(name = "m")]`.
+    #[token("#[")]
+    HashBracket,
+    #[token("||")]
+    Or,
 
     // Multi-char tokens
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| Slice::from(lex.span()))]
     Identifier(Slice),
-    #[regex(r"[0-9]+", |lex| lex.slice().parse::<i32>().unwrap())]
+    /// An integer literal, which is an [`Error`](Tokens::Error) if it
+    /// doesn't fit in an `int`.
+    #[regex(r"[0-9]+", |lex| lex.slice().parse::<i32>().ok())]
     Integer(i32),
     #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse::<f32>().unwrap())]
     Float(f32),
-    #[regex(r"'[^']*'", |lex| lex.slice().parse::<char>().unwrap())]
+    #[regex(r"'[^']*'", |lex| lex.slice().trim_matches('\'').parse::<char>().ok())]
     Char(char),
-    #[regex("\"(?:\\.|[^\"])*\"", |lex| Slice::from(lex.span()))]
+    /// The contents of a string literal, without its quotes. A quote only
+    /// ends it if it isn't escaped or in an embedded expression.
+    #[token("\"", string)]
     String(Slice),
 
     // Keywords
@@ -71,13 +94,47 @@ pub enum Tokens {
     Null,
     #[token("fn")]
     Fn,
+    #[token("let")]
+    Let,
+    #[token("const")]
+    Const,
+    #[token("static")]
+    Static,
+    #[token("mut")]
+    Mut,
+    #[token("use")]
+    Use,
+    #[token("struct")]
+    Struct,
+    #[token("enum")]
+    Enum,
+    #[token("pub")]
+    Pub,
+    #[token("extern")]
+    Extern,
+    #[token("return")]
+    Return,
+    #[token("if")]
+    If,
+    #[token("else")]
+    Else,
+    #[token("while")]
+    While,
+    #[token("for")]
+    For,
+    #[token("in")]
+    In,
+    #[token("match")]
+    Match,
 
     // Position tally
     #[regex("(\r\n|\r|\n)")]
     Newline,
     #[regex(r"[ \t\f]+")]
     Whitespace,
-    #[regex("(?:#|//).*")]
+    /// The rest of a line after `#` or `//`, unless the `#` starts an
+    /// attribute.
+    #[regex(r"#(?:[^\[\r\n].*)?|//.*")]
     Comment,
 
     // Others
@@ -85,6 +142,131 @@ pub enum Tokens {
     Error,
 }
 
+/// A piece of the contents of a string literal, which can have
+/// expressions embedded in braces, like `"hello {name}"`.
+#[derive(Debug, PartialEq)]
+pub enum Piece<'a> {
+    Text(String),
+    /// The source of an embedded expression, and the offset into the
+    /// contents it starts at.
+    Expr(usize, &'a str),
+}
+
+/// Lex the rest of the string literal whose opening quote was just lexed.
+fn string(lex: &mut Lexer<Tokens>) -> Option<Slice> {
+    let len = string_len(&lex.source()[lex.span().start..])?;
+
+    lex.bump(len - 1);
+
+    Some(Slice::from(lex.span()).trim())
+}
+
+/// The length of the string literal `s` starts with, quotes included. The
+/// expressions embedded in it can have string literals of their own.
+/// `None` if it isn't closed.
+fn string_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return Some(i + 1),
+            b'\\' => i += 1,
+            b'{' | b'}' if bytes.get(i + 1) == Some(&bytes[i]) => i += 1,
+            // a brace that isn't closed is text, which `pieces` reports
+            b'{' => i += embedded_len(&s[i + 1..]).map_or(0, |len| len + 1),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// The offset of the brace that closes the expression `s` starts with,
+/// `None` if there isn't one.
+fn embedded_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i += string_len(&s[i..])?;
+                continue;
+            }
+            b'\'' => {
+                i += s[i + 1..].find('\'')? + 2;
+                continue;
+            }
+            // struct literals inside bring their own braces
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+const UNMATCHED_BRACE: &str =
+    "unmatched brace in a string, write `{{` or `}}` for a brace on its own";
+
+/// Split the contents of a string literal into text and embedded
+/// expressions, where `{{` and `}}` stand for braces of their own and
+/// escape sequences are decoded. Fails with the offset of a brace that
+/// isn't matched or an escape sequence that isn't known, and why.
+pub fn pieces(contents: &str) -> Result<Vec<Piece<'_>>, (usize, &'static str)> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = contents.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek().map(|&(_, next)| next) == Some(c) => {
+                chars.next();
+                text.push(c);
+            }
+            '}' => return Err((i, UNMATCHED_BRACE)),
+            '\\' => text.push(match chars.next() {
+                Some((_, 'n')) => '\n',
+                Some((_, 't')) => '\t',
+                Some((_, '0')) => '\0',
+                Some((_, c @ ('\\' | '"'))) => c,
+                _ => {
+                    return Err((
+                        i,
+                        "unknown escape sequence, expected one of `\\n`, `\\t`, `\\0`, `\\\\` or `\\\"`",
+                    ))
+                }
+            }),
+            '{' => {
+                let end = i + 1 + embedded_len(&contents[i + 1..]).ok_or((i, UNMATCHED_BRACE))?;
+
+                while chars.next_if(|&(j, _)| j <= end).is_some() {}
+
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(mem::take(&mut text)));
+                }
+
+                pieces.push(Piece::Expr(i + 1, &contents[i + 1..end]));
+            }
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() || pieces.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+
+    Ok(pieces)
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Slice {
     pub start: u32,
@@ -99,6 +281,10 @@ impl Slice {
         }
     }
 
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start as usize..self.end as usize
+    }
+
     pub fn from(span: Span) -> Self {
         Self {
             start: span.start as _,
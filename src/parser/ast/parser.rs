@@ -1,5 +1,4 @@
-use std::iter::Peekable;
-use std::process::exit;
+use std::{iter::Peekable, mem};
 
 use logos::{Logos, Span, SpannedIter};
 
@@ -9,18 +8,26 @@ use crate::parser::{
             Node::{self, *},
             TopLevel,
         },
-        lexer::Tokens,
+        lexer::{self, Piece, Slice, Tokens},
     },
     error::{CompileError, CompileErrorBuilder, ErrorCodes},
 };
 
-use super::ast::{Function, NodeType, Op};
+use super::ast::{
+    Attribute, Constant, Enum, Extern, Function, Import, ImportMember, MatchArm, NodeType, Op,
+    Pattern, Payload, Pos, Static, Struct, Variant,
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
 
 pub struct Parser<'a> {
     raw: &'a str,
     lexer: Peekable<SpannedIter<'a, Tokens>>,
     current_token: (Tokens, Span),
     pos: (usize, usize),
+    /// Set while parsing the condition of an `if` or `while`, where a `{`
+    /// after a name starts the body rather than a struct literal.
+    no_struct_literal: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -30,91 +37,142 @@ impl<'a> Parser<'a> {
             lexer: Tokens::lexer(raw).spanned().peekable(),
             current_token: (Tokens::Error, 0..0),
             pos: (0, 0),
+            no_struct_literal: false,
         }
     }
 
-    pub fn parse(mut self) -> TopLevel {
-        let mut top_level = TopLevel { fns: Vec::new() };
-
-        while let Some(token) = self.next() {
+    pub fn parse(mut self) -> Result<TopLevel> {
+        let mut top_level = TopLevel {
+            fns: Vec::new(),
+            externs: Vec::new(),
+            consts: Vec::new(),
+            statics: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+            imports: Vec::new(),
+        };
+
+        while let Some(token) = self.next()? {
             match token {
                 Tokens::Semicolon => {}
                 _ => {
-                    let stmt = self.parse_statement();
+                    let stmt = self.parse_statement()?;
 
                     match stmt {
                         Fn { .. } => {
                             top_level.fns.push(stmt);
                         }
+                        Node::Extern(_) => {
+                            top_level.externs.push(stmt);
+                        }
+                        Const { .. } => {
+                            top_level.consts.push(stmt);
+                        }
+                        Node::Static(_) => {
+                            top_level.statics.push(stmt);
+                        }
+                        Node::Struct(_) => {
+                            top_level.structs.push(stmt);
+                        }
+                        Node::Enum(_) => {
+                            top_level.enums.push(stmt);
+                        }
+                        Use { .. } => {
+                            top_level.imports.push(stmt);
+                        }
                         _ => panic!("parse_statement failed"),
                     }
                 }
             }
         }
 
-        top_level
+        Ok(top_level)
     }
 
-    pub fn error(&self, message: &str) -> ! {
-        let m = message.to_string();
-        let mut e = CompileErrorBuilder::new().code(0).message(m).build();
+    /// Parse a sequence of statements outside of any function, as typed into
+    /// the REPL. The final expression may omit its semicolon.
+    pub fn parse_body(mut self) -> Result<Node> {
+        let mut body = Vec::new();
+
+        while let Some(token) = self.next()? {
+            match token {
+                Tokens::Semicolon => {}
+                _ => body.push(self.parse_stmt_or_expr()?),
+            }
+        }
 
-        self.emit_error(&mut e);
+        Ok(Block(body))
     }
 
-    fn emit_error(&self, err: &mut CompileError) -> ! {
-        let err = err.with_raw(self.raw.to_string()).set_pos(self.pos);
+    pub fn error(&self, message: &str) -> CompileError {
+        let m = message.to_string();
+        let e = CompileErrorBuilder::new().code(0).message(m).build();
+
+        self.emit_error(e)
+    }
 
-        eprintln!("{}", err);
-        exit(1);
+    /// Attach the source and the current position to an error.
+    fn emit_error(&self, mut err: CompileError) -> CompileError {
+        err.with_raw(self.raw.to_string()).set_pos(self.pos);
+        err
     }
 
-    fn unknown_token(&self, where_: &str) -> ! {
-        let mut e = CompileErrorBuilder::new()
+    fn unknown_token<T>(&self, where_: &str) -> Result<T> {
+        let e = CompileErrorBuilder::new()
             .from(ErrorCodes::UnexpectedToken)
             .note(format!("error occurred at {}", where_).as_str())
             .build();
 
-        self.emit_error(&mut e);
+        Err(self.emit_error(e))
     }
 
     // Lexer helpers
 
     #[inline]
-    fn next(&mut self) -> Option<Tokens> {
+    fn next(&mut self) -> Result<Option<Tokens>> {
+        self.skip_trivia();
+
         if let Some(next) = self.lexer.next() {
             self.pos.1 += next.1.len();
 
             match next.0 {
-                Tokens::Whitespace => self.next(),
-                Tokens::Newline => {
-                    self.pos.0 += 1;
-                    self.pos.1 = 0;
-                    self.next()
-                }
-                Tokens::Error => {
-                    self.unknown_token("lexer");
+                Tokens::Error if self.raw[next.1.clone()].bytes().all(|b| b.is_ascii_digit()) => {
+                    let e = CompileErrorBuilder::new()
+                        .from(ErrorCodes::LiteralOutOfRange)
+                        .note(&format!(
+                            "`{}` doesn't fit in an `int`",
+                            &self.raw[next.1.clone()]
+                        ))
+                        .hint(&format!("the largest `int` is {}", i32::MAX))
+                        .build();
+                    let mut e = self.emit_error(e);
+
+                    // point at the start of the literal rather than past it
+                    e.set_pos((self.pos.0, self.pos.1 - next.1.len() + 1));
+
+                    Err(e)
                 }
+                Tokens::Error => self.unknown_token("lexer"),
                 _ => {
                     self.current_token = next;
-                    Some(self.token())
+                    Ok(Some(self.token()))
                 }
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
     #[inline]
-    fn next_force(&mut self) -> Tokens {
-        match self.next() {
-            Some(t) => t,
+    fn next_force(&mut self) -> Result<Tokens> {
+        match self.next()? {
+            Some(t) => Ok(t),
             None => {
-                let mut e = CompileErrorBuilder::new()
+                let e = CompileErrorBuilder::new()
                     .from(ErrorCodes::UnexpectedEOF)
                     .build();
 
-                self.emit_error(&mut e);
+                Err(self.emit_error(e))
             }
         }
     }
@@ -138,248 +196,1265 @@ impl<'a> Parser<'a> {
 
     #[inline]
     fn peek(&mut self) -> Option<&(Tokens, Span)> {
+        self.skip_trivia();
         self.lexer.peek()
     }
 
+    /// Consume any whitespace, newlines and comments, keeping `pos` in sync.
+    fn skip_trivia(&mut self) {
+        while let Some((Tokens::Whitespace | Tokens::Newline | Tokens::Comment, _)) =
+            self.lexer.peek()
+        {
+            let (token, span) = self.lexer.next().unwrap();
+
+            if token == Tokens::Newline {
+                self.pos.0 += 1;
+                self.pos.1 = 0;
+            } else {
+                self.pos.1 += span.len();
+            }
+        }
+    }
+
+    /// The position the current token starts at.
+    #[inline(always)]
+    fn at(&self) -> Pos {
+        (self.pos.0, self.pos.1 - self.span().len())
+    }
+
     // Node parsers
 
-    fn parse_statement(&mut self) -> Node {
+    fn parse_statement(&mut self) -> Result<Node> {
         match self.current_token.0 {
             Tokens::Fn => self.parse_fn(),
+            Tokens::Extern => self.parse_extern(),
+            Tokens::HashBracket => self.parse_attributed(),
+            Tokens::Const => self.parse_const(),
+            Tokens::Static => self.parse_static(),
+            Tokens::Struct => self.parse_struct(),
+            Tokens::Enum => self.parse_enum(),
+            Tokens::Use => self.parse_use(),
+            Tokens::Pub => self.parse_pub(),
             _ => {
-                let mut e = CompileErrorBuilder::new()
-                    .from(ErrorCodes::UnexpectedEOF)
-                    .note("expected `fn` at top level")
+                let e = CompileErrorBuilder::new()
+                    .from(ErrorCodes::UnexpectedToken)
+                    .note("expected `fn`, `extern`, `const`, `static`, `struct`, `enum`, `use`, `pub` or an attribute at top level")
                     .build();
 
-                self.emit_error(&mut e)
+                Err(self.emit_error(e))
             }
         }
     }
 
-    fn parse_fn(&mut self) -> Node {
-        let name = self.resolve_ident();
-        let args = self.parse_fn_args();
-        let ret = self.parse_fn_ret();
-        let body = self.parse_block();
+    /// Parse an item visible to other modules.
+    fn parse_pub(&mut self) -> Result<Node> {
+        let mut item = match self.next_force()? {
+            Tokens::Fn
+            | Tokens::Extern
+            | Tokens::Const
+            | Tokens::Static
+            | Tokens::Struct
+            | Tokens::Enum => self.parse_statement()?,
+            _ => return self.unknown_token("`pub` item"),
+        };
+
+        match &mut item {
+            Node::Fn(func) => func.public = true,
+            Node::Extern(item) => item.public = true,
+            Node::Const(constant) => constant.public = true,
+            Node::Static(item) => item.public = true,
+            Node::Struct(item) => item.public = true,
+            Node::Enum(item) => item.public = true,
+            _ => unreachable!("only items can be `pub`"),
+        }
+
+        Ok(item)
+    }
+
+    fn parse_fn(&mut self) -> Result<Node> {
+        let at = self.at();
+        let name = self.resolve_ident()?;
+        let (args, variadic) = self.parse_fn_args()?;
+
+        if variadic {
+            let e = CompileErrorBuilder::new()
+                .from(ErrorCodes::UnexpectedToken)
+                .note("only `extern` functions can take `...`")
+                .build();
+
+            return Err(self.emit_error(e));
+        }
+
+        let ret = self.parse_fn_ret()?;
+        let body = self.parse_block()?;
 
-        Node::Fn(Box::new(Function {
+        Ok(Node::Fn(Box::new(Function {
             name,
+            public: false,
+            no_mangle: false,
             args,
             body,
             ret,
-        }))
+            at,
+        })))
+    }
+
+    /// Parse `extern fn name(args) -> :ret;`, which has no body.
+    fn parse_extern(&mut self) -> Result<Node> {
+        let at = self.at();
+
+        self.ensure(Tokens::Fn)?;
+
+        let name = self.resolve_ident()?;
+        let (args, variadic) = self.parse_fn_args()?;
+        let ret = self.parse_fn_ret()?;
+
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Node::Extern(Box::new(Extern {
+            name,
+            public: false,
+            args,
+            variadic,
+            ret,
+            link: Vec::new(),
+            at,
+        })))
+    }
+
+    /// Parse the attributes before an item and the item itself. There are
+    /// only two, `#[no_mangle]` on a `fn` and `#[link(name = "m")]` on an
+    /// `extern fn`.
+    fn parse_attributed(&mut self) -> Result<Node> {
+        let mut attributes = vec![self.parse_attribute()?];
+
+        while self.next_force()? == Tokens::HashBracket {
+            attributes.push(self.parse_attribute()?);
+        }
+
+        let mut item = self.parse_statement()?;
+
+        for attribute in attributes {
+            match (
+                &mut item,
+                attribute.name.as_str(),
+                attribute.args.as_slice(),
+            ) {
+                (Node::Fn(func), "no_mangle", []) => func.no_mangle = true,
+                (_, "no_mangle", _) => {
+                    return Err(
+                        self.attribute_error(&attribute, "expected `#[no_mangle]` on a `fn`")
+                    )
+                }
+                (Node::Extern(item), "link", [(key, name)]) if key == "name" => {
+                    item.link.push(name.clone())
+                }
+                (_, "link", _) => {
+                    return Err(self.attribute_error(
+                        &attribute,
+                        "expected `#[link(name = \"..\")]` on an `extern fn`",
+                    ))
+                }
+                (_, name, _) => {
+                    return Err(
+                        self.attribute_error(&attribute, &format!("unknown attribute `{}`", name))
+                    )
+                }
+            }
+        }
+
+        Ok(item)
+    }
+
+    /// Parse `#[name]` or `#[name(key = "value", ..)]`, starting at the `#[`.
+    fn parse_attribute(&mut self) -> Result<Attribute> {
+        let at = self.at();
+        let name = self.resolve_ident()?;
+        let mut args = Vec::new();
+
+        match self.next_force()? {
+            Tokens::RSquare => return Ok(Attribute { name, args, at }),
+            Tokens::LParen => {}
+            _ => return self.unknown_token("attribute"),
+        }
+
+        loop {
+            match self.next_force()? {
+                Tokens::RParen => break,
+                Tokens::Identifier(_) => {
+                    let key = self.slice();
+
+                    self.ensure(Tokens::Equals)?;
+
+                    let value = match self.next_force()? {
+                        Tokens::String(contents) => match self.parse_string(contents)? {
+                            String(value) => value,
+                            _ => return self.unknown_token("attribute value"),
+                        },
+                        _ => return self.unknown_token("attribute value"),
+                    };
+
+                    args.push((key, value));
+                }
+                _ => return self.unknown_token("attribute arguments"),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RParen => break,
+                _ => return self.unknown_token("attribute arguments"),
+            }
+        }
+
+        self.ensure(Tokens::RSquare)?;
+
+        Ok(Attribute { name, args, at })
+    }
+
+    fn attribute_error(&self, attribute: &Attribute, note: &str) -> CompileError {
+        let e = CompileErrorBuilder::new()
+            .from(ErrorCodes::UnexpectedToken)
+            .note(note)
+            .build();
+        let mut e = self.emit_error(e);
+
+        e.set_pos(attribute.at);
+        e
+    }
+
+    fn parse_const(&mut self) -> Result<Node> {
+        let at = self.at();
+        let name = self.resolve_ident()?;
+        let ty = self.resolve_type()?;
+
+        self.ensure(Tokens::Equals)?;
+        self.next_force()?;
+
+        let value = self.parse_expr()?;
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Node::Const(Box::new(Constant {
+            name,
+            public: false,
+            ty,
+            value,
+            at,
+        })))
+    }
+
+    fn parse_static(&mut self) -> Result<Node> {
+        let at = self.at();
+        let mutable = matches!(self.peek(), Some((Tokens::Mut, _)));
+
+        if mutable {
+            self.next()?;
+        }
+
+        let name = self.resolve_ident()?;
+        let ty = self.resolve_type()?;
+
+        self.ensure(Tokens::Equals)?;
+        self.next_force()?;
+
+        let value = self.parse_expr()?;
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Node::Static(Box::new(Static {
+            name,
+            public: false,
+            ty,
+            mutable,
+            value,
+            at,
+        })))
+    }
+
+    fn parse_struct(&mut self) -> Result<Node> {
+        let at = self.at();
+        let name = self.resolve_ident()?;
+
+        self.ensure(Tokens::LBrace)?;
+
+        let fields = self.parse_fields()?;
+
+        Ok(Node::Struct(Box::new(Struct {
+            name,
+            public: false,
+            fields,
+            at,
+        })))
+    }
+
+    /// The `name: type` fields after the opening brace of a struct or a
+    /// variant, up to the closing one.
+    fn parse_fields(&mut self) -> Result<Vec<(std::string::String, NodeType)>> {
+        let mut fields = Vec::new();
+
+        loop {
+            match self.next_force()? {
+                Tokens::RBrace => break,
+                Tokens::Identifier(_) => {
+                    fields.push((self.slice(), self.resolve_type()?));
+                }
+                _ => return self.unknown_token("fields"),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RBrace => break,
+                _ => return self.unknown_token("fields"),
+            }
+        }
+
+        Ok(fields)
+    }
+
+    fn parse_enum(&mut self) -> Result<Node> {
+        let at = self.at();
+        let name = self.resolve_ident()?;
+        let mut variants = Vec::new();
+
+        self.ensure(Tokens::LBrace)?;
+
+        loop {
+            match self.next_force()? {
+                Tokens::RBrace => break,
+                Tokens::Identifier(_) => {
+                    let at = self.at();
+                    let name = self.slice();
+                    let payload = match self.peek() {
+                        Some((Tokens::LParen, _)) => {
+                            self.next_force()?;
+                            Payload::Tuple(self.parse_types()?)
+                        }
+                        Some((Tokens::LBrace, _)) => {
+                            self.next_force()?;
+                            Payload::Named(self.parse_fields()?)
+                        }
+                        _ => Payload::Unit,
+                    };
+
+                    variants.push(Variant { name, payload, at });
+                }
+                _ => return self.unknown_token("enum variants"),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RBrace => break,
+                _ => return self.unknown_token("enum variants"),
+            }
+        }
+
+        Ok(Node::Enum(Box::new(Enum {
+            name,
+            public: false,
+            variants,
+            at,
+        })))
+    }
+
+    /// The types after the opening parenthesis of a variant like
+    /// `Circle(float)`, up to the closing one.
+    fn parse_types(&mut self) -> Result<Vec<NodeType>> {
+        let mut types = Vec::new();
+
+        loop {
+            match self.next_force()? {
+                Tokens::RParen => break,
+                _ => types.push(self.parse_type()?),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RParen => break,
+                _ => return self.unknown_token("variant fields"),
+            }
+        }
+
+        Ok(types)
+    }
+
+    fn parse_use(&mut self) -> Result<Node> {
+        let at = self.at();
+        let mut path = vec![self.resolve_ident()?];
+
+        let members = loop {
+            self.ensure(Tokens::PathSep)?;
+
+            match self.next_force()? {
+                Tokens::Identifier(_) => {
+                    let name = self.slice();
+
+                    match self.peek() {
+                        Some((Tokens::PathSep, _)) => path.push(name),
+                        _ => break ImportMember::Named(vec![Identifier(name)]),
+                    }
+                }
+                Tokens::Star => break ImportMember::AllDestructured,
+                Tokens::LBrace => break ImportMember::Named(self.parse_import_list()?),
+                _ => return self.unknown_token("import path"),
+            }
+        };
+
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Node::Use(Box::new(Import { path, members, at })))
+    }
+
+    /// The names between the braces of `use foo::{bar, baz};`.
+    fn parse_import_list(&mut self) -> Result<Vec<Node>> {
+        let mut names = Vec::new();
+
+        loop {
+            match self.next_force()? {
+                Tokens::RBrace => break,
+                Tokens::Identifier(_) => {
+                    names.push(Identifier(self.slice()));
+
+                    match self.next_force()? {
+                        Tokens::Comma => {}
+                        Tokens::RBrace => break,
+                        _ => return self.unknown_token("import list"),
+                    }
+                }
+                _ => return self.unknown_token("import list"),
+            }
+        }
+
+        Ok(names)
     }
 
-    fn parse_fn_args(&mut self) -> Vec<(std::string::String, NodeType)> {
+    /// The arguments of a function, and whether they end with `...`.
+    fn parse_fn_args(&mut self) -> Result<(Vec<(std::string::String, NodeType)>, bool)> {
         let mut args = Vec::new();
 
-        self.ensure(Tokens::LParen);
+        self.ensure(Tokens::LParen)?;
 
         loop {
-            match self.next_force() {
+            match self.next_force()? {
                 Tokens::RParen => break,
+                Tokens::Ellipsis => {
+                    self.ensure(Tokens::RParen)?;
+
+                    return Ok((args, true));
+                }
                 Tokens::Identifier(_) => {
-                    args.push((self.slice(), self.resolve_type()));
+                    args.push((self.slice(), self.resolve_type()?));
                 }
-                _ => self.unknown_token("function arguments"),
+                _ => return self.unknown_token("function arguments"),
             }
 
-            match self.next_force() {
+            match self.next_force()? {
                 Tokens::Comma => {}
                 Tokens::RParen => break,
-                _ => self.unknown_token("function arguments"),
+                _ => return self.unknown_token("function arguments"),
             }
         }
 
-        args
+        Ok((args, false))
     }
 
-    fn parse_fn_ret(&mut self) -> NodeType {
-        self.ensure(Tokens::RArrow);
+    fn parse_fn_ret(&mut self) -> Result<NodeType> {
+        self.ensure(Tokens::RArrow)?;
 
         self.resolve_type()
     }
 
-    fn parse_call_args(&mut self) -> Vec<Node> {
+    fn parse_call_args(&mut self) -> Result<Vec<Node>> {
         let mut args = Vec::new();
 
         loop {
-            match self.next_force() {
+            match self.next_force()? {
                 Tokens::RParen => break,
                 _ => {
-                    args.push(self.parse_value());
+                    args.push(self.parse_nested_expr()?);
                 }
             }
 
-            match self.next_force() {
+            match self.next_force()? {
                 Tokens::Comma => {}
                 Tokens::RParen => break,
-                _ => self.unknown_token("call arguments"),
+                _ => return self.unknown_token("call arguments"),
+            }
+        }
+
+        Ok(args)
+    }
+
+    /// The fields between the braces of a struct literal.
+    fn parse_struct_literal(&mut self) -> Result<Vec<(std::string::String, Node)>> {
+        let mut fields = Vec::new();
+
+        loop {
+            match self.next_force()? {
+                Tokens::RBrace => break,
+                Tokens::Identifier(_) => {
+                    let name = self.slice();
+
+                    self.ensure(Tokens::Colon)?;
+                    self.next_force()?;
+
+                    fields.push((name, self.parse_nested_expr()?));
+                }
+                _ => return self.unknown_token("struct literal"),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RBrace => break,
+                _ => return self.unknown_token("struct literal"),
+            }
+        }
+
+        Ok(fields)
+    }
+
+    /// The fields of a value like `Shape::Circle(1.0)`, after its name.
+    fn parse_variant_literal(&mut self) -> Result<Payload<Node>> {
+        let literal = !self.no_struct_literal;
+
+        match self.peek() {
+            Some((Tokens::LParen, _)) => {
+                self.next_force()?;
+                Ok(Payload::Tuple(self.parse_call_args()?))
+            }
+            Some((Tokens::LBrace, _)) if literal => {
+                self.next_force()?;
+                Ok(Payload::Named(self.parse_struct_literal()?))
+            }
+            _ => Ok(Payload::Unit),
+        }
+    }
+
+    fn parse_match(&mut self) -> Result<Node> {
+        self.next_force()?;
+
+        let scrutinee = self.parse_condition()?;
+        let mut arms = Vec::new();
+
+        self.ensure(Tokens::LBrace)?;
+
+        loop {
+            if let Some((Tokens::RBrace, _)) = self.peek() {
+                self.next_force()?;
+                break;
+            }
+
+            self.next_force()?;
+
+            let at = self.at();
+            let pattern = self.parse_pattern()?;
+
+            self.ensure(Tokens::FatArrow)?;
+
+            let block = matches!(self.peek(), Some((Tokens::LBrace, _)));
+            let body = if block {
+                self.parse_block()?
+            } else {
+                self.next_force()?;
+                self.parse_nested_expr()?
+            };
+
+            arms.push(MatchArm { pattern, body, at });
+
+            match self.peek() {
+                Some((Tokens::Comma, _)) => {
+                    self.next_force()?;
+                }
+                Some((Tokens::RBrace, _)) => {}
+                // an arm with a block doesn't need a comma after it
+                _ if block => {}
+                _ => {
+                    self.next_force()?;
+                    return self.unknown_token("match arms");
+                }
+            }
+        }
+
+        Ok(Node::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+        })
+    }
+
+    /// Parse a pattern starting at the current token, leaving its last token
+    /// as the current one.
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        match self.token() {
+            Tokens::Identifier(_) => {
+                let ty = self.slice();
+
+                if ty == "_" {
+                    return Ok(Pattern::Wildcard);
+                }
+
+                if !matches!(self.peek(), Some((Tokens::PathSep, _))) {
+                    return Ok(Pattern::Binding(ty));
+                }
+
+                self.next_force()?;
+
+                let name = self.resolve_ident()?;
+                let (payload, rest) = match self.peek() {
+                    Some((Tokens::LParen, _)) => {
+                        self.next_force()?;
+                        self.parse_tuple_pattern()?
+                    }
+                    Some((Tokens::LBrace, _)) => {
+                        self.next_force()?;
+                        self.parse_named_pattern()?
+                    }
+                    _ => (Payload::Unit, false),
+                };
+
+                Ok(Pattern::Variant {
+                    ty,
+                    name,
+                    payload,
+                    rest,
+                })
             }
+            Tokens::Integer(_)
+            | Tokens::Float(_)
+            | Tokens::Char(_)
+            | Tokens::Boolean(_)
+            | Tokens::String(_)
+            | Tokens::Minus => Ok(Pattern::Literal(self.parse_unary()?)),
+            _ => self.unknown_token("pattern"),
         }
+    }
+
+    /// The fields of a pattern like `Shape::Circle(r)` after the opening
+    /// parenthesis, and whether they end in `..`.
+    fn parse_tuple_pattern(&mut self) -> Result<(Payload<Pattern>, bool)> {
+        let mut fields = Vec::new();
+
+        let rest = loop {
+            match self.next_force()? {
+                Tokens::RParen => break false,
+                Tokens::Dot => {
+                    self.ensure(Tokens::Dot)?;
+                    self.ensure(Tokens::RParen)?;
+                    break true;
+                }
+                _ => fields.push(self.parse_pattern()?),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RParen => break false,
+                _ => return self.unknown_token("pattern fields"),
+            }
+        };
+
+        Ok((Payload::Tuple(fields), rest))
+    }
+
+    /// The fields of a pattern like `Shape::Rect { w, h: 1.0 }` after the
+    /// opening brace, and whether they end in `..`.
+    fn parse_named_pattern(&mut self) -> Result<(Payload<Pattern>, bool)> {
+        let mut fields = Vec::new();
+
+        let rest = loop {
+            match self.next_force()? {
+                Tokens::RBrace => break false,
+                Tokens::Dot => {
+                    self.ensure(Tokens::Dot)?;
+                    self.ensure(Tokens::RBrace)?;
+                    break true;
+                }
+                Tokens::Identifier(_) => {
+                    let name = self.slice();
+                    let pattern = match self.peek() {
+                        Some((Tokens::Colon, _)) => {
+                            self.next_force()?;
+                            self.next_force()?;
+                            self.parse_pattern()?
+                        }
+                        // `Rect { w }` is short for `Rect { w: w }`
+                        _ => Pattern::Binding(name.clone()),
+                    };
+
+                    fields.push((name, pattern));
+                }
+                _ => return self.unknown_token("pattern fields"),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RBrace => break false,
+                _ => return self.unknown_token("pattern fields"),
+            }
+        };
 
-        args
+        Ok((Payload::Named(fields), rest))
     }
 
-    fn parse_block(&mut self) -> Node {
+    fn parse_block(&mut self) -> Result<Node> {
         let mut body = Vec::new();
 
-        self.ensure(Tokens::LBrace);
+        self.ensure(Tokens::LBrace)?;
 
         loop {
-            match self.next_force() {
+            match self.next_force()? {
                 Tokens::RBrace => break,
                 Tokens::Semicolon => {}
                 _ => {
-                    body.push(self.parse_stmt_or_expr());
+                    body.push(self.parse_stmt_or_expr()?);
                 }
             }
         }
 
-        Block(body)
+        Ok(Block(body))
     }
 
-    fn parse_stmt_or_expr(&mut self) -> Node {
-        match self.token() {
-            Tokens::Fn => self.parse_fn(),
-            Tokens::LBrace => self.parse_block(),
+    fn parse_stmt_or_expr(&mut self) -> Result<Node> {
+        let at = self.at();
+
+        let node = match self.token() {
+            Tokens::Fn => self.parse_fn()?,
+            Tokens::LBrace => self.parse_block()?,
+            Tokens::Let => self.parse_let()?,
+            Tokens::Return => self.parse_return()?,
+            Tokens::If => self.parse_if()?,
+            Tokens::While => self.parse_while()?,
+            Tokens::For => self.parse_for()?,
+            Tokens::Match => {
+                let node = self.parse_match()?;
+
+                // like a block, a `match` statement ends at its closing brace
+                if let Some((Tokens::Semicolon, _)) = self.peek() {
+                    self.next_force()?;
+                }
+
+                node
+            }
             Tokens::Identifier(_)
             | Tokens::Integer(_)
             | Tokens::Boolean(_)
             | Tokens::Float(_)
-            | Tokens::Null => self.parse_expr(),
-            _ => self.unknown_token("statement or expression"),
-        }
-    }
-
-    fn parse_expr(&mut self) -> Node {
-        let lhs = self.parse_value();
-        match self.next_force() {
-            Tokens::Semicolon => lhs,
-            a => {
-                dbg!(a);
-                let op = self.resolve_op();
-                let rhs = match self.peek().unwrap().0 {
-                    Tokens::Semicolon => None,
-                    _ => {
-                        self.next_force();
-                        Some(Box::new(self.parse_value()))
-                    }
-                };
+            | Tokens::Char(_)
+            | Tokens::String(_)
+            | Tokens::LParen
+            | Tokens::LSquare
+            | Tokens::Minus
+            | Tokens::Bang
+            | Tokens::Null => {
+                let expr = self.parse_expr()?;
+
+                // only a trailing expression at the very end of the input may
+                // leave out its semicolon
+                if self.peek().is_some() {
+                    self.ensure(Tokens::Semicolon)?;
+                }
+
+                expr
+            }
+            _ => return self.unknown_token("statement or expression"),
+        };
+
+        Ok(Stmt {
+            node: Box::new(node),
+            at,
+        })
+    }
+
+    fn parse_let(&mut self) -> Result<Node> {
+        let name = self.resolve_ident()?;
+        let ty = match self.peek() {
+            Some((Tokens::Colon, _)) => Some(self.resolve_type()?),
+            _ => None,
+        };
+
+        // `let x: int;` declares a variable to be assigned later
+        if let (Some((Tokens::Semicolon, _)), Some(_)) = (self.peek(), &ty) {
+            self.next_force()?;
+
+            return Ok(Let {
+                name,
+                ty,
+                value: None,
+            });
+        }
+
+        self.ensure(Tokens::Equals)?;
+        self.next_force()?;
+
+        let value = self.parse_expr()?;
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Let {
+            name,
+            ty,
+            value: Some(Box::new(value)),
+        })
+    }
 
-                Node::Expr {
-                    lhs: Box::new(lhs),
-                    op,
-                    rhs,
+    fn parse_return(&mut self) -> Result<Node> {
+        if let Some((Tokens::Semicolon, _)) = self.peek() {
+            self.next_force()?;
+
+            return Ok(Return(None));
+        }
+
+        self.next_force()?;
+
+        let value = self.parse_expr()?;
+        self.ensure(Tokens::Semicolon)?;
+
+        Ok(Return(Some(Box::new(value))))
+    }
+
+    fn parse_if(&mut self) -> Result<Node> {
+        self.next_force()?;
+
+        let cond = self.parse_condition()?;
+        let then = self.parse_block()?;
+        let otherwise = match self.peek() {
+            Some((Tokens::Else, _)) => {
+                self.next_force()?;
+
+                match self.peek() {
+                    Some((Tokens::If, _)) => {
+                        self.next_force()?;
+                        let at = self.at();
+
+                        // `else if` is sugar for an `else` block holding another `if`
+                        Some(Block(vec![Stmt {
+                            node: Box::new(self.parse_if()?),
+                            at,
+                        }]))
+                    }
+                    _ => Some(self.parse_block()?),
                 }
             }
+            _ => None,
+        };
+
+        Ok(If {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise: otherwise.map(Box::new),
+        })
+    }
+
+    fn parse_while(&mut self) -> Result<Node> {
+        self.next_force()?;
+
+        let cond = self.parse_condition()?;
+        let body = self.parse_block()?;
+
+        Ok(While {
+            cond: Box::new(cond),
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<Node> {
+        let name = self.resolve_ident()?;
+        self.ensure(Tokens::In)?;
+        self.next_force()?;
+
+        let iter = self.parse_condition()?;
+        let body = self.parse_block()?;
+
+        Ok(Node::For {
+            name,
+            iter: Box::new(iter),
+            body: Box::new(body),
+        })
+    }
+
+    /// Parse an expression starting at the current token, leaving the last
+    /// token of the expression as the current one.
+    fn parse_expr(&mut self) -> Result<Node> {
+        self.parse_binary(0)
+    }
+
+    /// Parse the condition of an `if` or `while`, which can't be a struct
+    /// literal unless it's in parentheses.
+    fn parse_condition(&mut self) -> Result<Node> {
+        let outer = mem::replace(&mut self.no_struct_literal, true);
+        let cond = self.parse_expr();
+
+        self.no_struct_literal = outer;
+        cond
+    }
+
+    /// Parse an expression between delimiters, where struct literals are
+    /// allowed again.
+    fn parse_nested_expr(&mut self) -> Result<Node> {
+        let outer = mem::replace(&mut self.no_struct_literal, false);
+        let expr = self.parse_expr();
+
+        self.no_struct_literal = outer;
+        expr
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Node> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some(precedence) = self
+            .peek()
+            .and_then(|(token, _)| precedence(token))
+            .filter(|p| *p >= min_precedence)
+        {
+            self.next_force()?;
+            let op = self.resolve_op()?;
+            self.next_force()?;
+
+            // assignment is right-associative, everything else is left-associative
+            let rhs = if op == Op::Assign {
+                self.parse_binary(precedence)?
+            } else {
+                self.parse_binary(precedence + 1)?
+            };
+
+            lhs = Node::Expr {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Some(Box::new(rhs)),
+            };
         }
+
+        Ok(lhs)
     }
 
-    fn parse_value(&mut self) -> Node {
-        match self.token() {
-            Tokens::Identifier(val) => {
+    fn parse_unary(&mut self) -> Result<Node> {
+        let op = match self.token() {
+            Tokens::Minus => Op::Sub,
+            Tokens::Bang => Op::Not,
+            _ => return self.parse_value(),
+        };
+
+        self.next_force()?;
+
+        Ok(Node::Expr {
+            lhs: Box::new(self.parse_unary()?),
+            op,
+            rhs: None,
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<Node> {
+        let mut value = match self.token() {
+            Tokens::Identifier(_) => {
                 let id = self.slice();
+                let literal = !self.no_struct_literal;
 
-                match self.peek().unwrap().0 {
-                    Tokens::LParen => {
-                        self.next_force();
-                        let args = self.parse_call_args();
+                match self.peek() {
+                    Some((Tokens::LParen, _)) => {
+                        self.next_force()?;
+                        let args = self.parse_call_args()?;
                         Call { name: id, args }
                     }
-                    _ => Identifier(val),
+                    Some((Tokens::LBrace, _)) if literal => {
+                        self.next_force()?;
+                        let fields = self.parse_struct_literal()?;
+                        StructLiteral { name: id, fields }
+                    }
+                    Some((Tokens::PathSep, _)) => {
+                        self.next_force()?;
+                        let name = self.resolve_ident()?;
+                        let payload = self.parse_variant_literal()?;
+
+                        Node::Variant {
+                            ty: id,
+                            name,
+                            payload,
+                        }
+                    }
+                    _ => Identifier(id),
+                }
+            }
+            Tokens::Match => self.parse_match()?,
+            Tokens::Integer(i) => Integer(i),
+            Tokens::Float(f) => Float(f),
+            Tokens::Boolean(b) => Bool(b),
+            Tokens::Char(c) => Char(c),
+            Tokens::String(s) => self.parse_string(s)?,
+            Tokens::LParen => {
+                self.next_force()?;
+                let expr = self.parse_nested_expr()?;
+                self.ensure(Tokens::RParen)?;
+
+                expr
+            }
+            Tokens::LSquare => Node::Array(self.parse_array_literal()?),
+            _ => return self.unknown_token("value"),
+        };
+
+        loop {
+            value = match self.peek() {
+                Some((Tokens::Dot, _)) => {
+                    self.next_force()?;
+
+                    Field {
+                        base: Box::new(value),
+                        name: self.resolve_ident()?,
+                    }
+                }
+                Some((Tokens::LSquare, _)) => {
+                    self.next_force()?;
+                    self.next_force()?;
+                    let index = self.parse_nested_expr()?;
+                    self.ensure(Tokens::RSquare)?;
+
+                    Node::Index {
+                        base: Box::new(value),
+                        index: Box::new(index),
+                    }
+                }
+                _ => break,
+            };
+        }
+
+        Ok(value)
+    }
+
+    /// A string literal, which is interpolated if it has expressions
+    /// embedded in it.
+    fn parse_string(&mut self, contents: Slice) -> Result<Node> {
+        let raw = &self.raw[contents.range()];
+        // just after the opening quote
+        let (line, column) = self.at();
+        let column = column + 1;
+
+        let pieces = lexer::pieces(raw).map_err(|(offset, note)| {
+            let e = CompileErrorBuilder::new()
+                .from(ErrorCodes::UnexpectedToken)
+                .note(note)
+                .build();
+            let mut e = self.emit_error(e);
+
+            e.set_pos((line, column + offset + 1));
+            e
+        })?;
+
+        let mut parts = Vec::new();
+
+        for piece in pieces {
+            let part = match piece {
+                Piece::Text(text) => String(text),
+                Piece::Expr(offset, source) => {
+                    // where the braces around it are
+                    let open = (line, column + offset);
+                    let close = (line, column + offset + source.len() + 1);
+
+                    if source.trim().is_empty() {
+                        let e = CompileErrorBuilder::new()
+                            .from(ErrorCodes::UnexpectedToken)
+                            .note("expected an expression between the braces")
+                            .hint("write `{{}}` for braces of their own")
+                            .build();
+                        let mut e = self.emit_error(e);
+
+                        e.set_pos(open);
+                        return Err(e);
+                    }
+
+                    Parser::new(source).parse_embedded().map_err(|e| {
+                        let (mut e, at) = if e.is(ErrorCodes::UnexpectedEOF) {
+                            let e = CompileErrorBuilder::new()
+                                .from(ErrorCodes::UnexpectedToken)
+                                .note("expected the rest of the expression before the brace")
+                                .build();
+
+                            (e, close)
+                        } else {
+                            // errors are found relative to the embedded source
+                            let at = match e.at {
+                                (0, c) => (line, column + offset + c),
+                                (l, c) => (line + l, c),
+                            };
+
+                            (e, at)
+                        };
+
+                        e.with_raw(self.raw.to_string()).set_pos(at);
+                        e
+                    })?
                 }
+            };
+
+            parts.push(part);
+        }
+
+        match parts.as_slice() {
+            [String(_)] => Ok(parts.pop().unwrap()),
+            _ => Ok(Interpolation(parts)),
+        }
+    }
+
+    /// An expression embedded in a string, which has to be the whole of
+    /// what's between the braces.
+    fn parse_embedded(mut self) -> Result<Node> {
+        self.next_force()?;
+        let expr = self.parse_nested_expr()?;
+
+        match self.next()? {
+            Some(_) => self.unknown_token("embedded expression"),
+            None => Ok(expr),
+        }
+    }
+
+    /// The elements between the brackets of an array literal.
+    fn parse_array_literal(&mut self) -> Result<Vec<Node>> {
+        let mut elements = Vec::new();
+
+        loop {
+            match self.next_force()? {
+                Tokens::RSquare => break,
+                _ => elements.push(self.parse_nested_expr()?),
+            }
+
+            match self.next_force()? {
+                Tokens::Comma => {}
+                Tokens::RSquare => break,
+                _ => return self.unknown_token("array literal"),
             }
-            _ => unimplemented!(),
         }
+
+        Ok(elements)
     }
 
     // Guards
 
-    fn ensure(&mut self, token: Tokens) -> Tokens {
-        if self.next_force() != token {
-            let mut err = CompileErrorBuilder::new()
+    fn ensure(&mut self, token: Tokens) -> Result<Tokens> {
+        if self.next_force()? != token {
+            let err = CompileErrorBuilder::new()
                 .from(ErrorCodes::UnexpectedToken)
                 .note(format!("expected `{:?}`", token).as_str())
                 .build();
 
-            self.emit_error(&mut err);
+            return Err(self.emit_error(err));
         }
 
-        self.token()
+        Ok(self.token())
     }
 
     // Resolvers
 
-    fn resolve_ident(&mut self) -> std::string::String {
-        match self.next_force() {
-            Tokens::Identifier(_) => self.slice(),
+    fn resolve_ident(&mut self) -> Result<std::string::String> {
+        match self.next_force()? {
+            Tokens::Identifier(_) => Ok(self.slice()),
             _ => {
-                let mut err = CompileErrorBuilder::new()
+                let err = CompileErrorBuilder::new()
                     .from(ErrorCodes::UnexpectedToken)
-                    .note("expected identifier after function name")
+                    .note("expected identifier")
                     .build();
 
-                self.emit_error(&mut err);
+                Err(self.emit_error(err))
             }
         }
     }
 
-    fn resolve_type(&mut self) -> NodeType {
-        self.ensure(Tokens::Colon);
+    fn resolve_type(&mut self) -> Result<NodeType> {
+        self.ensure(Tokens::Colon)?;
+        self.next_force()?;
 
-        match self.next_force() {
-            Tokens::Identifier(_) => {
-                let id = self.slice();
+        self.parse_type()
+    }
 
-                match id.as_str() {
-                    "int" => NodeType::Integer,
-                    "float" => NodeType::Float,
-                    "char" => NodeType::Char,
-                    "str" => NodeType::String,
-                    _ => {
-                        let mut err = CompileErrorBuilder::new()
-                            .from(ErrorCodes::UnexpectedToken)
-                            .note("expected type")
-                            .build();
+    /// Parse the type named by the current token.
+    fn parse_type(&mut self) -> Result<NodeType> {
+        if let Tokens::LSquare = self.token() {
+            self.next_force()?;
+            let ty = self.parse_type()?;
 
-                        self.emit_error(&mut err);
-                    }
-                }
+            match self.next_force()? {
+                Tokens::RSquare => return Ok(NodeType::Slice(Box::new(ty))),
+                Tokens::Semicolon => {}
+                _ => return self.unknown_token("array type"),
             }
-            _ => {
-                let mut err = CompileErrorBuilder::new()
-                    .from(ErrorCodes::UnexpectedToken)
-                    .note("expected type")
-                    .build();
 
-                self.emit_error(&mut err);
+            let len = match self.next_force()? {
+                Tokens::Integer(len) => len as usize,
+                _ => return self.unknown_token("array length"),
+            };
+
+            self.ensure(Tokens::RSquare)?;
+
+            return Ok(NodeType::Array(Box::new(ty), len));
+        }
+
+        if let Tokens::Identifier(_) = self.token() {
+            let id = self.slice();
+
+            if let ("Vec", Some((Tokens::LessThan, _))) = (id.as_str(), self.peek()) {
+                self.next_force()?;
+                self.next_force()?;
+                let ty = self.parse_type()?;
+                self.ensure(Tokens::GreaterThan)?;
+
+                return Ok(NodeType::Vec(Box::new(ty)));
             }
+
+            // any other name is a struct or an enum, which the type checker
+            // resolves
+            return Ok(match id.as_str() {
+                "int" => NodeType::Integer,
+                "float" => NodeType::Float,
+                "char" => NodeType::Char,
+                "str" => NodeType::String,
+                "bool" => NodeType::Bool,
+                "void" => NodeType::Void,
+                _ => NodeType::Struct(id),
+            });
         }
+
+        let err = CompileErrorBuilder::new()
+            .from(ErrorCodes::UnexpectedToken)
+            .note("expected type")
+            .build();
+
+        Err(self.emit_error(err))
     }
 
-    fn resolve_op(&mut self) -> Op {
-        match self.token() {
+    fn resolve_op(&mut self) -> Result<Op> {
+        let op = match self.token() {
             Tokens::Plus => Op::Add,
             Tokens::Minus => Op::Sub,
             Tokens::Star => Op::Mul,
             Tokens::Slash => Op::Div,
             Tokens::Modulo => Op::Mod,
-            Tokens::Equals => Op::Eq,
+            Tokens::Equals => Op::Assign,
+            Tokens::EqualEqual => Op::Eq,
             Tokens::NotEqual => Op::Neq,
             Tokens::LessThan => Op::Lt,
             Tokens::GreaterThan => Op::Gt,
             Tokens::LessThanEqual => Op::Le,
             Tokens::GreaterThanEqual => Op::Ge,
+            Tokens::And => Op::And,
+            Tokens::Or => Op::Or,
             _ => {
-                let mut err = CompileErrorBuilder::new()
+                let err = CompileErrorBuilder::new()
                     .from(ErrorCodes::UnexpectedToken)
                     .note("expected operator")
                     .build();
 
-                self.emit_error(&mut err);
+                return Err(self.emit_error(err));
             }
-        }
+        };
+
+        Ok(op)
+    }
+}
+
+/// Binding power of a binary operator token, higher binds tighter.
+fn precedence(token: &Tokens) -> Option<u8> {
+    match token {
+        Tokens::Equals => Some(1),
+        Tokens::Or => Some(2),
+        Tokens::And => Some(3),
+        Tokens::EqualEqual | Tokens::NotEqual => Some(4),
+        Tokens::LessThan
+        | Tokens::GreaterThan
+        | Tokens::LessThanEqual
+        | Tokens::GreaterThanEqual => Some(5),
+        Tokens::Plus | Tokens::Minus => Some(6),
+        Tokens::Star | Tokens::Slash | Tokens::Modulo => Some(7),
+        _ => None,
     }
 }
@@ -1,4 +1,8 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    path::Path,
+};
 
 use ansi_term::{Colour, Style};
 
@@ -24,22 +28,86 @@ fn minus_one(i: usize) -> usize {
     }
 }
 
-#[derive(Default, Debug)]
-pub struct CompileError {
+/// How serious a diagnostic is. Warnings don't stop compilation.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
+/// An error or a warning found while compiling or running code. It's boxed
+/// so that results carrying one stay small.
+#[derive(Default, Debug, Clone)]
+pub struct CompileError(Box<Details>);
+
+/// What a [`CompileError`] says, which its fields can be read through.
+#[derive(Default, Debug, Clone)]
+pub struct Details {
     pub code: u32,
     pub message: String,
     pub at: (usize, usize),
     pub hints: Vec<String>,
     pub notes: Vec<String>,
+    pub severity: Severity,
     raw: String,
+    /// The file the error is in, if it isn't the one being compiled.
+    file: Option<String>,
+}
+
+impl Deref for CompileError {
+    type Target = Details;
+
+    fn deref(&self) -> &Details {
+        &self.0
+    }
+}
+
+impl DerefMut for CompileError {
+    fn deref_mut(&mut self) -> &mut Details {
+        &mut self.0
+    }
 }
 
 impl CompileError {
+    /// Whether this is an error of the kind `code`.
+    pub fn is(&self, code: ErrorCodes) -> bool {
+        self.code == code.code()
+    }
+
     pub fn with_raw(&mut self, raw: String) -> &mut Self {
+        if self.file.is_none() {
+            self.raw = raw;
+        }
+
+        self
+    }
+
+    /// Attach the file the error is in along with its source, which
+    /// [`CompileError::with_raw`] won't replace afterwards.
+    pub fn in_file(&mut self, file: &Path, raw: String) -> &mut Self {
         self.raw = raw;
+        self.file = Some(file.display().to_string());
         self
     }
 
+    /// Attach the code the error is in, `file` being `None` for the file
+    /// being compiled, unless it already has some. Errors are located by
+    /// the function they happen in, before the functions calling it.
+    pub fn in_source(&mut self, file: Option<&Path>, raw: &str) -> &mut Self {
+        if self.raw.is_empty() && self.file.is_none() {
+            self.raw = raw.to_string();
+            self.file = file.map(|file| file.display().to_string());
+        }
+
+        self
+    }
+
+    /// The file the error is in, if it isn't the one being compiled.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
     pub fn set_pos(&mut self, pos: (usize, usize)) -> &mut Self {
         self.at = pos;
         self
@@ -56,7 +124,10 @@ impl CompileError {
     fn fmt_at(&self, pos: Option<(usize, usize)>) -> String {
         let (line, column) = pos.unwrap_or(self.at);
 
-        format!("{}:{}", line, column)
+        match &self.file {
+            Some(file) => format!("{}:{}:{}", file, line, column),
+            None => format!("{}:{}", line, column),
+        }
     }
 
     fn prepare_line(&self) -> (String, (usize, usize)) {
@@ -67,14 +138,14 @@ impl CompileError {
 
         // check for unexpected EOF & adjust line/col accordingly
         if self.code == 1002 && line.is_none() {
-            let mut tmp = lines.get(idx - 1).unwrap_or(&"");
-            idx -= 1;
+            idx = idx.saturating_sub(1);
+            let mut tmp = lines.get(idx).unwrap_or(&"");
 
             // newline at end of file
             if tmp.trim().is_empty() {
                 // we can ignore a newline in this, because it's not the last line
-                tmp = lines.get(idx - 1).unwrap_or(&"");
-                idx -= 1;
+                idx = idx.saturating_sub(1);
+                tmp = lines.get(idx).unwrap_or(&"");
             }
 
             col = tmp.len();
@@ -90,17 +161,21 @@ impl Display for CompileError {
         let (l, pos) = self.prepare_line();
         let p = self.fmt_at(Some(pos));
         let s = Style::new();
+        let (label, colour) = match self.severity {
+            Severity::Error => ("Error", Colour::Red),
+            Severity::Warning => ("Warning", Colour::Yellow),
+        };
 
         f.write_str(&format!(
             "{} [E{}] {} at {}\n\t{} {}\n\t   {} {}",
-            &s.fg(Colour::Red).paint("Error"),
+            &s.fg(colour).paint(label),
             self.code,
             self.message,
             p,
             &s.dimmed().paint("at:"),
             l,
             " ".repeat(minus_one(pos.1)),
-            &s.fg(Colour::Red).paint("^")
+            &s.fg(colour).paint("^")
         ))
         .ok();
 
@@ -130,6 +205,8 @@ impl Display for CompileError {
     }
 }
 
+impl std::error::Error for CompileError {}
+
 pub enum Error {
     Compile(CompileError),
 }
@@ -140,6 +217,13 @@ pub struct CompileErrorBuilder {
     pub at: (usize, usize),
     pub hints: Vec<String>,
     pub notes: Vec<String>,
+    pub severity: Severity,
+}
+
+impl Default for CompileErrorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CompileErrorBuilder {
@@ -150,6 +234,7 @@ impl CompileErrorBuilder {
             at: (0, 0),
             hints: Vec::new(),
             notes: Vec::new(),
+            severity: Severity::Error,
         }
     }
 
@@ -160,6 +245,7 @@ impl CompileErrorBuilder {
             at: (0, 0),
             hints: Vec::new(),
             notes: Vec::new(),
+            severity: Severity::Error,
         }
     }
 
@@ -188,6 +274,12 @@ impl CompileErrorBuilder {
         self
     }
 
+    /// Report this as a warning rather than an error.
+    pub fn warning(mut self) -> Self {
+        self.severity = Severity::Warning;
+        self
+    }
+
     pub fn from(mut self, template: ErrorCodes) -> Self {
         self.code = template.code();
         self.message = template.message();
@@ -195,14 +287,16 @@ impl CompileErrorBuilder {
     }
 
     pub fn build(self) -> CompileError {
-        CompileError {
+        CompileError(Box::new(Details {
             code: self.code,
             message: self.message,
             at: self.at,
             hints: self.hints,
             notes: self.notes,
+            severity: self.severity,
             raw: String::new(),
-        }
+            file: None,
+        }))
     }
 }
 
@@ -212,6 +306,27 @@ pub enum ErrorCodes {
     UnknownError = 1000,
     UnexpectedToken,
     UnexpectedEOF,
+    MismatchedTypes,
+    UnknownIdentifier,
+    CodegenError,
+    RuntimeError,
+    DuplicateDefinition,
+    UninitialisedVariable,
+    UnreachableCode,
+    MissingReturn,
+    ConstEvalError,
+    UnresolvedImport,
+    ImportCycle,
+    PrivateItem,
+    RecursiveType,
+    NonExhaustiveMatch,
+    OutOfFuel,
+    OutOfMemory,
+    SandboxViolation,
+    LiteralOutOfRange,
+    InvalidAssignment,
+    NotAllowedHere,
+    OutOfStack,
 }
 
 impl ErrorCodes {
@@ -224,6 +339,27 @@ impl ErrorCodes {
             ErrorCodes::UnknownError => "Unknown error".to_string(),
             ErrorCodes::UnexpectedToken => "Unexpected token".to_string(),
             ErrorCodes::UnexpectedEOF => "Unexpected end of file".to_string(),
+            ErrorCodes::MismatchedTypes => "Mismatched types".to_string(),
+            ErrorCodes::UnknownIdentifier => "Unknown identifier".to_string(),
+            ErrorCodes::CodegenError => "Code generation failed".to_string(),
+            ErrorCodes::RuntimeError => "Runtime error".to_string(),
+            ErrorCodes::DuplicateDefinition => "Duplicate definition".to_string(),
+            ErrorCodes::UninitialisedVariable => "Use of uninitialised variable".to_string(),
+            ErrorCodes::UnreachableCode => "Unreachable code".to_string(),
+            ErrorCodes::MissingReturn => "Missing return".to_string(),
+            ErrorCodes::ConstEvalError => "Constant evaluation failed".to_string(),
+            ErrorCodes::UnresolvedImport => "Unresolved import".to_string(),
+            ErrorCodes::ImportCycle => "Cyclic import".to_string(),
+            ErrorCodes::PrivateItem => "Private item".to_string(),
+            ErrorCodes::RecursiveType => "Recursive type".to_string(),
+            ErrorCodes::NonExhaustiveMatch => "Non-exhaustive match".to_string(),
+            ErrorCodes::OutOfFuel => "Out of fuel".to_string(),
+            ErrorCodes::OutOfMemory => "Out of memory".to_string(),
+            ErrorCodes::SandboxViolation => "Not allowed in a sandbox".to_string(),
+            ErrorCodes::LiteralOutOfRange => "Literal out of range".to_string(),
+            ErrorCodes::InvalidAssignment => "Invalid assignment".to_string(),
+            ErrorCodes::NotAllowedHere => "Not allowed here".to_string(),
+            ErrorCodes::OutOfStack => "Out of stack".to_string(),
         }
     }
 
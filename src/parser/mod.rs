@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use self::ast::parser::Parser;
+use self::{ast::parser::Parser, error::CompileError};
 
 pub mod ast;
 pub mod error;
@@ -8,11 +8,11 @@ pub mod error;
 pub use ast::ast::TopLevel;
 
 impl FromStr for ast::ast::TopLevel {
-    type Err = ();
+    type Err = CompileError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parser = Parser::new(s);
 
-        Ok(parser.parse())
+        parser.parse()
     }
 }
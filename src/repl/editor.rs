@@ -0,0 +1,164 @@
+use std::{env, path::PathBuf};
+
+use logos::Logos;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+use qlang::parser::ast::lexer::Tokens;
+
+const PROMPT: &str = "%> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+const KEYWORDS: &[&str] = &[
+    "fn", "const", "static", "mut", "struct", "enum", "use", "pub", "extern", "let", "return",
+    "if", "else", "while", "for", "in", "match", "true", "false", "null", "int", "float", "char",
+    "str", "bool", "void",
+];
+
+/// A line editor with history that reads whole inputs, which may span
+/// several lines.
+pub struct Editor {
+    editor: rustyline::Editor<Completions>,
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    pub fn new(commands: &[&'static str]) -> Self {
+        let mut editor = rustyline::Editor::new();
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(".qlang_history"));
+
+        editor.set_helper(Some(Completions {
+            commands: commands.to_vec(),
+            functions: Vec::new(),
+        }));
+
+        if let Some(history) = &history {
+            editor.load_history(history).ok();
+        }
+
+        Self { editor, history }
+    }
+
+    /// Read the next input, asking for more lines while it has unclosed
+    /// brackets. Returns `None` once the user closes the input stream.
+    pub fn read(&mut self) -> Option<String> {
+        let mut input = String::new();
+
+        loop {
+            let prompt = if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    input.push_str(&line);
+                    input.push('\n');
+
+                    if depth(&input) <= 0 {
+                        break;
+                    }
+                }
+                // ^C throws away whatever was typed so far
+                Err(ReadlineError::Interrupted) => input.clear(),
+                Err(_) => return None,
+            }
+        }
+
+        let input = input.trim().to_string();
+
+        if !input.is_empty() {
+            self.editor.add_history_entry(input.as_str());
+
+            if let Some(history) = &self.history {
+                self.editor.save_history(history).ok();
+            }
+        }
+
+        Some(input)
+    }
+
+    /// Set the function names offered by tab completion.
+    pub fn set_functions(&mut self, functions: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.functions = functions;
+        }
+    }
+}
+
+/// How many more brackets have been opened than closed.
+fn depth(input: &str) -> i32 {
+    Tokens::lexer(input).fold(0, |depth, token| match token {
+        Tokens::LParen | Tokens::LBrace | Tokens::LSquare | Tokens::HashBracket => depth + 1,
+        Tokens::RParen | Tokens::RBrace | Tokens::RSquare => depth - 1,
+        _ => depth,
+    })
+}
+
+struct Completions {
+    commands: Vec<&'static str>,
+    functions: Vec<String>,
+}
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+
+        if line.starts_with('.') && !line.contains(' ') {
+            return Ok((0, candidates(line, self.commands.iter().copied())));
+        }
+
+        let start = line
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let word = &line[start..];
+
+        if word.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+
+        let names = KEYWORDS
+            .iter()
+            .copied()
+            .chain(self.functions.iter().map(String::as_str));
+
+        Ok((start, candidates(word, names)))
+    }
+}
+
+fn candidates<'a>(prefix: &str, names: impl Iterator<Item = &'a str>) -> Vec<Pair> {
+    names
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| Pair {
+            display: name.to_string(),
+            replacement: name.to_string(),
+        })
+        .collect()
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
@@ -1,42 +1,232 @@
-use std::io::{self, Write};
+use std::fs;
 
-use inkwell::context::Context;
-use qlang::llvm::codegen::Codegen;
+use logos::Logos;
+use qlang::{
+    hir::{Function, Program},
+    interp::{Interpreter, Value},
+    parser::{
+        ast::{lexer::Tokens, parser::Parser},
+        error::CompileError,
+        TopLevel,
+    },
+    typeck::TypeChecker,
+};
+#[cfg(feature = "llvm")]
+use qlang::{inkwell::context::Context, llvm::codegen::Codegen};
 
-#[inline]
-fn prompt() -> String {
-    print_flush("%> ");
-    let mut buf = String::new();
-    io::stdin().read_line(&mut buf).unwrap();
+use self::editor::Editor;
 
-    buf.trim().to_string()
+mod editor;
+
+const COMMANDS: &[&str] = &[
+    ".q", ".h", ".type", ".ast", ".ir", ".load", ".reset", ".save",
+];
+
+const HELP: &str = "\
+.q: quit
+.h: help
+.type <expr>: print the type of an expression
+.ast <code>: print the syntax tree of some code
+.ir <fn>: print the LLVM IR of a function
+.load <file>: define the functions in a file
+.reset: forget every definition
+.save <file>: write every definition to a file
+";
+
+/// Runs the code typed into the REPL.
+trait Backend {
+    fn define(&mut self, program: Program) -> Result<(), CompileError>;
+
+    fn evaluate(&mut self, func: Function) -> Result<Value, CompileError>;
+
+    /// The textual IR of a function, for backends that have one.
+    fn function_ir(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(feature = "llvm")]
+impl Backend for Codegen<'_> {
+    fn define(&mut self, program: Program) -> Result<(), CompileError> {
+        self.compile(program)
+    }
+
+    fn evaluate(&mut self, func: Function) -> Result<Value, CompileError> {
+        Codegen::evaluate(self, &func)
+    }
+
+    fn function_ir(&self, name: &str) -> Option<String> {
+        Codegen::function_ir(self, name)
+    }
 }
 
-#[inline]
-fn print_flush(s: &str) {
-    print!("{}", s);
-    io::stdout().flush().unwrap();
+impl Backend for Interpreter {
+    fn define(&mut self, program: Program) -> Result<(), CompileError> {
+        Interpreter::define(self, program)
+    }
+
+    fn evaluate(&mut self, func: Function) -> Result<Value, CompileError> {
+        Interpreter::evaluate(self, &func)
+    }
 }
 
-pub fn repl() {
+/// Everything defined so far in a REPL session.
+struct Session<B> {
+    backend: B,
+    checker: TypeChecker,
+    /// The source of every successful definition, in order.
+    definitions: Vec<String>,
+}
+
+/// Start the REPL, running code with the JIT unless `interp` is set.
+#[cfg(feature = "llvm")]
+pub fn repl(interp: bool) {
+    if interp {
+        return run(crate::interpreter);
+    }
+
     let context = Context::create();
-    let module = context.create_module("qlang_repl");
-    let codegen = Codegen::new(&context, module);
 
-    loop {
-        match prompt().as_str() {
+    run(|| Codegen::new(&context, context.create_module("qlang_repl")))
+}
+
+/// Start the REPL. Without LLVM, code is always interpreted.
+#[cfg(not(feature = "llvm"))]
+pub fn repl(_: bool) {
+    run(crate::interpreter)
+}
+
+fn run<B: Backend>(backend: impl Fn() -> B) {
+    let mut session = Session::new(backend());
+    let mut editor = Editor::new(COMMANDS);
+
+    while let Some(input) = editor.read() {
+        let (command, arg) = match input.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (input.as_str(), ""),
+        };
+
+        match command {
             ".q" => break,
-            ".h" => {
-                println!(
-                    "\
-                         .q: quit\n\
-                         .h: help\n\
-                         "
-                );
-            }
-            code => {
-                println!("{:?}", codegen.interpret(code));
+            ".h" => println!("{}", HELP),
+            ".type" => report(arg, session.type_of(arg)),
+            ".ast" => report(arg, session.ast(arg)),
+            ".ir" => match session.backend.function_ir(arg) {
+                Some(ir) => print!("{}", ir),
+                None => eprintln!("No function named `{}`", arg),
+            },
+            ".load" => match fs::read_to_string(arg) {
+                Ok(code) => report(&code, session.define(&code)),
+                Err(e) => eprintln!("Couldn't read {}: {}", arg, e),
+            },
+            ".reset" => session = Session::new(backend()),
+            ".save" => {
+                if let Err(e) = fs::write(arg, session.definitions.join("\n\n") + "\n") {
+                    eprintln!("Couldn't write {}: {}", arg, e);
+                }
             }
+            "" => {}
+            _ if command.starts_with('.') => eprintln!("Unknown command: {}", command),
+            _ if is_definition(&input) => report(&input, session.define(&input)),
+            _ => report(&input, session.evaluate(&input)),
         }
+
+        editor.set_functions(session.checker.function_names());
+    }
+}
+
+/// Print the error of a failed command, pointing into `code`.
+fn report(code: &str, result: Result<(), CompileError>) {
+    if let Err(mut e) = result {
+        eprintln!("{}", e.with_raw(code.to_string()));
+    }
+}
+
+/// Print the warnings found while checking `code`.
+fn warn(code: &str, warnings: Vec<CompileError>) {
+    for mut warning in warnings {
+        eprintln!("{}", warning.with_raw(code.to_string()));
+    }
+}
+
+/// Whether the input declares items rather than being code to run.
+fn is_definition(code: &str) -> bool {
+    let mut tokens = Tokens::lexer(code)
+        .filter(|t| !matches!(t, Tokens::Whitespace | Tokens::Newline | Tokens::Comment));
+
+    matches!(
+        tokens.next(),
+        Some(
+            Tokens::Fn
+                | Tokens::Const
+                | Tokens::Static
+                | Tokens::Struct
+                | Tokens::Enum
+                | Tokens::Use
+                | Tokens::Pub
+                | Tokens::Extern
+                | Tokens::HashBracket
+        )
+    )
+}
+
+impl<B: Backend> Session<B> {
+    fn new(backend: B) -> Self {
+        Self {
+            backend,
+            checker: TypeChecker::new(),
+            definitions: Vec::new(),
+        }
+    }
+
+    fn define(&mut self, code: &str) -> Result<(), CompileError> {
+        let ast: TopLevel = code.parse()?;
+
+        // only keep the new signatures around if everything compiles
+        let mut next = self.checker.clone();
+        let program = next.check(&ast)?;
+        self.backend.define(program)?;
+
+        warn(code, next.warnings());
+        self.checker = next;
+        self.definitions.push(code.trim().to_string());
+
+        Ok(())
+    }
+
+    fn evaluate(&mut self, code: &str) -> Result<(), CompileError> {
+        let body = Parser::new(code).parse_body()?;
+        let func = self.checker.check_body(&body)?;
+        let ty = func.ret.clone();
+
+        warn(code, self.checker.warnings());
+
+        match self.backend.evaluate(func)? {
+            Value::Void => {}
+            value => println!("{} : {}", value, ty),
+        }
+
+        Ok(())
+    }
+
+    fn type_of(&mut self, code: &str) -> Result<(), CompileError> {
+        let body = Parser::new(code).parse_body()?;
+
+        println!("{}", self.checker.check_body(&body)?.ret);
+
+        // only running the code should warn about it
+        self.checker.warnings();
+
+        Ok(())
+    }
+
+    fn ast(&self, code: &str) -> Result<(), CompileError> {
+        if is_definition(code) {
+            println!("{:#?}", code.parse::<TopLevel>()?);
+        } else {
+            println!("{:#?}", Parser::new(code).parse_body()?);
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,111 @@
+//! Evaluates constant expressions at compile time, for `const` items and to
+//! fold the constant parts of function bodies.
+//!
+//! Unlike at runtime, integer overflow is an error here rather than wrapping
+//! around, as is dividing by zero.
+
+use crate::{
+    hir::Literal,
+    parser::ast::ast::{Node, Op},
+};
+
+/// Evaluate a constant expression, looking up any names it uses with
+/// `constant`.
+pub fn eval(node: &Node, constant: &dyn Fn(&str) -> Option<Literal>) -> Result<Literal, String> {
+    match node {
+        Node::String(s) => Ok(Literal::String(s.clone())),
+        Node::Integer(i) => Ok(Literal::Integer(*i)),
+        Node::Float(f) => Ok(Literal::Float(*f)),
+        Node::Char(c) => Ok(Literal::Char(*c)),
+        Node::Bool(b) => Ok(Literal::Bool(*b)),
+        Node::Identifier(name) => {
+            constant(name).ok_or_else(|| format!("`{}` is not a constant", name))
+        }
+        Node::Expr { op: Op::Assign, .. } => {
+            Err("assignments can't be evaluated at compile time".to_string())
+        }
+        Node::Expr { lhs, op, rhs: None } => unary(*op, eval(lhs, constant)?),
+        Node::Expr {
+            lhs,
+            op,
+            rhs: Some(rhs),
+        } => binary(*op, eval(lhs, constant)?, eval(rhs, constant)?),
+        Node::Call { name, .. } => Err(format!(
+            "calls to `{}` can't be evaluated at compile time",
+            name
+        )),
+        _ => Err("expected a constant expression".to_string()),
+    }
+}
+
+pub fn unary(op: Op, value: Literal) -> Result<Literal, String> {
+    match (op, value) {
+        (Op::Sub, Literal::Integer(i)) => i
+            .checked_neg()
+            .map(Literal::Integer)
+            .ok_or_else(|| format!("`-{}` overflows an int", i)),
+        (Op::Sub, Literal::Float(f)) => Ok(Literal::Float(-f)),
+        (Op::Not, Literal::Bool(b)) => Ok(Literal::Bool(!b)),
+        (op, value) => Err(format!("cannot apply `{:?}` to {:?}", op, value)),
+    }
+}
+
+pub fn binary(op: Op, lhs: Literal, rhs: Literal) -> Result<Literal, String> {
+    use Literal::*;
+
+    let unsupported = || format!("cannot apply `{:?}` to these values", op);
+
+    let value = match (lhs, rhs) {
+        (Integer(l), Integer(r)) => {
+            let result = match op {
+                Op::Div | Op::Mod if r == 0 => return Err("attempt to divide by zero".into()),
+                Op::Add => l.checked_add(r),
+                Op::Sub => l.checked_sub(r),
+                Op::Mul => l.checked_mul(r),
+                Op::Div => l.checked_div(r),
+                Op::Mod => l.checked_rem(r),
+                _ => return compare(op, &l, &r).ok_or_else(unsupported),
+            };
+
+            Integer(result.ok_or_else(|| format!("`{} {} {}` overflows an int", l, op, r))?)
+        }
+        (Float(l), Float(r)) => match op {
+            Op::Add => Float(l + r),
+            Op::Sub => Float(l - r),
+            Op::Mul => Float(l * r),
+            Op::Div => Float(l / r),
+            Op::Mod => Float(l % r),
+            _ => compare(op, &l, &r).ok_or_else(unsupported)?,
+        },
+        (Char(l), Char(r)) => compare(op, &l, &r).ok_or_else(unsupported)?,
+        (String(l), String(r)) => match op {
+            Op::Add => String(l + &r),
+            _ => compare(op, &l, &r).ok_or_else(unsupported)?,
+        },
+        (Bool(l), Bool(r)) => match op {
+            Op::And => Bool(l && r),
+            Op::Or => Bool(l || r),
+            Op::Eq => Bool(l == r),
+            Op::Neq => Bool(l != r),
+            _ => return Err(unsupported()),
+        },
+        _ => return Err(format!("mismatched types for `{:?}`", op)),
+    };
+
+    Ok(value)
+}
+
+/// Apply a comparison operator, or `None` if `op` isn't one.
+fn compare<T: PartialOrd>(op: Op, l: &T, r: &T) -> Option<Literal> {
+    let result = match op {
+        Op::Eq => l == r,
+        Op::Neq => l != r,
+        Op::Lt => l < r,
+        Op::Gt => l > r,
+        Op::Le => l <= r,
+        Op::Ge => l >= r,
+        _ => return None,
+    };
+
+    Some(Literal::Bool(result))
+}
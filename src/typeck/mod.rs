@@ -0,0 +1,2523 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    rc::Rc,
+};
+
+use crate::{
+    hir::{
+        self, Block, Builtin, Expr, ExprKind, FnId, Global, GlobalId, Literal, Local, LocalId,
+        Place, Projection, Stmt, StmtKind,
+    },
+    loader::{self, Module},
+    mir,
+    parser::{
+        ast::ast::{
+            Constant, Enum, Extern, Function, ImportMember, MatchArm, Node, NodeType, Op, Pattern,
+            Payload, Pos, Static, Struct,
+        },
+        error::{CompileError, CompileErrorBuilder, ErrorCodes},
+        TopLevel,
+    },
+};
+
+use self::pattern::Pat;
+
+pub mod consteval;
+pub mod pattern;
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// The name given to code checked outside of any function.
+pub const ANONYMOUS_FN: &str = "__qlang_anonymous";
+
+/// Infers the type of every expression and checks that they are used
+/// consistently, lowering the syntax tree to [`hir`] for the backends.
+#[derive(Debug, Default, Clone)]
+pub struct TypeChecker {
+    /// Every function defined so far, indexed by [`FnId`].
+    fns: Vec<Signature>,
+    /// Every `static` defined so far, indexed by [`GlobalId`].
+    globals: Vec<Global>,
+    /// Every `struct` defined so far, by the name its type refers to.
+    structs: HashMap<String, hir::Struct>,
+    /// Every `enum` defined so far, by the name its type refers to.
+    enums: HashMap<String, hir::Enum>,
+    /// The items in scope in the module being checked, its own and the ones
+    /// it imports.
+    items: HashMap<String, Item>,
+    /// The names of the module's own items that were declared `pub`.
+    public: HashSet<String>,
+    /// Prepended to the names of the module's functions and statics, so
+    /// that they don't clash with those of other modules once compiled.
+    prefix: String,
+    /// The code of the module being checked, which its functions keep.
+    source: Option<Rc<hir::Source>>,
+    /// The variables of the function being checked, indexed by [`LocalId`].
+    locals: Vec<Local>,
+    scopes: Vec<HashMap<String, LocalId>>,
+    ret: Option<NodeType>,
+    /// Statements that have to run before the one being checked, like a
+    /// `match` whose value it uses.
+    hoisted: Vec<Stmt>,
+    at: Pos,
+    /// Warnings found since they were last taken.
+    warnings: Vec<CompileError>,
+}
+
+/// Something that can be referred to by name from anywhere in a module.
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Fn(FnId),
+    Const(Literal),
+    Static(GlobalId),
+    /// A struct, by the name its type refers to.
+    Struct(String),
+    /// An enum, by the name its type refers to.
+    Enum(String),
+}
+
+/// An item defined by a module, as seen by the modules importing it.
+#[derive(Debug)]
+struct Export {
+    item: Item,
+    public: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<NodeType>,
+    /// Whether it takes more arguments after `params`, like an `extern fn`
+    /// declared with `...`.
+    variadic: bool,
+    ret: NodeType,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check every item in a file that doesn't import anything.
+    pub fn check(&mut self, ast: &TopLevel) -> Result<hir::Program> {
+        if let Some(import) = loader::imports(ast).first() {
+            self.at = import.at;
+
+            return Err(self.error(
+                ErrorCodes::UnresolvedImport,
+                "modules can only be imported from files",
+            ));
+        }
+
+        self.source = None;
+        self.check_items(ast)
+    }
+
+    /// Check a program made of several modules, as loaded by
+    /// [`loader::load`], each in a namespace of its own. The root module's
+    /// items are in scope afterwards.
+    pub fn check_modules(&mut self, modules: &[Module]) -> Result<hir::Program> {
+        let mut exports = Vec::new();
+        let mut program = hir::Program {
+            structs: Vec::new(),
+            enums: Vec::new(),
+            globals: Vec::new(),
+            externs: Vec::new(),
+            functions: Vec::new(),
+        };
+
+        for module in modules {
+            let warnings = self.warnings.len();
+
+            self.items = HashMap::new();
+            self.public = HashSet::new();
+            self.prefix = module
+                .name
+                .iter()
+                .map(|part| format!("{}::", part))
+                .collect();
+            self.source = Some(Rc::new(hir::Source {
+                file: Some(module.path.clone()).filter(|_| !module.name.is_empty()),
+                text: module.source.clone(),
+            }));
+
+            let result = self.import(module, &exports).and_then(|imported| {
+                let checked = self.check_items(&module.ast)?;
+
+                Ok((checked, imported))
+            });
+
+            // errors in the root module are reported against its source as usual
+            let locate = |e: &mut CompileError| {
+                if !module.name.is_empty() {
+                    e.in_file(&module.path, module.source.clone());
+                }
+            };
+
+            self.warnings[warnings..].iter_mut().for_each(locate);
+
+            let (checked, imported) = result.map_err(|mut e| {
+                locate(&mut e);
+                e
+            })?;
+
+            program.structs.extend(checked.structs);
+            program.enums.extend(checked.enums);
+            program.globals.extend(checked.globals);
+            program.externs.extend(checked.externs);
+            program.functions.extend(checked.functions);
+
+            exports.push(
+                self.items
+                    .iter()
+                    .filter(|(name, _)| !imported.contains(*name))
+                    .map(|(name, item)| {
+                        let export = Export {
+                            item: item.clone(),
+                            public: self.public.contains(name),
+                        };
+
+                        (name.clone(), export)
+                    })
+                    .collect(),
+            );
+        }
+
+        Ok(program)
+    }
+
+    /// Bring the items a module imports into scope, returning their names.
+    /// Only `pub` items defined in a module can be imported from it, and
+    /// `*` leaves out the rest.
+    fn import(
+        &mut self,
+        module: &Module,
+        exports: &[HashMap<String, Export>],
+    ) -> Result<HashSet<String>> {
+        let mut imported = HashSet::new();
+
+        for (import, id) in loader::imports(&module.ast)
+            .into_iter()
+            .zip(&module.imports)
+        {
+            self.at = import.at;
+
+            let from = &exports[*id];
+            let names = match &import.members {
+                ImportMember::AllDestructured => from
+                    .iter()
+                    .filter(|(_, export)| export.public)
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+                ImportMember::Named(names) => names
+                    .iter()
+                    .filter_map(|name| match name {
+                        Node::Identifier(name) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            };
+
+            for name in names {
+                let item = match from.get(&name) {
+                    Some(Export { item, public: true }) => item,
+                    Some(_) => {
+                        return Err(self.error(
+                            ErrorCodes::PrivateItem,
+                            &format!(
+                                "`{}` is private to module `{}`, declare it `pub` to import it",
+                                name,
+                                import.module()
+                            ),
+                        ))
+                    }
+                    None => {
+                        return Err(self.error(
+                            ErrorCodes::UnresolvedImport,
+                            &format!("module `{}` has no item named `{}`", import.module(), name),
+                        ))
+                    }
+                };
+
+                // importing the same item twice is harmless
+                if self.items.get(&name) != Some(item) {
+                    self.unique_item(&name)?;
+                    self.items.insert(name.clone(), item.clone());
+                }
+
+                imported.insert(name);
+            }
+        }
+
+        Ok(imported)
+    }
+
+    fn check_items(&mut self, ast: &TopLevel) -> Result<hir::Program> {
+        let (structs, enums) = self.define_types(&structs(ast), &enums(ast))?;
+
+        for constant in constants(ast) {
+            self.define_const(constant)?;
+        }
+
+        let globals = statics(ast)
+            .into_iter()
+            .map(|item| self.define_static(item))
+            .collect::<Result<_>>()?;
+
+        let externs = externs(ast)
+            .into_iter()
+            .map(|item| self.define_extern(item))
+            .collect::<Result<_>>()?;
+
+        let funcs = functions(ast);
+        let ids = funcs
+            .iter()
+            .map(|func| self.define(func))
+            .collect::<Result<Vec<_>>>()?;
+
+        let functions = funcs
+            .into_iter()
+            .zip(ids)
+            .map(|(func, id)| self.check_function(func, id))
+            .collect::<Result<_>>()?;
+
+        Ok(hir::Program {
+            structs,
+            enums,
+            globals,
+            externs,
+            functions,
+        })
+    }
+
+    /// Define the structs and enums of a module. They're named before their
+    /// fields are checked, so that they can refer to each other in any order.
+    fn define_types(
+        &mut self,
+        structs: &[&Struct],
+        enums: &[&Enum],
+    ) -> Result<(Vec<hir::Struct>, Vec<hir::Enum>)> {
+        for item in structs {
+            self.name_type(&item.name, item.public, item.at, Item::Struct)?;
+        }
+
+        for item in enums {
+            self.name_type(&item.name, item.public, item.at, Item::Enum)?;
+        }
+
+        let mut defined = Vec::new();
+
+        for item in structs {
+            self.at = item.at;
+
+            let def = hir::Struct {
+                name: format!("{}{}", self.prefix, item.name),
+                fields: self.fields(&item.fields)?,
+                at: item.at,
+            };
+
+            self.structs.insert(def.name.clone(), def.clone());
+            defined.push(def);
+        }
+
+        let mut defined_enums = Vec::new();
+
+        for item in enums {
+            let mut variants: Vec<hir::Variant> = Vec::new();
+
+            for variant in &item.variants {
+                self.at = variant.at;
+
+                if variants.iter().any(|other| other.name == variant.name) {
+                    return Err(self.error(
+                        ErrorCodes::DuplicateDefinition,
+                        &format!("variant `{}` is already declared", variant.name),
+                    ));
+                }
+
+                let (fields, named) = match &variant.payload {
+                    Payload::Unit => (Vec::new(), false),
+                    Payload::Tuple(types) => {
+                        let fields = types
+                            .iter()
+                            .enumerate()
+                            .map(|(i, ty)| (i.to_string(), ty.clone()))
+                            .collect::<Vec<_>>();
+
+                        (self.fields(&fields)?, false)
+                    }
+                    Payload::Named(fields) => (self.fields(fields)?, true),
+                };
+
+                variants.push(hir::Variant {
+                    name: variant.name.clone(),
+                    fields,
+                    named,
+                });
+            }
+
+            let def = hir::Enum {
+                name: format!("{}{}", self.prefix, item.name),
+                variants,
+                at: item.at,
+            };
+
+            self.enums.insert(def.name.clone(), def.clone());
+            defined_enums.push(def);
+        }
+
+        let types = defined
+            .iter()
+            .map(|def| ("struct", NodeType::Struct(def.name.clone()), def.at))
+            .chain(
+                defined_enums
+                    .iter()
+                    .map(|def| ("enum", NodeType::Enum(def.name.clone()), def.at)),
+            )
+            .collect::<Vec<_>>();
+
+        for (kind, ty, at) in types {
+            self.at = at;
+
+            if self.contains(&ty, &ty, &mut HashSet::new()) {
+                return Err(self.error(
+                    ErrorCodes::RecursiveType,
+                    &format!(
+                        "{} `{}` contains itself, so it would be infinitely large",
+                        kind, ty
+                    ),
+                ));
+            }
+        }
+
+        Ok((defined, defined_enums))
+    }
+
+    /// Bring a struct or enum into scope under `name`, before its fields are
+    /// known.
+    fn name_type(
+        &mut self,
+        name: &str,
+        public: bool,
+        at: Pos,
+        item: fn(String) -> Item,
+    ) -> Result<()> {
+        self.at = at;
+        self.unique_item(name)?;
+
+        let ty = format!("{}{}", self.prefix, name);
+
+        self.items.insert(name.to_string(), item(ty));
+        self.publish(name, public);
+
+        Ok(())
+    }
+
+    /// Check the fields of a struct or a variant.
+    fn fields(&self, fields: &[(String, NodeType)]) -> Result<Vec<(String, NodeType)>> {
+        let mut checked: Vec<(String, NodeType)> = Vec::new();
+
+        for (name, ty) in fields {
+            if checked.iter().any(|(field, _)| field == name) {
+                return Err(self.error(
+                    ErrorCodes::DuplicateDefinition,
+                    &format!("field `{}` is already declared", name),
+                ));
+            }
+
+            let ty = self.resolve(ty)?;
+
+            if ty == NodeType::Void {
+                return Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    &format!("field `{}` can't have type void", name),
+                ));
+            }
+
+            checked.push((name.clone(), ty));
+        }
+
+        Ok(checked)
+    }
+
+    /// Whether a value of `ty` holds a `target` somewhere in its fields.
+    /// `seen` is the types already looked into.
+    fn contains(&self, ty: &NodeType, target: &NodeType, seen: &mut HashSet<String>) -> bool {
+        let fields = match ty {
+            NodeType::Struct(name) if seen.insert(name.clone()) => {
+                self.structs[name].fields.iter().collect::<Vec<_>>()
+            }
+            NodeType::Enum(name) if seen.insert(name.clone()) => self.enums[name]
+                .variants
+                .iter()
+                .flat_map(|variant| &variant.fields)
+                .collect(),
+            NodeType::Array(element, _) => {
+                return **element == *target || self.contains(element, target, seen)
+            }
+            _ => return false,
+        };
+
+        fields
+            .into_iter()
+            .any(|(_, ty)| ty == target || self.contains(ty, target, seen))
+    }
+
+    /// Resolve the type names in a type as it was written to the structs
+    /// and enums they refer to in the current module.
+    fn resolve(&self, ty: &NodeType) -> Result<NodeType> {
+        match ty {
+            NodeType::Struct(name) => match self.items.get(name) {
+                Some(Item::Struct(name)) => Ok(NodeType::Struct(name.clone())),
+                Some(Item::Enum(name)) => Ok(NodeType::Enum(name.clone())),
+                _ => Err(self.error(
+                    ErrorCodes::UnknownIdentifier,
+                    &format!("unknown type `{}`", name),
+                )),
+            },
+            NodeType::Fn { args, ret } => Ok(NodeType::Fn {
+                args: args
+                    .iter()
+                    .map(|arg| self.resolve(arg))
+                    .collect::<Result<_>>()?,
+                ret: Box::new(self.resolve(ret)?),
+            }),
+            NodeType::Array(element, len) => match self.resolve(element)? {
+                NodeType::Void => Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    "arrays can't hold values of type void",
+                )),
+                element => Ok(NodeType::Array(Box::new(element), *len)),
+            },
+            NodeType::Vec(element) => match self.resolve(element)? {
+                NodeType::Void => Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    "`Vec`s can't hold values of type void",
+                )),
+                element => Ok(NodeType::Vec(Box::new(element))),
+            },
+            NodeType::Slice(element) => match self.resolve(element)? {
+                NodeType::Void => Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    "slices can't hold values of type void",
+                )),
+                element => Ok(NodeType::Slice(Box::new(element))),
+            },
+            ty => Ok(ty.clone()),
+        }
+    }
+
+    /// Make a function's signature known to subsequent checks.
+    pub fn define(&mut self, func: &Function) -> Result<FnId> {
+        self.at = func.at;
+        self.unique_item(&func.name)?;
+
+        let id = self.fns.len();
+        let params = func
+            .args
+            .iter()
+            .map(|(_, ty)| self.resolve(ty))
+            .collect::<Result<Vec<_>>>()?;
+        let ret = self.resolve(&func.ret)?;
+
+        if func.no_mangle {
+            if func.name == "main" {
+                return Err(self.error(
+                    ErrorCodes::DuplicateDefinition,
+                    "`main` is always exported, so it can't be marked `#[no_mangle]`",
+                ));
+            }
+
+            self.expect_c_types("exported functions", &params, &ret)?;
+        }
+
+        self.fns.push(Signature {
+            params,
+            variadic: false,
+            ret,
+        });
+        self.items.insert(func.name.clone(), Item::Fn(id));
+        self.publish(&func.name, func.public);
+
+        Ok(id)
+    }
+
+    /// Make an `extern fn`'s signature known to subsequent checks. It can
+    /// only take and return values that C has a type for.
+    pub fn define_extern(&mut self, item: &Extern) -> Result<hir::Extern> {
+        self.at = item.at;
+        self.unique_item(&item.name)?;
+
+        let params = item
+            .args
+            .iter()
+            .map(|(_, ty)| self.resolve(ty))
+            .collect::<Result<Vec<_>>>()?;
+        let ret = self.resolve(&item.ret)?;
+
+        self.expect_c_types("`extern` functions", &params, &ret)?;
+
+        let id = self.fns.len();
+
+        self.fns.push(Signature {
+            params: params.clone(),
+            variadic: item.variadic,
+            ret: ret.clone(),
+        });
+        self.items.insert(item.name.clone(), Item::Fn(id));
+        self.publish(&item.name, item.public);
+
+        Ok(hir::Extern {
+            id,
+            name: item.name.clone(),
+            params,
+            variadic: item.variadic,
+            ret,
+            link: item.link.clone(),
+            at: item.at,
+        })
+    }
+
+    /// Evaluate a `const` item. It can only refer to constants defined
+    /// before it.
+    pub fn define_const(&mut self, constant: &Constant) -> Result<()> {
+        self.at = constant.at;
+        self.unique_item(&constant.name)?;
+
+        let value = consteval::eval(&constant.value, &|name| self.constant(name))
+            .map_err(|e| self.fold_error(e))?;
+
+        self.expect(&self.resolve(&constant.ty)?, &value.ty())?;
+        self.items.insert(constant.name.clone(), Item::Const(value));
+        self.publish(&constant.name, constant.public);
+
+        Ok(())
+    }
+
+    /// Evaluate the initial value of a `static` item, which like a `const`
+    /// can only refer to constants.
+    pub fn define_static(&mut self, item: &Static) -> Result<Global> {
+        self.at = item.at;
+        self.unique_item(&item.name)?;
+
+        let value = consteval::eval(&item.value, &|name| self.constant(name))
+            .map_err(|e| self.fold_error(e))?;
+
+        let ty = self.resolve(&item.ty)?;
+        self.expect(&ty, &value.ty())?;
+
+        let global = Global {
+            id: self.globals.len(),
+            name: format!("{}{}", self.prefix, item.name),
+            ty,
+            mutable: item.mutable,
+            value,
+            at: item.at,
+        };
+
+        self.globals.push(global.clone());
+        self.items
+            .insert(item.name.clone(), Item::Static(global.id));
+        self.publish(&item.name, item.public);
+
+        Ok(global)
+    }
+
+    /// Check that a function called from or calling into C only takes and
+    /// returns values that C has a type for.
+    fn expect_c_types(&self, what: &str, params: &[NodeType], ret: &NodeType) -> Result<()> {
+        let unrepresentable = params
+            .iter()
+            .chain(Some(ret).filter(|ret| **ret != NodeType::Void))
+            .find(|ty| !is_c_type(ty));
+
+        match unrepresentable {
+            Some(ty) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "{} can only take and return ints, floats, bools, chars and strings, not {}",
+                    what, ty
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn publish(&mut self, name: &str, public: bool) {
+        if public {
+            self.public.insert(name.to_string());
+        }
+    }
+
+    /// Functions, constants, statics, structs and enums share a namespace,
+    /// so none of them can reuse a name taken by another.
+    fn unique_item(&self, name: &str) -> Result<()> {
+        let kind = match self.items.get(name) {
+            Some(Item::Fn(_)) => "function",
+            Some(Item::Const(_)) => "constant",
+            Some(Item::Static(_)) => "static",
+            Some(Item::Struct(_)) => "struct",
+            Some(Item::Enum(_)) => "enum",
+            None => return Ok(()),
+        };
+
+        Err(self.error(
+            ErrorCodes::DuplicateDefinition,
+            &format!("{} `{}` is already defined", kind, name),
+        ))
+    }
+
+    /// Take the warnings found by the checks so far.
+    pub fn warnings(&mut self) -> Vec<CompileError> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// The names of all functions defined so far.
+    pub fn function_names(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|(_, item)| matches!(item, Item::Fn(_)))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Check the body of a function [`define`](Self::define) returned `id` for.
+    pub fn check_function(&mut self, func: &Function, id: FnId) -> Result<hir::Function> {
+        let Signature { params, ret, .. } = self.fns[id].clone();
+
+        self.at = func.at;
+        self.ret = Some(ret.clone());
+        self.locals = Vec::new();
+        self.scopes = vec![HashMap::new()];
+
+        for ((name, _), ty) in func.args.iter().zip(params) {
+            self.declare(name, ty);
+        }
+
+        let body = self.check_block(&func.body)?;
+
+        self.ret = None;
+
+        // whether every path returns is left to the control flow checks
+        if let (true, Some(value)) = (ret != NodeType::Void, body.value()) {
+            self.expect(&ret, &value.ty)?;
+        }
+
+        let func = hir::Function {
+            id,
+            name: format!("{}{}", self.prefix, func.name),
+            public: func.public,
+            export: Some(func.name.clone()).filter(|_| func.no_mangle),
+            params: func.args.len(),
+            locals: mem::take(&mut self.locals),
+            ret,
+            body,
+            at: func.at,
+            source: self.source.clone(),
+        };
+
+        self.analyse(func)
+    }
+
+    /// Check statements outside of any function, as an anonymous function
+    /// returning the value of the trailing expression (or `void` if there
+    /// isn't one). It can't be called by other code.
+    pub fn check_body(&mut self, body: &Node) -> Result<hir::Function> {
+        self.ret = None;
+        self.locals = Vec::new();
+        self.scopes = Vec::new();
+
+        let body = self.check_block(body)?;
+
+        let func = hir::Function {
+            id: self.fns.len(),
+            name: ANONYMOUS_FN.to_string(),
+            public: true,
+            export: None,
+            params: 0,
+            locals: mem::take(&mut self.locals),
+            ret: body.ty.clone(),
+            body,
+            at: (0, 0),
+            source: None,
+        };
+
+        self.analyse(func)
+    }
+
+    /// Run the checks that need to know the paths through a function.
+    fn analyse(&mut self, func: hir::Function) -> Result<hir::Function> {
+        let warnings = mir::check::check(&mir::build(&func))?;
+
+        self.warnings.extend(warnings);
+
+        Ok(func)
+    }
+
+    fn check_block(&mut self, node: &Node) -> Result<Block> {
+        let body = match node {
+            Node::Block(body) => body.as_slice(),
+            _ => std::slice::from_ref(node),
+        };
+
+        self.scopes.push(HashMap::new());
+
+        let outer = mem::take(&mut self.hoisted);
+        let stmts = body.iter().try_fold(Vec::new(), |mut stmts, stmt| {
+            let stmt = self.check_stmt(stmt)?;
+
+            stmts.append(&mut self.hoisted);
+            stmts.push(stmt);
+
+            Ok(stmts)
+        });
+
+        self.hoisted = outer;
+        self.scopes.pop();
+
+        let mut block = Block {
+            stmts: stmts?,
+            ty: NodeType::Void,
+        };
+
+        if let Some(value) = block.value() {
+            block.ty = value.ty.clone();
+        }
+
+        Ok(block)
+    }
+
+    fn check_stmt(&mut self, node: &Node) -> Result<Stmt> {
+        let at = self.at;
+
+        let kind = match node {
+            Node::Stmt { node, at } => {
+                self.at = *at;
+                return self.check_stmt(node);
+            }
+            Node::Block(_) => StmtKind::Block(self.check_block(node)?),
+            Node::Let {
+                name,
+                ty,
+                value: None,
+            } => {
+                // the parser only allows leaving out the value if there's a type
+                let ty = match ty {
+                    Some(ty) => self.resolve(ty)?,
+                    None => NodeType::Void,
+                };
+
+                if ty == NodeType::Void {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!("`{}` can't be declared with type void", name),
+                    ));
+                }
+
+                let local = self.declare(name, ty);
+
+                StmtKind::Let { local, value: None }
+            }
+            Node::Let {
+                name,
+                ty,
+                value: Some(value),
+            } => {
+                let value = match ty {
+                    Some(ty) => {
+                        let ty = self.resolve(ty)?;
+
+                        self.expected(value, &ty)?
+                    }
+                    None => self.expr(value)?,
+                };
+
+                if value.ty == NodeType::Void {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!("`{}` can't be bound to a value of type void", name),
+                    ));
+                }
+
+                let local = self.declare(name, value.ty.clone());
+
+                StmtKind::Let {
+                    local,
+                    value: Some(value),
+                }
+            }
+            Node::Return(value) => {
+                let value = value
+                    .as_ref()
+                    .map(|value| match self.ret.clone() {
+                        Some(ret) => self.expected(value, &ret),
+                        None => self.expr(value),
+                    })
+                    .transpose()?;
+                let found = value
+                    .as_ref()
+                    .map_or(NodeType::Void, |value| value.ty.clone());
+
+                match self.ret.clone() {
+                    Some(ret) => self.expect(&ret, &found)?,
+                    None => {
+                        return Err(self
+                            .error(ErrorCodes::NotAllowedHere, "`return` outside of a function"))
+                    }
+                }
+
+                StmtKind::Return(value)
+            }
+            Node::If {
+                cond,
+                then,
+                otherwise,
+            } => StmtKind::If {
+                cond: self.condition(cond)?,
+                then: self.check_block(then)?,
+                otherwise: otherwise
+                    .as_ref()
+                    .map(|otherwise| self.check_block(otherwise))
+                    .transpose()?,
+            },
+            Node::While { cond, body } => {
+                let cond = self.condition(cond)?;
+                // anything the condition hoisted has to run before every check
+                let mut stmts = mem::take(&mut self.hoisted);
+                let body = self.check_block(body)?;
+
+                // `while cond { body }` is `loop { if cond { body } else { break } }`
+                let stop = Block {
+                    stmts: vec![Stmt {
+                        kind: StmtKind::Break,
+                        at,
+                    }],
+                    ty: NodeType::Void,
+                };
+
+                stmts.push(Stmt {
+                    kind: StmtKind::If {
+                        cond,
+                        then: body,
+                        otherwise: Some(stop),
+                    },
+                    at,
+                });
+
+                StmtKind::Loop(Block {
+                    stmts,
+                    ty: NodeType::Void,
+                })
+            }
+            Node::For { name, iter, body } => self.check_for(name, iter, body)?,
+            Node::Match { scrutinee, arms } => match self.check_match(scrutinee, arms)? {
+                Some(value) => StmtKind::Expr(value),
+                // without a value, the `match` itself is the statement
+                None => self.hoisted.pop().unwrap().kind,
+            },
+            Node::Fn(func) => {
+                return Err(self.error(
+                    ErrorCodes::NotAllowedHere,
+                    &format!("nested function `{}` is not supported", func.name),
+                ))
+            }
+            expr => StmtKind::Expr(self.expr(expr)?),
+        };
+
+        Ok(Stmt { kind, at })
+    }
+
+    /// Infer the type of an expression.
+    pub fn expr(&mut self, node: &Node) -> Result<Expr> {
+        let (kind, ty) = match node {
+            Node::String(s) => (Literal::String(s.clone()).into(), NodeType::String),
+            Node::Integer(i) => (Literal::Integer(*i).into(), NodeType::Integer),
+            Node::Float(f) => (Literal::Float(*f).into(), NodeType::Float),
+            Node::Char(c) => (Literal::Char(*c).into(), NodeType::Char),
+            Node::Bool(b) => (Literal::Bool(*b).into(), NodeType::Bool),
+            Node::Identifier(name) => match self.local(name) {
+                Some(local) => (ExprKind::Local(local), self.locals[local].ty.clone()),
+                None => match self.items.get(name) {
+                    Some(Item::Const(value)) => (value.clone().into(), value.ty()),
+                    Some(Item::Static(global)) => {
+                        (ExprKind::Global(*global), self.globals[*global].ty.clone())
+                    }
+                    _ => return Err(self.unknown_variable(name)),
+                },
+            },
+            Node::Call { name, args } => {
+                let func = match self.items.get(name) {
+                    Some(Item::Fn(func)) => *func,
+                    // unless a function shadows it
+                    _ => match self.builtin(name, args)? {
+                        Some(call) => return Ok(call),
+                        None => {
+                            return Err(self.error(
+                                ErrorCodes::UnknownIdentifier,
+                                &format!("unknown function `{}`", name),
+                            ))
+                        }
+                    },
+                };
+                let Signature {
+                    params,
+                    variadic,
+                    ret,
+                } = self.fns[func].clone();
+
+                let arity = match variadic {
+                    true if args.len() < params.len() => Some("at least "),
+                    false if args.len() != params.len() => Some(""),
+                    _ => None,
+                };
+
+                if let Some(at_least) = arity {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "`{}` takes {}{} arguments but {} were given",
+                            name,
+                            at_least,
+                            params.len(),
+                            args.len()
+                        ),
+                    ));
+                }
+
+                let args = self.exprs(args)?;
+
+                for (param, arg) in params.iter().zip(&args) {
+                    self.expect(param, &arg.ty)?;
+                }
+
+                // whatever is passed through `...` still has to be
+                // something C can be given
+                if let Some(arg) = args[params.len()..].iter().find(|arg| !is_c_type(&arg.ty)) {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "`{}` can't be given a value of type {} through `...`",
+                            name, arg.ty
+                        ),
+                    ));
+                }
+
+                (ExprKind::Call { func, args }, ret)
+            }
+            Node::Expr {
+                lhs,
+                op: Op::Assign,
+                rhs: Some(rhs),
+            } => {
+                let mut value = self.expr(rhs)?;
+                let mark = self.hoisted.len();
+                let (place, path, ty) = self.assignee(lhs)?;
+
+                // the value still runs before anything the indices hoist
+                self.spill(&mut value, mark);
+                self.expect(&ty, &value.ty)?;
+
+                let value = Box::new(value);
+
+                (ExprKind::Assign { place, path, value }, ty)
+            }
+            Node::StructLiteral { name, fields } => {
+                let def = match self.items.get(name) {
+                    Some(Item::Struct(name)) => self.structs[name].clone(),
+                    _ => {
+                        return Err(self.error(
+                            ErrorCodes::UnknownIdentifier,
+                            &format!("unknown struct `{}`", name),
+                        ))
+                    }
+                };
+                let values = self.named_fields("type", &def.name, &def.fields, fields)?;
+
+                (ExprKind::Struct(values), NodeType::Struct(def.name))
+            }
+            Node::Variant { .. } if self.vec_new(node) => {
+                return Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    "cannot tell what type of values `Vec::new()` would hold, give the \
+                     variable it's bound to a type like `Vec<int>`",
+                ))
+            }
+            Node::Variant { ty, name, payload } => {
+                let (def, variant) = self.variant(ty, name)?;
+                let declared = &def.variants[variant];
+                let path = format!("{}::{}", ty, name);
+
+                let fields = match (payload, declared.named) {
+                    (Payload::Named(fields), true) => {
+                        self.named_fields("variant", &path, &declared.fields, fields)?
+                    }
+                    (Payload::Unit, true) => {
+                        self.named_fields("variant", &path, &declared.fields, &[])?
+                    }
+                    (Payload::Tuple(args), false) => {
+                        self.positional_fields(&path, &declared.fields, args)?
+                    }
+                    (Payload::Unit, false) => {
+                        self.positional_fields(&path, &declared.fields, &[])?
+                    }
+                    (Payload::Tuple(_), true) => {
+                        return Err(self.error(
+                            ErrorCodes::MismatchedTypes,
+                            &format!("`{0}` has named fields, write `{0} {{ .. }}`", path),
+                        ))
+                    }
+                    (Payload::Named(_), false) => {
+                        return Err(self.error(
+                            ErrorCodes::MismatchedTypes,
+                            &format!("`{0}` doesn't have named fields, write `{0}(..)`", path),
+                        ))
+                    }
+                };
+
+                (
+                    ExprKind::Variant { variant, fields },
+                    NodeType::Enum(def.name),
+                )
+            }
+            Node::Match { scrutinee, arms } => {
+                return match self.check_match(scrutinee, arms)? {
+                    Some(value) => Ok(value),
+                    None => Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        "this `match` has no value, since none of its arms have one",
+                    )),
+                }
+            }
+            Node::Field { base, name } => {
+                let base = self.expr(base)?;
+                let (index, ty) = self.field(&base.ty, name)?;
+                let base = Box::new(base);
+
+                (ExprKind::Field { base, index }, ty)
+            }
+            Node::Array(elements) => {
+                let elements = self.exprs(elements)?;
+                let ty = match elements.first() {
+                    Some(first) => first.ty.clone(),
+                    None => {
+                        return Err(self.error(
+                            ErrorCodes::MismatchedTypes,
+                            "cannot tell what type an empty array would hold",
+                        ))
+                    }
+                };
+
+                for element in &elements {
+                    self.expect(&ty, &element.ty)?;
+                }
+
+                if ty == NodeType::Void {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        "arrays can't hold values of type void",
+                    ));
+                }
+
+                let len = elements.len();
+
+                (
+                    ExprKind::Array(elements),
+                    NodeType::Array(Box::new(ty), len),
+                )
+            }
+            Node::Index { base, index } => {
+                let mut operands = self.exprs([base.as_ref(), index.as_ref()])?;
+                let index = operands.pop().unwrap();
+                let base = operands.pop().unwrap();
+                let ty = self.element(&base.ty, &index)?;
+
+                let (base, index) = (Box::new(base), Box::new(index));
+
+                (ExprKind::Index { base, index }, ty)
+            }
+            Node::Expr { lhs, op, rhs: None } => {
+                let operand = self.expr(lhs)?;
+                let ty = operand.ty.clone();
+
+                match (op, &ty) {
+                    (Op::Sub, NodeType::Integer | NodeType::Float) => {}
+                    (Op::Not, NodeType::Bool) => {}
+                    _ => {
+                        return Err(self.error(
+                            ErrorCodes::MismatchedTypes,
+                            &format!("cannot apply `{:?}` to a value of type {}", op, ty),
+                        ))
+                    }
+                }
+
+                if let ExprKind::Literal(value) = &operand.kind {
+                    let value =
+                        consteval::unary(*op, value.clone()).map_err(|e| self.fold_error(e))?;
+
+                    return Ok(Expr {
+                        kind: value.into(),
+                        ty,
+                    });
+                }
+
+                let operand = Box::new(operand);
+
+                (ExprKind::Unary { op: *op, operand }, ty)
+            }
+            Node::Expr {
+                lhs,
+                op: op @ (Op::And | Op::Or),
+                rhs: Some(rhs),
+            } => return self.logical(*op, lhs, rhs),
+            Node::Expr {
+                lhs,
+                op,
+                rhs: Some(rhs),
+            } => {
+                let mut operands = self.exprs([lhs.as_ref(), rhs.as_ref()])?;
+                let rhs = operands.pop().unwrap();
+                let lhs = operands.pop().unwrap();
+
+                self.expect(&lhs.ty, &rhs.ty)?;
+                let ty = self.binary(*op, lhs.ty.clone())?;
+
+                if let (ExprKind::Literal(l), ExprKind::Literal(r)) = (&lhs.kind, &rhs.kind) {
+                    let value = consteval::binary(*op, l.clone(), r.clone())
+                        .map_err(|e| self.fold_error(e))?;
+
+                    return Ok(Expr {
+                        kind: value.into(),
+                        ty,
+                    });
+                }
+
+                let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+
+                (ExprKind::Binary { op: *op, lhs, rhs }, ty)
+            }
+            Node::Interpolation(parts) => return self.interpolation(parts),
+            _ => return Err(self.error(ErrorCodes::NotAllowedHere, "expected an expression")),
+        };
+
+        Ok(Expr { kind, ty })
+    }
+
+    /// `lhs && rhs` or `lhs || rhs`, which only runs `rhs` when `lhs`
+    /// doesn't already decide the result. The result is kept in a new
+    /// variable that's set to `lhs`, and then to `rhs` by a hoisted `if`.
+    fn logical(&mut self, op: Op, lhs: &Node, rhs: &Node) -> Result<Expr> {
+        let lhs = self.expr(lhs)?;
+
+        // whatever `rhs` hoists only runs along with it
+        let outer = mem::take(&mut self.hoisted);
+        let rhs = self.expr(rhs);
+        let mut stmts = mem::replace(&mut self.hoisted, outer);
+        let rhs = rhs?;
+
+        self.expect(&lhs.ty, &rhs.ty)?;
+        let ty = self.binary(op, lhs.ty.clone())?;
+
+        // `&&` needs `rhs` when `lhs` is true, `||` when it's false
+        let needs_rhs = op == Op::And;
+
+        match lhs.kind {
+            ExprKind::Literal(Literal::Bool(b)) if b == needs_rhs => {
+                self.hoisted.append(&mut stmts);
+                return Ok(rhs);
+            }
+            ExprKind::Literal(_) => return Ok(lhs),
+            _ => {}
+        }
+
+        let at = self.at;
+        let result = self.temp(ty.clone());
+        let read = Expr {
+            kind: ExprKind::Local(result),
+            ty: ty.clone(),
+        };
+        let cond = if needs_rhs {
+            read.clone()
+        } else {
+            Expr {
+                kind: ExprKind::Unary {
+                    op: Op::Not,
+                    operand: Box::new(read.clone()),
+                },
+                ty: ty.clone(),
+            }
+        };
+
+        stmts.push(Stmt {
+            kind: StmtKind::Expr(Expr {
+                kind: ExprKind::Assign {
+                    place: Place::Local(result),
+                    path: Vec::new(),
+                    value: Box::new(rhs),
+                },
+                ty,
+            }),
+            at,
+        });
+
+        self.hoisted.push(Stmt {
+            kind: StmtKind::Let {
+                local: result,
+                value: Some(lhs),
+            },
+            at,
+        });
+        self.hoisted.push(Stmt {
+            kind: StmtKind::If {
+                cond,
+                then: Block {
+                    stmts,
+                    ty: NodeType::Void,
+                },
+                otherwise: None,
+            },
+            at,
+        });
+
+        Ok(read)
+    }
+
+    /// `"a {b} c"`, which is `"a " + str(b) + " c"`.
+    fn interpolation(&mut self, parts: &[Node]) -> Result<Expr> {
+        let mut string: Option<Expr> = None;
+
+        for part in self.exprs(parts)? {
+            let part = self.stringify(part)?;
+
+            string = Some(match string {
+                Some(lhs) => Expr {
+                    kind: ExprKind::Binary {
+                        op: Op::Add,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(part),
+                    },
+                    ty: NodeType::String,
+                },
+                None => part,
+            });
+        }
+
+        Ok(string.unwrap_or_else(|| Expr {
+            kind: Literal::String(String::new()).into(),
+            ty: NodeType::String,
+        }))
+    }
+
+    /// Check expressions that run one after another. If one of them hoists
+    /// a `match` out, the ones before it are kept in variables first, so
+    /// that they still run before the `match` does.
+    fn exprs<'n>(&mut self, nodes: impl IntoIterator<Item = &'n Node>) -> Result<Vec<Expr>> {
+        let mut exprs = Vec::new();
+        let mut marks = Vec::new();
+
+        for node in nodes {
+            exprs.push(self.expr(node)?);
+            marks.push(self.hoisted.len());
+        }
+
+        self.spill_all(&mut exprs, marks);
+
+        Ok(exprs)
+    }
+
+    /// Like `exprs`, for expressions whose values should have the types
+    /// paired with them.
+    fn expected_exprs<'n>(
+        &mut self,
+        nodes: impl IntoIterator<Item = (&'n Node, &'n NodeType)>,
+    ) -> Result<Vec<Expr>> {
+        let mut exprs = Vec::new();
+        let mut marks = Vec::new();
+
+        for (node, ty) in nodes {
+            exprs.push(self.expected(node, ty)?);
+            marks.push(self.hoisted.len());
+        }
+
+        self.spill_all(&mut exprs, marks);
+
+        Ok(exprs)
+    }
+
+    /// Spill each of `exprs`, checked at the matching one of `marks`.
+    fn spill_all(&mut self, exprs: &mut [Expr], marks: Vec<usize>) {
+        // going backwards keeps the earlier marks where they were
+        for (expr, mark) in exprs.iter_mut().zip(marks).rev() {
+            self.spill(expr, mark);
+        }
+    }
+
+    /// If anything was hoisted after `mark`, which is where `expr` was
+    /// checked, keep its value in a variable that's set before them.
+    fn spill(&mut self, expr: &mut Expr, mark: usize) {
+        if mark == self.hoisted.len() || matches!(expr.kind, ExprKind::Literal(_)) {
+            return;
+        }
+
+        let ty = expr.ty.clone();
+        let local = self.temp(ty.clone());
+        let value = mem::replace(
+            expr,
+            Expr {
+                kind: ExprKind::Local(local),
+                ty,
+            },
+        );
+
+        let stmt = Stmt {
+            kind: StmtKind::Let {
+                local,
+                value: Some(value),
+            },
+            at: self.at,
+        };
+
+        self.hoisted.insert(mark, stmt);
+    }
+
+    /// Check an expression whose value should have type `ty`, which is what
+    /// tells `Vec::new()` what it holds.
+    fn expected(&mut self, node: &Node, ty: &NodeType) -> Result<Expr> {
+        if let (true, NodeType::Vec(_)) = (self.vec_new(node), ty) {
+            return Ok(Expr {
+                kind: ExprKind::Builtin {
+                    builtin: Builtin::VecNew,
+                    args: Vec::new(),
+                },
+                ty: ty.clone(),
+            });
+        }
+
+        let value = self.expr(node)?;
+
+        self.expect(ty, &value.ty)?;
+
+        Ok(value)
+    }
+
+    /// Whether `node` is `Vec::new()`, unless there's an enum called `Vec`.
+    fn vec_new(&self, node: &Node) -> bool {
+        match node {
+            Node::Variant {
+                ty,
+                name,
+                payload: Payload::Tuple(args),
+            } => {
+                ty == "Vec"
+                    && name == "new"
+                    && args.is_empty()
+                    && !matches!(self.items.get("Vec"), Some(Item::Enum(_)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Check a call to a function built into the language, or `None` if
+    /// there isn't one called `name`. `get(v, i)` is `v[i]`.
+    fn builtin(&mut self, name: &str, args: &[Node]) -> Result<Option<Expr>> {
+        let (builtin, arity) = match name {
+            "get" => (None, 2),
+            name => match Builtin::ALL.iter().find(|builtin| builtin.name() == name) {
+                Some(builtin) => (Some(*builtin), builtin.arity()),
+                None => return Ok(None),
+            },
+        };
+
+        if args.len() != arity {
+            return Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "`{}` takes {} argument{} but {} were given",
+                    name,
+                    arity,
+                    if arity == 1 { "" } else { "s" },
+                    args.len()
+                ),
+            ));
+        }
+
+        let mut args = self.exprs(args)?;
+
+        let builtin = match builtin {
+            Some(builtin) => builtin,
+            None => {
+                let index = args.pop().unwrap();
+                let base = args.pop().unwrap();
+                let ty = self.element(&base.ty, &index)?;
+                let (base, index) = (Box::new(base), Box::new(index));
+
+                return Ok(Some(Expr {
+                    kind: ExprKind::Index { base, index },
+                    ty,
+                }));
+            }
+        };
+
+        let ty = match builtin {
+            Builtin::Len => match &args[0].ty {
+                // the length of an array is known from its type, but the
+                // array is still evaluated for any side effects it has
+                NodeType::Array(_, len) => {
+                    let len = *len as i32;
+                    let array = args.pop().unwrap();
+
+                    if !pure(&array) {
+                        self.hoisted.push(Stmt {
+                            kind: StmtKind::Expr(array),
+                            at: self.at,
+                        });
+                    }
+
+                    return Ok(Some(Expr {
+                        kind: Literal::Integer(len).into(),
+                        ty: NodeType::Integer,
+                    }));
+                }
+                NodeType::Vec(_) | NodeType::Slice(_) | NodeType::String => NodeType::Integer,
+                ty => {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "`len` takes an array, a `Vec`, a slice or a string, found a value of type {}",
+                            ty
+                        ),
+                    ))
+                }
+            },
+            Builtin::Push => {
+                let element = self.elements(builtin, &args[0].ty)?;
+
+                self.expect(&element, &args[1].ty)?;
+                NodeType::Void
+            }
+            Builtin::Pop => self.elements(builtin, &args[0].ty)?,
+            Builtin::Set => {
+                let element = self.elements(builtin, &args[0].ty)?;
+
+                self.expect(&NodeType::Integer, &args[1].ty)?;
+                self.expect(&element, &args[2].ty)?;
+                NodeType::Void
+            }
+            Builtin::Slice => {
+                let ty = match &args[0].ty {
+                    NodeType::String => NodeType::String,
+                    ty => NodeType::Slice(Box::new(self.elements(builtin, ty)?)),
+                };
+
+                self.expect(&NodeType::Integer, &args[1].ty)?;
+                self.expect(&NodeType::Integer, &args[2].ty)?;
+                ty
+            }
+            Builtin::Free => {
+                self.elements(builtin, &args[0].ty)?;
+                NodeType::Void
+            }
+            Builtin::Str => match &args[0].ty {
+                NodeType::Integer | NodeType::Float | NodeType::Bool | NodeType::Char => {
+                    NodeType::String
+                }
+                ty => {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "`str` takes an int, a float, a bool or a char, found a value of type {}",
+                            ty
+                        ),
+                    ))
+                }
+            },
+            Builtin::Int => {
+                self.expect(&NodeType::String, &args[0].ty)?;
+                NodeType::Integer
+            }
+            Builtin::Float => {
+                self.expect(&NodeType::String, &args[0].ty)?;
+                NodeType::Float
+            }
+            Builtin::Print | Builtin::Println => {
+                let string = self.stringify(args.pop().unwrap())?;
+
+                args.push(string);
+                NodeType::Void
+            }
+            Builtin::ReadLine => NodeType::String,
+            Builtin::VecNew => unreachable!("`Vec::new()` isn't called like a function"),
+        };
+
+        Ok(Some(Expr {
+            kind: ExprKind::Builtin { builtin, args },
+            ty,
+        }))
+    }
+
+    /// `expr` written out as a string with `str`, unless it already is one.
+    fn stringify(&self, expr: Expr) -> Result<Expr> {
+        match &expr.ty {
+            NodeType::String => Ok(expr),
+            NodeType::Integer | NodeType::Float | NodeType::Bool | NodeType::Char => Ok(Expr {
+                kind: ExprKind::Builtin {
+                    builtin: Builtin::Str,
+                    args: vec![expr],
+                },
+                ty: NodeType::String,
+            }),
+            ty => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "cannot write out a value of type {}, only ints, floats, bools, chars and strings",
+                    ty
+                ),
+            )),
+        }
+    }
+
+    /// The type of the elements of the `Vec` of type `ty` given to
+    /// `builtin`, or of the slice if it takes one.
+    fn elements(&self, builtin: Builtin, ty: &NodeType) -> Result<NodeType> {
+        match (builtin, ty) {
+            (_, NodeType::Vec(element)) => Ok(element.as_ref().clone()),
+            (Builtin::Slice, NodeType::Slice(element)) => Ok(element.as_ref().clone()),
+            (Builtin::Slice, ty) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "`slice` takes a `Vec`, a slice or a string, found a value of type {}",
+                    ty
+                ),
+            )),
+            (builtin, ty) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("`{}` takes a `Vec`, found a value of type {}", builtin, ty),
+            )),
+        }
+    }
+
+    /// `for name in iter { body }`, which is
+    ///
+    /// ```text
+    /// let i = 0;
+    /// loop {
+    ///     if i < len(iter) { let name = iter[i]; i = i + 1; body } else { break }
+    /// }
+    /// ```
+    ///
+    /// with `iter` evaluated once, before the loop. The length of a `Vec` is
+    /// read again on every iteration, so the body can push to it.
+    fn check_for(&mut self, name: &str, iter: &Node, body: &Node) -> Result<StmtKind> {
+        let at = self.at;
+        let iter = self.expr(iter)?;
+        let element = match &iter.ty {
+            NodeType::Array(element, _) | NodeType::Vec(element) | NodeType::Slice(element) => {
+                element.as_ref().clone()
+            }
+            ty => {
+                return Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    &format!("cannot loop over a value of type {}", ty),
+                ))
+            }
+        };
+
+        let local = |local, ty| Expr {
+            kind: ExprKind::Local(local),
+            ty,
+        };
+        let stmt = |kind| Stmt { kind, at };
+
+        let values = self.temp(iter.ty.clone());
+        let index = self.temp(NodeType::Integer);
+        let len = match &iter.ty {
+            NodeType::Array(_, len) => Expr {
+                kind: Literal::Integer(*len as i32).into(),
+                ty: NodeType::Integer,
+            },
+            ty => Expr {
+                kind: ExprKind::Builtin {
+                    builtin: Builtin::Len,
+                    args: vec![local(values, ty.clone())],
+                },
+                ty: NodeType::Integer,
+            },
+        };
+
+        self.hoisted.push(stmt(StmtKind::Let {
+            local: values,
+            value: Some(iter),
+        }));
+        self.hoisted.push(stmt(StmtKind::Let {
+            local: index,
+            value: Some(Expr {
+                kind: Literal::Integer(0).into(),
+                ty: NodeType::Integer,
+            }),
+        }));
+
+        // the variable is only in scope in the body
+        self.scopes.push(HashMap::new());
+        let binding = self.declare(name, element.clone());
+        let body = self.check_block(body);
+        self.scopes.pop();
+
+        let cond = Expr {
+            kind: ExprKind::Binary {
+                op: Op::Lt,
+                lhs: Box::new(local(index, NodeType::Integer)),
+                rhs: Box::new(len),
+            },
+            ty: NodeType::Bool,
+        };
+        let next = Expr {
+            kind: ExprKind::Binary {
+                op: Op::Add,
+                lhs: Box::new(local(index, NodeType::Integer)),
+                rhs: Box::new(Expr {
+                    kind: Literal::Integer(1).into(),
+                    ty: NodeType::Integer,
+                }),
+            },
+            ty: NodeType::Integer,
+        };
+        let then = vec![
+            stmt(StmtKind::Let {
+                local: binding,
+                value: Some(Expr {
+                    kind: ExprKind::Index {
+                        base: Box::new(local(values, self.locals[values].ty.clone())),
+                        index: Box::new(local(index, NodeType::Integer)),
+                    },
+                    ty: element,
+                }),
+            }),
+            stmt(StmtKind::Expr(Expr {
+                kind: ExprKind::Assign {
+                    place: Place::Local(index),
+                    path: Vec::new(),
+                    value: Box::new(next),
+                },
+                ty: NodeType::Integer,
+            })),
+            stmt(StmtKind::Block(body?)),
+        ];
+        let stop = vec![stmt(StmtKind::Break)];
+
+        Ok(StmtKind::Loop(Block {
+            stmts: vec![stmt(StmtKind::If {
+                cond,
+                then: Block {
+                    stmts: then,
+                    ty: NodeType::Void,
+                },
+                otherwise: Some(Block {
+                    stmts: stop,
+                    ty: NodeType::Void,
+                }),
+            })],
+            ty: NodeType::Void,
+        }))
+    }
+
+    /// Check the values given by name for the fields of a struct or a
+    /// variant, putting them in declaration order.
+    fn named_fields(
+        &mut self,
+        kind: &str,
+        name: &str,
+        declared: &[(String, NodeType)],
+        given: &[(String, Node)],
+    ) -> Result<Vec<Expr>> {
+        let mut indices = Vec::new();
+
+        for (field, _) in given {
+            let index = declared
+                .iter()
+                .position(|(declared, _)| declared == field)
+                .ok_or_else(|| {
+                    self.error(
+                        ErrorCodes::UnknownIdentifier,
+                        &format!("{} `{}` has no field `{}`", kind, name, field),
+                    )
+                })?;
+
+            if indices.contains(&index) {
+                return Err(self.error(
+                    ErrorCodes::DuplicateDefinition,
+                    &format!("field `{}` is given more than once", field),
+                ));
+            }
+
+            indices.push(index);
+        }
+
+        let missing = declared
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !indices.contains(index))
+            .map(|(_, (field, _))| format!("`{}`", field))
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("missing {} in `{}`", missing.join(", "), name),
+            ));
+        }
+
+        let values = self.expected_exprs(
+            given
+                .iter()
+                .zip(&indices)
+                .map(|((_, value), &index)| (value, &declared[index].1)),
+        )?;
+        let mut fields = vec![None; declared.len()];
+
+        for (index, value) in indices.into_iter().zip(values) {
+            fields[index] = Some(value);
+        }
+
+        Ok(fields.into_iter().flatten().collect())
+    }
+
+    /// Check the values given in order for the fields of a variant.
+    fn positional_fields(
+        &mut self,
+        name: &str,
+        declared: &[(String, NodeType)],
+        given: &[Node],
+    ) -> Result<Vec<Expr>> {
+        if given.len() != declared.len() {
+            return Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "`{}` takes {} fields but {} were given",
+                    name,
+                    declared.len(),
+                    given.len()
+                ),
+            ));
+        }
+
+        self.expected_exprs(given.iter().zip(declared.iter().map(|(_, ty)| ty)))
+    }
+
+    /// The enum called `ty` in the current module, and the index of its
+    /// variant called `name`.
+    fn variant(&self, ty: &str, name: &str) -> Result<(hir::Enum, usize)> {
+        let def = match self.items.get(ty) {
+            Some(Item::Enum(ty)) => &self.enums[ty],
+            _ => {
+                return Err(self.error(
+                    ErrorCodes::UnknownIdentifier,
+                    &format!("unknown enum `{}`", ty),
+                ))
+            }
+        };
+
+        match def.variants.iter().position(|variant| variant.name == name) {
+            Some(variant) => Ok((def.clone(), variant)),
+            None => Err(self.error(
+                ErrorCodes::UnknownIdentifier,
+                &format!("enum `{}` has no variant `{}`", ty, name),
+            )),
+        }
+    }
+
+    /// Check a `match`, which is hoisted out to run before the statement
+    /// it's in. Its value, if it has one, is kept in a new variable that the
+    /// returned expression reads.
+    fn check_match(&mut self, scrutinee: &Node, arms: &[MatchArm]) -> Result<Option<Expr>> {
+        let at = self.at;
+        let scrutinee = self.expr(scrutinee)?;
+        let ty = scrutinee.ty.clone();
+
+        if ty == NodeType::Void {
+            return Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                "cannot match on a value of type void",
+            ));
+        }
+
+        // every pattern reads the value, so it's only evaluated once
+        let subject = match scrutinee.kind {
+            ExprKind::Local(local) => local,
+            _ => {
+                let local = self.temp(ty.clone());
+
+                self.hoisted.push(Stmt {
+                    kind: StmtKind::Let {
+                        local,
+                        value: Some(scrutinee),
+                    },
+                    at,
+                });
+
+                local
+            }
+        };
+
+        let mut pats = Vec::new();
+        let mut checked = Vec::new();
+        let mut value: Option<NodeType> = None;
+
+        for arm in arms {
+            self.at = arm.at;
+            self.scopes.push(HashMap::new());
+
+            let result = self.check_arm(arm, &ty, subject);
+
+            self.scopes.pop();
+
+            let (pat, conds, body) = result?;
+
+            // an arm that returns doesn't need a value
+            if !returns(&body) {
+                self.at = arm.at;
+
+                match &value {
+                    Some(value) => self.expect(value, &body.ty)?,
+                    None => value = Some(body.ty.clone()),
+                }
+            }
+
+            pats.push(pat);
+            checked.push((arm.at, conds, body));
+        }
+
+        self.at = at;
+
+        if let Some(missing) = pattern::missing(&pats, &ty, &self.enums) {
+            return Err(CompileErrorBuilder::new()
+                .from(ErrorCodes::NonExhaustiveMatch)
+                .at((at.0, at.1 + 1))
+                .note(&format!("pattern `{}` isn't covered", missing))
+                .hint("add an arm for it, or a `_` arm to match everything else")
+                .build());
+        }
+
+        let value = value.unwrap_or(NodeType::Void);
+        let result = match value {
+            NodeType::Void => None,
+            _ => Some(self.temp(value.clone())),
+        };
+        let mut lowered = Vec::new();
+
+        for (i, (arm_at, conds, mut body)) in checked.into_iter().enumerate() {
+            if !pattern::useful(&pats[..i], &pats[i], &ty, &self.enums) {
+                let warning = CompileErrorBuilder::new()
+                    .from(ErrorCodes::UnreachableCode)
+                    .warning()
+                    .at((arm_at.0, arm_at.1 + 1))
+                    .note("this arm will never run, the ones before it match everything it does")
+                    .build();
+
+                self.warnings.push(warning);
+                continue;
+            }
+
+            if let (Some(result), false) = (result, returns(&body)) {
+                // the arm's value becomes the value of the whole `match`
+                match body.stmts.pop() {
+                    Some(Stmt {
+                        kind: StmtKind::Expr(arm_value),
+                        at,
+                    }) => {
+                        let assign = ExprKind::Assign {
+                            place: Place::Local(result),
+                            path: Vec::new(),
+                            value: Box::new(arm_value),
+                        };
+
+                        body.stmts.push(Stmt {
+                            kind: StmtKind::Expr(Expr {
+                                kind: assign,
+                                ty: value.clone(),
+                            }),
+                            at,
+                        });
+                    }
+                    _ => unreachable!("an arm with a value ends in an expression"),
+                }
+            }
+
+            lowered.push(hir::Arm { conds, body });
+        }
+
+        // by the time the last arm is reached, it's the only one left
+        if let Some(last) = lowered.last_mut() {
+            last.conds.clear();
+        }
+
+        self.hoisted.push(Stmt {
+            kind: StmtKind::Match(lowered),
+            at,
+        });
+
+        Ok(result.map(|local| Expr {
+            kind: ExprKind::Local(local),
+            ty: value,
+        }))
+    }
+
+    /// Check an arm of a `match` on the variable `subject`, returning its
+    /// pattern, the conditions for it to match and its body.
+    fn check_arm(
+        &mut self,
+        arm: &MatchArm,
+        ty: &NodeType,
+        subject: LocalId,
+    ) -> Result<(Pat, Vec<Expr>, Block)> {
+        let value = Expr {
+            kind: ExprKind::Local(subject),
+            ty: ty.clone(),
+        };
+        let mut conds = Vec::new();
+        let mut stmts = Vec::new();
+
+        let pat = self.check_pattern(&arm.pattern, value, &mut conds, &mut stmts)?;
+        let body = self.check_block(&arm.body)?;
+
+        stmts.extend(body.stmts);
+
+        let body = Block { stmts, ty: body.ty };
+
+        Ok((pat, conds, body))
+    }
+
+    /// Check a pattern against `value`, the part of the matched value it's
+    /// for. The tests the value has to pass are added to `conds`, and a
+    /// `let` for each variable it binds to `binds`.
+    fn check_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: Expr,
+        conds: &mut Vec<Expr>,
+        binds: &mut Vec<Stmt>,
+    ) -> Result<Pat> {
+        match pattern {
+            Pattern::Wildcard => Ok(Pat::Wild),
+            Pattern::Binding(name) => {
+                if self.scopes.last().unwrap().contains_key(name) {
+                    return Err(self.error(
+                        ErrorCodes::DuplicateDefinition,
+                        &format!("`{}` is bound more than once in the same pattern", name),
+                    ));
+                }
+
+                let local = self.declare(name, value.ty.clone());
+
+                binds.push(Stmt {
+                    kind: StmtKind::Let {
+                        local,
+                        value: Some(value),
+                    },
+                    at: self.at,
+                });
+
+                Ok(Pat::Wild)
+            }
+            Pattern::Literal(node) => {
+                let literal = match self.expr(node)?.kind {
+                    ExprKind::Literal(literal) => literal,
+                    _ => {
+                        return Err(self.error(
+                            ErrorCodes::NotAllowedHere,
+                            "expected a literal in the pattern",
+                        ))
+                    }
+                };
+
+                self.expect(&value.ty, &literal.ty())?;
+                self.binary(Op::Eq, value.ty.clone())?;
+
+                conds.push(equals(value, literal.clone()));
+
+                Ok(Pat::Literal(literal))
+            }
+            Pattern::Variant {
+                ty,
+                name,
+                payload,
+                rest,
+            } => {
+                let (def, variant) = self.variant(ty, name)?;
+
+                self.expect(&value.ty, &NodeType::Enum(def.name.clone()))?;
+
+                let declared = &def.variants[variant];
+                let path = format!("{}::{}", ty, name);
+                let patterns = self.pattern_fields(&path, declared, payload, *rest)?;
+
+                let tag = Expr {
+                    kind: ExprKind::Tag(Box::new(value.clone())),
+                    ty: NodeType::Integer,
+                };
+
+                // the fields are only looked at once the variant is known
+                conds.push(equals(tag, Literal::Integer(variant as i32)));
+
+                let mut fields = Vec::new();
+
+                for (index, ((_, ty), pattern)) in declared.fields.iter().zip(patterns).enumerate()
+                {
+                    let field = Expr {
+                        kind: ExprKind::Payload {
+                            base: Box::new(value.clone()),
+                            variant,
+                            index,
+                        },
+                        ty: ty.clone(),
+                    };
+
+                    fields.push(match pattern {
+                        Some(pattern) => self.check_pattern(pattern, field, conds, binds)?,
+                        None => Pat::Wild,
+                    });
+                }
+
+                Ok(Pat::Variant(variant, fields))
+            }
+        }
+    }
+
+    /// The pattern for each field of a variant, in declaration order, or
+    /// `None` for fields left out with `..`.
+    fn pattern_fields<'p>(
+        &self,
+        path: &str,
+        declared: &hir::Variant,
+        payload: &'p Payload<Pattern>,
+        rest: bool,
+    ) -> Result<Vec<Option<&'p Pattern>>> {
+        let count = declared.fields.len();
+
+        match (payload, declared.named) {
+            (Payload::Unit, _) if count == 0 => Ok(Vec::new()),
+            (Payload::Unit, true) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("`{0}` has fields, match them with `{0} {{ .. }}`", path),
+            )),
+            (Payload::Unit, false) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("`{0}` has fields, match them with `{0}(..)`", path),
+            )),
+            (Payload::Tuple(patterns), false) => {
+                if patterns.len() > count || (patterns.len() < count && !rest) {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "`{}` has {} fields but the pattern has {}",
+                            path,
+                            count,
+                            patterns.len()
+                        ),
+                    ));
+                }
+
+                let mut fields = patterns.iter().map(Some).collect::<Vec<_>>();
+
+                fields.resize(count, None);
+
+                Ok(fields)
+            }
+            (Payload::Named(patterns), true) => {
+                let mut fields = vec![None; count];
+
+                for (field, pattern) in patterns {
+                    let index = declared
+                        .fields
+                        .iter()
+                        .position(|(declared, _)| declared == field)
+                        .ok_or_else(|| {
+                            self.error(
+                                ErrorCodes::UnknownIdentifier,
+                                &format!("variant `{}` has no field `{}`", path, field),
+                            )
+                        })?;
+
+                    if fields[index].is_some() {
+                        return Err(self.error(
+                            ErrorCodes::DuplicateDefinition,
+                            &format!("field `{}` is matched more than once", field),
+                        ));
+                    }
+
+                    fields[index] = Some(pattern);
+                }
+
+                let missing = declared
+                    .fields
+                    .iter()
+                    .zip(&fields)
+                    .filter(|(_, pattern)| pattern.is_none())
+                    .map(|((field, _), _)| format!("`{}`", field))
+                    .collect::<Vec<_>>();
+
+                if !missing.is_empty() && !rest {
+                    return Err(self.error(
+                        ErrorCodes::MismatchedTypes,
+                        &format!(
+                            "missing {} in the pattern for `{}`, end it with `..` to ignore them",
+                            missing.join(", "),
+                            path
+                        ),
+                    ));
+                }
+
+                Ok(fields)
+            }
+            (Payload::Tuple(_), true) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "`{0}` has named fields, match them with `{0} {{ .. }}`",
+                    path
+                ),
+            )),
+            (Payload::Named(_), false) => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!(
+                    "`{0}` doesn't have named fields, match them with `{0}(..)`",
+                    path
+                ),
+            )),
+        }
+    }
+
+    /// The result type of a binary operator applied to two values of `ty`.
+    fn binary(&self, op: Op, ty: NodeType) -> Result<NodeType> {
+        use NodeType::*;
+
+        match (op, &ty) {
+            (Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod, Integer | Float) => Ok(ty),
+            (Op::Add, String) => Ok(ty),
+            (Op::Lt | Op::Gt | Op::Le | Op::Ge, Integer | Float | Char | String) => Ok(Bool),
+            (Op::Eq | Op::Neq, Integer | Float | Char | Bool | String) => Ok(Bool),
+            (Op::And | Op::Or, Bool) => Ok(Bool),
+            _ => Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("cannot apply `{:?}` to values of type {}", op, ty),
+            )),
+        }
+    }
+
+    fn condition(&mut self, cond: &Node) -> Result<Expr> {
+        let cond = self.expr(cond)?;
+
+        self.expect(&NodeType::Bool, &cond.ty)?;
+
+        Ok(cond)
+    }
+
+    /// Give a new variable its own ID, shadowing any earlier one by the same
+    /// name in the current scope.
+    fn declare(&mut self, name: &str, ty: NodeType) -> LocalId {
+        let local = self.locals.len();
+
+        self.locals.push(Local {
+            name: name.to_string(),
+            ty,
+        });
+
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), local);
+
+        local
+    }
+
+    /// A variable for a value the checker needs to keep, which code can't
+    /// refer to by name.
+    fn temp(&mut self, ty: NodeType) -> LocalId {
+        self.locals.push(Local {
+            name: String::new(),
+            ty,
+        });
+
+        self.locals.len() - 1
+    }
+
+    /// The innermost variable called `name`, if there is one.
+    fn local(&self, name: &str) -> Option<LocalId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    /// The place, path and type of the left-hand side of an assignment.
+    fn assignee(&mut self, node: &Node) -> Result<(Place, Vec<Projection>, NodeType)> {
+        match node {
+            Node::Identifier(name) => {
+                let place = self.place(name)?;
+                let ty = match place {
+                    Place::Local(local) => self.locals[local].ty.clone(),
+                    Place::Global(global) => self.globals[global].ty.clone(),
+                };
+
+                Ok((place, Vec::new(), ty))
+            }
+            Node::Field { base, name } => {
+                let (place, mut path, ty) = self.assignee(base)?;
+                let (index, ty) = self.field(&ty, name)?;
+
+                path.push(Projection::Field(index));
+
+                Ok((place, path, ty))
+            }
+            Node::Index { base, index } => {
+                let (place, mut path, ty) = self.assignee(base)?;
+
+                // copies of a `Vec` share its elements, so they aren't part
+                // of the place
+                if let NodeType::Vec(_) | NodeType::Slice(_) = ty {
+                    return Err(self.error(
+                        ErrorCodes::InvalidAssignment,
+                        &format!(
+                            "cannot assign to an element of a value of type {}, use `set` instead",
+                            ty
+                        ),
+                    ));
+                }
+
+                let mark = self.hoisted.len();
+                let index = self.expr(index)?;
+                let ty = self.element(&ty, &index)?;
+
+                // earlier indices still run before anything this one hoists
+                for projection in path.iter_mut().rev() {
+                    if let Projection::Index(earlier) = projection {
+                        self.spill(earlier, mark);
+                    }
+                }
+
+                path.push(Projection::Index(index));
+
+                Ok((place, path, ty))
+            }
+            _ => Err(self.error(
+                ErrorCodes::InvalidAssignment,
+                "can only assign to variables, their fields and their elements",
+            )),
+        }
+    }
+
+    /// The type of the elements of an array, a `Vec` or a slice of type
+    /// `ty`, checking that `index` can index into it.
+    fn element(&self, ty: &NodeType, index: &Expr) -> Result<NodeType> {
+        let element = match ty {
+            NodeType::Array(element, _) | NodeType::Vec(element) | NodeType::Slice(element) => {
+                element.as_ref().clone()
+            }
+            ty => {
+                return Err(self.error(
+                    ErrorCodes::MismatchedTypes,
+                    &format!("cannot index into a value of type {}", ty),
+                ))
+            }
+        };
+
+        self.expect(&NodeType::Integer, &index.ty)?;
+
+        Ok(element)
+    }
+
+    /// The index and type of a field of a value of type `ty`.
+    fn field(&self, ty: &NodeType, name: &str) -> Result<(usize, NodeType)> {
+        let field = match ty {
+            NodeType::Struct(def) => self.structs[def]
+                .fields
+                .iter()
+                .enumerate()
+                .find(|(_, (field, _))| field == name)
+                .map(|(index, (_, ty))| (index, ty.clone())),
+            _ => None,
+        };
+
+        field.ok_or_else(|| {
+            self.error(
+                ErrorCodes::UnknownIdentifier,
+                &format!("type `{}` has no field `{}`", ty, name),
+            )
+        })
+    }
+
+    /// What assigning to `name` would change.
+    fn place(&self, name: &str) -> Result<Place> {
+        if let Some(local) = self.local(name) {
+            return Ok(Place::Local(local));
+        }
+
+        match self.items.get(name) {
+            Some(Item::Static(global)) if self.globals[*global].mutable => {
+                Ok(Place::Global(*global))
+            }
+            Some(Item::Static(_)) => Err(self.error(
+                ErrorCodes::InvalidAssignment,
+                &format!(
+                    "cannot assign to immutable static `{}`, declare it `static mut`",
+                    name
+                ),
+            )),
+            Some(Item::Const(_)) => Err(self.error(
+                ErrorCodes::InvalidAssignment,
+                &format!("cannot assign to constant `{}`", name),
+            )),
+            _ => Err(self.unknown_variable(name)),
+        }
+    }
+
+    /// The value of the constant called `name`, if there is one.
+    fn constant(&self, name: &str) -> Option<Literal> {
+        match self.items.get(name) {
+            Some(Item::Const(value)) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn unknown_variable(&self, name: &str) -> CompileError {
+        self.error(
+            ErrorCodes::UnknownIdentifier,
+            &format!("unknown variable `{}`", name),
+        )
+    }
+
+    /// An operation on constants that would fail at runtime.
+    fn fold_error(&self, note: String) -> CompileError {
+        self.error(ErrorCodes::ConstEvalError, &note)
+    }
+
+    fn expect(&self, expected: &NodeType, found: &NodeType) -> Result<()> {
+        if expected == found {
+            Ok(())
+        } else {
+            Err(self.error(
+                ErrorCodes::MismatchedTypes,
+                &format!("expected `{}`, found `{}`", expected, found),
+            ))
+        }
+    }
+
+    fn error(&self, code: ErrorCodes, note: &str) -> CompileError {
+        CompileErrorBuilder::new()
+            .from(code)
+            .at((self.at.0, self.at.1 + 1))
+            .note(note)
+            .build()
+    }
+}
+
+/// Whether a block ends by returning, so it doesn't need a value.
+fn returns(block: &Block) -> bool {
+    matches!(
+        block.stmts.last(),
+        Some(Stmt {
+            kind: StmtKind::Return(_),
+            ..
+        })
+    )
+}
+
+/// Whether evaluating an expression can't have any effect.
+fn pure(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Literal(_) | ExprKind::Local(_) | ExprKind::Global(_) => true,
+        ExprKind::Field { base, .. } => pure(base),
+        _ => false,
+    }
+}
+
+/// `value == literal`, to test a value against a pattern.
+fn equals(value: Expr, literal: Literal) -> Expr {
+    let literal = Expr {
+        ty: literal.ty(),
+        kind: literal.into(),
+    };
+
+    Expr {
+        kind: ExprKind::Binary {
+            op: Op::Eq,
+            lhs: Box::new(value),
+            rhs: Box::new(literal),
+        },
+        ty: NodeType::Bool,
+    }
+}
+
+/// The functions declared in a file.
+pub fn functions(ast: &TopLevel) -> Vec<&Function> {
+    ast.fns
+        .iter()
+        .filter_map(|func| match func {
+            Node::Fn(func) => Some(func.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The `extern fn` items declared in a file.
+pub fn externs(ast: &TopLevel) -> Vec<&Extern> {
+    ast.externs
+        .iter()
+        .filter_map(|item| match item {
+            Node::Extern(item) => Some(item.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether values of a type can be passed to and returned from C, as an
+/// `int32_t`, a `float`, a `bool`, a `uint32_t` code point or a
+/// NUL-terminated `char *`.
+pub fn is_c_type(ty: &NodeType) -> bool {
+    matches!(
+        ty,
+        NodeType::Integer | NodeType::Float | NodeType::Bool | NodeType::Char | NodeType::String
+    )
+}
+
+/// The `const` items declared in a file.
+pub fn constants(ast: &TopLevel) -> Vec<&Constant> {
+    ast.consts
+        .iter()
+        .filter_map(|constant| match constant {
+            Node::Const(constant) => Some(constant.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The `struct` items declared in a file.
+pub fn structs(ast: &TopLevel) -> Vec<&Struct> {
+    ast.structs
+        .iter()
+        .filter_map(|item| match item {
+            Node::Struct(item) => Some(item.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The `enum` items declared in a file.
+pub fn enums(ast: &TopLevel) -> Vec<&Enum> {
+    ast.enums
+        .iter()
+        .filter_map(|item| match item {
+            Node::Enum(item) => Some(item.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The `static` items declared in a file.
+pub fn statics(ast: &TopLevel) -> Vec<&Static> {
+    ast.statics
+        .iter()
+        .filter_map(|item| match item {
+            Node::Static(item) => Some(item.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,253 @@
+//! Checks that the arms of a `match` cover every value and that each one
+//! matches something the arms before it don't.
+//!
+//! Both come down to the same question, answered over a matrix of patterns
+//! with a row per arm: is there a value that the rows don't match? Rows are
+//! narrowed down to one variant at a time, until there's either nothing left
+//! to match or no row left to match it.
+
+use std::{collections::HashMap, slice};
+
+use crate::{
+    hir::{Enum, Literal},
+    parser::ast::ast::NodeType,
+};
+
+/// A pattern reduced to what matters for the checks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pat {
+    /// Matches anything, like `_` or a binding.
+    Wild,
+    /// A variant of an enum by index, with a pattern for each of its fields.
+    Variant(usize, Vec<Pat>),
+    /// There are too many literals of most types to list them all, but a
+    /// `bool` only has two.
+    Literal(Literal),
+}
+
+/// A pattern the arms don't cover, as it would be written, if there is one.
+pub fn missing(arms: &[Pat], ty: &NodeType, enums: &HashMap<String, Enum>) -> Option<String> {
+    let rows = arms.iter().map(|pat| vec![pat.clone()]).collect();
+    let witness = Matrix { enums }.missing(rows, slice::from_ref(ty))?;
+
+    Some(show(&witness[0], ty, enums))
+}
+
+/// Whether `pat` matches a value that none of `arms` do.
+pub fn useful(arms: &[Pat], pat: &Pat, ty: &NodeType, enums: &HashMap<String, Enum>) -> bool {
+    let rows = arms.iter().map(|pat| vec![pat.clone()]).collect();
+
+    Matrix { enums }.useful(rows, vec![pat.clone()], slice::from_ref(ty))
+}
+
+/// What a pattern matches at the top level, without its fields.
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Variant(usize),
+    Literal(Literal),
+}
+
+struct Matrix<'a> {
+    enums: &'a HashMap<String, Enum>,
+}
+
+impl Matrix<'_> {
+    /// Whether `row` matches a value no row of `rows` does. The first
+    /// pattern of every row is for a value of the first of `tys`, and so on.
+    fn useful(&self, rows: Vec<Vec<Pat>>, row: Vec<Pat>, tys: &[NodeType]) -> bool {
+        let (ty, rest) = match tys.split_first() {
+            Some(split) => split,
+            None => return rows.is_empty(),
+        };
+
+        match ctor(&row[0]) {
+            Some(ctor) => {
+                let fields = self.fields(&ctor, ty);
+                let row = specialise_row(&row, &ctor, fields.len()).unwrap();
+
+                self.useful(
+                    specialise(&rows, &ctor, fields.len()),
+                    row,
+                    &[fields, rest.to_vec()].concat(),
+                )
+            }
+            None => match self.complete(&rows, ty) {
+                Some(ctors) => ctors.into_iter().any(|ctor| {
+                    let fields = self.fields(&ctor, ty);
+                    let row = specialise_row(&row, &ctor, fields.len()).unwrap();
+
+                    self.useful(
+                        specialise(&rows, &ctor, fields.len()),
+                        row,
+                        &[fields, rest.to_vec()].concat(),
+                    )
+                }),
+                None => self.useful(default(&rows), row[1..].to_vec(), rest),
+            },
+        }
+    }
+
+    /// A row of patterns, one for each of `tys`, that matches a value no row
+    /// of `rows` does, or `None` if they match every value.
+    fn missing(&self, rows: Vec<Vec<Pat>>, tys: &[NodeType]) -> Option<Vec<Pat>> {
+        let (ty, rest) = match tys.split_first() {
+            Some(split) => split,
+            None if rows.is_empty() => return Some(Vec::new()),
+            None => return None,
+        };
+
+        if let Some(ctors) = self.complete(&rows, ty) {
+            // every variant is mentioned, so one of them has to be missing
+            // something in its fields
+            return ctors.into_iter().find_map(|ctor| {
+                let fields = self.fields(&ctor, ty);
+                let arity = fields.len();
+                let mut witness = self.missing(
+                    specialise(&rows, &ctor, arity),
+                    &[fields, rest.to_vec()].concat(),
+                )?;
+                let rest = witness.split_off(arity);
+
+                Some([vec![apply(ctor, witness)], rest].concat())
+            });
+        }
+
+        let witness = self.missing(default(&rows), rest)?;
+
+        // name a variant nothing mentions, if there is one
+        let head = match self.ctors(ty) {
+            Some(ctors) => {
+                let used = heads(&rows);
+                let ctor = ctors.into_iter().find(|ctor| !used.contains(ctor)).unwrap();
+                let arity = self.fields(&ctor, ty).len();
+
+                apply(ctor, vec![Pat::Wild; arity])
+            }
+            None => Pat::Wild,
+        };
+
+        Some([vec![head], witness].concat())
+    }
+
+    /// Every constructor of `ty` if the first column of `rows` mentions them
+    /// all, so that a wildcard has to be split into each of them.
+    fn complete(&self, rows: &[Vec<Pat>], ty: &NodeType) -> Option<Vec<Ctor>> {
+        let ctors = self.ctors(ty)?;
+        let used = heads(rows);
+
+        if ctors.iter().all(|ctor| used.contains(ctor)) {
+            Some(ctors)
+        } else {
+            None
+        }
+    }
+
+    /// Every constructor of `ty`, if there are few enough to list.
+    fn ctors(&self, ty: &NodeType) -> Option<Vec<Ctor>> {
+        match ty {
+            NodeType::Enum(name) => Some(
+                (0..self.enums[name].variants.len())
+                    .map(Ctor::Variant)
+                    .collect(),
+            ),
+            NodeType::Bool => Some(vec![
+                Ctor::Literal(Literal::Bool(false)),
+                Ctor::Literal(Literal::Bool(true)),
+            ]),
+            _ => None,
+        }
+    }
+
+    /// The types of the fields of a constructor of `ty`.
+    fn fields(&self, ctor: &Ctor, ty: &NodeType) -> Vec<NodeType> {
+        match (ctor, ty) {
+            (Ctor::Variant(variant), NodeType::Enum(name)) => self.enums[name].variants[*variant]
+                .fields
+                .iter()
+                .map(|(_, ty)| ty.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn ctor(pat: &Pat) -> Option<Ctor> {
+    match pat {
+        Pat::Wild => None,
+        Pat::Variant(variant, _) => Some(Ctor::Variant(*variant)),
+        Pat::Literal(literal) => Some(Ctor::Literal(literal.clone())),
+    }
+}
+
+/// The constructors the first column of `rows` mentions.
+fn heads(rows: &[Vec<Pat>]) -> Vec<Ctor> {
+    rows.iter().filter_map(|row| ctor(&row[0])).collect()
+}
+
+fn apply(ctor: Ctor, fields: Vec<Pat>) -> Pat {
+    match ctor {
+        Ctor::Variant(variant) => Pat::Variant(variant, fields),
+        Ctor::Literal(literal) => Pat::Literal(literal),
+    }
+}
+
+/// The rows that match a value made with `ctor`, with their first pattern
+/// replaced by ones for its `arity` fields.
+fn specialise(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| specialise_row(row, ctor, arity))
+        .collect()
+}
+
+fn specialise_row(row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Vec<Pat>> {
+    let fields = match &row[0] {
+        Pat::Wild => vec![Pat::Wild; arity],
+        Pat::Variant(variant, fields) if Ctor::Variant(*variant) == *ctor => fields.clone(),
+        Pat::Literal(literal) if Ctor::Literal(literal.clone()) == *ctor => Vec::new(),
+        _ => return None,
+    };
+
+    Some([fields, row[1..].to_vec()].concat())
+}
+
+/// The rows that match anything in their first column, without it.
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| row[0] == Pat::Wild)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Write a pattern for a value of `ty` the way it would appear in code.
+fn show(pat: &Pat, ty: &NodeType, enums: &HashMap<String, Enum>) -> String {
+    match (pat, ty) {
+        (Pat::Variant(variant, fields), NodeType::Enum(name)) => {
+            let variant = &enums[name].variants[*variant];
+            let path = format!("{}::{}", name, variant.name);
+            let fields = fields
+                .iter()
+                .zip(&variant.fields)
+                .map(|(pat, (name, ty))| (name, show(pat, ty, enums)))
+                .collect::<Vec<_>>();
+
+            if fields.is_empty() {
+                path
+            } else if !variant.named {
+                let fields = fields.into_iter().map(|(_, pat)| pat).collect::<Vec<_>>();
+
+                format!("{}({})", path, fields.join(", "))
+            } else if fields.iter().all(|(_, pat)| pat == "_") {
+                format!("{} {{ .. }}", path)
+            } else {
+                let fields = fields
+                    .into_iter()
+                    .map(|(name, pat)| format!("{}: {}", name, pat))
+                    .collect::<Vec<_>>();
+
+                format!("{} {{ {} }}", path, fields.join(", "))
+            }
+        }
+        (Pat::Literal(Literal::Bool(b)), _) => b.to_string(),
+        _ => "_".to_string(),
+    }
+}
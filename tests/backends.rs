@@ -0,0 +1,490 @@
+//! Runs the same programs with every backend, which have to agree on what
+//! they return.
+
+use qlang::{
+    bytecode::{compiler::Compiler, vm::Vm},
+    interp::{Interpreter, Value},
+    parser::{ast::ast::Node, error::CompileError, TopLevel},
+    typeck::TypeChecker,
+};
+#[cfg(feature = "llvm")]
+use qlang::{
+    inkwell::context::Context,
+    llvm::{codegen::Codegen, target::Target},
+};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// What calling `main` in `code` returns with every backend.
+fn run(code: &str) -> Vec<Result<Value>> {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let mut checker = TypeChecker::new();
+    let program = checker.check(&ast).expect("the program type checks");
+    let main = checker
+        .check_body(&Node::Call {
+            name: "main".to_string(),
+            args: Vec::new(),
+        })
+        .unwrap();
+
+    let mut results = Vec::new();
+
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program.clone()).unwrap();
+    results.push(interpreter.evaluate(&main));
+
+    let compiled = Compiler::new().compile(&program).unwrap();
+    results.push(Vm::new(&compiled).call("main", Vec::new()));
+
+    #[cfg(feature = "llvm")]
+    {
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, context.create_module("test"));
+
+        codegen.compile(program).unwrap();
+        results.push(codegen.evaluate(&main));
+    }
+
+    results
+}
+
+/// Check that every backend returns `expected` from `main` in `code`.
+fn returns(code: &str, expected: Value) {
+    for result in run(code) {
+        assert_eq!(result.unwrap(), expected);
+    }
+}
+
+/// Check that `main` in `code` fails with every backend.
+fn fails(code: &str) {
+    for result in run(code) {
+        assert!(result.is_err(), "expected an error, got {:?}", result);
+    }
+}
+
+#[test]
+fn and_skips_its_right_side_when_the_left_is_false() {
+    returns(
+        "
+fn zero_at(a: [int; 3], i: int) -> :bool {
+    return i < 3 && a[i] == 0;
+}
+
+fn main() -> :bool {
+    let a = [1, 2, 3];
+    return zero_at(a, 3);
+}",
+        Value::Bool(false),
+    );
+}
+
+#[test]
+fn or_skips_its_right_side_when_the_left_is_true() {
+    returns(
+        "
+fn main() -> :int {
+    let a = [1, 0, 2];
+    let i = 3;
+    if i >= 3 || a[i] == 0 {
+        return 1;
+    }
+    return 0;
+}",
+        Value::Integer(1),
+    );
+}
+
+#[test]
+fn short_circuiting_conditions_are_checked_on_every_iteration() {
+    returns(
+        "
+fn main() -> :int {
+    let a = [1, 2, 0];
+    let i = 0;
+    while i < 3 && a[i] != 0 {
+        i = i + 1;
+    }
+    return i;
+}",
+        Value::Integer(2),
+    );
+}
+
+#[test]
+fn right_sides_still_run_when_they_are_needed() {
+    returns(
+        "
+static mut calls: int = 0;
+
+fn count(result: bool) -> :bool {
+    calls = calls + 1;
+    return result;
+}
+
+fn main() -> :int {
+    let a = count(true) && count(false);
+    let b = count(false) || count(true);
+    let c = count(false) && count(true);
+    let d = count(true) || count(false);
+    if a || !b || c || !d {
+        return -1;
+    }
+    return calls;
+}",
+        Value::Integer(6),
+    );
+}
+
+#[test]
+fn indexing_out_of_bounds_fails() {
+    fails(
+        "
+fn main() -> :bool {
+    let a = [1, 2, 3];
+    let i = 3;
+    return i < 4 && a[i] == 0;
+}",
+    );
+}
+
+/// The `main` the program in `code` is started at when it's built into an
+/// executable.
+#[cfg(feature = "llvm")]
+fn entry_point(code: &str) -> Result<Option<String>> {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let program = TypeChecker::new()
+        .check(&ast)
+        .expect("the program type checks");
+
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, context.create_module("test")).with_entry_point();
+    codegen.compile(program)?;
+
+    Ok(codegen.function_ir("main"))
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn executables_exit_with_0_for_a_void_main() {
+    let main = entry_point("fn main() -> :void {}").unwrap().unwrap();
+
+    assert!(main.contains("ret i32 0"), "{}", main);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn executables_exit_with_what_main_returns() {
+    let main = entry_point("fn main() -> :int { return 3; }")
+        .unwrap()
+        .unwrap();
+
+    assert!(main.contains("call i32 @main.qlang()"), "{}", main);
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn executables_need_a_main_without_arguments_returning_an_int() {
+    assert!(entry_point("fn main(x: int) -> :int { return x; }").is_err());
+    assert!(entry_point("fn main() -> :str { return \"\"; }").is_err());
+}
+
+/// The LLVM IR of the function `name` once the program in `code` is
+/// compiled for WebAssembly.
+#[cfg(feature = "llvm")]
+fn wasm(code: &str, name: &str) -> Option<String> {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let program = TypeChecker::new()
+        .check(&ast)
+        .expect("the program type checks");
+
+    let context = Context::create();
+    let mut codegen = Codegen::with_target(&context, context.create_module("test"), Target::Wasm32);
+    codegen.compile(program).unwrap();
+
+    codegen.function_ir(name)
+}
+
+#[cfg(feature = "llvm")]
+#[test]
+fn webassembly_exports_public_functions_through_c_types() {
+    let code = "
+struct P { x: int }
+
+pub fn greet(name: str) -> :str { return \"hi \" + name; }
+pub fn make() -> :P { return P { x: 1 }; }
+fn hidden() -> :int { return 1; }";
+
+    let greet = wasm(code, "greet.wasm").unwrap();
+
+    assert!(greet.contains("i8* @greet.wasm(i8*"), "{}", greet);
+    assert!(wasm(code, "make.wasm").is_none());
+    assert!(wasm(code, "hidden.wasm").is_none());
+}
+
+#[test]
+fn strings_are_interpolated() {
+    returns(
+        r#"
+struct P { x: int }
+
+fn main() -> :str {
+    let name = "wörld";
+    let n = 41;
+    return "hello {name}, {n + 1} {n > 40} {{ok}} {P { x: 3 }.x} {int("2") * 2}";
+}"#,
+        Value::String("hello wörld, 42 true {ok} 3 4".to_string()),
+    );
+}
+
+#[test]
+fn functions_can_end_in_a_while_true_loop() {
+    returns(
+        "
+fn first_over(limit: int) -> :int {
+    let i = 0;
+    while true {
+        if i * i > limit {
+            return i;
+        }
+        i = i + 1;
+    }
+}
+
+fn main() -> :int { return first_over(50); }",
+        Value::Integer(8),
+    );
+}
+
+#[test]
+fn fields_tell_vec_new_what_it_holds() {
+    returns(
+        "
+struct P { v: Vec<int> }
+
+enum E { Named { v: Vec<str> }, Tuple(Vec<bool>) }
+
+fn main() -> :int {
+    let p = P { v: Vec::new() };
+    push(p.v, 1);
+    let n = E::Named { v: Vec::new() };
+    let t = E::Tuple(Vec::new());
+    return len(p.v);
+}",
+        Value::Integer(1),
+    );
+}
+
+#[test]
+fn matches_run_the_first_arm_that_matches() {
+    returns(
+        r#"
+enum Shape { Circle(float), Rect { w: float, h: float }, Empty }
+enum Opt { Some(Shape), None }
+
+fn kind(o: Opt) -> :int {
+    match o {
+        Opt::Some(Shape::Circle(_)) => 1,
+        Opt::Some(Shape::Rect { w: 2.0, .. }) => 2,
+        Opt::Some(_) => 3,
+        Opt::None => { return 4; }
+    }
+}
+
+fn digit(n: int) -> :int {
+    match n {
+        0 => 100,
+        1 => 101,
+        x => x + 1,
+    }
+}
+
+fn word(s: str) -> :int {
+    match s { "one" => 1, "two" => 2, _ => 0 }
+}
+
+fn main() -> :int {
+    let kinds = kind(Opt::Some(Shape::Circle(1.0))) * 1000
+        + kind(Opt::Some(Shape::Rect { h: 1.0, w: 2.0 })) * 100
+        + kind(Opt::Some(Shape::Rect { w: 3.0, h: 1.0 })) * 10
+        + kind(Opt::None);
+    return kinds + digit(0) + digit(1) + digit(5) + word("two") + word("three");
+}"#,
+        Value::Integer(1234 + 100 + 101 + 6 + 2),
+    );
+}
+
+#[test]
+fn arrays_are_indexed_and_assigned_to() {
+    returns(
+        "
+struct Grid { cells: [[int; 3]; 2], n: int }
+
+fn sum(a: [int; 4]) -> :int {
+    let total = 0;
+    let i = 0;
+    while i < len(a) {
+        total = total + a[i];
+        i = i + 1;
+    }
+    total;
+}
+
+fn main() -> :int {
+    let a: [int; 4] = [1, 2, 3, 4];
+    a[2] = 10;
+    let g = Grid { cells: [[1, 2, 3], [4, 5, 6]], n: 0 };
+    g.cells[1][2] = 60;
+    g.n = g.cells[1][2] + g.cells[0][0];
+    let x = (a[1] = 7) + 1;
+    return sum(a) + g.n + x + len([true, false]);
+}",
+        Value::Integer(22 + 61 + 8 + 2),
+    );
+}
+
+#[test]
+fn arrays_are_copied_when_they_are_passed_or_assigned() {
+    returns(
+        "
+fn clear(a: [int; 2]) -> :int {
+    a[0] = 0;
+    return a[0];
+}
+
+fn main() -> :int {
+    let a = [1, 2];
+    let b = a;
+    b[1] = 20;
+    let cleared = clear(a);
+    return a[0] * 100 + a[1] * 10 + b[1] + cleared;
+}",
+        Value::Integer(100 + 20 + 20),
+    );
+}
+
+#[test]
+fn indexing_before_the_start_fails() {
+    fails(
+        "
+fn main() -> :int {
+    let a = [1, 2, 3];
+    let i = 0 - 1;
+    return a[i];
+}",
+    );
+}
+
+#[test]
+fn vecs_grow_shrink_and_are_sliced() {
+    returns(
+        "
+fn sum(values: [int]) -> :int {
+    let total = 0;
+    for v in values {
+        total = total + v;
+    }
+    total;
+}
+
+fn squares(n: int) -> :Vec<int> {
+    let v: Vec<int> = Vec::new();
+    let i = 0;
+    while i < n {
+        push(v, i * i);
+        i = i + 1;
+    }
+    return v;
+}
+
+fn main() -> :int {
+    let v = squares(5);
+    let last = pop(v);
+    set(v, 0, 7);
+    let part = slice(slice(v, 1, 4), 1, 3);
+    return sum(slice(v, 0, len(v))) * 100 + last * 10 + len(part) + get(part, 1) * 1000;
+}",
+        Value::Integer(21 * 100 + 16 * 10 + 2 + 9 * 1000),
+    );
+}
+
+#[test]
+fn copies_of_a_vec_share_its_values() {
+    returns(
+        "
+struct Stack { items: Vec<int> }
+
+fn fill(v: Vec<int>) -> :void {
+    push(v, 1);
+    push(v, 2);
+}
+
+fn main() -> :int {
+    let v: Vec<int> = Vec::new();
+    let s = Stack { items: v };
+    fill(v);
+    push(s.items, 3);
+    let total = len(v) * 10 + pop(v);
+    free(v);
+    return total + len(s.items) * 100;
+}",
+        Value::Integer(33),
+    );
+}
+
+#[test]
+fn reading_past_the_end_of_a_vec_fails() {
+    for code in [
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); return pop(v); }",
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); push(v, 1); return get(v, 1); }",
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); push(v, 1); set(v, 2, 0); return 0; }",
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); push(v, 1); return len(slice(v, 0, 2)); }",
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); push(v, 1); return len(slice(v, 1, 0)); }",
+    ] {
+        fails(code);
+    }
+}
+
+#[test]
+fn strings_are_joined_compared_and_sliced_by_byte() {
+    returns(
+        r#"
+fn greet(name: str) -> :str {
+    return "hello " + name;
+}
+
+fn main() -> :int {
+    let s = greet("wörld");
+    let total = len(s);
+    if slice(s, 6, 12) == "wörld" { total = total + 100; }
+    if "abc" < "abd" && "ab" < "abc" && "b" > "abc" { total = total + 1000; }
+    if s != "hello" { total = total + 10000; }
+    return total;
+}"#,
+        Value::Integer(12 + 100 + 1000 + 10000),
+    );
+}
+
+#[test]
+fn strings_are_converted_to_and_from_other_types() {
+    returns(
+        r#"
+fn main() -> :str {
+    let n = int("42") + int(str(8));
+    let f = float("2.5");
+    return str(n) + " " + str(f) + " " + str(1.0 / 3.0) + " " + str(true) + " " + str('c');
+}"#,
+        Value::String("50 2.5 0.333333 true c".to_string()),
+    );
+}
+
+#[test]
+fn strings_that_arent_numbers_and_slices_inside_a_character_fail() {
+    for code in [
+        r#"fn main() -> :int { return int("4x"); }"#,
+        r#"fn main() -> :float { return float("one"); }"#,
+        r#"fn main() -> :int { return len(slice("wörld", 0, 2)); }"#,
+        r#"fn main() -> :int { return len(slice("abc", 2, 4)); }"#,
+    ] {
+        fails(code);
+    }
+}
@@ -0,0 +1,175 @@
+//! Compiling to bytecode, writing it to a `.qbc` file and reading it back,
+//! and running it with the VM.
+
+use qlang::{
+    bytecode::{compiler::Compiler, format, vm::Vm, Function, Instr, Program},
+    interp::Value,
+    parser::{ast::ast::Op, TopLevel},
+    typeck::TypeChecker,
+};
+
+/// A program of the single function `main`, which takes `arity` arguments
+/// and runs `code`.
+fn program(arity: u16, code: Vec<Instr>) -> Program {
+    let positions = vec![(0, 0); code.len()];
+
+    Program {
+        constants: vec![Value::Integer(1)],
+        globals: Vec::new(),
+        functions: vec![Function {
+            name: "main".to_string(),
+            file: None,
+            arity,
+            locals: arity,
+            code,
+            positions,
+        }],
+    }
+}
+
+/// The program in `code`, compiled to bytecode.
+fn compile(code: &str) -> Program {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let program = TypeChecker::new()
+        .check(&ast)
+        .expect("the program type checks");
+
+    Compiler::new().compile(&program).unwrap()
+}
+
+/// A program using every kind of constant, global and value there is.
+const EVERYTHING: &str = "
+struct Point { x: int, y: float }
+
+enum Shape { Circle(float), Square { side: float }, Empty }
+
+static mut calls: int = 0;
+static NAME: str = \"shapes\";
+
+fn area(s: Shape) -> :float {
+    calls = calls + 1;
+    match s {
+        Shape::Circle(r) => 3.0 * r * r,
+        Shape::Square { side } => side * side,
+        Shape::Empty => 0.0,
+    }
+}
+
+fn main() -> :str {
+    let p = Point { x: 2, y: 0.5 };
+    let sides: [float; 2] = [1.0, 2.0];
+    let shapes: Vec<Shape> = Vec::new();
+    push(shapes, Shape::Circle(p.y));
+    push(shapes, Shape::Square { side: sides[p.x - 1] });
+    push(shapes, Shape::Empty);
+    let total = 0.0;
+    for s in shapes {
+        total = total + area(s);
+    }
+    let c = 'q';
+    return \"{NAME} {total} {calls} {c} {len(shapes) == 3}\";
+}";
+
+#[test]
+fn compiled_programs_are_read_back_as_written() {
+    let program = compile(EVERYTHING);
+    let bytes = format::serialise(&program);
+
+    assert!(format::is_bytecode(&bytes));
+
+    let read = format::deserialise(&bytes).unwrap();
+
+    assert_eq!(read, program);
+    assert_eq!(read.to_string(), program.to_string());
+    assert_eq!(
+        Vm::new(&read).call("main", Vec::new()).unwrap(),
+        Value::String("shapes 4.75 3 q true".to_string())
+    );
+}
+
+#[test]
+fn cut_short_or_mismatched_files_are_rejected() {
+    let bytes = format::serialise(&compile(EVERYTHING));
+
+    for len in 0..bytes.len() {
+        assert!(format::deserialise(&bytes[..len]).is_err(), "{}", len);
+    }
+
+    assert!(!format::is_bytecode(b"fn main() -> :int { return 0; }"));
+
+    // a file written by another version of the format
+    let mut other = bytes;
+    other[format::MAGIC.len()] = format::VERSION + 1;
+
+    assert!(format::deserialise(&other).is_err());
+}
+
+#[test]
+fn functions_are_called_with_as_many_arguments_as_they_take() {
+    let program = program(1, vec![Instr::Load(0), Instr::Return]);
+    let mut vm = Vm::new(&program);
+
+    assert!(vm.call("main", Vec::new()).is_err());
+    assert!(vm
+        .call("main", vec![Value::Integer(1), Value::Integer(2)])
+        .is_err());
+    assert_eq!(
+        vm.call("main", vec![Value::Integer(3)]).unwrap(),
+        Value::Integer(3)
+    );
+}
+
+#[test]
+fn bytecode_that_pops_more_than_it_pushes_is_rejected() {
+    for code in [
+        vec![Instr::Pop, Instr::Void, Instr::Return],
+        vec![Instr::Return],
+        vec![Instr::Dup, Instr::Return],
+        vec![Instr::Const(0), Instr::Dup2, Instr::Return],
+        vec![Instr::Const(0), Instr::MakeStruct(2), Instr::Return],
+        vec![Instr::MakeArray(1), Instr::Return],
+        vec![Instr::MakeVariant(0, 1), Instr::Return],
+        vec![Instr::Const(0), Instr::Binary(Op::Eq), Instr::Return],
+    ] {
+        let bytes = format::serialise(&program(0, code.clone()));
+
+        assert!(format::deserialise(&bytes).is_err(), "{:?}", code);
+    }
+}
+
+#[test]
+fn bytecode_that_leaves_the_stack_uneven_is_rejected() {
+    // the jump skips pushing two values, only one of which is popped
+    let code = vec![
+        Instr::Const(0),
+        Instr::Const(0),
+        Instr::Binary(Op::Eq),
+        Instr::JumpIfFalse(6),
+        Instr::Const(0),
+        Instr::Const(0),
+        Instr::Return,
+    ];
+    let bytes = format::serialise(&program(0, code));
+
+    assert!(format::deserialise(&bytes).is_err());
+}
+
+#[test]
+fn valid_bytecode_is_read_back_as_written() {
+    let code = vec![
+        Instr::Const(0),
+        Instr::Load(0),
+        Instr::Binary(Op::Eq),
+        Instr::Return,
+    ];
+    let program = program(1, code);
+    let read = format::deserialise(&format::serialise(&program)).unwrap();
+
+    assert_eq!(read, program);
+    assert_eq!(
+        Vm::new(&read)
+            .call("main", vec![Value::Integer(2)])
+            .unwrap(),
+        Value::Bool(false)
+    );
+}
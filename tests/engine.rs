@@ -0,0 +1,204 @@
+//! Embedding Qlang with an [`Engine`]: calling into compiled code, letting it
+//! call back into the host and getting its errors back.
+
+#[cfg(feature = "llvm")]
+use qlang::inkwell::context::Context;
+use qlang::{parser::error::ErrorCodes, Engine};
+
+/// Run `test` with an engine for every backend.
+fn each(test: impl Fn(Engine)) {
+    test(Engine::interpreted());
+
+    #[cfg(feature = "llvm")]
+    {
+        let context = Context::create();
+        test(Engine::new(&context));
+    }
+}
+
+#[test]
+fn calls_into_compiled_code() {
+    each(|mut engine| {
+        engine
+            .compile(
+                "
+fn square(x: int) -> :int { return x * x; }
+fn greet(name: str) -> :str { return \"hello \" + name; }
+fn even(x: int) -> :bool { return x % 2 == 0; }
+fn nothing() -> :void {}",
+            )
+            .unwrap();
+
+        assert_eq!(engine.call::<i32>("square", (-4,)).unwrap(), 16);
+        assert_eq!(
+            engine
+                .call::<String>("greet", ("world".to_string(),))
+                .unwrap(),
+            "hello world"
+        );
+        assert!(engine.call::<bool>("even", (10,)).unwrap());
+        engine.call::<()>("nothing", ()).unwrap();
+    });
+}
+
+#[test]
+fn compiled_code_calls_host_functions() {
+    each(|mut engine| {
+        engine.register("add", |a: i32, b: i32| a + b).unwrap();
+        engine
+            .register("shout", |s: String| s.to_uppercase())
+            .unwrap();
+        engine
+            .compile(
+                "
+fn sum(n: int) -> :int {
+    let total = 0;
+    let i = 1;
+    while i <= n {
+        total = add(total, i);
+        i = i + 1;
+    }
+    return total;
+}
+
+fn loud(s: str) -> :str { return shout(s) + \"!\"; }",
+            )
+            .unwrap();
+
+        assert_eq!(engine.call::<i32>("sum", (10,)).unwrap(), 55);
+        assert_eq!(
+            engine.call::<String>("loud", ("hey".to_string(),)).unwrap(),
+            "HEY!"
+        );
+    });
+}
+
+#[test]
+fn code_compiled_later_uses_what_was_compiled_before() {
+    each(|mut engine| {
+        engine
+            .compile("fn double(x: int) -> :int { return x * 2; }")
+            .unwrap();
+        engine
+            .compile("fn quadruple(x: int) -> :int { return double(double(x)); }")
+            .unwrap();
+
+        assert_eq!(engine.call::<i32>("quadruple", (3,)).unwrap(), 12);
+    });
+}
+
+#[test]
+fn compile_errors_show_the_source() {
+    each(|mut engine| {
+        let error = engine
+            .compile("fn broken() -> :int { return true; }")
+            .unwrap_err();
+
+        assert!(error.is(ErrorCodes::MismatchedTypes));
+        assert!(error.to_string().contains("return true;"), "{}", error);
+    });
+}
+
+#[test]
+fn nothing_is_kept_of_code_that_fails_to_compile() {
+    each(|mut engine| {
+        engine
+            .compile("fn fine() -> :int { return 1; } fn broken() -> :int { return true; }")
+            .unwrap_err();
+
+        assert!(engine.call::<i32>("fine", ()).is_err());
+        engine.compile("fn fine() -> :int { return 2; }").unwrap();
+        assert_eq!(engine.call::<i32>("fine", ()).unwrap(), 2);
+    });
+}
+
+#[test]
+fn calls_are_checked_against_the_signature() {
+    each(|mut engine| {
+        engine
+            .compile("fn square(x: int) -> :int { return x * x; }")
+            .unwrap();
+
+        assert!(engine.call::<i32>("missing", ()).is_err());
+        assert!(engine.call::<i32>("square", (1, 2)).is_err());
+        assert!(engine.call::<i32>("square", (true,)).is_err());
+        assert!(engine
+            .call::<bool>("square", (2,))
+            .unwrap_err()
+            .is(ErrorCodes::MismatchedTypes));
+    });
+}
+
+#[test]
+fn runtime_errors_are_returned_to_the_host() {
+    each(|mut engine| {
+        engine
+            .compile(
+                "
+fn divide(a: int, b: int) -> :int { return a / b; }
+fn remainder(a: int, b: int) -> :int { return a % b; }
+fn at(i: int) -> :int {
+    let a = [1, 2, 3];
+    return a[i];
+}",
+            )
+            .unwrap();
+
+        for (result, line) in [
+            (engine.call::<i32>("divide", (1, 0)), "return a / b;"),
+            (engine.call::<i32>("remainder", (1, 0)), "return a % b;"),
+            (engine.call::<i32>("at", (3,)), "return a[i];"),
+            (engine.call::<i32>("at", (-1,)), "return a[i];"),
+        ] {
+            let error = result.unwrap_err();
+
+            assert!(error.is(ErrorCodes::RuntimeError));
+            assert!(error.to_string().contains(line), "{}", error);
+        }
+
+        // and the engine keeps working afterwards
+        assert_eq!(engine.call::<i32>("divide", (7, 2)).unwrap(), 3);
+        assert_eq!(engine.call::<i32>("at", (2,)).unwrap(), 3);
+    });
+}
+
+#[test]
+fn dividing_the_smallest_int_by_minus_one_wraps_around() {
+    each(|mut engine| {
+        engine
+            .compile(
+                "
+fn divide(a: int, b: int) -> :int { return a / b; }
+fn remainder(a: int, b: int) -> :int { return a % b; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            engine.call::<i32>("divide", (i32::MIN, -1)).unwrap(),
+            i32::MIN
+        );
+        assert_eq!(engine.call::<i32>("remainder", (i32::MIN, -1)).unwrap(), 0);
+    });
+}
+
+#[test]
+fn calls_share_globals_with_each_other_and_with_code_compiled_later() {
+    each(|mut engine| {
+        engine
+            .compile(
+                "static mut count: int = 0; fn bump() -> :int { count = count + 1; return count; }",
+            )
+            .unwrap();
+
+        for expected in 1..=100 {
+            assert_eq!(engine.call::<i32>("bump", ()).unwrap(), expected);
+        }
+
+        engine
+            .compile("fn twice() -> :int { bump(); return bump(); }")
+            .unwrap();
+
+        assert_eq!(engine.call::<i32>("twice", ()).unwrap(), 102);
+        assert_eq!(engine.call::<i32>("bump", ()).unwrap(), 103);
+    });
+}
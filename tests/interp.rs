@@ -0,0 +1,147 @@
+//! Running programs with the interpreter, and calling into them from the
+//! host.
+
+use qlang::{
+    hir::Program,
+    interp::{HostFn, Interpreter, Value},
+    parser::{ast::ast::Node, error::ErrorCodes, TopLevel},
+    typeck::TypeChecker,
+};
+
+/// Check the program in `code`, along with the checker it was checked with.
+fn check(code: &str) -> (Program, TypeChecker) {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let mut checker = TypeChecker::new();
+    let program = checker.check(&ast).expect("the program type checks");
+
+    (program, checker)
+}
+
+/// An interpreter with the program in `code` defined.
+fn interpreter(code: &str) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+
+    interpreter.define(check(code).0).unwrap();
+    interpreter
+}
+
+#[test]
+fn functions_are_called_by_name() {
+    let interpreter = interpreter(
+        "
+fn fib(n: int) -> :int {
+    if n < 2 {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+fn greet(name: str, times: int) -> :str {
+    let s = \"\";
+    let i = 0;
+    while i < times {
+        s = s + \"hi \" + name;
+        i = i + 1;
+    }
+    return s;
+}",
+    );
+
+    assert_eq!(
+        interpreter.call("fib", vec![Value::Integer(15)]).unwrap(),
+        Value::Integer(610)
+    );
+    assert_eq!(
+        interpreter
+            .call(
+                "greet",
+                vec![Value::String("ann".to_string()), Value::Integer(2)]
+            )
+            .unwrap(),
+        Value::String("hi annhi ann".to_string())
+    );
+}
+
+#[test]
+fn calls_need_a_known_function_and_as_many_arguments_as_it_takes() {
+    let interpreter = interpreter("fn square(x: int) -> :int { return x * x; }");
+
+    assert!(interpreter.call("cube", vec![Value::Integer(2)]).is_err());
+    assert!(interpreter.call("square", Vec::new()).is_err());
+    assert!(interpreter
+        .call("square", vec![Value::Integer(2), Value::Integer(3)])
+        .is_err());
+}
+
+#[test]
+fn statics_keep_their_values_between_calls() {
+    let interpreter = interpreter(
+        "static mut count: int = 0; fn bump() -> :int { count = count + 1; return count; }",
+    );
+
+    for expected in 1..=3 {
+        assert_eq!(
+            interpreter.call("bump", Vec::new()).unwrap(),
+            Value::Integer(expected)
+        );
+    }
+}
+
+#[test]
+fn code_outside_of_functions_is_evaluated() {
+    let (program, mut checker) = check("fn double(x: int) -> :int { return x * 2; }");
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program).unwrap();
+
+    let body = Node::Call {
+        name: "double".to_string(),
+        args: vec![Node::Integer(21)],
+    };
+    let body = checker.check_body(&body).unwrap();
+
+    assert_eq!(interpreter.evaluate(&body).unwrap(), Value::Integer(42));
+}
+
+#[test]
+fn extern_functions_are_only_called_through_a_host_function() {
+    let code = "extern fn twice(x: int) -> :int; fn main() -> :int { return twice(4); }";
+    let (program, _) = check(code);
+    let id = program.externs[0].id;
+
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program).unwrap();
+    assert!(interpreter.call("main", Vec::new()).is_err());
+
+    interpreter.define_host(
+        id,
+        HostFn::new(|args| match args[..] {
+            [Value::Integer(x)] => Value::Integer(x * 2),
+            _ => Value::Void,
+        }),
+    );
+    assert_eq!(
+        interpreter.call("main", Vec::new()).unwrap(),
+        Value::Integer(8)
+    );
+}
+
+#[test]
+fn functions_are_only_defined_once() {
+    let (program, _) = check("fn one() -> :int { return 1; }");
+    let mut interpreter = Interpreter::new();
+
+    interpreter.define(program.clone()).unwrap();
+    assert!(interpreter.define(program).is_err());
+}
+
+#[test]
+fn runtime_errors_point_at_the_statement_that_failed() {
+    let interpreter =
+        interpreter("fn divide(a: int, b: int) -> :int {\n    let q = a / b;\n    return q;\n}");
+    let e = interpreter
+        .call("divide", vec![Value::Integer(1), Value::Integer(0)])
+        .unwrap_err();
+
+    assert!(e.is(ErrorCodes::RuntimeError), "{:?}", e);
+    assert_eq!(e.at, (1, 5));
+}
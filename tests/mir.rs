@@ -0,0 +1,158 @@
+//! The control flow graphs functions are lowered to, the dataflow analyses
+//! over them and the checks built on those.
+
+use qlang::{
+    mir::{
+        self,
+        dataflow::{self, DefinitelyInitialised, Liveness, ReachingDefinitions},
+        Body, TerminatorKind, ENTRY,
+    },
+    parser::{error::ErrorCodes, TopLevel},
+    typeck::TypeChecker,
+};
+
+/// The control flow graph of the only function in `code`.
+fn body(code: &str) -> Body {
+    let ast: TopLevel = code.parse().expect("the program parses");
+    let program = TypeChecker::new()
+        .check(&ast)
+        .expect("the program type checks");
+
+    mir::build(&program.functions[0])
+}
+
+/// The local called `name` in `body`.
+fn local(body: &Body, name: &str) -> usize {
+    body.locals
+        .iter()
+        .position(|local| local.name == name)
+        .unwrap()
+}
+
+/// The block `body` returns a value from.
+fn returning(body: &Body) -> usize {
+    body.blocks
+        .iter()
+        .position(|block| matches!(block.terminator.kind, TerminatorKind::Return(Some(_))))
+        .unwrap()
+}
+
+/// The kind of error checking `code` fails with.
+fn error(code: &str) -> u32 {
+    let ast: TopLevel = code.parse().expect("the program parses");
+
+    TypeChecker::new().check(&ast).unwrap_err().code
+}
+
+#[test]
+fn arguments_are_live_until_they_are_last_read() {
+    let body = body("fn f(a: int, b: int) -> :int { let c = a + 1; b = c; return b; }");
+    let live = dataflow::solve(&Liveness, &body);
+
+    // `b` is assigned before it's read, so what it's called with is dead
+    assert!(live.before[ENTRY].contains(local(&body, "a")));
+    assert!(!live.before[ENTRY].contains(local(&body, "b")));
+    assert!(!live.before[ENTRY].contains(local(&body, "c")));
+}
+
+#[test]
+fn every_assignment_on_a_path_reaches_where_the_paths_meet() {
+    let body = body(
+        "fn f(c: bool) -> :int { let x = 1; let y = 1; if c { x = 2; } y = 3; return x + y; }",
+    );
+    let analysis = ReachingDefinitions::new(&body);
+    let reaching = dataflow::solve(&analysis, &body);
+    let reaching_at = |name: &str| {
+        reaching.before[returning(&body)]
+            .iter()
+            .filter(|def| analysis.defs[*def].local == local(&body, name))
+            .count()
+    };
+
+    assert_eq!(reaching_at("x"), 2);
+    assert_eq!(reaching_at("y"), 1);
+}
+
+#[test]
+fn variables_are_only_initialised_once_every_path_assigns_them() {
+    let body = body(
+        "
+fn f(c: bool) -> :int {
+    let x: int;
+    let y: int;
+    if c {
+        x = 1;
+        y = 1;
+    } else {
+        x = 2;
+    }
+    y = 2;
+    return x + y;
+}",
+    );
+    let initialised = dataflow::solve(&DefinitelyInitialised, &body);
+
+    assert!(!initialised.before[ENTRY].contains(local(&body, "x")));
+    assert!(initialised.before[ENTRY].contains(local(&body, "c")));
+    assert!(initialised.after[returning(&body)].contains(local(&body, "x")));
+}
+
+#[test]
+fn variables_read_before_every_path_assigns_them_are_errors() {
+    for code in [
+        "fn f(c: bool) -> :int { let x: int; if c { x = 1; } return x; }",
+        "fn f() -> :int { let x: int; return x + 1; }",
+        // declaring it in a loop makes it uninitialised again
+        "fn f(n: int) -> :int {
+            let last = 0;
+            while n > 0 {
+                let x: int;
+                if n == 2 { x = n; }
+                last = x;
+                n = n - 1;
+            }
+            return last;
+        }",
+    ] {
+        assert_eq!(
+            error(code),
+            ErrorCodes::UninitialisedVariable.code(),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn functions_have_to_return_a_value_on_every_path() {
+    assert_eq!(
+        error("fn f(c: bool) -> :int { if c { return 1; } }"),
+        ErrorCodes::MissingReturn.code()
+    );
+    assert_eq!(
+        error("fn f(c: bool) -> :int { while c { return 1; } }"),
+        ErrorCodes::MissingReturn.code()
+    );
+
+    let ast: TopLevel = "fn f(c: bool) -> :int { if c { return 1; } else { return 2; } }"
+        .parse()
+        .unwrap();
+
+    assert!(TypeChecker::new().check(&ast).is_ok());
+}
+
+#[test]
+fn code_after_a_return_is_a_warning() {
+    let ast: TopLevel = "fn f() -> :int {\n    return 1;\n    let x = 2;\n}"
+        .parse()
+        .unwrap();
+    let mut checker = TypeChecker::new();
+
+    checker.check(&ast).unwrap();
+
+    let warnings = checker.warnings();
+
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].is(ErrorCodes::UnreachableCode));
+    assert_eq!(warnings[0].at, (2, 5));
+}
@@ -0,0 +1,295 @@
+//! Programs made of several files, which import each other's items with
+//! `use`.
+
+use std::{env, error::Error, fs, process};
+
+use qlang::{
+    bytecode::{compiler::Compiler, vm::Vm},
+    interp::{Interpreter, Value},
+    loader::{self, Module},
+    parser::{
+        ast::ast::Node,
+        error::{CompileError, ErrorCodes},
+    },
+    typeck::TypeChecker,
+};
+#[cfg(feature = "llvm")]
+use qlang::{hir, inkwell::context::Context, llvm::codegen::Codegen};
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// Write `files`, each a path and its source, to a directory of their own
+/// named after `test`, and load the program rooted at the first of them.
+fn try_load(
+    test: &str,
+    files: &[(&str, &str)],
+) -> std::result::Result<Vec<Module>, Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("qlang-{}-{}", process::id(), test));
+
+    for (path, source) in files {
+        let path = dir.join(path);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+
+    let modules = loader::load(&dir.join(files[0].0));
+
+    fs::remove_dir_all(dir).unwrap();
+    modules
+}
+
+/// Like [`try_load`], for programs that load.
+fn load(test: &str, files: &[(&str, &str)]) -> Vec<Module> {
+    try_load(test, files).expect("the program loads")
+}
+
+/// The kind of error loading or checking the program in `files` fails with.
+fn error(test: &str, files: &[(&str, &str)]) -> u32 {
+    match try_load(test, files) {
+        Ok(modules) => TypeChecker::new().check_modules(&modules).unwrap_err().code,
+        Err(e) => e.downcast_ref::<CompileError>().unwrap().code,
+    }
+}
+
+/// Check that every backend returns `expected` from `main` in `modules`.
+fn returns(modules: &[Module], expected: Value) {
+    for result in run(modules) {
+        assert_eq!(result.unwrap(), expected);
+    }
+}
+
+/// What calling `main` in `modules` returns with every backend.
+fn run(modules: &[Module]) -> Vec<Result<Value>> {
+    let mut checker = TypeChecker::new();
+    let program = checker
+        .check_modules(modules)
+        .expect("the program type checks");
+    let main = checker
+        .check_body(&Node::Call {
+            name: "main".to_string(),
+            args: Vec::new(),
+        })
+        .unwrap();
+
+    let mut results = Vec::new();
+
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program.clone()).unwrap();
+    results.push(interpreter.evaluate(&main));
+
+    let compiled = Compiler::new().compile(&program).unwrap();
+    results.push(Vm::new(&compiled).call("main", Vec::new()));
+
+    #[cfg(feature = "llvm")]
+    results.push(jit(program, &main));
+
+    results
+}
+
+#[cfg(feature = "llvm")]
+fn jit(program: hir::Program, main: &hir::Function) -> Result<Value> {
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, context.create_module("test"));
+
+    codegen.compile(program).unwrap();
+    codegen.evaluate(main)
+}
+
+#[test]
+fn runtime_errors_in_a_module_are_shown_in_its_file() {
+    let modules = load(
+        "runtime_errors",
+        &[
+            (
+                "main.q",
+                "use maths::div;\n\nfn main() -> :int {\n    return div(1, 0);\n}",
+            ),
+            (
+                "maths.q",
+                "pub fn div(a: int, b: int) -> :int {\n    return a / b;\n}",
+            ),
+        ],
+    );
+    let path = modules[0].path.display().to_string();
+
+    for result in run(&modules) {
+        let error = result.unwrap_err();
+
+        assert_eq!(error.file(), Some(&*path));
+        assert_eq!(error.at, (1, 5));
+    }
+}
+
+#[test]
+fn items_are_imported_from_modules_in_other_directories() {
+    let modules = load(
+        "nested",
+        &[
+            (
+                "main.q",
+                "
+use geom::{Vec2, add};
+use util::shapes::{Shape, area};
+
+fn main() -> :int {
+    let v = add(Vec2 { x: 1, y: 2 }, Vec2 { x: 3, y: 4 });
+    return v.x * 10 + v.y + area(Shape::Square(3));
+}",
+            ),
+            (
+                "geom.q",
+                "
+pub struct Vec2 { x: int, y: int }
+
+pub fn add(a: Vec2, b: Vec2) -> :Vec2 {
+    return Vec2 { x: a.x + b.x, y: a.y + b.y };
+}",
+            ),
+            (
+                "util/shapes.q",
+                "
+pub enum Shape { Square(int), Dot }
+
+pub fn area(s: Shape) -> :int {
+    match s {
+        Shape::Square(n) => n * n,
+        Shape::Dot => 0,
+    }
+}",
+            ),
+        ],
+    );
+
+    returns(&modules, Value::Integer(55));
+}
+
+#[test]
+fn modules_can_have_private_items_of_the_same_name() {
+    let modules = load(
+        "same_names",
+        &[
+            (
+                "main.q",
+                "use a::a;\nuse b::b;\n\nfn main() -> :int { return a() + b() * 10; }",
+            ),
+            (
+                "a.q",
+                "fn helper() -> :int { return 1; }\npub fn a() -> :int { return helper(); }",
+            ),
+            (
+                "b.q",
+                "fn helper() -> :int { return 2; }\npub fn b() -> :int { return helper(); }",
+            ),
+        ],
+    );
+
+    returns(&modules, Value::Integer(21));
+}
+
+#[test]
+fn imports_have_to_resolve() {
+    assert_eq!(
+        error(
+            "missing_module",
+            &[("main.q", "use nowhere::f;\nfn main() -> :int { return 0; }")],
+        ),
+        ErrorCodes::UnresolvedImport.code()
+    );
+    assert_eq!(
+        error(
+            "missing_item",
+            &[
+                ("main.q", "use a::g;\nfn main() -> :int { return 0; }"),
+                ("a.q", "pub fn f() -> :int { return 1; }"),
+            ],
+        ),
+        ErrorCodes::UnresolvedImport.code()
+    );
+}
+
+#[test]
+fn modules_cant_import_each_other_in_a_cycle() {
+    assert_eq!(
+        error(
+            "cycle",
+            &[
+                ("main.q", "use a::f;\nfn main() -> :int { return f(); }"),
+                ("a.q", "use b::g;\npub fn f() -> :int { return g(); }"),
+                ("b.q", "use a::f;\npub fn g() -> :int { return 1; }"),
+            ],
+        ),
+        ErrorCodes::ImportCycle.code()
+    );
+}
+
+#[test]
+fn only_pub_items_can_be_imported() {
+    for (test, item) in [
+        ("private_fn", "fn f() -> :int { return 1; }"),
+        ("private_struct", "struct f { x: int }"),
+        ("private_enum", "enum f { A }"),
+        ("private_static", "static f: int = 1;"),
+        ("private_const", "const f: int = 1;"),
+    ] {
+        assert_eq!(
+            error(
+                test,
+                &[
+                    ("main.q", "use a::f;\nfn main() -> :int { return 0; }"),
+                    ("a.q", item),
+                ],
+            ),
+            ErrorCodes::PrivateItem.code(),
+            "{}",
+            item
+        );
+    }
+}
+
+#[test]
+fn public_items_are_only_seen_where_they_are_imported() {
+    assert_eq!(
+        error(
+            "not_imported",
+            &[
+                (
+                    "main.q",
+                    "use a::f;\nfn main() -> :int { return f() + g(); }"
+                ),
+                (
+                    "a.q",
+                    "pub fn f() -> :int { return 1; }\npub fn g() -> :int { return 2; }",
+                ),
+            ],
+        ),
+        ErrorCodes::UnknownIdentifier.code()
+    );
+}
+
+#[test]
+fn pub_items_can_use_private_ones_of_their_module() {
+    let modules = load(
+        "private_helpers",
+        &[
+            (
+                "main.q",
+                "use counter::{Counter, bump};\n\nfn main() -> :int { return bump(Counter { n: 1 }).n; }",
+            ),
+            (
+                "counter.q",
+                "
+pub struct Counter { n: int }
+
+static STEP: int = 10;
+const TWICE: int = 2;
+
+fn step() -> :int { return STEP * TWICE; }
+
+pub fn bump(c: Counter) -> :Counter { return Counter { n: c.n + step() }; }",
+            ),
+        ],
+    );
+
+    returns(&modules, Value::Integer(21));
+}
@@ -0,0 +1,102 @@
+//! Turning source code into a syntax tree, and the errors doing so reports.
+
+use qlang::parser::{
+    ast::{ast::Node, parser::Parser},
+    error::{CompileError, ErrorCodes},
+    TopLevel,
+};
+
+fn parse(code: &str) -> Result<TopLevel, CompileError> {
+    code.parse()
+}
+
+#[test]
+fn integer_literals_too_big_for_an_int_are_errors() {
+    for code in [
+        "fn main() -> :int { return 3000000000; }",
+        "const A: int = 3000000000;",
+    ] {
+        let e = parse(code).unwrap_err();
+
+        assert!(e.is(ErrorCodes::LiteralOutOfRange), "{:?}", e);
+    }
+
+    assert!(Parser::new("3000000000")
+        .parse_body()
+        .unwrap_err()
+        .is(ErrorCodes::LiteralOutOfRange));
+}
+
+#[test]
+fn literals_out_of_range_are_pointed_at_where_they_start() {
+    let e = parse("fn main() -> :int {\n    let x = -2147483648;\n}").unwrap_err();
+
+    assert_eq!(e.at, (1, 14));
+}
+
+#[test]
+fn the_largest_int_is_a_valid_literal() {
+    assert!(parse("const A: int = 2147483647;").is_ok());
+}
+
+/// The value of the string literal `literal`.
+fn string(literal: &str) -> String {
+    match Parser::new(literal).parse_body() {
+        Ok(Node::Block(mut body)) => match body.pop() {
+            Some(Node::Stmt { node, .. }) => match *node {
+                Node::String(s) => s,
+                node => panic!("expected a string, found {:?}", node),
+            },
+            node => panic!("expected a string, found {:?}", node),
+        },
+        result => panic!("expected a string, found {:?}", result),
+    }
+}
+
+#[test]
+fn escape_sequences_in_strings_are_decoded() {
+    assert_eq!(string(r#""a\n""#), "a\n");
+    assert_eq!(string(r#""\t\0""#), "\t\0");
+    assert_eq!(string(r#""\\""#), "\\");
+    assert_eq!(string(r#""\"hi\"""#), "\"hi\"");
+}
+
+#[test]
+fn unknown_escape_sequences_are_errors() {
+    assert!(Parser::new(r#""\q""#).parse_body().is_err());
+}
+
+#[test]
+fn an_escaped_quote_does_not_end_a_string() {
+    let ast = parse(r#"fn main() -> :str { return "say \"hi\"" + "!"; }"#);
+
+    assert!(ast.is_ok(), "{:?}", ast);
+}
+
+#[test]
+fn embedded_expressions_can_have_strings_of_their_own() {
+    for code in [
+        r#"fn main() -> :str { return "{int("42") + 1}"; }"#,
+        r#"fn main() -> :str { return "a {"b {"c"}"} d"; }"#,
+        r#"fn main() -> :str { return "{'}'}"; }"#,
+    ] {
+        let ast = parse(code);
+
+        assert!(ast.is_ok(), "{}: {:?}", code, ast);
+    }
+}
+
+#[test]
+fn empty_and_unfinished_embedded_expressions_are_errors() {
+    // pointing at the opening brace of an empty one and the closing one
+    // of an unfinished one
+    for (code, at) in [
+        ("const A: str = \"{}\";", (0, 17)),
+        ("const A: str = \"a {1 +} b\";", (0, 23)),
+    ] {
+        let e = parse(code).unwrap_err();
+
+        assert!(e.is(ErrorCodes::UnexpectedToken), "{}: {:?}", code, e);
+        assert_eq!(e.at, at, "{}", code);
+    }
+}
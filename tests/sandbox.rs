@@ -0,0 +1,207 @@
+//! Running code that isn't trusted in a [`Sandbox`]: what it's allowed to
+//! do, and the limits it's stopped at.
+
+use qlang::{parser::error::ErrorCodes, Engine, Limits, Sandbox};
+
+const LIMITS: Limits = Limits {
+    fuel: 10_000,
+    memory: 10_000,
+    stack: 256 * 1024,
+};
+
+/// An engine sandboxed within [`LIMITS`], allowed to use `allowed`, with
+/// `code` compiled.
+fn engine(allowed: &[&str], code: &str) -> Engine<'static> {
+    let sandbox = allowed
+        .iter()
+        .fold(Sandbox::new(LIMITS), |sandbox, name| sandbox.allow(name));
+    let mut engine = Engine::sandboxed(sandbox);
+
+    engine.compile(code).unwrap();
+    engine
+}
+
+#[test]
+fn code_within_the_limits_runs() {
+    let mut engine = engine(
+        &[],
+        "
+fn sum(n: int) -> :int {
+    let total = 0;
+    let i = 0;
+    while i < n {
+        total = total + i;
+        i = i + 1;
+    }
+    return total;
+}",
+    );
+
+    assert_eq!(engine.call::<i32>("sum", (100,)).unwrap(), 4950);
+}
+
+#[test]
+fn running_out_of_fuel_stops_the_code() {
+    let mut engine = engine(
+        &[],
+        "
+fn spin() -> :int {
+    while true {}
+    return 0;
+}
+
+fn one() -> :int { return 1; }",
+    );
+
+    assert!(engine
+        .call::<i32>("spin", ())
+        .unwrap_err()
+        .is(ErrorCodes::OutOfFuel));
+    // every call gets the whole of the fuel again
+    assert_eq!(engine.call::<i32>("one", ()).unwrap(), 1);
+}
+
+#[test]
+fn copying_values_uses_up_memory() {
+    let mut engine = engine(
+        &[],
+        "
+fn copy(times: int) -> :int {
+    let s = \"0123456789012345678901234567890123456789\";
+    let i = 0;
+    while i < times {
+        let t = s;
+        i = i + 1;
+    }
+    return i;
+}
+
+fn copy_array(times: int) -> :int {
+    let a = [1, 2, 3, 4, 5, 6, 7, 8];
+    let i = 0;
+    while i < times {
+        let b = a;
+        i = i + 1;
+    }
+    return i;
+}",
+    );
+
+    assert_eq!(engine.call::<i32>("copy", (10,)).unwrap(), 10);
+
+    for function in ["copy", "copy_array"] {
+        assert!(engine
+            .call::<i32>(function, (1000,))
+            .unwrap_err()
+            .is(ErrorCodes::OutOfMemory));
+    }
+}
+
+#[test]
+fn pushing_copies_uses_up_memory() {
+    let mut engine = engine(
+        &[],
+        "
+fn hoard(times: int) -> :int {
+    let s = \"0123456789012345678901234567890123456789\";
+    let v: Vec<str> = Vec::new();
+    let i = 0;
+    while i < times {
+        push(v, s);
+        i = i + 1;
+    }
+    return len(v);
+}",
+    );
+
+    assert_eq!(engine.call::<i32>("hoard", (10,)).unwrap(), 10);
+    assert!(engine
+        .call::<i32>("hoard", (1000,))
+        .unwrap_err()
+        .is(ErrorCodes::OutOfMemory));
+}
+
+#[test]
+fn recursing_too_deeply_uses_up_the_stack() {
+    let mut engine = engine(
+        &[],
+        "
+fn depth(n: int) -> :int {
+    if n == 0 {
+        return 0;
+    }
+    return depth(n - 1) + 1;
+}",
+    );
+
+    assert_eq!(engine.call::<i32>("depth", (10,)).unwrap(), 10);
+    assert!(engine
+        .call::<i32>("depth", (1100,))
+        .unwrap_err()
+        .is(ErrorCodes::OutOfStack));
+    assert_eq!(engine.call::<i32>("depth", (10,)).unwrap(), 10);
+}
+
+#[test]
+fn extern_functions_cant_be_declared() {
+    let mut engine = Engine::sandboxed(Sandbox::new(LIMITS));
+    let error = engine
+        .compile("extern fn abs(x: int) -> :int;")
+        .unwrap_err();
+
+    assert!(error.is(ErrorCodes::SandboxViolation));
+}
+
+#[test]
+fn only_allowed_host_functions_can_be_registered() {
+    let mut engine = Engine::sandboxed(Sandbox::new(LIMITS).allow("double"));
+
+    assert!(engine
+        .register("triple", |x: i32| x * 3)
+        .unwrap_err()
+        .is(ErrorCodes::SandboxViolation));
+
+    engine.register("double", |x: i32| x * 2).unwrap();
+    engine
+        .compile("fn quadruple(x: int) -> :int { return double(double(x)); }")
+        .unwrap();
+
+    assert_eq!(engine.call::<i32>("quadruple", (3,)).unwrap(), 12);
+}
+
+#[test]
+fn console_builtins_have_to_be_allowed() {
+    let mut engine = Engine::sandboxed(Sandbox::new(LIMITS));
+
+    for code in [
+        "fn f() -> :void { print(\"hi\"); }",
+        "fn f() -> :void { if true { println(\"hi\"); } }",
+        "fn f() -> :str { return \"> \" + read_line(); }",
+    ] {
+        let error = engine.compile(code).unwrap_err();
+
+        assert!(error.is(ErrorCodes::SandboxViolation), "{}", error);
+    }
+
+    assert!(engine
+        .call::<()>("println", ("hi".to_string(),))
+        .unwrap_err()
+        .is(ErrorCodes::SandboxViolation));
+}
+
+#[test]
+fn allowed_console_builtins_can_be_called() {
+    let mut engine = engine(
+        &["print"],
+        "fn greet(name: str) -> :int { print(\"\"); return len(name); }",
+    );
+
+    assert_eq!(
+        engine.call::<i32>("greet", ("qlang".to_string(),)).unwrap(),
+        5
+    );
+    assert!(engine
+        .compile("fn shout() -> :void { println(\"\"); }")
+        .unwrap_err()
+        .is(ErrorCodes::SandboxViolation));
+}
@@ -0,0 +1,188 @@
+//! The errors type checking reports.
+
+use qlang::{
+    parser::{ast::parser::Parser, error::ErrorCodes, TopLevel},
+    typeck::TypeChecker,
+};
+
+/// The kind of error checking `code` fails with.
+fn error(code: &str) -> u32 {
+    let ast: TopLevel = code.parse().expect("the program parses");
+
+    TypeChecker::new().check(&ast).unwrap_err().code
+}
+
+#[test]
+fn assigning_to_what_cant_be_assigned_is_an_invalid_assignment() {
+    for code in [
+        "static A: int = 1; fn main() -> :void { A = 2; }",
+        "const A: int = 1; fn main() -> :void { A = 2; }",
+        "fn main() -> :void { let v: Vec<int> = Vec::new(); v[0] = 1; }",
+        "fn f() -> :int { return 1; } fn main() -> :void { f() = 2; }",
+    ] {
+        assert_eq!(
+            error(code),
+            ErrorCodes::InvalidAssignment.code(),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn return_outside_of_a_function_is_not_allowed() {
+    let body = Parser::new("return 1;").parse_body().unwrap();
+    let e = TypeChecker::new().check_body(&body).unwrap_err();
+
+    assert!(e.is(ErrorCodes::NotAllowedHere), "{:?}", e);
+}
+
+#[test]
+fn mismatched_types_are_reported_as_such() {
+    assert_eq!(
+        error("fn main() -> :int { return true; }"),
+        ErrorCodes::MismatchedTypes.code()
+    );
+}
+
+#[test]
+fn only_values_that_can_be_written_out_are_interpolated() {
+    assert_eq!(
+        error("struct P { x: int } fn main() -> :str { let p = P { x: 1 }; return \"{p}\"; }"),
+        ErrorCodes::MismatchedTypes.code()
+    );
+    assert_eq!(
+        error("fn main() -> :str { let a = [1, 2]; return \"{a}\"; }"),
+        ErrorCodes::MismatchedTypes.code()
+    );
+}
+
+#[test]
+fn a_while_true_loop_never_falls_through() {
+    for code in [
+        "fn f() -> :int { while true {} }",
+        "fn f(x: int) -> :int { while true { if x > 0 { return x; } } }",
+    ] {
+        let ast: TopLevel = code.parse().unwrap();
+
+        assert!(TypeChecker::new().check(&ast).is_ok(), "{}", code);
+    }
+
+    // unlike a loop whose condition can be false
+    assert_eq!(
+        error("fn f(x: int) -> :int { while x > 0 {} }"),
+        ErrorCodes::MissingReturn.code()
+    );
+}
+
+/// The enums the `match` tests use.
+const SHAPES: &str = "
+enum Shape { Circle(float), Rect { w: float, h: float }, Empty }
+enum Opt { Some(Shape), None }
+";
+
+#[test]
+fn matches_have_to_cover_every_value() {
+    for (body, missing) in [
+        (
+            "match s { Shape::Circle(_) => 1, Shape::Rect { .. } => 2 }",
+            "Shape::Empty",
+        ),
+        (
+            "match Opt::Some(s) { Opt::Some(Shape::Circle(_)) => 1, Opt::None => 2 }",
+            "Opt::Some(Shape::Rect",
+        ),
+        ("match 3 { 0 => 1, 1 => 2 }", "_"),
+        ("match true { true => 1 }", "false"),
+        ("match \"a\" { \"a\" => 1 }", "_"),
+    ] {
+        let code = format!("{} fn f(s: Shape) -> :int {{ return {}; }}", SHAPES, body);
+        let ast: TopLevel = code.parse().expect("the program parses");
+        let e = TypeChecker::new().check(&ast).unwrap_err();
+
+        assert!(e.is(ErrorCodes::NonExhaustiveMatch), "{}: {:?}", body, e);
+        assert!(
+            e.notes.iter().any(|note| note.contains(missing)),
+            "{}: {:?}",
+            body,
+            e.notes
+        );
+    }
+}
+
+#[test]
+fn arms_that_can_never_match_are_warnings() {
+    for (body, unreachable) in [
+        ("match s { _ => 1, Shape::Empty => 2 }", 1),
+        (
+            "match s { Shape::Empty => 1, Shape::Empty => 2, _ => 3 }",
+            1,
+        ),
+        ("match true { true => 1, false => 2, _ => 3 }", 1),
+        (
+            "match Opt::Some(s) { Opt::Some(_) => 1, Opt::Some(Shape::Empty) => 2, Opt::None => 3, _ => 4 }",
+            2,
+        ),
+        (
+            "match s { Shape::Circle(_) => 1, Shape::Rect { .. } => 2, Shape::Empty => 3 }",
+            0,
+        ),
+    ] {
+        let code = format!("{} fn f(s: Shape) -> :int {{ return {}; }}", SHAPES, body);
+        let ast: TopLevel = code.parse().expect("the program parses");
+        let mut checker = TypeChecker::new();
+
+        checker.check(&ast).unwrap();
+
+        let warnings = checker.warnings();
+
+        assert_eq!(warnings.len(), unreachable, "{}", body);
+        assert!(warnings
+            .iter()
+            .all(|warning| warning.is(ErrorCodes::UnreachableCode)));
+    }
+}
+
+#[test]
+fn arrays_hold_values_of_one_type_and_are_indexed_by_ints() {
+    for code in [
+        "fn main() -> :int { let a = [1, true]; return 0; }",
+        "fn main() -> :int { let a: [int; 3] = [1, 2]; return 0; }",
+        "fn main() -> :int { let a = [1, 2]; return a[true]; }",
+        "fn main() -> :bool { let a = [1, 2]; return a[0]; }",
+        "fn main() -> :int { let a = [1, 2]; a[0] = 1.5; return 0; }",
+    ] {
+        assert_eq!(error(code), ErrorCodes::MismatchedTypes.code(), "{}", code);
+    }
+}
+
+#[test]
+fn vecs_only_hold_values_of_the_type_they_are_declared_with() {
+    for code in [
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); push(v, true); return 0; }",
+        "fn main() -> :bool { let v: Vec<int> = Vec::new(); return pop(v); }",
+        "fn main() -> :int { let v: Vec<str> = Vec::new(); set(v, 0, 1); return 0; }",
+        "fn main() -> :int { let v: Vec<int> = Vec::new(); for x in v { let b: bool = x; } return 0; }",
+        // nothing says what it holds
+        "fn main() -> :int { let v = Vec::new(); return 0; }",
+    ] {
+        assert_eq!(
+            error(code),
+            ErrorCodes::MismatchedTypes.code(),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn strings_only_join_and_compare_with_other_strings() {
+    for code in [
+        r#"fn main() -> :str { return "a" + 1; }"#,
+        r#"fn main() -> :bool { return "a" < 'a'; }"#,
+        r#"fn main() -> :bool { return "1" == 1; }"#,
+        r#"fn main() -> :int { return len(slice("abc", 0, true)); }"#,
+    ] {
+        assert_eq!(error(code), ErrorCodes::MismatchedTypes.code(), "{}", code);
+    }
+}